@@ -22,6 +22,15 @@ enum Commands {
     Tree,
     /// Get version
     Version,
+    /// Show the keyboard layout, or switch it with key=value pairs
+    /// (rules, model, layout, variant, options), e.g. `layout layout=de variant=nodeadkeys`
+    Layout {
+        args: Vec<String>,
+    },
+    /// Load an XKB text keymap from a file
+    Keymap {
+        path: PathBuf,
+    },
 }
 
 fn main() {
@@ -39,10 +48,17 @@ fn main() {
     };
 
     let cmd = match cli.command {
-        Commands::Ping => "ping\n",
-        Commands::Windows => "windows\n",
-        Commands::Tree => "tree\n",
-        Commands::Version => "version\n",
+        Commands::Ping => "ping\n".to_string(),
+        Commands::Windows => "windows\n".to_string(),
+        Commands::Tree => "tree\n".to_string(),
+        Commands::Version => "version\n".to_string(),
+        Commands::Layout { args } if args.is_empty() => "layout\n".to_string(),
+        Commands::Layout { args } => format!("layout {}\n", args.join(" ")),
+        Commands::Keymap { path } => {
+            // The compositor reads the file itself, so hand it an absolute path
+            let path = std::fs::canonicalize(&path).unwrap_or(path);
+            format!("keymap {}\n", path.display())
+        }
     };
 
     if let Err(e) = stream.write_all(cmd.as_bytes()) {
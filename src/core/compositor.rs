@@ -122,6 +122,11 @@ pub struct CompositorConfig {
     pub keyboard_repeat_delay: i32,
     /// Whether to advertise zwp_fullscreen_shell_v1
     pub advertise_fullscreen_shell: bool,
+    /// Keep the clipboard contents alive after the source client exits
+    pub clipboard_persistence: bool,
+    /// Move and resize windows in the core, for platforms without a
+    /// window manager of their own
+    pub core_window_grabs: bool,
 }
 
 impl Default for CompositorConfig {
@@ -135,6 +140,8 @@ impl Default for CompositorConfig {
             keyboard_repeat_rate: 33,
             keyboard_repeat_delay: 500,
             advertise_fullscreen_shell: false,
+            clipboard_persistence: false,
+            core_window_grabs: false,
         }
     }
 }
@@ -195,6 +202,10 @@ pub enum CompositorEvent {
     CursorShapeChanged { shape: u32 },
     /// System bell / notification requested by client
     SystemBell { client_id: ClientId, surface_id: u32 },
+    /// Keyboard layout switch attempted; `layout` is the layout now active
+    KeyboardLayoutChanged { layout: String, success: bool },
+    /// The host's idle timeout elapsed (`idle`) or input resumed
+    IdleStateChanged { idle: bool },
     /// Redraw needed
     RedrawNeeded { window_id: u32 },
 }
@@ -486,6 +497,12 @@ impl Compositor {
         // Dispatch events to clients
         let dispatched = self.display.dispatch_clients(state)
             .context("Failed to dispatch Wayland events")?;
+        if dispatched > 0 {
+            state.scene_dirty = true;
+        }
+        
+        // Pull clipboard data into the cache and take over orphaned selections
+        state.service_clipboard_cache(&self.display.handle());
         
         // Flush client event queues
         self.display.flush_clients()
@@ -665,9 +682,6 @@ impl Compositor {
             shell.ping(serial);
             state.xdg.pending_pings.insert(serial, (client_id.clone(), *resource_id, now));
         }
-
-        // Check idle timeouts and send idled/resumed events
-        state.ext.idle_notify.check_idle();
     }
 }
 
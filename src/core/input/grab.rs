@@ -0,0 +1,344 @@
+//! Input grabs.
+//!
+//! A grab takes events from one or more input devices before they reach
+//! the focused client. Grabs stack per seat: the most recent grab on a
+//! device sees an event first, and one that passes it lets the grab below
+//! (and finally normal delivery) have it. Whether a new grab may start on
+//! top of the active ones, and which of them it cancels, is decided by
+//! [`stacking`].
+
+use std::any::Any;
+
+use wayland_server::protocol::wl_keyboard;
+
+use crate::core::state::CompositorState;
+
+/// What started a grab
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum GrabKind {
+    /// An xdg_popup.grab chain
+    Popup,
+    /// wl_data_device.start_drag
+    Drag,
+    /// Interactive move or resize of a window
+    Window,
+    /// zwp_input_method_keyboard_grab_v2
+    InputMethod,
+    /// zwp_xwayland_keyboard_grab_v1
+    XwaylandKeyboard,
+    /// ext-session-lock
+    SessionLock,
+}
+
+/// Input device a grab can hold
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GrabDevice {
+    Pointer,
+    Keyboard,
+    Touch,
+}
+
+impl GrabKind {
+    /// Whether grabs of this kind see events from `device`
+    pub fn covers(self, device: GrabDevice) -> bool {
+        match self {
+            GrabKind::Popup | GrabKind::SessionLock => device != GrabDevice::Keyboard,
+            GrabKind::Drag => device == GrabDevice::Pointer,
+            GrabKind::Window => device != GrabDevice::Keyboard,
+            GrabKind::InputMethod | GrabKind::XwaylandKeyboard => device == GrabDevice::Keyboard,
+        }
+    }
+}
+
+/// The held input a grab request's serial belongs to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GrabSerial {
+    /// The pointer button press still held
+    Pointer,
+    /// The touch point with this id, still down
+    Touch(i32),
+    /// The latest key press, still held
+    Key,
+}
+
+/// How an active grab treats a new one
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stacking {
+    /// The new grab goes on top
+    Allow,
+    /// The new grab extends the active one of the same kind
+    Join,
+    /// The active grab is cancelled to make room
+    Cancel,
+    /// The new grab doesn't start
+    Refuse,
+}
+
+/// Decide how the `active` grab treats a `new` one.
+///
+/// The lock screen cancels pointer grabs and admits none while it's up;
+/// keyboard grabs stay and step aside on their own while locked. A move
+/// or resize and a drag each want the pointer to themselves, so they
+/// refuse popups and each other; a move or resize dismisses open popups,
+/// while a drag started from a popup runs on top of it.
+pub fn stacking(active: GrabKind, new: GrabKind) -> Stacking {
+    use GrabKind::*;
+    match (active, new) {
+        (SessionLock, InputMethod | XwaylandKeyboard) | (InputMethod | XwaylandKeyboard, SessionLock) => Stacking::Allow,
+        _ if active == new && active != Drag && active != Window => Stacking::Join,
+        (SessionLock, _) => Stacking::Refuse,
+        (_, SessionLock) => Stacking::Cancel,
+        (Drag | Window, Popup | Drag | Window) => Stacking::Refuse,
+        (Popup, Window) => Stacking::Cancel,
+        _ => Stacking::Allow,
+    }
+}
+
+/// What became of an event a grab was shown
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GrabStatus {
+    /// Not for this grab; the grab below or normal delivery gets it
+    Pass,
+    /// The grab took the event
+    Consumed,
+    /// The grab is over. An event it didn't consume is delivered on.
+    End { consumed: bool },
+}
+
+/// A key event as shown to keyboard grabs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GrabKey {
+    pub key: u32,
+    pub state: wl_keyboard::KeyState,
+    pub time: u32,
+    /// Serialized modifiers, when the key changed them
+    pub modifiers: Option<(u32, u32, u32, u32)>,
+}
+
+/// A grab on some of a seat's input devices.
+///
+/// Handlers get the compositor state with the grab itself taken out of
+/// the stack, so they may start, end or cancel grabs, this one included.
+/// Events from devices the kind doesn't [cover](GrabKind::covers) never
+/// reach it.
+pub trait InputGrab: Any + Send + Sync + std::fmt::Debug {
+    fn kind(&self) -> GrabKind;
+
+    /// Absolute pointer position changed to (x, y)
+    fn pointer_motion(&mut self, _state: &mut CompositorState, _x: f64, _y: f64, _time: u32) -> GrabStatus {
+        GrabStatus::Pass
+    }
+
+    /// A pointer button changed; the seat's button count is already updated
+    fn pointer_button(&mut self, _state: &mut CompositorState, _button: u32, _pressed: bool, _time: u32) -> GrabStatus {
+        GrabStatus::Pass
+    }
+
+    fn key(&mut self, _state: &mut CompositorState, _key: &GrabKey) -> GrabStatus {
+        GrabStatus::Pass
+    }
+
+    /// A touch point went down at (x, y)
+    fn touch_down(&mut self, _state: &mut CompositorState, _id: i32, _x: f64, _y: f64, _time: u32) -> GrabStatus {
+        GrabStatus::Pass
+    }
+
+    /// A touch point moved to (x, y)
+    fn touch_motion(&mut self, _state: &mut CompositorState, _id: i32, _x: f64, _y: f64, _time: u32) -> GrabStatus {
+        GrabStatus::Pass
+    }
+
+    /// A touch point was lifted
+    fn touch_up(&mut self, _state: &mut CompositorState, _id: i32, _time: u32) -> GrabStatus {
+        GrabStatus::Pass
+    }
+
+    /// The grab was cancelled by another one starting; undo what it holds
+    fn cancel(&mut self, _state: &mut CompositorState) {}
+
+    fn as_any(&self) -> &dyn Any;
+}
+
+/// Outcome of asking the stack whether a grab may start
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Admission {
+    /// Push it, cancelling these active grabs first, topmost first
+    Push { cancel: Vec<GrabKind> },
+    /// An active grab of the same kind covers it already
+    Join,
+    /// Refused by an active grab of this kind
+    Refused(GrabKind),
+}
+
+#[derive(Debug)]
+struct GrabEntry {
+    id: u64,
+    kind: GrabKind,
+    /// `None` while the grab is handling an event
+    grab: Option<Box<dyn InputGrab>>,
+}
+
+/// The grabs active on a seat, bottom first
+#[derive(Debug, Default)]
+pub struct GrabStack {
+    entries: Vec<GrabEntry>,
+    next_id: u64,
+}
+
+impl GrabStack {
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn is_active(&self, kind: GrabKind) -> bool {
+        self.entries.iter().any(|e| e.kind == kind)
+    }
+
+    /// Kinds of the active grabs, bottom first
+    pub fn kinds(&self) -> impl Iterator<Item = GrabKind> + '_ {
+        self.entries.iter().map(|e| e.kind)
+    }
+
+    /// The active grab of type `T`, unless it is handling an event
+    pub fn get<T: InputGrab>(&self) -> Option<&T> {
+        self.entries.iter()
+            .filter_map(|e| e.grab.as_deref())
+            .find_map(|grab| grab.as_any().downcast_ref::<T>())
+    }
+
+    /// Whether a grab of `kind` could start now
+    pub fn admits(&self, kind: GrabKind) -> bool {
+        !matches!(self.admission(kind), Admission::Refused(_))
+    }
+
+    pub fn admission(&self, kind: GrabKind) -> Admission {
+        let mut cancel = Vec::new();
+        let mut joined = false;
+        for active in self.entries.iter().rev().map(|e| e.kind) {
+            match stacking(active, kind) {
+                Stacking::Allow => {}
+                Stacking::Join => joined = true,
+                Stacking::Cancel => cancel.push(active),
+                Stacking::Refuse => return Admission::Refused(active),
+            }
+        }
+        if joined {
+            Admission::Join
+        } else {
+            Admission::Push { cancel }
+        }
+    }
+
+    pub(crate) fn push(&mut self, grab: Box<dyn InputGrab>) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.entries.push(GrabEntry { id, kind: grab.kind(), grab: Some(grab) });
+        id
+    }
+
+    /// Drop the grab of `kind` from the stack, handing it back unless it is
+    /// handling an event
+    pub(crate) fn remove(&mut self, kind: GrabKind) -> Option<Option<Box<dyn InputGrab>>> {
+        let index = self.entries.iter().rposition(|e| e.kind == kind)?;
+        Some(self.entries.remove(index).grab)
+    }
+
+    /// Ids of the grabs covering `device`, topmost first
+    pub(crate) fn ids_for(&self, device: GrabDevice) -> Vec<u64> {
+        self.entries.iter().rev()
+            .filter(|e| e.kind.covers(device))
+            .map(|e| e.id)
+            .collect()
+    }
+
+    /// Take a grab out to hand it an event; its place in the stack stays
+    pub(crate) fn take(&mut self, id: u64) -> Option<Box<dyn InputGrab>> {
+        self.entries.iter_mut().find(|e| e.id == id)?.grab.take()
+    }
+
+    /// Put back a grab after [`take`](Self::take). It is dropped instead if
+    /// it `ended`, or was removed from the stack in the meantime.
+    pub(crate) fn restore(&mut self, id: u64, grab: Box<dyn InputGrab>, ended: bool) {
+        let Some(index) = self.entries.iter().position(|e| e.id == id) else { return };
+        if ended {
+            self.entries.remove(index);
+        } else {
+            self.entries[index].grab = Some(grab);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug)]
+    struct TestGrab(GrabKind);
+
+    impl InputGrab for TestGrab {
+        fn kind(&self) -> GrabKind {
+            self.0
+        }
+
+        fn as_any(&self) -> &dyn Any {
+            self
+        }
+    }
+
+    #[test]
+    fn test_grab_stacking() {
+        use GrabKind::*;
+        // A popup can't open over a drag or a move, and a drag or a move
+        // can't start over each other
+        assert_eq!(stacking(Drag, Popup), Stacking::Refuse);
+        assert_eq!(stacking(Window, Popup), Stacking::Refuse);
+        assert_eq!(stacking(Drag, Window), Stacking::Refuse);
+        assert_eq!(stacking(Drag, Drag), Stacking::Refuse);
+        // Dragging out of a menu keeps the menu; moving a window closes it
+        assert_eq!(stacking(Popup, Drag), Stacking::Allow);
+        assert_eq!(stacking(Popup, Window), Stacking::Cancel);
+        assert_eq!(stacking(Popup, Popup), Stacking::Join);
+        // Locking ends pointer grabs and holds off new ones
+        for kind in [Popup, Drag, Window] {
+            assert_eq!(stacking(kind, SessionLock), Stacking::Cancel);
+            assert_eq!(stacking(SessionLock, kind), Stacking::Refuse);
+        }
+        // Keyboard grabs live alongside everything
+        for kind in [Popup, Drag, Window, SessionLock, XwaylandKeyboard] {
+            assert_eq!(stacking(kind, InputMethod), if kind == InputMethod { Stacking::Join } else { Stacking::Allow });
+            assert_eq!(stacking(InputMethod, kind), Stacking::Allow);
+        }
+    }
+
+    #[test]
+    fn test_grab_stack() {
+        let mut stack = GrabStack::default();
+        let popup = stack.push(Box::new(TestGrab(GrabKind::Popup)));
+        assert_eq!(stack.admission(GrabKind::Popup), Admission::Join);
+        let drag = stack.push(Box::new(TestGrab(GrabKind::Drag)));
+        let im = stack.push(Box::new(TestGrab(GrabKind::InputMethod)));
+
+        // Newest first, per device
+        assert_eq!(stack.ids_for(GrabDevice::Pointer), vec![drag, popup]);
+        assert_eq!(stack.ids_for(GrabDevice::Touch), vec![popup]);
+        assert_eq!(stack.ids_for(GrabDevice::Keyboard), vec![im]);
+
+        assert_eq!(stack.admission(GrabKind::Popup), Admission::Refused(GrabKind::Drag));
+        assert!(!stack.admits(GrabKind::Window));
+        assert_eq!(
+            stack.admission(GrabKind::SessionLock),
+            Admission::Push { cancel: vec![GrabKind::Drag, GrabKind::Popup] }
+        );
+
+        // A grab removed while it handles an event isn't put back
+        let grab = stack.take(drag).unwrap();
+        assert!(stack.get::<TestGrab>().is_some());
+        assert!(matches!(stack.remove(GrabKind::Drag), Some(None)));
+        stack.restore(drag, grab, false);
+        assert!(!stack.is_active(GrabKind::Drag));
+
+        let grab = stack.take(popup).unwrap();
+        stack.restore(popup, grab, true);
+        assert_eq!(stack.kinds().collect::<Vec<_>>(), vec![GrabKind::InputMethod]);
+    }
+}
@@ -0,0 +1,85 @@
+//! Picking the surface under a pointer or touch point.
+
+use crate::core::render::scene::FlattenedSurface;
+use crate::util::region::Region;
+
+/// A surface in the scene as seen by input
+#[derive(Debug, Clone)]
+pub struct HitTarget<'a> {
+    pub surface: &'a FlattenedSurface,
+    /// wl_surface.set_input_region in surface-local coordinates; `None`
+    /// accepts input on the whole surface
+    pub input_region: Option<Region>,
+    /// Input falls through to whatever is below
+    pub passthrough: bool,
+}
+
+impl HitTarget<'_> {
+    /// Surface-local coordinates of `(x, y)` if this target takes input
+    /// there.
+    pub fn accepts(&self, x: f64, y: f64) -> Option<(f64, f64)> {
+        let surface = self.surface;
+        // Fully transparent surfaces (alpha modifier at 0) can't be aimed at
+        if self.passthrough || surface.opacity <= 0.0 {
+            return None;
+        }
+        let (sx, sy) = (surface.x as f64, surface.y as f64);
+        let inside = x >= sx
+            && x < sx + surface.width as f64
+            && y >= sy
+            && y < sy + surface.height as f64;
+        if !inside {
+            return None;
+        }
+        let (lx, ly) = surface.scene_to_surface(x, y);
+        match &self.input_region {
+            Some(region) if !region.contains_point(lx.floor() as i32, ly.floor() as i32) => None,
+            _ => Some((lx, ly)),
+        }
+    }
+}
+
+/// Find the topmost target taking input at scene point `(x, y)`.
+///
+/// `targets` is in z-order, bottom first, and should include occluded
+/// surfaces: a surface above may let input through its input region or be
+/// passthrough. Returns the surface and the surface-local coordinates.
+pub fn pick(targets: &[HitTarget<'_>], x: f64, y: f64) -> Option<(u32, f64, f64)> {
+    targets
+        .iter()
+        .rev()
+        .find_map(|target| target.accepts(x, y).map(|(lx, ly)| (target.surface.surface_id, lx, ly)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::geometry::Rect;
+
+    #[test]
+    fn test_input_region_and_passthrough() {
+        let below = FlattenedSurface::covering(1, Rect::new(0, 0, 400, 400));
+        // A window with a 20px drop shadow outside its input region
+        let shadowed = FlattenedSurface::covering(2, Rect::new(100, 100, 240, 240));
+        let overlay = FlattenedSurface::covering(3, Rect::new(0, 0, 400, 400));
+        let mut targets = vec![
+            HitTarget { surface: &below, input_region: None, passthrough: false },
+            HitTarget {
+                surface: &shadowed,
+                input_region: Some(Region::from_rect(Rect::new(20, 20, 200, 200))),
+                passthrough: false,
+            },
+            HitTarget { surface: &overlay, input_region: None, passthrough: true },
+        ];
+
+        assert_eq!(pick(&targets, 110.0, 110.0), Some((1, 110.0, 110.0)));
+        assert_eq!(pick(&targets, 130.0, 125.0), Some((2, 30.0, 25.0)));
+
+        // An empty input region takes no input at all
+        targets[1].input_region = Some(Region::new());
+        assert_eq!(pick(&targets, 130.0, 125.0).map(|hit| hit.0), Some(1));
+
+        targets[2].passthrough = false;
+        assert_eq!(pick(&targets, 130.0, 125.0).map(|hit| hit.0), Some(3));
+    }
+}
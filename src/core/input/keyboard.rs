@@ -1,5 +1,5 @@
 use std::sync::Arc;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 use wayland_server::Resource;
 use wayland_server::protocol::wl_keyboard::{self, WlKeyboard};
 use wayland_server::protocol::wl_surface::WlSurface;
@@ -24,13 +24,25 @@ pub struct KeyboardState {
     pub xkb_context: Arc<XkbContext>,
     /// XKB state machine (None if keymap compilation failed)
     pub xkb_state: Option<Arc<std::sync::Mutex<XkbState>>>,
+    /// Name of the active layout, for display by the frontend
+    pub layout_name: String,
     /// Key repeat configuration
     pub repeat_rate: i32,
     pub repeat_delay: i32,
+    /// Generate key repeats in the compositor instead of leaving them to
+    /// clients (clients are then told the repeat rate is 0)
+    pub compositor_repeat: bool,
+    /// Key and serial of the latest press, while that key is held
+    pub grab_serial: Option<(u32, u32)>,
     /// Key repeat tracking
     repeat_key: Option<u32>,
+    /// Surface focused when the repeating key was pressed
+    repeat_focus: Option<u32>,
     repeat_started_at: Option<Instant>,
-    last_repeat_at: Option<Instant>,
+    /// Client timestamp (ms) of the press that started the repeat
+    repeat_time_ms: u32,
+    /// Number of repeats already emitted for the current key
+    repeat_count: u32,
 }
 
 impl Default for KeyboardState {
@@ -41,6 +53,8 @@ impl Default for KeyboardState {
             .ok()
             .map(|s| Arc::new(std::sync::Mutex::new(s)));
 
+        let layout_name = layout_name_of(&xkb_state);
+
         Self {
             focus: None,
             pressed_keys: Vec::new(),
@@ -51,11 +65,16 @@ impl Default for KeyboardState {
             resources: Vec::new(),
             xkb_context,
             xkb_state,
+            layout_name,
             repeat_rate: 33,
             repeat_delay: 500,
+            compositor_repeat: false,
+            grab_serial: None,
             repeat_key: None,
+            repeat_focus: None,
             repeat_started_at: None,
-            last_repeat_at: None,
+            repeat_time_ms: 0,
+            repeat_count: 0,
         }
     }
 }
@@ -72,10 +91,12 @@ impl KeyboardState {
             })
             .ok()
             .map(|s| Arc::new(std::sync::Mutex::new(s)));
+        let layout_name = layout_name_of(&xkb_state);
 
         Self {
             xkb_context,
             xkb_state,
+            layout_name,
             ..Default::default()
         }
     }
@@ -137,16 +158,8 @@ impl KeyboardState {
             if !self.pressed_keys.contains(&keycode) {
                 self.pressed_keys.push(keycode);
             }
-            self.repeat_key = Some(keycode);
-            self.repeat_started_at = Some(Instant::now());
-            self.last_repeat_at = None;
         } else {
             self.pressed_keys.retain(|&k| k != keycode);
-            if self.repeat_key == Some(keycode) {
-                self.repeat_key = None;
-                self.repeat_started_at = None;
-                self.last_repeat_at = None;
-            }
         }
 
         if let Some(xkb) = &self.xkb_state {
@@ -165,34 +178,121 @@ impl KeyboardState {
         None
     }
 
-    /// Check if a key repeat event should fire. Returns the keycode to repeat, if any.
-    pub fn check_repeat(&mut self) -> Option<u32> {
-        if self.repeat_rate == 0 {
+    /// Track the press a client may start a grab with: the latest one, until
+    /// its key is released
+    pub fn update_grab_serial(&mut self, keycode: u32, pressed: bool, serial: u32) {
+        if pressed {
+            self.grab_serial = Some((keycode, serial));
+        } else if self.grab_serial.is_some_and(|(key, _)| key == keycode) {
+            self.grab_serial = None;
+        }
+    }
+
+    /// Whether a client may start a grab with this serial: it must be the
+    /// latest key press, still held
+    pub fn grab_serial_valid(&self, serial: u32) -> bool {
+        self.grab_serial.is_some_and(|(_, s)| s == serial)
+    }
+
+    /// Track a key press/release for compositor-side repeat.
+    ///
+    /// A press starts repeating the key (if the keymap says it repeats) and
+    /// replaces any key already repeating; releasing that key stops it.
+    pub fn update_repeat(&mut self, keycode: u32, pressed: bool, time_ms: u32) {
+        if !pressed {
+            if self.repeat_key == Some(keycode) {
+                self.cancel_repeat();
+            }
+            return;
+        }
+
+        let repeats = self.xkb_state.as_ref()
+            .and_then(|s| s.lock().ok().map(|s| s.key_repeats(keycode)))
+            .unwrap_or(true);
+        if !repeats {
+            self.cancel_repeat();
+            return;
+        }
+
+        self.repeat_key = Some(keycode);
+        self.repeat_focus = self.focus;
+        self.repeat_started_at = Some(Instant::now());
+        self.repeat_time_ms = time_ms;
+        self.repeat_count = 0;
+    }
+
+    /// Stop any key repeat in progress.
+    pub fn cancel_repeat(&mut self) {
+        self.repeat_key = None;
+        self.repeat_focus = None;
+        self.repeat_started_at = None;
+        self.repeat_count = 0;
+    }
+
+    fn repeat_interval(&self) -> Option<Duration> {
+        if self.repeat_rate <= 0 {
             return None;
         }
+        Some(Duration::from_micros(1_000_000 / self.repeat_rate as u64))
+    }
 
-        let key = self.repeat_key?;
+    /// When the next compositor-side repeat is due, if one is pending.
+    pub fn next_repeat_at(&self) -> Option<Instant> {
+        if !self.compositor_repeat {
+            return None;
+        }
+        self.repeat_key?;
+        let interval = self.repeat_interval()?;
         let started = self.repeat_started_at?;
-        let now = Instant::now();
-        let elapsed = now.duration_since(started);
-        let delay = std::time::Duration::from_millis(self.repeat_delay as u64);
+        let delay = Duration::from_millis(self.repeat_delay.max(0) as u64);
+        Some(started + delay + interval * self.repeat_count)
+    }
 
-        if elapsed < delay {
+    /// Check if a key repeat event should fire at `now`.
+    ///
+    /// Returns the keycode to repeat and its timestamp in the same
+    /// millisecond timebase as the original press. If the caller fell behind
+    /// by several intervals, only the latest due repeat is returned. The
+    /// repeat is cancelled if keyboard focus moved since the press.
+    pub fn check_repeat(&mut self, now: Instant) -> Option<(u32, u32)> {
+        let due = self.next_repeat_at()?;
+        if self.focus != self.repeat_focus {
+            self.cancel_repeat();
+            return None;
+        }
+        if now < due {
             return None;
         }
 
-        let interval = std::time::Duration::from_millis(1000 / self.repeat_rate as u64);
-        if let Some(last) = self.last_repeat_at {
-            if now.duration_since(last) >= interval {
-                self.last_repeat_at = Some(now);
-                return Some(key);
-            }
+        let key = self.repeat_key?;
+        let interval = self.repeat_interval()?;
+        let delay = Duration::from_millis(self.repeat_delay.max(0) as u64);
+        let since_first = now.duration_since(self.repeat_started_at? + delay);
+        let index = (since_first.as_micros() / interval.as_micros()) as u32;
+        self.repeat_count = index + 1;
+
+        let offset = delay + interval * index;
+        Some((key, self.repeat_time_ms.wrapping_add(offset.as_millis() as u32)))
+    }
+
+    /// Repeat info to advertise to clients: rate 0 disables client-side
+    /// repeat while the compositor generates repeats itself.
+    pub fn advertised_repeat_info(&self) -> (i32, i32) {
+        if self.compositor_repeat {
+            (0, self.repeat_delay)
         } else {
-            self.last_repeat_at = Some(now);
-            return Some(key);
+            (self.repeat_rate, self.repeat_delay)
         }
+    }
 
-        None
+    /// Send the current repeat info to every bound keyboard (version 4+).
+    pub fn broadcast_repeat_info(&self) {
+        let (rate, delay) = self.advertised_repeat_info();
+        for kbd in &self.resources {
+            if kbd.version() >= 4 {
+                kbd.repeat_info(rate, delay);
+            }
+        }
     }
 
     /// Send enter event to all keyboard resources matching the surface's client.
@@ -219,7 +319,8 @@ impl KeyboardState {
                     self.mods_group,
                 );
                 if kbd.version() >= 4 {
-                    kbd.repeat_info(self.repeat_rate, self.repeat_delay);
+                    let (rate, delay) = self.advertised_repeat_info();
+                    kbd.repeat_info(rate, delay);
                 }
             }
         }
@@ -275,6 +376,9 @@ impl KeyboardState {
     }
 
     /// Switch to a new keymap at runtime. All connected keyboards receive the new keymap.
+    ///
+    /// The keymap is compiled and validated before anything is sent, so on
+    /// failure the previous keymap stays active and clients see no change.
     pub fn switch_keymap(
         &mut self,
         rules: &str,
@@ -283,8 +387,6 @@ impl KeyboardState {
         variant: &str,
         options: Option<String>,
     ) -> Result<(), ()> {
-        use std::os::unix::io::AsFd;
-
         let new_state = XkbState::new_from_names(
             self.xkb_context.clone(),
             rules,
@@ -293,6 +395,24 @@ impl KeyboardState {
             variant,
             options,
         )?;
+        self.install_keymap(new_state)
+    }
+
+    /// Switch to a keymap given in XKB text format. Same failure semantics
+    /// as `switch_keymap`.
+    pub fn switch_keymap_from_string(&mut self, keymap: &str) -> Result<(), ()> {
+        let new_state = XkbState::new_from_string(self.xkb_context.clone(), keymap)?;
+        self.install_keymap(new_state)
+    }
+
+    /// Make `new_state` the active keymap and send it to every bound keyboard.
+    fn install_keymap(&mut self, new_state: XkbState) -> Result<(), ()> {
+        use std::os::unix::io::AsFd;
+
+        // A keymap without layouts compiles but cannot produce any keysyms.
+        if new_state.keymap.num_layouts() == 0 {
+            return Err(());
+        }
 
         let file = new_state.keymap_file();
         let size = new_state.keymap_size;
@@ -305,6 +425,7 @@ impl KeyboardState {
             );
         }
 
+        self.layout_name = new_state.layout_name();
         self.xkb_state = Some(Arc::new(std::sync::Mutex::new(new_state)));
         self.mods_depressed = 0;
         self.mods_latched = 0;
@@ -314,9 +435,163 @@ impl KeyboardState {
         Ok(())
     }
 
+    /// Re-read the layout name from the effective group, after a key or
+    /// modifier update may have switched groups. Returns whether it changed.
+    pub fn refresh_layout_name(&mut self) -> bool {
+        let name = layout_name_of(&self.xkb_state);
+        if name == self.layout_name {
+            return false;
+        }
+        self.layout_name = name;
+        true
+    }
+
     /// Clean up dead resources
     pub fn cleanup_resources(&mut self) {
         // Note: keyboards are not aggressively cleaned — they are removed
         // when clients explicitly release them or disconnect.
     }
 }
+
+/// Layout name of an optional XKB state, empty when no keymap is loaded.
+fn layout_name_of(xkb_state: &Option<Arc<std::sync::Mutex<XkbState>>>) -> String {
+    xkb_state
+        .as_ref()
+        .and_then(|s| s.lock().ok().map(|s| s.layout_name()))
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::compositor::CompositorEvent;
+    use crate::core::state::CompositorState;
+
+    /// Two layouts, with Right Alt locking the next one
+    const TWO_LAYOUT_KEYMAP: &str = concat!(
+        "xkb_keymap {\n",
+        "  xkb_keycodes \"test\" {\n",
+        "    minimum = 8;\n",
+        "    maximum = 255;\n",
+        "    <AC01> = 38;\n",
+        "    <RALT> = 108;\n",
+        "  };\n",
+        "  xkb_types \"test\" {\n",
+        "    type \"ONE_LEVEL\" {\n",
+        "      modifiers = none;\n",
+        "      map[none] = Level1;\n",
+        "      level_name[Level1] = \"Any\";\n",
+        "    };\n",
+        "  };\n",
+        "  xkb_compatibility \"test\" {\n",
+        "    interpret ISO_Next_Group { action = LockGroup(group=+1); };\n",
+        "  };\n",
+        "  xkb_symbols \"test\" {\n",
+        "    name[Group1] = \"English (US)\";\n",
+        "    name[Group2] = \"French\";\n",
+        "    key <AC01> { [ a ], [ q ] };\n",
+        "    key <RALT> { [ ISO_Next_Group ], [ ISO_Next_Group ] };\n",
+        "  };\n",
+        "};\n",
+    );
+
+    /// Linux evdev code of Right Alt
+    const KEY_RIGHTALT: u32 = 100;
+
+    fn layout_events(state: &mut CompositorState) -> Vec<(String, bool)> {
+        state.pending_compositor_events.drain(..).filter_map(|event| match event {
+            CompositorEvent::KeyboardLayoutChanged { layout, success } => Some((layout, success)),
+            _ => None,
+        }).collect()
+    }
+
+    #[test]
+    fn test_switch_keymap() {
+        let mut keyboard = KeyboardState::new(Arc::new(XkbContext::new()));
+        keyboard.mods_locked = 2;
+
+        assert!(keyboard.switch_keymap_from_string(TWO_LAYOUT_KEYMAP).is_ok());
+        assert_eq!(keyboard.layout_name, "English (US)");
+        assert_eq!(keyboard.mods_locked, 0);
+        let state = keyboard.xkb_state.as_ref().unwrap().lock().unwrap();
+        assert_eq!(state.keymap.num_layouts(), 2);
+    }
+
+    #[test]
+    fn test_switch_keymap_failure_keeps_keymap() {
+        let mut keyboard = KeyboardState::new(Arc::new(XkbContext::new()));
+        keyboard.switch_keymap_from_string(TWO_LAYOUT_KEYMAP).unwrap();
+        let before = keyboard.xkb_state.clone().unwrap();
+
+        assert!(keyboard.switch_keymap_from_string("xkb_keymap { not a keymap").is_err());
+        assert_eq!(keyboard.layout_name, "English (US)");
+        assert!(Arc::ptr_eq(keyboard.xkb_state.as_ref().unwrap(), &before));
+    }
+
+    #[test]
+    fn test_keymap_switch_event() {
+        let mut state = CompositorState::new(None);
+        state.pending_compositor_events.clear();
+
+        state.set_keymap_string(TWO_LAYOUT_KEYMAP).unwrap();
+        assert_eq!(layout_events(&mut state), vec![("English (US)".to_string(), true)]);
+
+        // A failed switch still reports, naming the layout that stays active
+        assert!(state.set_keymap_string("xkb_keymap {").is_err());
+        assert_eq!(layout_events(&mut state), vec![("English (US)".to_string(), false)]);
+    }
+
+    #[test]
+    fn test_group_switch_updates_layout() {
+        let mut state = CompositorState::new(None);
+        state.set_keymap_string(TWO_LAYOUT_KEYMAP).unwrap();
+        state.pending_compositor_events.clear();
+
+        // A key locking the next group
+        state.inject_key(KEY_RIGHTALT, wl_keyboard::KeyState::Pressed, 0);
+        state.inject_key(KEY_RIGHTALT, wl_keyboard::KeyState::Released, 10);
+        assert_eq!(state.seat.keyboard.mods_group, 1);
+        assert_eq!(state.seat.keyboard.layout_name, "French");
+        assert_eq!(layout_events(&mut state), vec![("French".to_string(), true)]);
+
+        // Modifiers from the platform naming the group
+        state.inject_modifiers(0, 0, 0, 0);
+        assert_eq!(state.seat.keyboard.layout_name, "English (US)");
+        assert_eq!(layout_events(&mut state), vec![("English (US)".to_string(), true)]);
+
+        // Modifier updates that keep the group report nothing
+        state.inject_modifiers(1, 0, 0, 0);
+        assert!(layout_events(&mut state).is_empty());
+    }
+
+    #[test]
+    fn test_compositor_key_repeat() {
+        let mut keyboard = KeyboardState::default();
+        keyboard.compositor_repeat = true;
+        keyboard.repeat_rate = 25;
+        keyboard.repeat_delay = 500;
+
+        keyboard.update_repeat(30, true, 1000);
+        assert!(keyboard.check_repeat(Instant::now()).is_none());
+
+        // First repeat fires after the delay, stamped press time + delay
+        let first = Instant::now() + Duration::from_millis(500);
+        assert_eq!(keyboard.check_repeat(first), Some((30, 1500)));
+        assert!(keyboard.check_repeat(first).is_none());
+
+        // Repeats are spaced 1000 / rate ms apart; falling behind skips
+        // straight to the latest one that is due
+        let late = keyboard.next_repeat_at().unwrap() + Duration::from_millis(40);
+        assert_eq!(keyboard.check_repeat(late), Some((30, 1580)));
+
+        // Releasing the key stops repeating
+        keyboard.update_repeat(30, false, 1600);
+        assert!(keyboard.next_repeat_at().is_none());
+
+        // A focus change cancels a pending repeat
+        keyboard.update_repeat(30, true, 2000);
+        keyboard.focus = Some(7);
+        assert!(keyboard.check_repeat(Instant::now() + Duration::from_secs(1)).is_none());
+        assert!(keyboard.next_repeat_at().is_none());
+    }
+}
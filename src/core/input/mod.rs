@@ -3,10 +3,12 @@ pub mod keyboard;
 pub mod pointer;
 pub mod touch;
 pub mod seat;
+pub mod hit_test;
+pub mod grab;
 
 // Re-export key types for convenience
 pub use keyboard::KeyboardState;
-pub use pointer::PointerState;
+pub use pointer::{AxisFrame, PointerState};
 pub use touch::TouchState;
 pub use seat::Seat;
 pub use xkb::{XkbContext, XkbState, KeyResult};
@@ -34,8 +36,15 @@ pub enum InputEvent {
         time_ms: u32,
     },
     PointerAxis {
-        horizontal: f64,
-        vertical: f64,
+        frame: AxisFrame,
+    },
+    /// One frame of tablet tool changes, in global coordinates
+    TabletTool {
+        events: Vec<crate::core::wayland::ext::tablet::TabletToolEvent>,
+        time_ms: u32,
+    },
+    TabletPad {
+        event: crate::core::wayland::ext::tablet::TabletPadEvent,
         time_ms: u32,
     },
     KeyboardKey {
@@ -2,6 +2,43 @@ use wayland_server::Resource;
 use wayland_server::protocol::wl_pointer::{self, WlPointer};
 use wayland_server::protocol::wl_surface::WlSurface;
 
+/// One logical scroll event covering both axes, delivered to clients as a
+/// single `wl_pointer.frame`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AxisFrame {
+    /// Physical source of the scroll (None if the platform does not know)
+    pub source: Option<wl_pointer::AxisSource>,
+    /// Continuous scroll distance in surface-local units
+    pub horizontal: f64,
+    pub vertical: f64,
+    /// High-resolution wheel steps; 120 is one full detent
+    pub horizontal_v120: i32,
+    pub vertical_v120: i32,
+    /// The axis stopped scrolling (e.g. fingers lifted off a touchpad)
+    pub horizontal_stop: bool,
+    pub vertical_stop: bool,
+    /// Scroll direction is inverted relative to the physical motion (natural scrolling)
+    pub inverted: bool,
+    pub time_ms: u32,
+}
+
+impl AxisFrame {
+    /// A plain continuous scroll with no source or discrete information.
+    pub fn continuous(horizontal: f64, vertical: f64, time_ms: u32) -> Self {
+        Self {
+            source: None,
+            horizontal,
+            vertical,
+            horizontal_v120: 0,
+            vertical_v120: 0,
+            horizontal_stop: false,
+            vertical_stop: false,
+            inverted: false,
+            time_ms,
+        }
+    }
+}
+
 /// Pointer state for a seat, managing position, focus, buttons, and cursor.
 #[derive(Debug, Clone, Default)]
 pub struct PointerState {
@@ -15,6 +52,8 @@ pub struct PointerState {
     pub focus_y: f64,
     /// Number of buttons currently pressed (for implicit grab tracking)
     pub button_count: u32,
+    /// Serial of the press that started the implicit grab
+    pub grab_serial: Option<u32>,
     /// Cursor surface ID set by the client via wl_pointer.set_cursor
     pub cursor_surface: Option<u32>,
     /// Cursor hotspot
@@ -24,6 +63,9 @@ pub struct PointerState {
     pub cursor_shape: Option<u32>,
     /// Bound pointer resources from clients
     pub resources: Vec<WlPointer>,
+    /// Leftover value120 per axis (horizontal, vertical) not yet reported as
+    /// a whole axis_discrete step to pre-v8 clients
+    discrete_remainder: (i32, i32),
 }
 
 impl PointerState {
@@ -64,6 +106,12 @@ impl PointerState {
         self.button_count > 0
     }
 
+    /// Whether a client may start a grab with this serial: it must be the
+    /// press that is still being held
+    pub fn grab_serial_valid(&self, serial: u32) -> bool {
+        self.has_implicit_grab() && self.grab_serial == Some(serial)
+    }
+
     /// Send enter event to pointer resources matching the surface's client
     pub fn broadcast_enter(
         &self,
@@ -126,16 +174,93 @@ impl PointerState {
     }
 
     /// Send frame event to focused client's pointer resources
+    /// (wl_pointer.frame only exists from version 5)
     pub fn broadcast_frame(&self, focused_client: Option<&wayland_server::Client>) {
         if let Some(focused) = focused_client {
             for ptr in &self.resources {
-                if ptr.client().as_ref() == Some(focused) {
+                if ptr.client().as_ref() == Some(focused) && ptr.version() >= 5 {
                     ptr.frame();
                 }
             }
         }
     }
 
+    /// Send a complete scroll frame to the focused client's pointer resources.
+    ///
+    /// Each resource receives only the events its version knows about:
+    /// axis_source/axis_stop/axis_discrete/frame from v5, axis_value120
+    /// instead of axis_discrete from v8, and axis_relative_direction from v9.
+    pub fn broadcast_axis_frame(
+        &mut self,
+        frame: &AxisFrame,
+        focused_client: Option<&wayland_server::Client>,
+    ) {
+        let (h_steps, v_steps) = self.discrete_steps(frame);
+
+        let Some(focused) = focused_client else { return };
+        let direction = if frame.inverted {
+            wl_pointer::AxisRelativeDirection::Inverted
+        } else {
+            wl_pointer::AxisRelativeDirection::Identical
+        };
+        let axes = [
+            (wl_pointer::Axis::VerticalScroll, frame.vertical, frame.vertical_v120, v_steps, frame.vertical_stop),
+            (wl_pointer::Axis::HorizontalScroll, frame.horizontal, frame.horizontal_v120, h_steps, frame.horizontal_stop),
+        ];
+
+        for ptr in &self.resources {
+            if ptr.client().as_ref() != Some(focused) {
+                continue;
+            }
+            let version = ptr.version();
+
+            if version >= 5 {
+                if let Some(source) = frame.source {
+                    // wheel_tilt was only added in version 6
+                    if version >= 6 || source != wl_pointer::AxisSource::WheelTilt {
+                        ptr.axis_source(source);
+                    }
+                }
+            }
+
+            for &(axis, value, v120, steps, stop) in &axes {
+                if value != 0.0 || v120 != 0 {
+                    if version >= 9 {
+                        ptr.axis_relative_direction(axis, direction);
+                    }
+                    if version >= 8 {
+                        if v120 != 0 {
+                            ptr.axis_value120(axis, v120);
+                        }
+                    } else if version >= 5 && steps != 0 {
+                        ptr.axis_discrete(axis, steps);
+                    }
+                    ptr.axis(frame.time_ms, axis, value);
+                } else if stop && version >= 5 {
+                    ptr.axis_stop(frame.time_ms, axis);
+                }
+            }
+
+            // Close the group even when it only carried the source
+            if version >= 5 {
+                ptr.frame();
+            }
+        }
+    }
+
+    /// Whole wheel detents in a frame, for pre-v8 clients that only
+    /// understand axis_discrete. The rest of the high-resolution steps is
+    /// carried over to the next frame.
+    fn discrete_steps(&mut self, frame: &AxisFrame) -> (i32, i32) {
+        if frame.horizontal_v120 == 0 && frame.vertical_v120 == 0 {
+            return (0, 0);
+        }
+        let h = self.discrete_remainder.0 + frame.horizontal_v120;
+        let v = self.discrete_remainder.1 + frame.vertical_v120;
+        self.discrete_remainder = (h % 120, v % 120);
+        (h / 120, v / 120)
+    }
+
     /// Send axis event to focused client's pointer resources
     pub fn broadcast_axis(
         &self,
@@ -166,3 +291,44 @@ impl PointerState {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wheel(horizontal_v120: i32, vertical_v120: i32) -> AxisFrame {
+        AxisFrame {
+            source: Some(wl_pointer::AxisSource::Wheel),
+            horizontal_v120,
+            vertical_v120,
+            ..AxisFrame::continuous(horizontal_v120 as f64 / 12.0, vertical_v120 as f64 / 12.0, 0)
+        }
+    }
+
+    #[test]
+    fn test_discrete_steps_carry_remainder() {
+        let mut pointer = PointerState::default();
+        // Half detents add up to a whole one on the second frame
+        assert_eq!(pointer.discrete_steps(&wheel(0, 60)), (0, 0));
+        assert_eq!(pointer.discrete_steps(&wheel(0, 60)), (0, 1));
+        // Whole detents pass straight through, leaving the rest
+        assert_eq!(pointer.discrete_steps(&wheel(0, 270)), (0, 2));
+        assert_eq!(pointer.discrete_remainder, (0, 30));
+        // Each axis keeps its own remainder
+        assert_eq!(pointer.discrete_steps(&wheel(-90, 90)), (0, 1));
+        assert_eq!(pointer.discrete_steps(&wheel(-30, 0)), (-1, 0));
+        assert_eq!(pointer.discrete_remainder, (0, 0));
+    }
+
+    #[test]
+    fn test_discrete_steps_continuous_scroll() {
+        let mut pointer = PointerState::default();
+        assert_eq!(pointer.discrete_steps(&wheel(0, 100)), (0, 0));
+        // A touchpad scroll has no detents and leaves the remainder alone
+        assert_eq!(pointer.discrete_steps(&AxisFrame::continuous(0.0, 15.0, 0)), (0, 0));
+        assert_eq!(pointer.discrete_remainder, (0, 100));
+        // Scrolling back cancels the partial detent
+        assert_eq!(pointer.discrete_steps(&wheel(0, -100)), (0, 0));
+        assert_eq!(pointer.discrete_steps(&wheel(0, -120)), (0, -1));
+    }
+}
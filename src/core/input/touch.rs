@@ -13,6 +13,8 @@ pub struct TouchPoint {
     /// Current position in surface-local coordinates
     pub x: f64,
     pub y: f64,
+    /// Serial of the touch down event
+    pub serial: u32,
 }
 
 /// Touch state for a seat, managing active touch points and resources.
@@ -40,7 +42,7 @@ impl TouchState {
     }
 
     /// Record a new touch point
-    pub fn touch_down(&mut self, id: i32, surface_id: u32, x: f64, y: f64) {
+    pub fn touch_down(&mut self, id: i32, surface_id: u32, x: f64, y: f64, serial: u32) {
         self.active_points.insert(
             id,
             TouchPoint {
@@ -48,6 +50,7 @@ impl TouchState {
                 surface_id,
                 x,
                 y,
+                serial,
             },
         );
     }
@@ -75,6 +78,11 @@ impl TouchState {
         self.active_points.get(&id).map(|p| p.surface_id)
     }
 
+    /// The touch point still down whose touch down event had `serial`
+    pub fn point_for_serial(&self, serial: u32) -> Option<&TouchPoint> {
+        self.active_points.values().find(|p| p.serial == serial)
+    }
+
     /// Whether any touch points are active
     pub fn has_active_touches(&self) -> bool {
         !self.active_points.is_empty()
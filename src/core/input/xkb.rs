@@ -197,6 +197,17 @@ impl XkbState {
         (depressed, latched, locked, group)
     }
 
+    /// Whether the keymap says `keycode` (evdev scancode) should repeat.
+    pub fn key_repeats(&self, keycode: u32) -> bool {
+        self.keymap.key_repeats(xkb::Keycode::from(keycode + 8))
+    }
+
+    /// Human-readable name of the effective layout (e.g. "English (US)").
+    pub fn layout_name(&self) -> String {
+        let index = self.state.serialize_layout(xkb::STATE_LAYOUT_EFFECTIVE);
+        self.keymap.layout_get_name(index).to_string()
+    }
+
     /// Check if a specific modifier is active
     pub fn mod_is_active(&self, name: &str) -> bool {
         // xkbcommon mod names: "Shift", "Control", "Mod1" (Alt), "Mod4" (Super)
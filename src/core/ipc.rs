@@ -83,6 +83,12 @@ impl IpcServer {
                                             "error: lock failed\n".to_string()
                                         }
                                     },
+                                    _ if cmd == "layout" || cmd.starts_with("layout ") => {
+                                        layout_command(&state, cmd["layout".len()..].trim())
+                                    },
+                                    _ if cmd.starts_with("keymap ") => {
+                                        keymap_command(&state, cmd["keymap ".len()..].trim())
+                                    },
                                     _ => "error: unknown command\n".to_string(),
                                 };
                                 
@@ -104,6 +110,53 @@ impl IpcServer {
     }
 }
 
+/// `layout` prints the active layout; `layout key=value ...` switches it.
+/// Recognised keys are rules, model, layout, variant and options.
+fn layout_command(state: &RwLock<CompositorState>, args: &str) -> String {
+    let Ok(mut state) = state.write() else {
+        return "error: lock failed\n".to_string();
+    };
+    if args.is_empty() {
+        return format!("{}\n", state.seat.keyboard.layout_name);
+    }
+
+    let (mut rules, mut model, mut layout, mut variant, mut options) =
+        (String::new(), String::new(), String::new(), String::new(), None);
+    for arg in args.split_whitespace() {
+        let Some((key, value)) = arg.split_once('=') else {
+            return format!("error: expected key=value, got {:?}\n", arg);
+        };
+        match key {
+            "rules" => rules = value.to_string(),
+            "model" => model = value.to_string(),
+            "layout" => layout = value.to_string(),
+            "variant" => variant = value.to_string(),
+            "options" => options = Some(value.to_string()),
+            _ => return format!("error: unknown key {:?}\n", key),
+        }
+    }
+
+    match state.set_keyboard_layout(&rules, &model, &layout, &variant, options) {
+        Ok(()) => format!("ok: {}\n", state.seat.keyboard.layout_name),
+        Err(e) => format!("error: {}\n", e),
+    }
+}
+
+/// `keymap <path>` loads an XKB text keymap from a file on the compositor host.
+fn keymap_command(state: &RwLock<CompositorState>, path: &str) -> String {
+    let keymap = match std::fs::read_to_string(path) {
+        Ok(k) => k,
+        Err(e) => return format!("error: cannot read {}: {}\n", path, e),
+    };
+    let Ok(mut state) = state.write() else {
+        return "error: lock failed\n".to_string();
+    };
+    match state.set_keymap_string(&keymap) {
+        Ok(()) => format!("ok: {}\n", state.seat.keyboard.layout_name),
+        Err(e) => format!("error: {}\n", e),
+    }
+}
+
 impl Drop for IpcServer {
     fn drop(&mut self) {
         if let Some(ref path) = self.socket_path {
@@ -1,16 +1,193 @@
+use std::collections::{HashMap, VecDeque};
+
+use crate::core::render::scene::FlattenedSurface;
+use crate::core::render::transform::BufferMapping;
+use crate::ffi::types::ContentRect;
 use crate::util::geometry::Rect;
+use crate::util::region::Region;
+
+/// Frames of damage kept per output. Renderers with more buffers in flight
+/// than this repaint fully.
+pub const MAX_BUFFER_AGE: usize = 4;
+
+/// Frame damage with more rectangles than this collapses to its bounding box
+const MAX_DAMAGE_RECTS: usize = 32;
+
+/// Add `region` to a frame's damage, collapsing it to its bounding box
+/// as soon as it has too many rectangles rather than after the whole union
+fn add_damage(frame: &mut Region, region: &Region) {
+    frame.union(region);
+    frame.limit(MAX_DAMAGE_RECTS);
+}
+
+/// What a surface looked like in the previous frame
+#[derive(Debug, Clone)]
+struct SurfaceSnapshot {
+    rect: Rect,
+    opacity: f32,
+    scale: f32,
+    content_rect: ContentRect,
+    visible_region: Region,
+    buffer: BufferMapping,
+}
+
+impl SurfaceSnapshot {
+    fn of(surface: &FlattenedSurface) -> Self {
+        Self {
+            rect: surface.rect(),
+            opacity: surface.opacity,
+            scale: surface.scale,
+            content_rect: surface.content_rect,
+            visible_region: surface.visible_region.clone(),
+            buffer: surface.buffer,
+        }
+    }
+
+    /// Whether every pixel of the surface has to be redrawn
+    fn needs_full_repaint(&self, other: &SurfaceSnapshot) -> bool {
+        self.rect != other.rect
+            || self.opacity != other.opacity
+            || self.scale != other.scale
+            || self.content_rect != other.content_rect
+            || self.buffer != other.buffer
+    }
+}
+
+/// Damage history of one output
+#[derive(Debug, Default)]
+struct OutputDamage {
+    rect: Rect,
+    /// Damage of recent frames, newest first, in scene coordinates
+    history: VecDeque<Region>,
+}
 
 /// Tracks damage across the entire scene.
+///
+/// Each frame, [`update`](Self::update) diffs the flattened scene against
+/// the previous one: mapped, unmapped, moved, resized and restacked
+/// surfaces and opacity changes damage what they cover, on top of the
+/// damage clients posted. Damage is also kept per output so renderers
+/// using buffer age can repaint only what changed since a buffer was last
+/// drawn.
 #[derive(Debug, Default)]
 pub struct SceneDamage {
-    pub global_damage: Vec<Rect>,
+    /// Damage of the current frame in scene coordinates
+    pub global_damage: Region,
+    previous: HashMap<u32, SurfaceSnapshot>,
+    /// Stacking order of the previous frame, bottom first
+    previous_order: Vec<u32>,
+    outputs: HashMap<u32, OutputDamage>,
 }
 
 impl SceneDamage {
     pub fn new() -> Self {
-        Self {
-            global_damage: Vec::new(),
+        Self::default()
+    }
+
+    /// Compute the damage of a new frame.
+    ///
+    /// `scene` is the flattened scene, `surface_damage` the damage clients
+    /// committed since the last frame (scene coordinates) and `outputs` the
+    /// current output rectangles.
+    pub fn update(
+        &mut self,
+        scene: &[FlattenedSurface],
+        surface_damage: &HashMap<u32, Region>,
+        outputs: &[(u32, Rect)],
+    ) {
+        let mut frame = Region::new();
+        let current: HashMap<u32, SurfaceSnapshot> = scene
+            .iter()
+            .map(|s| (s.surface_id, SurfaceSnapshot::of(s)))
+            .collect();
+        let restacked = self.restacked(scene, &current);
+
+        for surface in scene {
+            let new = &current[&surface.surface_id];
+            match self.previous.get(&surface.surface_id) {
+                // Newly mapped
+                None => add_damage(&mut frame, &new.visible_region),
+                Some(old) if old.needs_full_repaint(new) || restacked.contains(&surface.surface_id) => {
+                    add_damage(&mut frame, &old.visible_region);
+                    add_damage(&mut frame, &new.visible_region);
+                }
+                Some(old) => {
+                    // Parts exposed or covered by changes above
+                    if old.visible_region != new.visible_region {
+                        let mut exposed = new.visible_region.clone();
+                        exposed.subtract(&old.visible_region);
+                        add_damage(&mut frame, &exposed);
+                        let mut covered = old.visible_region.clone();
+                        covered.subtract(&new.visible_region);
+                        add_damage(&mut frame, &covered);
+                    }
+                    if let Some(damage) = surface_damage.get(&surface.surface_id) {
+                        add_damage(&mut frame, &surface.clip_to_visible(damage));
+                    }
+                }
+            }
+        }
+
+        // Unmapped surfaces leave their area behind
+        for (surface_id, old) in &self.previous {
+            if !current.contains_key(surface_id) {
+                add_damage(&mut frame, &old.visible_region);
+            }
+        }
+
+        self.outputs.retain(|id, _| outputs.iter().any(|(oid, _)| oid == id));
+        for &(output_id, rect) in outputs {
+            let output = self.outputs.entry(output_id).or_default();
+            if output.rect != rect {
+                // A moved or resized output has no usable history
+                output.rect = rect;
+                output.history.clear();
+            }
+            output.history.push_front(frame.intersect_rect(&rect));
+            output.history.truncate(MAX_BUFFER_AGE);
         }
+
+        self.global_damage = frame;
+        self.previous = current;
+        self.previous_order = scene.iter().map(|s| s.surface_id).collect();
+    }
+
+    /// Surfaces whose position in the stack changed relative to the
+    /// surfaces present in both frames
+    fn restacked(&self, scene: &[FlattenedSurface], current: &HashMap<u32, SurfaceSnapshot>) -> Vec<u32> {
+        let old_order = self.previous_order.iter().filter(|id| current.contains_key(id));
+        let new_order = scene
+            .iter()
+            .map(|s| &s.surface_id)
+            .filter(|id| self.previous.contains_key(id));
+        old_order
+            .zip(new_order)
+            .filter(|(old, new)| old != new)
+            .flat_map(|(old, new)| [*old, *new])
+            .collect()
+    }
+
+    /// Region to repaint on `output_id`, in output-local coordinates, when
+    /// drawing into a buffer last used `buffer_age` frames ago. A buffer age
+    /// of 0 (unknown contents) or older than the history repaints the
+    /// whole output.
+    pub fn output_damage(&self, output_id: u32, buffer_age: u32) -> Region {
+        let output = match self.outputs.get(&output_id) {
+            Some(output) => output,
+            None => return Region::new(),
+        };
+        let age = buffer_age as usize;
+        let mut damage = if age == 0 || age > output.history.len() {
+            Region::from_rect(output.rect)
+        } else {
+            let mut damage = Region::new();
+            for frame in output.history.iter().take(age) {
+                damage.union(frame);
+            }
+            damage
+        };
+        damage.translate(-output.rect.x, -output.rect.y);
+        damage
     }
 
     /// Adds damage from a specific surface, translated to absolute scene coordinates.
@@ -33,9 +210,24 @@ impl SceneDamage {
         }
     }
 
+    /// Adds surface-local damage of a flattened surface, dropping the parts
+    /// hidden behind opaque surfaces above it.
+    pub fn add_visible_surface_damage(
+        &mut self,
+        surface: &FlattenedSurface,
+        surface_damage: &[crate::core::surface::damage::DamageRegion],
+    ) {
+        let scene_damage = Region::from_rects(surface_damage.iter().map(|region| Rect {
+            x: surface.x + (region.x as f32 * surface.scale) as i32,
+            y: surface.y + (region.y as f32 * surface.scale) as i32,
+            width: (region.width.max(0) as f32 * surface.scale) as u32,
+            height: (region.height.max(0) as f32 * surface.scale) as u32,
+        }));
+        self.global_damage.union(&surface.clip_to_visible(&scene_damage));
+    }
+
     pub fn add_rect(&mut self, rect: Rect) {
-        // In a more advanced implementation, we would merge overlapping rects
-        self.global_damage.push(rect);
+        self.global_damage.add_rect(rect);
     }
 
     pub fn clear(&mut self) {
@@ -45,4 +237,70 @@ impl SceneDamage {
     pub fn is_empty(&self) -> bool {
         self.global_damage.is_empty()
     }
+
+    /// Forget the previous frame and all output history, so the next
+    /// frame repaints everything (e.g. after the renderer lost its buffers).
+    pub fn reset(&mut self) {
+        self.global_damage.clear();
+        self.previous.clear();
+        self.previous_order.clear();
+        self.outputs.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scene_diff() {
+        let output = [(0, Rect::new(0, 0, 1000, 1000))];
+        let none = HashMap::new();
+        let mut damage = SceneDamage::new();
+
+        damage.update(&[FlattenedSurface::covering(1, Rect::new(0, 0, 100, 100))], &none, &output);
+        assert_eq!(damage.global_damage.area(), 100 * 100);
+
+        // Nothing changed
+        damage.update(&[FlattenedSurface::covering(1, Rect::new(0, 0, 100, 100))], &none, &output);
+        assert!(damage.global_damage.is_empty());
+
+        // Client damage only
+        let mut posted = HashMap::new();
+        posted.insert(1, Region::from_rect(Rect::new(10, 10, 5, 5)));
+        damage.update(&[FlattenedSurface::covering(1, Rect::new(0, 0, 100, 100))], &posted, &output);
+        assert_eq!(damage.global_damage.rects(), &[Rect::new(10, 10, 5, 5)]);
+
+        // A move damages both the old and the new position
+        damage.update(&[FlattenedSurface::covering(1, Rect::new(200, 0, 100, 100))], &none, &output);
+        assert_eq!(damage.global_damage.area(), 2 * 100 * 100);
+
+        // Unmapping damages what it covered
+        damage.update(&[], &none, &output);
+        assert_eq!(damage.global_damage.rects(), &[Rect::new(200, 0, 100, 100)]);
+
+        // Scattered damage collapses once it has too many rectangles
+        let mut posted = HashMap::new();
+        posted.insert(1, Region::from_rects((0..40).map(|i| Rect::new(i * 20, i * 20, 5, 5))));
+        damage.update(&[FlattenedSurface::covering(1, Rect::new(0, 0, 1000, 1000))], &none, &output);
+        damage.update(&[FlattenedSurface::covering(1, Rect::new(0, 0, 1000, 1000))], &posted, &output);
+        assert_eq!(damage.global_damage.rects(), &[Rect::new(0, 0, 785, 785)]);
+    }
+
+    #[test]
+    fn test_buffer_age() {
+        let output = [(7, Rect::new(1000, 0, 500, 500))];
+        let none = HashMap::new();
+        let mut damage = SceneDamage::new();
+
+        damage.update(&[FlattenedSurface::covering(1, Rect::new(1000, 0, 10, 10))], &none, &output);
+        damage.update(&[FlattenedSurface::covering(1, Rect::new(1000, 0, 10, 10)), FlattenedSurface::covering(2, Rect::new(1100, 0, 10, 10))], &none, &output);
+
+        // Output-local coordinates
+        assert_eq!(damage.output_damage(7, 1).rects(), &[Rect::new(100, 0, 10, 10)]);
+        assert_eq!(damage.output_damage(7, 2).area(), 200);
+        // Unknown or too old contents repaint the whole output
+        assert_eq!(damage.output_damage(7, 0).rects(), &[Rect::new(0, 0, 500, 500)]);
+        assert_eq!(damage.output_damage(7, 3).area(), 500 * 500);
+    }
 }
@@ -1,3 +1,5 @@
 pub mod scene;
 pub mod node;
 pub mod damage;
+pub mod visibility;
+pub mod transform;
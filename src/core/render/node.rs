@@ -1,5 +1,6 @@
 use crate::util::geometry::Rect;
 use crate::ffi::types::ContentRect;
+use crate::core::render::transform::BufferMapping;
 
 /// Represents a node in the scene graph.
 /// A node can be a surface, a container for other nodes, or a decorator.
@@ -19,6 +20,11 @@ pub struct SceneNode {
     /// Default is [0, 0, 1, 1] (full buffer).
     /// Non-default when xdg_surface.set_window_geometry crops to a CSD content area.
     pub content_rect: ContentRect,
+    /// Opaque parts of the node in node-local coordinates (from the
+    /// surface's wl_surface.set_opaque_region)
+    pub opaque_region: crate::util::region::Region,
+    /// Buffer transform, scale and viewport of the surface
+    pub buffer: BufferMapping,
 }
 
 impl SceneNode {
@@ -35,6 +41,8 @@ impl SceneNode {
             visible: true,
             children: Vec::new(),
             content_rect: ContentRect::default(),
+            opaque_region: Default::default(),
+            buffer: BufferMapping::default(),
         }
     }
 
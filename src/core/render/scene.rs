@@ -1,6 +1,9 @@
 use std::collections::HashMap;
 use crate::core::render::node::SceneNode;
+use crate::core::render::transform::{BufferMapping, SourceRect};
 use crate::ffi::types::ContentRect;
+use crate::util::geometry::Rect;
+use crate::util::region::Region;
 
 /// Represents a flattened surface to be rendered.
 #[derive(Debug, Clone)]
@@ -14,6 +17,93 @@ pub struct FlattenedSurface {
     pub scale: f32,
     /// Normalized content rect within the buffer (0..1 range)
     pub content_rect: ContentRect,
+    /// Opaque parts of the surface in scene coordinates
+    pub opaque_region: Region,
+    /// The whole surface is opaque, so renderers can skip blending it
+    pub opaque: bool,
+    /// Parts of the surface not hidden behind opaque surfaces above it, in
+    /// scene coordinates
+    pub visible_region: Region,
+    /// Buffer transform, scale and viewport of the surface
+    pub buffer: BufferMapping,
+}
+
+impl FlattenedSurface {
+    /// A fully visible, translucent surface covering `rect`, with an
+    /// untransformed buffer
+    #[cfg(test)]
+    pub(crate) fn covering(surface_id: u32, rect: Rect) -> Self {
+        Self {
+            surface_id,
+            x: rect.x,
+            y: rect.y,
+            width: rect.width,
+            height: rect.height,
+            opacity: 1.0,
+            scale: 1.0,
+            content_rect: ContentRect::default(),
+            opaque_region: Region::new(),
+            opaque: false,
+            visible_region: Region::from_rect(rect),
+            buffer: BufferMapping::default(),
+        }
+    }
+
+    /// Bounds in scene coordinates
+    pub fn rect(&self) -> Rect {
+        Rect::new(self.x, self.y, self.width, self.height)
+    }
+
+    /// Clip a scene-space region (e.g. damage) to the visible region
+    pub fn clip_to_visible(&self, region: &Region) -> Region {
+        region.intersect(&self.visible_region)
+    }
+
+    /// Origin of the node within the surface (the window geometry offset
+    /// when the content is cropped to it)
+    fn surface_origin(&self) -> (f64, f64) {
+        let (w, h) = self.buffer.surface_size;
+        (self.content_rect.x as f64 * w as f64, self.content_rect.y as f64 * h as f64)
+    }
+
+    /// Map a scene point to surface-local coordinates, the space of
+    /// wl_pointer and wl_touch events and of input regions. These are
+    /// already past the buffer transform and viewport;
+    /// [`scene_to_buffer`](Self::scene_to_buffer) undoes those too.
+    pub fn scene_to_surface(&self, x: f64, y: f64) -> (f64, f64) {
+        let (ox, oy) = self.surface_origin();
+        let scale = self.scale as f64;
+        ((x - self.x as f64) / scale + ox, (y - self.y as f64) / scale + oy)
+    }
+
+    /// Map a surface-local point to scene coordinates; the inverse of
+    /// [`scene_to_surface`](Self::scene_to_surface).
+    pub fn surface_to_scene(&self, x: f64, y: f64) -> (f64, f64) {
+        let (ox, oy) = self.surface_origin();
+        let scale = self.scale as f64;
+        (self.x as f64 + (x - ox) * scale, self.y as f64 + (y - oy) * scale)
+    }
+
+    /// Map a scene point to the buffer pixel shown there, undoing the
+    /// viewport, buffer scale and buffer transform.
+    pub fn scene_to_buffer(&self, x: f64, y: f64) -> (f64, f64) {
+        let (sx, sy) = self.scene_to_surface(x, y);
+        self.buffer.surface_to_buffer(sx, sy)
+    }
+
+    /// The buffer area drawn into this node, in buffer pixels before the
+    /// transform
+    pub fn buffer_source(&self) -> SourceRect {
+        let (ox, oy) = self.surface_origin();
+        let (w, h) = self.buffer.surface_size;
+        let crop = SourceRect::new(
+            ox,
+            oy,
+            self.content_rect.w as f64 * w as f64,
+            self.content_rect.h as f64 * h as f64,
+        );
+        self.buffer.surface_rect_to_buffer(crop)
+    }
 }
 
 /// Manages the scene graph.
@@ -56,7 +146,30 @@ impl Scene {
     }
 
     /// Flattens the scene graph into a z-ordered list of visible surfaces.
+    ///
+    /// Surfaces entirely hidden behind the opaque regions of surfaces above
+    /// them are culled; the rest carry the region that remains visible.
     pub fn flatten(&self) -> Vec<FlattenedSurface> {
+        Self::cull(self.flatten_unculled())
+    }
+
+    /// Cull a list from [`flatten_unculled`](Self::flatten_unculled) the
+    /// way [`flatten`](Self::flatten) does
+    pub fn cull(mut result: Vec<FlattenedSurface>) -> Vec<FlattenedSurface> {
+        // Walk top-down, collecting the opaque area that hides what's below
+        let mut covered = Region::new();
+        for surface in result.iter_mut().rev() {
+            surface.visible_region.subtract(&covered);
+            covered.union(&surface.opaque_region);
+        }
+        // Empty nodes (not sized yet) have nothing to hide and stay listed
+        result.retain(|s| s.rect().is_empty() || !s.visible_region.is_empty());
+        result
+    }
+
+    /// Like [`flatten`](Self::flatten), but keeps occluded surfaces. Every
+    /// entry's visible region is its full bounds.
+    pub fn flatten_unculled(&self) -> Vec<FlattenedSurface> {
         let mut result = Vec::new();
         if let Some(root_id) = self.root_id {
             self.flatten_recursive(root_id, 0, 0, 1.0, 1.0, &mut result);
@@ -85,6 +198,25 @@ impl Scene {
 
             // If this node points to a surface, add it to the list
             if let Some(surface_id) = node.surface_id {
+                let rect = Rect::new(current_abs_x, current_abs_y, node.width, node.height);
+                // Translucent nodes hide nothing
+                let opaque_region = if current_abs_opacity >= 1.0 {
+                    Region::from_rects(node.opaque_region
+                        .rects()
+                        .iter()
+                        .filter_map(|r| {
+                            let scaled = Rect::new(
+                                current_abs_x + (r.x as f32 * current_abs_scale) as i32,
+                                current_abs_y + (r.y as f32 * current_abs_scale) as i32,
+                                (r.width as f32 * current_abs_scale) as u32,
+                                (r.height as f32 * current_abs_scale) as u32,
+                            );
+                            scaled.intersection(&rect)
+                        }))
+                } else {
+                    Region::new()
+                };
+                let opaque = !rect.is_empty() && opaque_region.area() == rect.width as u64 * rect.height as u64;
                 result.push(FlattenedSurface {
                     surface_id,
                     x: current_abs_x,
@@ -94,6 +226,10 @@ impl Scene {
                     opacity: current_abs_opacity,
                     scale: current_abs_scale,
                     content_rect: node.content_rect,
+                    opaque_region,
+                    opaque,
+                    visible_region: Region::from_rect(rect),
+                    buffer: node.buffer,
                 });
             }
 
@@ -134,3 +270,58 @@ impl Scene {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn surface_node(id: u32, surface_id: u32, x: i32, y: i32, w: u32, h: u32, opaque: bool) -> SceneNode {
+        let mut node = SceneNode::new(id).with_surface(surface_id);
+        node.set_position(x, y);
+        node.set_size(w, h);
+        if opaque {
+            node.opaque_region = Region::from_rect(Rect::new(0, 0, w, h));
+        }
+        node
+    }
+
+    #[test]
+    fn test_flatten_culls_occluded() {
+        let mut scene = Scene::new();
+        scene.add_node(SceneNode::new(1));
+        scene.set_root(1);
+        // Two full-screen terminals; the bottom one is hidden
+        scene.add_node(surface_node(2, 10, 0, 0, 800, 600, true));
+        scene.add_node(surface_node(3, 11, 0, 0, 800, 600, true));
+        // A translucent panel on top hides nothing
+        let mut panel = surface_node(4, 12, 0, 0, 800, 30, true);
+        panel.opacity = 0.5;
+        scene.add_node(panel);
+        for child in 2..=4 {
+            scene.add_child(1, child);
+        }
+
+        let flat = scene.flatten();
+        let ids: Vec<u32> = flat.iter().map(|s| s.surface_id).collect();
+        assert_eq!(ids, vec![11, 12]);
+        assert!(flat[0].opaque);
+        assert!(!flat[1].opaque);
+        assert_eq!(scene.flatten_unculled().len(), 3);
+    }
+
+    #[test]
+    fn test_visible_region_excludes_opaque_cover() {
+        let mut scene = Scene::new();
+        scene.add_node(SceneNode::new(1));
+        scene.set_root(1);
+        scene.add_node(surface_node(2, 10, 0, 0, 200, 100, false));
+        scene.add_node(surface_node(3, 11, 100, 0, 200, 100, true));
+        scene.add_child(1, 2);
+        scene.add_child(1, 3);
+
+        let flat = scene.flatten();
+        assert_eq!(flat[0].visible_region.rects(), &[Rect::new(0, 0, 100, 100)]);
+        let damage = flat[0].clip_to_visible(&Region::from_rect(Rect::new(50, 0, 100, 10)));
+        assert_eq!(damage.rects(), &[Rect::new(50, 0, 50, 10)]);
+    }
+}
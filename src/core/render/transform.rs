@@ -0,0 +1,232 @@
+//! Mapping between surface-local and buffer coordinates.
+//!
+//! A surface's buffer reaches the screen through three steps: the buffer
+//! transform (`wl_surface.set_buffer_transform`), the buffer scale and the
+//! viewport (`wp_viewport` source crop and destination size). The scene
+//! carries a [`BufferMapping`] per node so renderers can sample the right
+//! part of the buffer and input can be mapped back the same way.
+
+use wayland_server::protocol::wl_output::Transform;
+
+/// Rectangle with fractional coordinates, as used by `wp_viewport.set_source`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SourceRect {
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+impl SourceRect {
+    pub fn new(x: f64, y: f64, width: f64, height: f64) -> Self {
+        Self { x, y, width, height }
+    }
+}
+
+/// How a surface's buffer maps onto the surface
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BufferMapping {
+    pub transform: Transform,
+    pub scale: i32,
+    /// Buffer size in pixels, before the transform
+    pub buffer_size: (i32, i32),
+    /// Viewport source crop, in surface coordinates before viewport scaling
+    pub source: Option<SourceRect>,
+    /// Size of the surface after the viewport was applied
+    pub surface_size: (i32, i32),
+}
+
+impl Default for BufferMapping {
+    fn default() -> Self {
+        Self {
+            transform: Transform::Normal,
+            scale: 1,
+            buffer_size: (0, 0),
+            source: None,
+            surface_size: (0, 0),
+        }
+    }
+}
+
+impl BufferMapping {
+    /// Size of the buffer after transform and scale, before the viewport
+    pub fn transformed_size(&self) -> (f64, f64) {
+        let scale = self.scale.max(1) as f64;
+        let (w, h) = if swaps_axes(self.transform) {
+            (self.buffer_size.1, self.buffer_size.0)
+        } else {
+            self.buffer_size
+        };
+        (w as f64 / scale, h as f64 / scale)
+    }
+
+    /// Part of the transformed buffer shown on the surface
+    pub fn source_rect(&self) -> SourceRect {
+        self.source.unwrap_or_else(|| {
+            let (w, h) = self.transformed_size();
+            SourceRect::new(0.0, 0.0, w, h)
+        })
+    }
+
+    /// Map a surface-local point to buffer pixel coordinates.
+    pub fn surface_to_buffer(&self, x: f64, y: f64) -> (f64, f64) {
+        let src = self.source_rect();
+        let (sw, sh) = self.surface_size;
+        // Undo the viewport scaling
+        let vx = src.x + if sw > 0 { x * src.width / sw as f64 } else { 0.0 };
+        let vy = src.y + if sh > 0 { y * src.height / sh as f64 } else { 0.0 };
+
+        let (tw, th) = self.transformed_size();
+        let (bx, by) = untransform(self.transform, tw, th, vx, vy);
+        let scale = self.scale.max(1) as f64;
+        (bx * scale, by * scale)
+    }
+
+    /// Map a surface-local rectangle to the buffer rectangle it samples.
+    pub fn surface_rect_to_buffer(&self, rect: SourceRect) -> SourceRect {
+        let (x1, y1) = self.surface_to_buffer(rect.x, rect.y);
+        let (x2, y2) = self.surface_to_buffer(rect.x + rect.width, rect.y + rect.height);
+        SourceRect::new(x1.min(x2), y1.min(y2), (x2 - x1).abs(), (y2 - y1).abs())
+    }
+
+    /// Map buffer pixel coordinates to a surface-local point; the inverse
+    /// of [`surface_to_buffer`](Self::surface_to_buffer).
+    pub fn buffer_to_surface(&self, x: f64, y: f64) -> (f64, f64) {
+        let scale = self.scale.max(1) as f64;
+        let (tw, th) = self.transformed_size();
+        let (vx, vy) = transform(self.transform, tw, th, x / scale, y / scale);
+
+        // Apply the viewport scaling
+        let src = self.source_rect();
+        let (sw, sh) = self.surface_size;
+        let sx = if src.width > 0.0 { (vx - src.x) * sw as f64 / src.width } else { 0.0 };
+        let sy = if src.height > 0.0 { (vy - src.y) * sh as f64 / src.height } else { 0.0 };
+        (sx, sy)
+    }
+
+    /// Map a buffer rectangle (as given to `wl_surface.damage_buffer`) to
+    /// the surface-local rectangle showing it.
+    pub fn buffer_rect_to_surface(&self, rect: SourceRect) -> SourceRect {
+        let (x1, y1) = self.buffer_to_surface(rect.x, rect.y);
+        let (x2, y2) = self.buffer_to_surface(rect.x + rect.width, rect.y + rect.height);
+        SourceRect::new(x1.min(x2), y1.min(y2), (x2 - x1).abs(), (y2 - y1).abs())
+    }
+}
+
+/// Whether a buffer transform swaps width and height
+pub fn swaps_axes(transform: Transform) -> bool {
+    matches!(
+        transform,
+        Transform::_90 | Transform::_270 | Transform::Flipped90 | Transform::Flipped270
+    )
+}
+
+/// Map a point in transformed space of size `width`×`height` back into the
+/// untransformed buffer (in buffer-scale units).
+fn untransform(transform: Transform, width: f64, height: f64, x: f64, y: f64) -> (f64, f64) {
+    match transform {
+        Transform::_90 => (y, width - x),
+        Transform::_180 => (width - x, height - y),
+        Transform::_270 => (height - y, x),
+        Transform::Flipped => (width - x, y),
+        Transform::Flipped90 => (y, x),
+        Transform::Flipped180 => (x, height - y),
+        Transform::Flipped270 => (height - y, width - x),
+        _ => (x, y),
+    }
+}
+
+/// Map a point of the untransformed buffer (in buffer-scale units) into
+/// transformed space of size `width`×`height`; the inverse of
+/// [`untransform`].
+fn transform(transform: Transform, width: f64, height: f64, x: f64, y: f64) -> (f64, f64) {
+    match transform {
+        Transform::_90 => (width - y, x),
+        Transform::_180 => (width - x, height - y),
+        Transform::_270 => (y, height - x),
+        Transform::Flipped => (width - x, y),
+        Transform::Flipped90 => (y, x),
+        Transform::Flipped180 => (x, height - y),
+        Transform::Flipped270 => (width - y, height - x),
+        _ => (x, y),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_untransform_corners() {
+        // 100x200 buffer; the surface sees it as 200x100 when rotated
+        let mut mapping = BufferMapping {
+            buffer_size: (100, 200),
+            surface_size: (200, 100),
+            ..Default::default()
+        };
+        let cases = [
+            (Transform::_90, (0.0, 200.0)),
+            (Transform::_270, (100.0, 0.0)),
+            (Transform::Flipped90, (0.0, 0.0)),
+            (Transform::Flipped270, (100.0, 200.0)),
+        ];
+        for (transform, expected) in cases {
+            mapping.transform = transform;
+            assert_eq!(mapping.surface_to_buffer(0.0, 0.0), expected, "{:?}", transform);
+        }
+
+        mapping.surface_size = (100, 200);
+        let cases = [
+            (Transform::Normal, (10.0, 20.0)),
+            (Transform::_180, (90.0, 180.0)),
+            (Transform::Flipped, (90.0, 20.0)),
+            (Transform::Flipped180, (10.0, 180.0)),
+        ];
+        for (transform, expected) in cases {
+            mapping.transform = transform;
+            assert_eq!(mapping.surface_to_buffer(10.0, 20.0), expected, "{:?}", transform);
+        }
+    }
+
+    #[test]
+    fn test_viewport_and_scale() {
+        // 2x buffer, cropped to its right half and stretched to 400x200
+        let mapping = BufferMapping {
+            scale: 2,
+            buffer_size: (400, 200),
+            source: Some(SourceRect::new(100.0, 0.0, 100.0, 100.0)),
+            surface_size: (400, 200),
+            ..Default::default()
+        };
+        assert_eq!(mapping.surface_to_buffer(0.0, 0.0), (200.0, 0.0));
+        assert_eq!(mapping.surface_to_buffer(400.0, 200.0), (400.0, 200.0));
+        let sampled = mapping.surface_rect_to_buffer(SourceRect::new(0.0, 0.0, 200.0, 200.0));
+        assert_eq!(sampled, SourceRect::new(200.0, 0.0, 100.0, 200.0));
+
+        // Buffer damage maps back onto the surface
+        let damaged = mapping.buffer_rect_to_surface(SourceRect::new(200.0, 0.0, 100.0, 200.0));
+        assert_eq!(damaged, SourceRect::new(0.0, 0.0, 200.0, 200.0));
+    }
+
+    #[test]
+    fn test_buffer_to_surface_inverts() {
+        let transforms = [
+            Transform::Normal, Transform::_90, Transform::_180, Transform::_270,
+            Transform::Flipped, Transform::Flipped90, Transform::Flipped180, Transform::Flipped270,
+        ];
+        for transform in transforms {
+            let surface_size = if swaps_axes(transform) { (100, 60) } else { (60, 100) };
+            let mapping = BufferMapping {
+                transform,
+                scale: 2,
+                buffer_size: (120, 200),
+                surface_size,
+                ..Default::default()
+            };
+            for (x, y) in [(0.0, 0.0), (10.0, 20.0), (surface_size.0 as f64, 5.0)] {
+                let (bx, by) = mapping.surface_to_buffer(x, y);
+                assert_eq!(mapping.buffer_to_surface(bx, by), (x, y), "{:?}", transform);
+            }
+        }
+    }
+}
@@ -0,0 +1,120 @@
+use std::collections::HashMap;
+
+use crate::util::geometry::Rect;
+use crate::util::region::Region;
+
+/// Why a surface can or cannot be seen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SurfaceVisibility {
+    /// At least part of the surface is on screen
+    Visible,
+    /// Completely covered by opaque surfaces above it
+    Occluded,
+    /// Belongs to a minimized window
+    Minimized,
+    /// Belongs to a window on a workspace that is not shown
+    InactiveWorkspace,
+    /// Lies outside every output
+    OffScreen,
+}
+
+impl SurfaceVisibility {
+    pub fn is_visible(self) -> bool {
+        self == SurfaceVisibility::Visible
+    }
+}
+
+/// A surface as placed in the scene, for visibility classification.
+#[derive(Debug, Clone)]
+pub struct PlacedSurface {
+    pub surface_id: u32,
+    /// Bounds in scene coordinates
+    pub rect: Rect,
+    /// Opaque parts in scene coordinates (empty if translucent)
+    pub opaque: Region,
+}
+
+/// Classify surfaces as visible, occluded or off-screen.
+///
+/// `surfaces` is in z-order, bottom first, as produced by `Scene::flatten`.
+/// A surface is occluded when the opaque regions of the surfaces above it
+/// cover all of it within the outputs.
+pub fn classify(surfaces: &[PlacedSurface], outputs: &[Rect]) -> HashMap<u32, SurfaceVisibility> {
+    let mut result = HashMap::with_capacity(surfaces.len());
+    // Opaque area accumulated from the top of the stack down
+    let mut covered = Region::new();
+
+    for surface in surfaces.iter().rev() {
+        let on_screen = Region::from_rects(
+            outputs.iter().filter_map(|output| surface.rect.intersection(output)),
+        );
+        let mut uncovered = on_screen.clone();
+        uncovered.subtract(&covered);
+
+        let visibility = if on_screen.is_empty() {
+            SurfaceVisibility::OffScreen
+        } else if uncovered.is_empty() {
+            SurfaceVisibility::Occluded
+        } else {
+            SurfaceVisibility::Visible
+        };
+
+        // A surface listed twice (e.g. spanning nodes) counts as visible if
+        // any placement is
+        result
+            .entry(surface.surface_id)
+            .and_modify(|v: &mut SurfaceVisibility| {
+                if visibility.is_visible() {
+                    *v = visibility;
+                }
+            })
+            .or_insert(visibility);
+
+        covered.union(&surface.opaque.intersect_rect(&surface.rect));
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn placed(surface_id: u32, rect: Rect, opaque: bool) -> PlacedSurface {
+        PlacedSurface {
+            surface_id,
+            rect,
+            opaque: if opaque { Region::from_rect(rect) } else { Region::new() },
+        }
+    }
+
+    #[test]
+    fn test_occlusion() {
+        let output = Rect::new(0, 0, 1920, 1080);
+        let surfaces = vec![
+            placed(1, Rect::new(100, 100, 400, 300), true),
+            placed(2, Rect::new(600, 100, 400, 300), true),
+            // Two opaque halves together cover surface 1
+            placed(3, Rect::new(0, 0, 300, 1080), true),
+            placed(4, Rect::new(300, 0, 300, 1080), true),
+            // Translucent surface on top of 2 does not hide it
+            placed(5, Rect::new(500, 0, 1000, 1080), false),
+        ];
+        let vis = classify(&surfaces, &[output]);
+        assert_eq!(vis[&1], SurfaceVisibility::Occluded);
+        assert_eq!(vis[&2], SurfaceVisibility::Visible);
+        assert_eq!(vis[&3], SurfaceVisibility::Visible);
+        assert_eq!(vis[&5], SurfaceVisibility::Visible);
+    }
+
+    #[test]
+    fn test_off_screen() {
+        let outputs = [Rect::new(0, 0, 1920, 1080), Rect::new(1920, 0, 1280, 800)];
+        let surfaces = vec![
+            placed(1, Rect::new(3300, 0, 200, 200), false),
+            placed(2, Rect::new(1800, 0, 400, 400), false),
+        ];
+        let vis = classify(&surfaces, &outputs);
+        assert_eq!(vis[&1], SurfaceVisibility::OffScreen);
+        assert_eq!(vis[&2], SurfaceVisibility::Visible);
+    }
+}
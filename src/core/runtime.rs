@@ -14,11 +14,12 @@
 
 use std::sync::Mutex;
 use std::time::{Duration, Instant};
-use std::collections::VecDeque;
+use std::collections::{HashMap, VecDeque};
 
 use crate::core::compositor::{Compositor, CompositorEvent};
 use crate::core::state::CompositorState;
 use crate::core::errors::CoreError;
+use crate::core::time::{FrameClock, OutputClock};
 
 // ============================================================================
 // Task System
@@ -88,7 +89,10 @@ impl Default for FrameTimingConfig {
 }
 
 impl FrameTimingConfig {
-    /// Create config for a specific refresh rate
+    /// Create config for a specific refresh rate.
+    ///
+    /// This only seeds the fallback clock used before any output is known;
+    /// once outputs exist each one is scheduled at its own refresh.
     pub fn for_refresh_rate(hz: u32) -> Self {
         Self {
             target_interval: Duration::from_nanos(1_000_000_000 / hz as u64),
@@ -98,10 +102,16 @@ impl FrameTimingConfig {
 }
 
 /// Frame timing state
+///
+/// Keeps one [`OutputClock`] per output, following that output's refresh
+/// and adaptive-sync range, plus a fallback clock for when no outputs exist.
 pub struct FrameTiming {
     #[allow(dead_code)]
     config: FrameTimingConfig,
-    clock: crate::core::time::FrameClock,
+    clock: FrameClock,
+    outputs: HashMap<u32, OutputClock>,
+    /// Set once the platform reports presentations per output
+    per_output_feedback: bool,
     frame_count: u64,
     frame_time_sum: Duration,
     fps_update_time: Instant,
@@ -112,7 +122,9 @@ impl FrameTiming {
     pub fn new(config: FrameTimingConfig) -> Self {
         let now = Instant::now();
         Self {
-            clock: crate::core::time::FrameClock::new(config.target_interval),
+            clock: FrameClock::new(config.target_interval),
+            outputs: HashMap::new(),
+            per_output_feedback: false,
             config,
             frame_count: 0,
             frame_time_sum: Duration::ZERO,
@@ -123,11 +135,19 @@ impl FrameTiming {
     
     /// Check if it's time for a new frame
     pub fn should_render(&self) -> bool {
+        Instant::now() >= self.plan_render()
+    }
+    
+    /// Earliest time rendering has to start for any output
+    fn plan_render(&self) -> Instant {
         // Use a conservative render time estimate (example: 4ms)
         // In the future this could be dynamic based on history
         let render_estimate = Duration::from_millis(4);
-        let start_time = self.clock.plan_render(render_estimate);
-        Instant::now() >= start_time
+        self.outputs
+            .values()
+            .map(|clock| clock.plan_render(render_estimate))
+            .min()
+            .unwrap_or_else(|| self.clock.plan_render(render_estimate))
     }
     
     /// Mark frame as started
@@ -165,8 +185,7 @@ impl FrameTiming {
     
     /// Get time until next frame
     pub fn time_until_next_frame(&self) -> Duration {
-        let render_estimate = Duration::from_millis(4);
-        let start_time = self.clock.plan_render(render_estimate);
+        let start_time = self.plan_render();
         let now = Instant::now();
         if now >= start_time {
             Duration::ZERO
@@ -283,10 +302,21 @@ impl Runtime {
         // Execute pending tasks
         self.execute_tasks(state);
         
+        // Emit compositor-side key repeats that came due
+        state.dispatch_key_repeat(Instant::now());
+        
         // Dispatch Wayland events
         compositor.dispatch(state)
             .map_err(|e| CoreError::wayland_error(e.to_string()))?;
         
+        // Follow output changes and content hints, then apply FIFO /
+        // commit-timing commits that are due for the next vblank
+        self.frame_timing.sync_outputs(state);
+        state.process_commit_queue(self.frame_timing.next_vblank());
+        
+        // Send idle notifications that came due
+        state.dispatch_idle(Instant::now());
+        
         // Collect events from compositor
         let mut events = compositor.take_events();
         events.append(&mut self.events);
@@ -309,10 +339,21 @@ impl Runtime {
         // Execute pending tasks
         self.execute_tasks(state);
         
+        // Emit compositor-side key repeats that came due
+        state.dispatch_key_repeat(Instant::now());
+        
         // Dispatch Wayland events with timeout
         compositor.dispatch_timeout(state, timeout)
             .map_err(|e| CoreError::wayland_error(e.to_string()))?;
         
+        // Follow output changes and content hints, then apply FIFO /
+        // commit-timing commits that are due for the next vblank
+        self.frame_timing.sync_outputs(state);
+        state.process_commit_queue(self.frame_timing.next_vblank());
+        
+        // Send idle notifications that came due
+        state.dispatch_idle(Instant::now());
+        
         // Collect events
         let mut events = compositor.take_events();
         events.append(&mut self.events);
@@ -358,6 +399,27 @@ impl Runtime {
         self.frame_timing.time_until_next_frame()
     }
     
+    /// Get time until the runtime next needs to run: the next frame, the
+    /// next compositor-side key repeat, the next timed commit or the next
+    /// idle timeout, whichever comes first
+    pub fn time_until_next_wakeup(&self, state: &CompositorState) -> Duration {
+        let now = Instant::now();
+        let mut wakeup = self.time_until_next_frame();
+        if let Some(at) = state.seat.keyboard.next_repeat_at() {
+            wakeup = wakeup.min(at.saturating_duration_since(now));
+        }
+        if let Some(target_ns) = state.commit_queue.next_target_ns() {
+            // The commit has to be applied one refresh before its target
+            let at = crate::core::time::monotonic_ns_to_instant(target_ns);
+            let at = at.checked_sub(self.frame_timing.refresh_interval()).unwrap_or(at);
+            wakeup = wakeup.min(at.saturating_duration_since(now));
+        }
+        if let Some(at) = state.ext.idle_notify.next_deadline() {
+            wakeup = wakeup.min(at.saturating_duration_since(now));
+        }
+        wakeup
+    }
+    
     /// Get frame timestamp for Wayland
     pub fn frame_timestamp_ms(&self) -> u32 {
         self.frame_timing.frame_timestamp_ms()
@@ -404,12 +466,87 @@ impl Runtime {
     pub fn report_presentation(&mut self, timestamp: Instant, refresh_mhz: u32) {
         self.frame_timing.report_presentation(timestamp, refresh_mhz);
     }
+    
+    /// Report a vblank on one output
+    pub fn report_output_presentation(&mut self, output_id: u32, timestamp: Instant, refresh_mhz: u32) {
+        self.frame_timing.report_output_presentation(output_id, timestamp, refresh_mhz);
+    }
+    
+    /// Frame clock of an output
+    pub fn output_clock(&self, output_id: u32) -> Option<&OutputClock> {
+        self.frame_timing.output_clock(output_id)
+    }
 }
 
 impl FrameTiming {
-    /// Report presentation feedback to the clock
+    /// Report presentation feedback to the clock.
+    ///
+    /// Platforms that don't report per output drive every output clock from
+    /// this; once per-output reports arrive it only feeds the fallback clock.
     pub fn report_presentation(&mut self, timestamp: Instant, refresh_mhz: u32) {
         self.clock.update_vblank(timestamp, refresh_mhz);
+        if !self.per_output_feedback {
+            for clock in self.outputs.values_mut() {
+                clock.report_presentation(timestamp, refresh_mhz);
+            }
+        }
+    }
+
+    /// Report a vblank on one output
+    pub fn report_output_presentation(&mut self, output_id: u32, timestamp: Instant, refresh_mhz: u32) {
+        self.per_output_feedback = true;
+        self.outputs
+            .entry(output_id)
+            .or_insert_with(|| OutputClock::new(refresh_mhz))
+            .report_presentation(timestamp, refresh_mhz);
+    }
+
+    /// Bring the output clocks in line with the compositor's outputs: one
+    /// clock per output at its refresh and adaptive-sync range, with the
+    /// scheduling hint of the surfaces it shows.
+    pub fn sync_outputs(&mut self, state: &mut CompositorState) {
+        let committed = std::mem::take(&mut state.committed_since_sync);
+        let visible = state.visible_surfaces_by_output().clone();
+        let now = Instant::now();
+
+        self.outputs.retain(|id, _| state.outputs.iter().any(|o| o.id == *id));
+        for output in &state.outputs {
+            let clock = self
+                .outputs
+                .entry(output.id)
+                .or_insert_with(|| OutputClock::new(output.refresh));
+            clock.set_refresh(output.refresh);
+            clock.set_vrr_range(Some(output.min_refresh).filter(|&min| min > 0));
+
+            let surfaces = visible.get(&output.id).map_or(&[][..], Vec::as_slice);
+            clock.set_hint(state.refresh_hint_for(surfaces));
+            if surfaces.iter().any(|id| committed.contains(id)) {
+                clock.note_damage(now);
+            }
+        }
+    }
+
+    /// Frame clock of an output
+    pub fn output_clock(&self, output_id: u32) -> Option<&OutputClock> {
+        self.outputs.get(&output_id)
+    }
+
+    /// Predicted time of the next vblank on any output
+    pub fn next_vblank(&self) -> Instant {
+        self.outputs
+            .values()
+            .map(OutputClock::next_frame)
+            .min()
+            .unwrap_or_else(|| self.clock.next_vblank())
+    }
+
+    /// Refresh interval of the fastest output
+    pub fn refresh_interval(&self) -> Duration {
+        self.outputs
+            .values()
+            .map(|clock| crate::core::time::output_clock::interval_for_mhz(clock.refresh_mhz()))
+            .min()
+            .unwrap_or_else(|| self.clock.refresh_interval())
     }
 }
 
@@ -426,6 +563,7 @@ impl Default for Runtime {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::core::state::OutputState;
     
     #[test]
     fn test_frame_timing_config() {
@@ -433,6 +571,31 @@ mod tests {
         assert!(config.target_interval < Duration::from_millis(10));
     }
     
+    #[test]
+    fn test_clock_per_output() {
+        let mut state = CompositorState::new(None);
+        let mut external = OutputState::new(1, "external".to_string(), 2560, 1440);
+        external.x = 1920;
+        external.refresh = 120_000;
+        external.min_refresh = 24_000;
+        state.outputs.push(external);
+
+        let mut timing = FrameTiming::default();
+        timing.sync_outputs(&mut state);
+        let internal = timing.output_clock(0).unwrap();
+        let external = timing.output_clock(1).unwrap();
+        assert_eq!(internal.refresh_mhz(), 60_000);
+        assert!(!internal.is_variable());
+        assert_eq!(external.refresh_mhz(), 120_000);
+        assert!(external.is_variable());
+        assert!(timing.refresh_interval() < Duration::from_millis(9));
+
+        // Removed outputs lose their clock
+        state.outputs.truncate(1);
+        timing.sync_outputs(&mut state);
+        assert!(timing.output_clock(1).is_none());
+    }
+    
     #[test]
     fn test_task_queue() {
         let queue = TaskQueue::new();
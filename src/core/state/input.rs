@@ -4,15 +4,31 @@
 //! touch input injection and the high-level `process_input_event` dispatcher.
 
 use super::*;
+use crate::core::errors::CoreError;
+use crate::core::input::grab::{Admission, GrabDevice, GrabKey, GrabKind, GrabStatus, InputGrab};
 use wayland_server::protocol::{wl_keyboard, wl_pointer};
 
+/// Linux evdev code of the left mouse button
+const BTN_LEFT: u32 = 0x110;
+
 impl CompositorState {
     // =========================================================================
     // Input Injection
     // =========================================================================
 
-    /// Inject a key event and broadcast to all bound keyboards
+    /// Inject a key event and broadcast to all bound keyboards.
+    /// Keyboard grabs, such as an input method's, see it first.
     pub fn inject_key(&mut self, key: u32, key_state: wl_keyboard::KeyState, time: u32) {
+        self.deliver_key(key, key_state, time, true);
+    }
+
+    /// Inject a key that bypasses keyboard grabs: keys the input method
+    /// itself passes on through a virtual keyboard.
+    pub fn inject_key_ungrabbed(&mut self, key: u32, key_state: wl_keyboard::KeyState, time: u32) {
+        self.deliver_key(key, key_state, time, false);
+    }
+
+    fn deliver_key(&mut self, key: u32, key_state: wl_keyboard::KeyState, time: u32, grabbed: bool) {
         self.ext.idle_notify.record_activity();
         let mut new_mods = None;
         
@@ -34,11 +50,24 @@ impl CompositorState {
             self.seat.keyboard.mods_latched = latched;
             self.seat.keyboard.mods_locked = locked;
             self.seat.keyboard.mods_group = group;
+            self.refresh_keyboard_layout();
+        }
+
+        // Track repeat before a grab takes the key: a key pressed before the
+        // grab started and released into it must still stop repeating
+        self.seat.keyboard.update_repeat(key, key_state == wl_keyboard::KeyState::Pressed, time);
+
+        if grabbed {
+            let event = GrabKey { key, state: key_state, time, modifiers: new_mods };
+            if self.dispatch_grab(GrabDevice::Keyboard, |grab, compositor| grab.key(compositor, &event)) {
+                return;
+            }
         }
 
         let serial = self.next_serial();
+        self.seat.keyboard.update_grab_serial(key, key_state == wl_keyboard::KeyState::Pressed, serial);
         self.seat.cleanup_resources();
-        for keyboard in &self.seat.keyboard.resources {
+        for keyboard in self.seat.keyboard.resources.iter().filter(|k| self.client_accepts_input(*k)) {
             keyboard.key(serial, time, key, key_state);
             if let Some((depressed, latched, locked, group)) = new_mods {
                 keyboard.modifiers(serial, depressed, latched, locked, group);
@@ -58,20 +87,116 @@ impl CompositorState {
         self.seat.keyboard.mods_latched = latched;
         self.seat.keyboard.mods_locked = locked;
         self.seat.keyboard.mods_group = group;
+        self.refresh_keyboard_layout();
 
         let serial = self.next_serial();
         self.seat.cleanup_resources();
-        for keyboard in &self.seat.keyboard.resources {
+        for keyboard in self.seat.keyboard.resources.iter().filter(|k| self.client_accepts_input(*k)) {
             keyboard.modifiers(serial, depressed, latched, locked, group);
         }
     }
 
+    /// Pick up a group switch in a keymap with several layouts: update the
+    /// layout name and tell the frontend, as for a keymap switch.
+    pub(crate) fn refresh_keyboard_layout(&mut self) {
+        if self.seat.keyboard.refresh_layout_name() {
+            let layout = self.seat.keyboard.layout_name.clone();
+            tracing::info!("Keyboard layout group switched to {:?}", layout);
+            self.pending_compositor_events.push(CompositorEvent::KeyboardLayoutChanged { layout, success: true });
+        }
+    }
+
+    /// Send the compositor-generated key repeat that is due at `now`, if any,
+    /// to the focused client. Keyboard grabs see it first, like the press.
+    pub fn dispatch_key_repeat(&mut self, now: std::time::Instant) {
+        let Some((key, time)) = self.seat.keyboard.check_repeat(now) else {
+            return;
+        };
+        let event = GrabKey { key, state: wl_keyboard::KeyState::Pressed, time, modifiers: None };
+        if self.dispatch_grab(GrabDevice::Keyboard, |grab, compositor| grab.key(compositor, &event)) {
+            return;
+        }
+        let serial = self.next_serial();
+        let focused = self.focused_keyboard_client();
+        self.seat.keyboard.broadcast_key(serial, time, key, wl_keyboard::KeyState::Pressed, focused.as_ref());
+    }
+
+    /// Send `idled` for idle notifications that came due at `now` and report
+    /// changes of the host's idleness.
+    pub fn dispatch_idle(&mut self, now: std::time::Instant) {
+        let inhibited = self.idle_inhibited();
+        self.ext.idle_notify.set_inhibited(inhibited, now);
+        self.ext.idle_notify.check_idle(now);
+        if let Some(idle) = self.ext.idle_notify.take_host_change() {
+            tracing::info!("Session {}", if idle { "idle" } else { "active" });
+            self.pending_compositor_events.push(CompositorEvent::IdleStateChanged { idle });
+        }
+    }
+
+    /// Whether an idle inhibitor's surface is visible in the last built scene
+    pub fn idle_inhibited(&self) -> bool {
+        if self.ext.idle_inhibit.inhibitors.is_empty() {
+            return false;
+        }
+        let visible: Vec<u32> = self.scene.flatten().iter().map(|s| s.surface_id).collect();
+        self.ext.idle_inhibit.inhibits(&visible)
+    }
+
+    /// Switch the seat keymap to one compiled from RMLVO names.
+    ///
+    /// On failure the previous keymap stays active. Either way a
+    /// `KeyboardLayoutChanged` event reports the layout now in effect.
+    pub fn set_keyboard_layout(
+        &mut self,
+        rules: &str,
+        model: &str,
+        layout: &str,
+        variant: &str,
+        options: Option<String>,
+    ) -> Result<(), CoreError> {
+        let result = self.seat.keyboard.switch_keymap(rules, model, layout, variant, options.clone());
+        self.finish_keymap_switch(result.is_ok());
+        result.map_err(|_| CoreError::state_error(format!(
+            "failed to compile keymap rules={:?} model={:?} layout={:?} variant={:?} options={:?}",
+            rules, model, layout, variant, options
+        )))
+    }
+
+    /// Switch the seat keymap to one given in XKB text format.
+    /// Same failure semantics as `set_keyboard_layout`.
+    pub fn set_keymap_string(&mut self, keymap: &str) -> Result<(), CoreError> {
+        let result = self.seat.keyboard.switch_keymap_from_string(keymap);
+        self.finish_keymap_switch(result.is_ok());
+        result.map_err(|_| CoreError::state_error("failed to compile keymap string"))
+    }
+
+    fn finish_keymap_switch(&mut self, success: bool) {
+        let layout = self.seat.keyboard.layout_name.clone();
+        if success {
+            tracing::info!("Keyboard layout switched to {:?}", layout);
+            // The new keymap resets modifier state; tell the focused client.
+            let serial = self.next_serial();
+            let focused = self.focused_keyboard_client();
+            self.seat.keyboard.broadcast_modifiers(serial, focused.as_ref());
+        } else {
+            tracing::warn!("Keyboard layout switch failed; keeping {:?}", layout);
+        }
+        self.pending_compositor_events.push(CompositorEvent::KeyboardLayoutChanged { layout, success });
+    }
+
     /// Inject relative pointer motion and broadcast to all bound pointers
     pub fn inject_pointer_motion_relative(&mut self, dx: f64, dy: f64, time: u32) {
         self.seat.pointer.x += dx;
         self.seat.pointer.y += dy;
 
-        self.ext.relative_pointers.broadcast_relative_motion(0, 0, time, dx, dy, dx, dy);
+        let (x, y) = (self.seat.pointer.x, self.seat.pointer.y);
+        if self.dispatch_grab(GrabDevice::Pointer, |grab, compositor| grab.pointer_motion(compositor, x, y, time)) {
+            return;
+        }
+
+        if !self.session_locked() {
+            self.ext.relative_pointers.broadcast_relative_motion(0, 0, time, dx, dy, dx, dy);
+        }
 
         if let Some(surface_id) = self.focus.pointer_focus {
             let client_id = self.surfaces.get(&surface_id).and_then(|s| s.read().unwrap().client_id.clone());
@@ -83,111 +208,73 @@ impl CompositorState {
         }
 
         self.seat.cleanup_resources();
-        let x = self.seat.pointer.x;
-        let y = self.seat.pointer.y;
-        for pointer in &self.seat.pointer.resources {
+        for pointer in self.seat.pointer.resources.iter().filter(|p| self.client_accepts_input(*p)) {
             pointer.motion(time, x, y);
         }
     }
 
     /// Find the surface at the given absolute coordinates.
-    /// Phase E: Respects subsurface input region clipping — a point is only accepted
-    /// if it lies within the surface's input_region (None = whole surface).
+    ///
+    /// Walks the scene top-down through windows, popups, layers and
+    /// subsurfaces. A point is only accepted if it lies within the surface's
+    /// input_region (None = whole surface), so input outside it (e.g. CSD
+    /// drop shadows) reaches the surface below. Fully transparent surfaces
+    /// and input-passthrough windows are skipped. Returned coordinates are
+    /// surface-local.
     pub fn find_surface_at(&mut self, x: f64, y: f64) -> Option<(u32, f64, f64)> {
+        use crate::core::input::hit_test::{pick, HitTarget};
+
         self.build_scene();
-        let flattened = self.scene.flatten();
-        
-        for surface in flattened.iter().rev() {
-            let sx = surface.x as f64;
-            let sy = surface.y as f64;
-            let sw = surface.width as f64;
-            let sh = surface.height as f64;
-            
-            if x >= sx && x < sx + sw && y >= sy && y < sy + sh {
-                let local_x = (x - sx) / surface.scale as f64;
-                let local_y = (y - sy) / surface.scale as f64;
-                let lx = local_x as i32;
-                let ly = local_y as i32;
-                
-                if let Some(surf) = self.surfaces.get(&surface.surface_id) {
-                    let guard = surf.read().unwrap();
-                    if let Some(regions) = &guard.current.input_region {
-                        let inside = regions.iter().any(|r| r.contains_point(lx, ly));
-                        if !inside {
-                            continue;
-                        }
-                    }
-                }
-                return Some((surface.surface_id, local_x, local_y));
-            }
-        }
-        
-        None
+        // Occluded surfaces stay: what covers them may not take input
+        let flattened = self.scene.flatten_unculled();
+        let targets: Vec<HitTarget<'_>> = flattened.iter()
+            .map(|surface| HitTarget {
+                surface,
+                input_region: self.surfaces.get(&surface.surface_id).and_then(|surf| {
+                    let surf = surf.read().unwrap();
+                    surf.current.input_region.as_ref().map(|rects| {
+                        crate::util::region::Region::from_rects(rects.iter().map(|r| r.to_rect()))
+                    })
+                }),
+                passthrough: self.resolve_window_id_for_surface(surface.surface_id)
+                    .and_then(|wid| self.windows.get(&wid))
+                    .map_or(false, |w| w.read().unwrap().input_passthrough),
+            })
+            .collect();
+        pick(&targets, x, y)
+    }
+
+    /// Find the window owning the surface under the given absolute
+    /// coordinates, using the same hit-testing as pointer focus.
+    pub fn window_at(&mut self, x: f64, y: f64) -> Option<u32> {
+        let (surface_id, _, _) = self.find_surface_at(x, y)?;
+        self.resolve_window_id_for_surface(surface_id)
     }
 
     /// Inject absolute pointer motion and broadcast to all bound pointers.
-    /// During an active drag, sends wl_data_device enter/leave/motion instead.
+    /// Pointer grabs, such as a drag, see it first.
     pub fn inject_pointer_motion_absolute(&mut self, x: f64, y: f64, time: u32) {
         self.ext.idle_notify.record_activity();
         self.seat.pointer.x = x;
         self.seat.pointer.y = y;
         self.seat.cleanup_resources();
+
+        if self.dispatch_grab(GrabDevice::Pointer, |grab, compositor| grab.pointer_motion(compositor, x, y, time)) {
+            return;
+        }
         
         let picking_res = self.find_surface_at(x, y);
         let old_focus = self.seat.pointer.focus;
 
-        if self.data.drag.is_some() {
-            let new_surface_id = picking_res.as_ref().map(|(sid, _, _)| *sid);
-            let drag_focus = self.data.drag.as_ref().unwrap().focus_surface_id;
-
-            if drag_focus != new_surface_id {
-                if drag_focus.is_some() {
-                    for device_data in self.data.devices.values() {
-                        if device_data.resource.is_alive() {
-                            device_data.resource.leave();
-                        }
-                    }
-                }
-
-                if let Some((sid, lx, ly)) = &picking_res {
-                    if let Some(surface) = self.get_surface(*sid) {
-                        let surface = surface.read().unwrap();
-                        if let Some(res) = &surface.resource {
-                            let serial = self.next_serial();
-                            for device_data in self.data.devices.values() {
-                                if device_data.resource.is_alive() {
-                                    device_data.resource.enter(serial, res, *lx, *ly, None);
-                                }
-                            }
-                        }
-                    }
-                }
-
-                if let Some(drag) = &mut self.data.drag {
-                    drag.focus_surface_id = new_surface_id;
-                }
-            } else if let Some((_sid, lx, ly)) = &picking_res {
-                for device_data in self.data.devices.values() {
-                    if device_data.resource.is_alive() {
-                        device_data.resource.motion(time, *lx, *ly);
-                    }
-                }
-            }
-
-            if let Some(attachment) = &self.xdg.toplevel_drag.active {
-                if let Some(wid) = attachment.window_id {
-                    let new_x = x as i32 + attachment.x_offset;
-                    let new_y = y as i32 + attachment.y_offset;
-                    if let Some(window) = self.get_window(wid) {
-                        let mut window = window.write().unwrap();
-                        window.x = new_x;
-                        window.y = new_y;
-                    }
-                }
-            }
-
-            return;
-        }
+        let decoration_hit = picking_res.and_then(|(surface_id, _, _)| {
+            let (window_id, _) = crate::core::window::decoration::DecorationPiece::from_surface_id(surface_id)?;
+            let layout = self.decorations.windows.get(&window_id)?.layout();
+            let window = self.get_window(window_id)?;
+            let window = window.read().unwrap();
+            let hit = layout.hit_test(x.floor() as i32 - window.x, y.floor() as i32 - window.y)?;
+            Some((window_id, hit))
+        });
+        self.hover_decoration(decoration_hit);
 
         if let Some((surface_id, lx, ly)) = picking_res {
             if Some(surface_id) != old_focus {
@@ -196,7 +283,7 @@ impl CompositorState {
                         let surface = surface.read().unwrap();
                         if let Some(res) = &surface.resource {
                             let serial = self.next_serial();
-                            for pointer in &self.seat.pointer.resources {
+                            for pointer in self.seat.pointer.resources.iter().filter(|p| self.client_accepts_input(*p)) {
                                 pointer.leave(serial, res);
                             }
                         }
@@ -207,15 +294,20 @@ impl CompositorState {
                     let surface = surface.read().unwrap();
                     if let Some(res) = &surface.resource {
                         let serial = self.next_serial();
-                        for pointer in &self.seat.pointer.resources {
+                        for pointer in self.seat.pointer.resources.iter().filter(|p| self.client_accepts_input(*p)) {
                             pointer.enter(serial, res, lx, ly);
                         }
                     }
                 }
                 self.seat.pointer.focus = Some(surface_id);
             }
+
+            // The core owns decoration surfaces; no client sees the pointer there
+            if crate::core::window::decoration::is_decoration_surface(surface_id) {
+                return;
+            }
             
-            for pointer in &self.seat.pointer.resources {
+            for pointer in self.seat.pointer.resources.iter().filter(|p| self.client_accepts_input(*p)) {
                 pointer.motion(time, lx, ly);
             }
         } else {
@@ -224,7 +316,7 @@ impl CompositorState {
                     let surface = surface.read().unwrap();
                     if let Some(res) = &surface.resource {
                         let serial = self.next_serial();
-                        for pointer in &self.seat.pointer.resources {
+                        for pointer in self.seat.pointer.resources.iter().filter(|p| self.client_accepts_input(*p)) {
                             pointer.leave(serial, res);
                         }
                     }
@@ -235,52 +327,46 @@ impl CompositorState {
     }
 
     /// Inject a pointer button event and broadcast to all bound pointers.
+    /// Pointer grabs see it first.
     pub fn inject_pointer_button(&mut self, button: u32, state: wl_pointer::ButtonState, time: u32) {
         self.ext.idle_notify.record_activity();
         let serial = self.next_serial();
         self.seat.cleanup_resources();
         
-        if state == wl_pointer::ButtonState::Pressed {
-            self.seat.pointer.button_count += 1;
-            
-            if !self.seat.popup_grab_stack.is_empty() {
-                let mut on_grab_tree = false;
-                if let Some(focus_id) = self.seat.pointer.focus {
-                    for &(ref cid, popup_id) in &self.seat.popup_grab_stack {
-                        if let Some(popup) = self.xdg.popups.get(&(cid.clone(), popup_id)) {
-                            if popup.surface_id == focus_id {
-                                on_grab_tree = true;
-                                break;
-                            }
-                            if let Some(&wid) = self.surface_to_window.get(&focus_id) {
-                                if wid == popup.window_id {
-                                    on_grab_tree = true;
-                                    break;
-                                }
-                            }
-                        }
-                    }
-                }
-                
-                if !on_grab_tree {
-                    self.dismiss_popup_grab();
-                }
+        let pressed = state == wl_pointer::ButtonState::Pressed;
+        if pressed {
+            if self.seat.pointer.button_count == 0 {
+                self.seat.pointer.grab_serial = Some(serial);
             }
+            self.seat.pointer.button_count += 1;
         } else {
             self.seat.pointer.button_count = self.seat.pointer.button_count.saturating_sub(1);
+            if self.seat.pointer.button_count == 0 {
+                self.seat.pointer.grab_serial = None;
+            }
+        }
 
-            if self.seat.pointer.button_count == 0 && self.data.drag.is_some() {
-                let has_focus = self.data.drag.as_ref().unwrap().focus_surface_id.is_some();
-                self.end_drag(has_focus);
-                return;
+        if self.dispatch_grab(GrabDevice::Pointer, |grab, compositor| grab.pointer_button(compositor, button, pressed, time)) {
+            return;
+        }
+
+        if pressed {
+            if let Some(focus_id) = self.seat.pointer.focus {
+                self.focus_layer_on_click(focus_id);
             }
         }
 
-        if self.data.drag.is_some() {
+        // Presses on a decoration stay in the core; so does the release of
+        // a press that started on one
+        let on_decoration = self.seat.pointer.focus.map_or(false, crate::core::window::decoration::is_decoration_surface);
+        if on_decoration || self.decorations.pressed.is_some() {
+            if button == BTN_LEFT {
+                self.handle_decoration_button(state == wl_pointer::ButtonState::Pressed, serial);
+            }
             return;
         }
 
-        for pointer in &self.seat.pointer.resources {
+        for pointer in self.seat.pointer.resources.iter().filter(|p| self.client_accepts_input(*p)) {
             pointer.button(serial, time, button, state);
         }
     }
@@ -292,16 +378,11 @@ impl CompositorState {
         self.seat.broadcast_pointer_frame(client.as_ref());
     }
 
-    /// Look up a surface's absolute position in the scene graph.
-    fn surface_position_in_scene(&mut self, surface_id: u32) -> Option<(i32, i32, f32)> {
+    /// Look up a surface's placement in the scene graph. Occluded surfaces
+    /// are included, since a touch sequence keeps its surface.
+    pub(crate) fn surface_in_scene(&mut self, surface_id: u32) -> Option<crate::core::render::scene::FlattenedSurface> {
         self.build_scene();
-        let flattened = self.scene.flatten();
-        for node in &flattened {
-            if node.surface_id == surface_id {
-                return Some((node.x, node.y, node.scale));
-            }
-        }
-        None
+        self.scene.flatten_unculled().into_iter().find(|s| s.surface_id == surface_id)
     }
 
     /// Inject touch down event.
@@ -311,16 +392,21 @@ impl CompositorState {
         self.ext.idle_notify.record_activity();
         self.seat.cleanup_resources();
 
+        if self.dispatch_grab(GrabDevice::Touch, |grab, compositor| grab.touch_down(compositor, id, x, y, time)) {
+            return;
+        }
+
         let picking = self.find_surface_at(x, y);
         if let Some((surface_id, local_x, local_y)) = picking {
-            self.seat.touch.touch_down(id, surface_id, local_x, local_y);
+            let serial = self.next_serial();
+            self.seat.touch.touch_down(id, surface_id, local_x, local_y, serial);
 
             if let Some(&window_id) = self.surface_to_window.get(&surface_id) {
                 self.set_focused_window(Some(window_id));
                 self.window_tree.bring_to_front(window_id);
             }
+            self.focus_layer_on_click(surface_id);
 
-            let serial = self.next_serial();
             if let Some(surface) = self.surfaces.get(&surface_id).cloned() {
                 let surface = surface.read().unwrap();
                 if let Some(res) = &surface.resource {
@@ -337,6 +423,10 @@ impl CompositorState {
     pub fn inject_touch_up(&mut self, id: i32, time: u32) {
         self.seat.cleanup_resources();
 
+        if self.dispatch_grab(GrabDevice::Touch, |grab, compositor| grab.touch_up(compositor, id, time)) {
+            return;
+        }
+
         let client = self.seat.touch.get_touch_surface(id).and_then(|sid| {
             self.surfaces.get(&sid).and_then(|surf| {
                 surf.read().unwrap().resource.as_ref().and_then(|r| r.client())
@@ -355,12 +445,14 @@ impl CompositorState {
     pub fn inject_touch_motion(&mut self, id: i32, x: f64, y: f64, time: u32) {
         self.seat.cleanup_resources();
 
+        if self.dispatch_grab(GrabDevice::Touch, |grab, compositor| grab.touch_motion(compositor, id, x, y, time)) {
+            return;
+        }
+
         let surface_id = self.seat.touch.get_touch_surface(id);
         if let Some(sid) = surface_id {
-            let pos = self.surface_position_in_scene(sid);
-            if let Some((sx, sy, scale)) = pos {
-                let local_x = (x - sx as f64) / scale as f64;
-                let local_y = (y - sy as f64) / scale as f64;
+            if let Some(placed) = self.surface_in_scene(sid) {
+                let (local_x, local_y) = placed.scene_to_surface(x, y);
 
                 self.seat.touch.touch_motion(id, local_x, local_y);
 
@@ -420,32 +512,154 @@ impl CompositorState {
     
     /// Set focused window
     pub fn set_focused_window(&mut self, window_id: Option<u32>) {
+        if self.focus.keyboard_focus != window_id {
+            self.seat.keyboard.cancel_repeat();
+        }
         self.focus.set_keyboard_focus(window_id);
         
+        // The window is remembered, but keyboard focus stays on the lock
+        // screen or an exclusive layer surface
+        let held = self.session_locked() || self.exclusive_keyboard_layer().is_some();
         if let Some(wid) = window_id {
             if let Some(window) = self.windows.get(&wid) {
-                let window = window.read().unwrap();
-                self.seat.keyboard.focus = Some(window.surface_id);
+                let surface_id = window.read().unwrap().surface_id;
+                if self.accepts_keyboard_focus(surface_id) {
+                    self.seat.keyboard.focus = Some(surface_id);
+                }
             }
-        } else {
+        } else if !held {
             self.seat.keyboard.focus = None;
         }
+        self.update_text_input_focus();
         
         tracing::debug!("Focus changed to window: {:?}", window_id);
     }
 
     /// Get the client of the currently focused keyboard surface
     pub fn focused_keyboard_client(&self) -> Option<wayland_server::Client> {
-        self.seat.keyboard.focus.and_then(|sid| {
+        self.seat.keyboard.focus.filter(|&sid| self.accepts_input(sid)).and_then(|sid| {
             self.surfaces.get(&sid).and_then(|surf| {
                 surf.read().unwrap().resource.as_ref().and_then(|res| res.client())
             })
         })
     }
 
+    /// Inject a tablet tool frame with global coordinates.
+    ///
+    /// The tool is hit-tested on proximity-in and motion; while the tip is
+    /// down it stays on the surface it went down on (implicit grab).
+    pub fn inject_tablet_tool(&mut self, events: &[crate::core::wayland::ext::tablet::TabletToolEvent], time: u32) {
+        self.dispatch_tablet_tool(None, events, time);
+    }
+
+    /// Inject a tablet tool frame whose coordinates are local to `surface_id`
+    /// (platform windows that already know the target surface).
+    pub fn inject_tablet_tool_on_surface(
+        &mut self,
+        surface_id: u32,
+        events: &[crate::core::wayland::ext::tablet::TabletToolEvent],
+        time: u32,
+    ) {
+        let target = Some(surface_id).filter(|&sid| self.accepts_input(sid));
+        self.dispatch_tablet_tool(target, events, time);
+    }
+
+    fn dispatch_tablet_tool(
+        &mut self,
+        target: Option<u32>,
+        events: &[crate::core::wayland::ext::tablet::TabletToolEvent],
+        time: u32,
+    ) {
+        use crate::core::wayland::ext::tablet::TabletToolEvent;
+
+        self.ext.idle_notify.record_activity();
+        self.ext.tablet.cleanup();
+
+        for event in events {
+            match event {
+                TabletToolEvent::ProximityIn { tool, x, y } => {
+                    self.ext.tablet.ensure_tool(tool);
+                    self.tablet_tool_move(target, Some(tool), *x, *y, time);
+                }
+                TabletToolEvent::Motion { x, y } => {
+                    self.tablet_tool_move(target, None, *x, *y, time);
+                }
+                TabletToolEvent::ProximityOut => self.ext.tablet.proximity_out(time),
+                TabletToolEvent::Down => {
+                    let serial = self.next_serial();
+                    self.ext.tablet.down(serial);
+                }
+                TabletToolEvent::Up => self.ext.tablet.up(),
+                TabletToolEvent::Button { .. } => {
+                    let serial = self.next_serial();
+                    self.ext.tablet.tool_event(serial, event);
+                }
+                other => self.ext.tablet.tool_event(0, other),
+            }
+        }
+        self.ext.tablet.tool_frame(time);
+    }
+
+    /// Resolve the surface under the tool and send proximity/motion.
+    /// `new_tool` is set on proximity-in; otherwise the active tool is moved.
+    fn tablet_tool_move(
+        &mut self,
+        target: Option<u32>,
+        new_tool: Option<&crate::core::wayland::ext::tablet::TabletToolInfo>,
+        x: f64,
+        y: f64,
+        time: u32,
+    ) {
+        let Some(tool) = new_tool.or(self.ext.tablet.active_tool_info()).cloned() else {
+            return;
+        };
+
+        let hit = match target {
+            Some(sid) => Some((sid, x, y)),
+            None => match self.ext.tablet.active_surface() {
+                Some(sid) if self.ext.tablet.is_tool_down() => {
+                    self.surface_local_coords(sid, x, y).map(|(lx, ly)| (sid, lx, ly))
+                }
+                _ => self.find_surface_at(x, y),
+            },
+        };
+
+        let Some((sid, lx, ly)) = hit else {
+            self.ext.tablet.proximity_out(time);
+            return;
+        };
+        let Some(res) = self.surfaces.get(&sid).and_then(|s| s.read().unwrap().resource.clone()) else {
+            return;
+        };
+        if self.ext.tablet.active_surface() != Some(sid) || new_tool.is_some() {
+            let serial = self.next_serial();
+            self.ext.tablet.proximity_in(serial, &tool, sid, &res, time);
+        }
+        self.ext.tablet.motion(lx, ly);
+    }
+
+    /// Convert global coordinates into a surface's local space using the
+    /// current scene layout.
+    pub fn surface_local_coords(&mut self, surface_id: u32, x: f64, y: f64) -> Option<(f64, f64)> {
+        self.surface_in_scene(surface_id).map(|s| s.scene_to_surface(x, y))
+    }
+
+    /// Inject a tablet pad event; pads follow keyboard focus.
+    pub fn inject_tablet_pad(&mut self, event: &crate::core::wayland::ext::tablet::TabletPadEvent, time: u32) {
+        self.ext.idle_notify.record_activity();
+        self.ext.tablet.cleanup();
+
+        let target = self.seat.keyboard.focus.and_then(|sid| {
+            self.surfaces.get(&sid).and_then(|s| s.read().unwrap().resource.clone()).map(|res| (sid, res))
+        });
+        let serial = self.next_serial();
+        self.ext.tablet.pad_focus(serial, target);
+        self.ext.tablet.pad_event(event, time);
+    }
+
     /// Get the client of the currently focused pointer surface
     pub fn focused_pointer_client(&self) -> Option<wayland_server::Client> {
-        self.seat.pointer.focus.and_then(|sid| {
+        self.seat.pointer.focus.filter(|&sid| self.accepts_input(sid)).and_then(|sid| {
             self.surfaces.get(&sid).and_then(|surf| {
                 surf.read().unwrap().resource.as_ref().and_then(|res| res.client())
             })
@@ -457,6 +671,143 @@ impl CompositorState {
         self.focus.keyboard_focus
     }
 
+    /// Whether the session is locked (ext-session-lock). Only lock surfaces
+    /// are shown and take input until it is unlocked.
+    pub fn session_locked(&self) -> bool {
+        #[cfg(feature = "desktop-protocols")]
+        {
+            self.ext.session_lock.is_locked()
+        }
+        #[cfg(not(feature = "desktop-protocols"))]
+        {
+            false
+        }
+    }
+
+    /// Whether `surface_id` may receive input: any surface normally, only
+    /// lock surfaces while the session is locked.
+    pub fn accepts_input(&self, surface_id: u32) -> bool {
+        #[cfg(feature = "desktop-protocols")]
+        if self.ext.session_lock.is_locked() {
+            return self.ext.session_lock.is_lock_surface(surface_id);
+        }
+        let _ = surface_id;
+        true
+    }
+
+    /// Whether `surface_id` may take keyboard focus. A mapped top or overlay
+    /// layer surface with exclusive interactivity keeps it for itself.
+    pub fn accepts_keyboard_focus(&self, surface_id: u32) -> bool {
+        self.accepts_input(surface_id)
+            && self.exclusive_keyboard_layer().map_or(true, |layer| layer == surface_id)
+    }
+
+    /// Move keyboard focus to `surface_id`, or to nothing, sending leave
+    /// and enter.
+    pub fn set_keyboard_focus_surface(&mut self, surface_id: Option<u32>) {
+        if let Some(old) = self.seat.keyboard.focus.take() {
+            if let Some(res) = self.get_surface(old).and_then(|s| s.read().unwrap().resource.clone()) {
+                let serial = self.next_serial();
+                self.seat.broadcast_keyboard_leave(serial, &res);
+            }
+        }
+        self.seat.keyboard.cancel_repeat();
+        if let Some(sid) = surface_id {
+            if let Some(res) = self.get_surface(sid).and_then(|s| s.read().unwrap().resource.clone()) {
+                let serial = self.next_serial();
+                let keys = self.seat.keyboard.pressed_keys.clone();
+                self.seat.keyboard.focus = Some(sid);
+                self.seat.broadcast_keyboard_enter(serial, &res, &keys);
+            }
+        }
+        self.update_text_input_focus();
+    }
+
+    /// Give keyboard focus back to the focused window, e.g. after a layer
+    /// surface or the lock screen held it. An exclusive layer surface keeps
+    /// it instead.
+    pub fn restore_window_keyboard_focus(&mut self) {
+        let surface_id = self.exclusive_keyboard_layer().or_else(|| {
+            self.focus.keyboard_focus
+                .and_then(|wid| self.get_window(wid))
+                .map(|window| window.read().unwrap().surface_id)
+                .filter(|&sid| self.accepts_keyboard_focus(sid))
+        });
+        self.set_keyboard_focus_surface(surface_id);
+    }
+
+    /// Whether input may be sent to the client owning `resource`: any client
+    /// normally, only the locker while the session is locked.
+    fn client_accepts_input<R: Resource>(&self, resource: &R) -> bool {
+        #[cfg(feature = "desktop-protocols")]
+        if self.ext.session_lock.is_locked() {
+            return self.ext.session_lock.lock_client().map_or(false, |c| resource.client() == Some(c));
+        }
+        let _ = resource;
+        true
+    }
+
+    // =========================================================================
+    // Input Grabs
+    // =========================================================================
+
+    /// Start an input grab on the seat. Active grabs it doesn't stack with
+    /// are cancelled first. If an active grab refuses it, the new grab is
+    /// dropped and the refusing kind returned; one joining an active grab
+    /// of its own kind is dropped too, as that grab covers it.
+    pub fn start_grab(&mut self, grab: Box<dyn InputGrab>) -> Result<(), GrabKind> {
+        let kind = grab.kind();
+        match self.seat.grabs.admission(kind) {
+            Admission::Refused(by) => {
+                tracing::debug!("{:?} grab refused by {:?} grab", kind, by);
+                Err(by)
+            }
+            Admission::Join => Ok(()),
+            Admission::Push { cancel } => {
+                for active in cancel {
+                    self.cancel_grab(active);
+                }
+                self.seat.grabs.push(grab);
+                tracing::debug!("{:?} grab started", kind);
+                Ok(())
+            }
+        }
+    }
+
+    /// End the grab of `kind`, as its owner does once it's done
+    pub fn end_grab(&mut self, kind: GrabKind) {
+        if self.seat.grabs.remove(kind).is_some() {
+            tracing::debug!("{:?} grab ended", kind);
+        }
+    }
+
+    /// Cancel the grab of `kind`, letting it undo what it holds
+    pub fn cancel_grab(&mut self, kind: GrabKind) {
+        let Some(grab) = self.seat.grabs.remove(kind) else { return };
+        tracing::debug!("{:?} grab cancelled", kind);
+        if let Some(mut grab) = grab {
+            grab.cancel(self);
+        }
+    }
+
+    /// Show an event to the grabs on `device`, topmost first, until one
+    /// consumes it. Returns whether one did.
+    fn dispatch_grab(
+        &mut self,
+        device: GrabDevice,
+        mut handle: impl FnMut(&mut dyn InputGrab, &mut Self) -> GrabStatus,
+    ) -> bool {
+        for id in self.seat.grabs.ids_for(device) {
+            let Some(mut grab) = self.seat.grabs.take(id) else { continue };
+            let status = handle(grab.as_mut(), self);
+            self.seat.grabs.restore(id, grab, matches!(status, GrabStatus::End { .. }));
+            if matches!(status, GrabStatus::Consumed | GrabStatus::End { consumed: true }) {
+                return true;
+            }
+        }
+        false
+    }
+
     // =========================================================================
     // Input Processing
     // =========================================================================
@@ -487,21 +838,9 @@ impl CompositorState {
                 self.seat.pointer.x = x;
                 self.seat.pointer.y = y;
 
-                let window_info = {
-                     let under = self.window_tree.window_under(x, y, &self.windows);
-                     if let Some(wid) = under {
-                         if let Some(window) = self.windows.get(&wid) {
-                             let w = window.read().unwrap();
-                             Some((wid, w.surface_id, w.geometry()))
-                         } else {
-                             None
-                         }
-                     } else {
-                         None
-                     }
-                };
+                let picked = self.find_surface_at(x, y);
                 
-                if let Some((_window_id, surface_id, win_geo)) = window_info {
+                if let Some((surface_id, lx, ly)) = picked {
                         if self.seat.pointer.focus != Some(surface_id) {
                             if let Some(old_focus) = self.seat.pointer.focus {
                                 let old_resource = if let Some(surf) = self.surfaces.get(&old_focus) {
@@ -526,9 +865,6 @@ impl CompositorState {
                             };
 
                             if let Some(res) = new_resource {
-                                let lx = x - win_geo.x as f64;
-                                let ly = y - win_geo.y as f64;
-                                
                                 self.serial += 1;
                                 let serial = self.serial;
                                 self.seat.broadcast_pointer_enter(serial, &res, lx, ly);
@@ -539,7 +875,9 @@ impl CompositorState {
                         
                          let dx = x - self.seat.pointer.x;
                          let dy = y - self.seat.pointer.y;
-                         self.ext.relative_pointers.broadcast_relative_motion(0, 0, time_ms, dx, dy, dx, dy);
+                         if !self.session_locked() {
+                             self.ext.relative_pointers.broadcast_relative_motion(0, 0, time_ms, dx, dy, dx, dy);
+                         }
 
                         self.seat.pointer.x = x;
                         self.seat.pointer.y = y;
@@ -551,9 +889,6 @@ impl CompositorState {
                              false
                         };
                         if !locked {
-                             let lx = x - win_geo.x as f64;
-                             let ly = y - win_geo.y as f64;
-                             
                              let client = if let Some(sid) = self.seat.pointer.focus {
                                 if let Some(surf) = self.surfaces.get(&sid) {
                                     surf.read().unwrap().resource.as_ref().and_then(|res| res.client())
@@ -593,7 +928,7 @@ impl CompositorState {
                 };
                 
                 if wl_state == ButtonState::Pressed {
-                    let window_under = self.window_tree.window_under(self.seat.pointer.x, self.seat.pointer.y, &self.windows);
+                    let window_under = self.window_at(self.seat.pointer.x, self.seat.pointer.y);
                     if let Some(window_id) = window_under {
                         self.set_focused_window(Some(window_id));
                         self.window_tree.bring_to_front(window_id);
@@ -614,7 +949,7 @@ impl CompositorState {
                 let serial = self.serial;
                 self.seat.broadcast_pointer_button(serial, time_ms, button, wl_state, client.as_ref());
             }
-            InputEvent::PointerAxis { horizontal, vertical, time_ms } => {
+            InputEvent::PointerAxis { frame } => {
                 self.ext.idle_notify.record_activity();
                 let client = self.seat.pointer.focus.as_ref().and_then(|s| {
                     self.get_surface(*s).and_then(|sf| {
@@ -622,23 +957,13 @@ impl CompositorState {
                         sf.resource.as_ref().and_then(|r| r.client())
                     })
                 });
-                if vertical != 0.0 {
-                    self.seat.broadcast_pointer_axis(
-                        time_ms,
-                        wl_pointer::Axis::VerticalScroll,
-                        vertical,
-                        client.as_ref(),
-                    );
-                }
-                if horizontal != 0.0 {
-                    self.seat.broadcast_pointer_axis(
-                        time_ms,
-                        wl_pointer::Axis::HorizontalScroll,
-                        horizontal,
-                        client.as_ref(),
-                    );
-                }
-                self.seat.broadcast_pointer_frame(client.as_ref());
+                self.seat.broadcast_pointer_axis_frame(&frame, client.as_ref());
+            }
+            InputEvent::TabletTool { events, time_ms } => {
+                self.inject_tablet_tool(&events, time_ms);
+            }
+            InputEvent::TabletPad { event, time_ms } => {
+                self.inject_tablet_pad(&event, time_ms);
             }
             InputEvent::KeyboardKey { keycode, state, time_ms } => {
                  let wl_state = if state == crate::core::input::KeyState::Pressed {
@@ -657,15 +982,23 @@ impl CompositorState {
                     None
                 };
 
+                self.seat.keyboard.update_repeat(keycode, state == crate::core::input::KeyState::Pressed, time_ms);
+
                 self.serial += 1;
                 let serial = self.serial;
                 self.seat.broadcast_key(serial, time_ms, keycode, wl_state, client.as_ref());
             }
             InputEvent::KeyboardModifiers { depressed, latched, locked, group } => {
+                if let Some(xkb) = &self.seat.keyboard.xkb_state {
+                    if let Ok(mut xkb) = xkb.lock() {
+                        xkb.update_mask(depressed, latched, locked, group);
+                    }
+                }
                 self.seat.keyboard.mods_depressed = depressed;
                 self.seat.keyboard.mods_latched = latched;
                 self.seat.keyboard.mods_locked = locked;
                 self.seat.keyboard.mods_group = group;
+                self.refresh_keyboard_layout();
                 
                 let client = if let Some(sid) = self.seat.keyboard.focus {
                     if let Some(surf) = self.surfaces.get(&sid) {
@@ -11,7 +11,7 @@
 
 use std::collections::HashMap;
 use std::sync::{Arc, RwLock};
-use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use std::time::Instant;
 
 use crate::core::input::xkb::{XkbContext, XkbState};
 use crate::core::input::keyboard::KeyboardState;
@@ -21,6 +21,7 @@ use crate::core::input::touch::TouchState;
 use wayland_server::Resource;
 
 use wayland_server::protocol::wl_callback::WlCallback;
+use wayland_protocols::wp::presentation_time::server::wp_presentation_feedback;
 use wayland_server::backend::{ClientData, ClientId, DisconnectReason};
 
 
@@ -54,6 +55,7 @@ use crate::core::wayland::xdg::xdg_foreign::XdgForeignState;
 
 use crate::core::render::scene::Scene;
 use crate::core::render::damage::SceneDamage;
+use crate::core::render::visibility::SurfaceVisibility;
 use crate::core::render::node::SceneNode;
 use crate::ffi::types::ContentRect;
 use crate::core::wayland::xdg::xdg_output::XdgOutputState;
@@ -211,18 +213,32 @@ pub struct LayerSurface {
     pub margin: (i32, i32, i32, i32),
     /// Exclusive zone
     pub exclusive_zone: i32,
+    /// Edge the exclusive zone is reserved on (0 = deduced from the anchor)
+    pub exclusive_edge: u32,
     /// Keyboard interactivity
     pub interactivity: u32,
-    /// Desired width
+    /// Size requested with `set_size` (0 = stretch between anchors)
+    pub requested_size: (u32, u32),
+    /// Arranged width
     pub width: u32,
-    /// Desired height
+    /// Arranged height
     pub height: u32,
     /// Calculated X position
     pub x: i32,
     /// Calculated Y position
     pub y: i32,
+    /// Creation order, which decides who claims an edge first
+    pub sequence: u64,
+    /// State as of the last commit, None until the initial commit; only
+    /// committed state is arranged, and only a commit that changes it
+    /// re-arranges the output
+    pub committed_state: Option<crate::core::wayland::wlr::layer_shell::LayerArrangement>,
+    /// Size sent in the last configure
+    pub configured_size: Option<(u32, u32)>,
     /// Whether initial configure was acked
     pub configured: bool,
+    /// Whether a buffer is attached
+    pub mapped: bool,
     /// Pending configure serial
     pub pending_serial: u32,
     /// Protocol resource (optional as it might be held in a wrapper)
@@ -239,16 +255,35 @@ impl LayerSurface {
             anchor: 0,
             margin: (0, 0, 0, 0),
             exclusive_zone: 0,
+            exclusive_edge: 0,
             interactivity: 0,
+            requested_size: (0, 0),
             width: 0,
             height: 0,
             x: 0,
             y: 0,
+            sequence: 0,
+            committed_state: None,
+            configured_size: None,
             configured: false,
+            mapped: false,
             pending_serial: 0,
             resource: None,
         }
     }
+
+    /// The placement state the client has set, which its next commit
+    /// applies
+    pub fn arrangement(&self) -> crate::core::wayland::wlr::layer_shell::LayerArrangement {
+        crate::core::wayland::wlr::layer_shell::LayerArrangement {
+            layer: self.layer,
+            anchor: self.anchor,
+            exclusive_zone: self.exclusive_zone,
+            exclusive_edge: self.exclusive_edge,
+            margin: self.margin,
+            size: self.requested_size,
+        }
+    }
 }
 
 
@@ -328,6 +363,8 @@ pub struct XdgToplevelData {
     pub pending_maximized: bool,
     /// Pending fullscreen state
     pub pending_fullscreen: bool,
+    /// An interactive resize is in progress
+    pub pending_resizing: bool,
     /// The actual protocol resource
     pub resource: Option<xdg_toplevel::XdgToplevel>,
 }
@@ -354,6 +391,7 @@ impl XdgToplevelData {
             fullscreen: false,
             pending_maximized: false,
             pending_fullscreen: false,
+            pending_resizing: false,
             resource: None,
         }
     }
@@ -389,9 +427,15 @@ pub struct XdgPopupData {
     pub surface_id: u32,
     pub xdg_surface_id: u32,
     pub window_id: u32,
+    /// Window id of the parent toplevel or popup
     pub parent_id: Option<u32>,
     pub geometry: (i32, i32, i32, i32), // x, y, width, height
     pub anchor_rect: (i32, i32, i32, i32),
+    /// Positioner the popup was last placed with
+    pub positioner: XdgPositionerData,
+    /// Layer surface (surface id) the popup was parented to with
+    /// `zwlr_layer_surface_v1.get_popup`; geometry is then relative to it
+    pub parent_layer: Option<u32>,
     pub grabbed: bool,
     pub repositioned_token: Option<u32>,
     /// The actual protocol resource
@@ -411,103 +455,128 @@ pub struct XdgPositionerData {
     pub gravity: u32, // xdg_positioner::Gravity
     pub constraint_adjustment: u32, // xdg_positioner::ConstraintAdjustment
     pub offset: (i32, i32),
+    /// Re-place the popup when its parent moves or resizes
+    pub reactive: bool,
+    /// Parent size the popup is positioned for, with the parent configure
+    /// serial it answers
+    pub parent_size: Option<(i32, i32)>,
+    pub parent_configure: Option<u32>,
 }
 
-impl XdgPositionerData {
-    /// Calculate final position relative to parent anchor rect, bounded by output_rect
-    pub fn calculate_position(&self, output_rect: crate::util::geometry::Rect) -> (i32, i32) {
-        let (ax, ay, aw, ah) = self.anchor_rect;
-        let mut x = ax;
-        let mut y = ay;
-
-        // 1. Calculate base position from anchor
-        if (self.anchor & 4) != 0 { // Left
-             // x = ax
-        } else if (self.anchor & 8) != 0 { // Right
-            x += aw;
-        } else { // Center
-            x += aw / 2;
-        }
-
-        if (self.anchor & 1) != 0 { // Top
-            // y = ay
-        } else if (self.anchor & 2) != 0 { // Bottom
-            y += ah;
-        } else { // Center
-            y += ah / 2;
-        }
-
-        // 2. Apply gravity and offset
-        let mut px = x + self.offset.0;
-        let mut py = y + self.offset.1;
+// xdg_positioner::ConstraintAdjustment bits
+const SLIDE_X: u32 = 1;
+const SLIDE_Y: u32 = 2;
+const FLIP_X: u32 = 4;
+const FLIP_Y: u32 = 8;
+const RESIZE_X: u32 = 16;
+const RESIZE_Y: u32 = 32;
+
+/// Horizontal and vertical direction of an xdg_positioner anchor or
+/// gravity value: -1 towards left/top, 1 towards right/bottom, 0 centered
+fn positioner_edges(value: u32) -> (i32, i32) {
+    match value {
+        1 => (0, -1),  // top
+        2 => (0, 1),   // bottom
+        3 => (-1, 0),  // left
+        4 => (1, 0),   // right
+        5 => (-1, -1), // top left
+        6 => (-1, 1),  // bottom left
+        7 => (1, -1),  // top right
+        8 => (1, 1),   // bottom right
+        _ => (0, 0),
+    }
+}
 
-        if (self.gravity & 4) != 0 { // Left
-            px -= self.width;
-        } else if (self.gravity & 8) != 0 { // Right
-            // px stays
-        } else { // Center
-            px -= self.width / 2;
-        }
+/// One axis of a positioner: where the popup starts and how long it is,
+/// given the anchor rect span `(start, len)`, the anchor and gravity
+/// directions, the offset and the popup length
+fn positioner_span(anchor_span: (i32, i32), anchor: i32, gravity: i32, offset: i32, len: i32) -> i32 {
+    let (start, span) = anchor_span;
+    let point = match anchor {
+        -1 => start,
+        1 => start + span,
+        _ => start + span / 2,
+    };
+    point + offset + match gravity {
+        -1 => -len,
+        1 => 0,
+        _ => -len / 2,
+    }
+}
 
-        if (self.gravity & 1) != 0 { // Top
-            py -= self.height;
-        } else if (self.gravity & 2) != 0 { // Bottom
-            // py stays
-        } else { // Center
-            py -= self.height / 2;
+/// Unconstrain one axis: flip, then slide, then resize, each only while
+/// the popup still sticks out of `bounds` (start, len). Returns the
+/// popup's start and length on the axis.
+fn unconstrain_axis(
+    anchor_span: (i32, i32),
+    (anchor, gravity): (i32, i32),
+    offset: i32,
+    len: i32,
+    bounds: (i32, i32),
+    (flip, slide, resize): (bool, bool, bool),
+) -> (i32, i32) {
+    let (lo, hi) = (bounds.0, bounds.0 + bounds.1);
+    let constrained = |pos: i32, len: i32| pos < lo || pos + len > hi;
+
+    let mut pos = positioner_span(anchor_span, anchor, gravity, offset, len);
+    if !constrained(pos, len) {
+        return (pos, len);
+    }
+    // The flipped placement is kept only if it fits
+    if flip && (anchor != 0 || gravity != 0) {
+        let flipped = positioner_span(anchor_span, -anchor, -gravity, offset, len);
+        if !constrained(flipped, len) {
+            return (flipped, len);
         }
-
-        // 3. Apply constraint adjustments (basic Slide and Flip)
-        // Adjustments: Slide X(1), Slide Y(2), Flip X(4), Flip Y(8)
-        
-        // Horizontal adjustment
-        if px < output_rect.x {
-            if (self.constraint_adjustment & 4) != 0 { // Flip X
-                // Simple flip: try mirroring across anchor point
-                let flipped_px = x - (px - x) - self.width;
-                if flipped_px + self.width <= (output_rect.x + output_rect.width as i32) {
-                    px = flipped_px;
-                }
-            }
-            if px < output_rect.x && (self.constraint_adjustment & 1) != 0 { // Slide X
-                px = output_rect.x;
-            }
-        } else if px + self.width > (output_rect.x + output_rect.width as i32) {
-            if (self.constraint_adjustment & 4) != 0 { // Flip X
-                let flipped_px = x - (px - x) - self.width;
-                if flipped_px >= output_rect.x {
-                    px = flipped_px;
-                }
-            }
-            if px + self.width > (output_rect.x + output_rect.width as i32) && (self.constraint_adjustment & 1) != 0 { // Slide X
-                px = (output_rect.x + output_rect.width as i32) - self.width;
-            }
+    }
+    if slide {
+        // Too long to fit either way: keep the start edge visible
+        pos = pos.min(hi - len).max(lo);
+        if !constrained(pos, len) {
+            return (pos, len);
         }
-
-        // Vertical adjustment
-        if py < output_rect.y {
-            if (self.constraint_adjustment & 8) != 0 { // Flip Y
-                let flipped_py = y - (py - y) - self.height;
-                if flipped_py + self.height <= (output_rect.y + output_rect.height as i32) {
-                    py = flipped_py;
-                }
-            }
-            if py < output_rect.y && (self.constraint_adjustment & 2) != 0 { // Slide Y
-                py = output_rect.y;
-            }
-        } else if py + self.height > (output_rect.y + output_rect.height as i32) {
-             if (self.constraint_adjustment & 8) != 0 { // Flip Y
-                let flipped_py = y - (py - y) - self.height;
-                if flipped_py >= output_rect.y {
-                    py = flipped_py;
-                }
-            }
-            if py + self.height > (output_rect.y + output_rect.height as i32) && (self.constraint_adjustment & 2) != 0 { // Slide Y
-                py = (output_rect.y + output_rect.height as i32) - self.height;
-            }
+    }
+    if resize {
+        let start = pos.max(lo);
+        let end = (pos + len).min(hi);
+        if end > start {
+            return (start, end - start);
         }
+    }
+    (pos, len)
+}
 
-        (px, py)
+impl XdgPositionerData {
+    /// Place the popup: its geometry (x, y, width, height) relative to the
+    /// parent, like the anchor rect. The constraint adjustments are tried
+    /// per axis in the order the protocol gives (flip, slide, resize)
+    /// against `bounds`, the usable area in the same coordinates. Empty
+    /// bounds leave the popup unconstrained.
+    pub fn place(&self, bounds: crate::util::geometry::Rect) -> (i32, i32, i32, i32) {
+        let (ax, ay, aw, ah) = self.anchor_rect;
+        let anchor = positioner_edges(self.anchor);
+        let gravity = positioner_edges(self.gravity);
+        let adjustment = if bounds.is_empty() { 0 } else { self.constraint_adjustment };
+        let adjust = |flip: u32, slide: u32, resize: u32| {
+            (adjustment & flip != 0, adjustment & slide != 0, adjustment & resize != 0)
+        };
+        let (x, width) = unconstrain_axis(
+            (ax, aw),
+            (anchor.0, gravity.0),
+            self.offset.0,
+            self.width,
+            (bounds.x, bounds.width as i32),
+            adjust(FLIP_X, SLIDE_X, RESIZE_X),
+        );
+        let (y, height) = unconstrain_axis(
+            (ay, ah),
+            (anchor.1, gravity.1),
+            self.offset.1,
+            self.height,
+            (bounds.y, bounds.height as i32),
+            adjust(FLIP_Y, SLIDE_Y, RESIZE_Y),
+        );
+        (x, y, width, height)
     }
 }
 
@@ -521,6 +590,9 @@ impl Default for XdgPositionerData {
             gravity: 0,
             constraint_adjustment: 0,
             offset: (0, 0),
+            reactive: false,
+            parent_size: None,
+            parent_configure: None,
         }
     }
 }
@@ -623,6 +695,9 @@ pub struct OutputState {
     pub height: u32,
     /// Refresh rate in mHz
     pub refresh: u32,
+    /// Minimum refresh rate in mHz when the output supports adaptive sync
+    /// (VRR / ProMotion); 0 for fixed-rate outputs
+    pub min_refresh: u32,
     /// Scale factor
     pub scale: f32,
     /// List of modes
@@ -661,6 +736,7 @@ impl OutputState {
             width,
             height,
             refresh: 60000, // 60Hz
+            min_refresh: 0,
             scale: 1.0,
             modes: vec![mode],
             power_mode: 1, // Default to ON
@@ -711,11 +787,13 @@ use wayland_server::protocol::{wl_pointer, wl_keyboard, wl_touch};
 
 use crate::core::wayland::protocol::wlroots::wlr_data_control_unstable_v1::zwlr_data_control_source_v1;
 
-/// Abstract selection source (standard or wlr)
+/// Abstract selection source (standard, wlr, or the compositor's clipboard cache)
 #[derive(Debug, Clone)]
 pub enum SelectionSource {
     Wayland(wayland_server::protocol::wl_data_source::WlDataSource),
     Wlr(zwlr_data_control_source_v1::ZwlrDataControlSourceV1),
+    /// Served from `DataDeviceState::clipboard_cache` after the owner went away
+    Cached,
 }
 
 /// Collection of seat resources bound by clients.
@@ -732,8 +810,11 @@ pub struct SeatState {
     pub pointer: PointerState,
     /// Touch sub-state (active points, resources)
     pub touch: TouchState,
-    /// Active popup grab stack (ClientId, protocol_id)
+    /// Grabbing popups (ClientId, protocol_id), the topmost last. Each one
+    /// is the child of the one below it.
     pub popup_grab_stack: Vec<(wayland_server::backend::ClientId, u32)>,
+    /// Active input grabs (popups, drag-and-drop, move/resize, ...)
+    pub grabs: crate::core::input::grab::GrabStack,
 }
 
 impl Clone for SeatState {
@@ -795,9 +876,24 @@ impl SeatState {
             pointer: PointerState::new(),
             touch: TouchState::new(),
             popup_grab_stack: Vec::new(),
+            grabs: crate::core::input::grab::GrabStack::default(),
         }
     }
 
+    /// The held input that a grab requested with `serial` would follow:
+    /// the pointer button press, key press or touch point down that
+    /// `serial` was sent with, while it is still held
+    pub fn grab_serial(&self, serial: u32) -> Option<crate::core::input::grab::GrabSerial> {
+        use crate::core::input::grab::GrabSerial;
+        if self.pointer.grab_serial_valid(serial) {
+            return Some(GrabSerial::Pointer);
+        }
+        if self.keyboard.grab_serial_valid(serial) {
+            return Some(GrabSerial::Key);
+        }
+        self.touch.point_for_serial(serial).map(|p| GrabSerial::Touch(p.id))
+    }
+
     /// Add a pointer resource
     pub fn add_pointer(&mut self, pointer: wl_pointer::WlPointer) {
         self.pointer.add_resource(pointer);
@@ -858,6 +954,10 @@ impl SeatState {
         self.pointer.broadcast_axis(time, axis, value, focused_client);
     }
 
+    pub fn broadcast_pointer_axis_frame(&mut self, frame: &crate::core::input::AxisFrame, focused_client: Option<&wayland_server::Client>) {
+        self.pointer.broadcast_axis_frame(frame, focused_client);
+    }
+
     pub fn broadcast_key(&mut self, serial: u32, time: u32, key: u32, state: wl_keyboard::KeyState, focused_client: Option<&wayland_server::Client>) {
         self.keyboard.broadcast_key(serial, time, key, state, focused_client);
     }
@@ -1033,6 +1133,8 @@ pub struct ExtProtocolState {
     pub keyboard_shortcuts_inhibit: KeyboardShortcutsInhibitState,
     /// Pointer gestures state
     pub pointer_gestures: PointerGesturesState,
+    /// Tablet (stylus/pad) devices and focus
+    pub tablet: crate::core::wayland::ext::tablet::TabletState,
     /// Content type hints per surface
     pub content_type: crate::core::wayland::ext::content_type::ContentTypeState,
     /// Tearing control hints per surface
@@ -1090,6 +1192,7 @@ impl Default for ExtProtocolState {
             idle_inhibit: IdleInhibitState::default(),
             keyboard_shortcuts_inhibit: KeyboardShortcutsInhibitState::default(),
             pointer_gestures: PointerGesturesState::default(),
+            tablet: crate::core::wayland::ext::tablet::TabletState::default(),
             content_type: crate::core::wayland::ext::content_type::ContentTypeState::default(),
             tearing_control: crate::core::wayland::ext::tearing_control::TearingControlState::default(),
             alpha_modifier: crate::core::wayland::ext::alpha_modifier::AlphaModifierState::default(),
@@ -1128,6 +1231,8 @@ pub struct WlrState {
     pub layer_surfaces: HashMap<(ClientId, u32), Arc<RwLock<LayerSurface>>>,
     /// Surface ID to layer surface ID mapping (for buffer handling), keyed by (ClientId, surface_id)
     pub surface_to_layer: HashMap<(ClientId, u32), u32>,
+    /// Creation sequence number for the next layer surface
+    pub next_layer_sequence: u64,
     /// Active virtual pointers (client_id, resource_id) -> pointer_state
     pub virtual_pointers: HashMap<(ClientId, u32), VirtualPointerState>,
     /// Active virtual keyboards (client_id, resource_id) -> keyboard_state
@@ -1158,6 +1263,7 @@ impl Default for WlrState {
         Self {
             layer_surfaces: HashMap::new(),
             surface_to_layer: HashMap::new(),
+            next_layer_sequence: 0,
             virtual_pointers: HashMap::new(),
             virtual_keyboards: HashMap::new(),
             data_control: DataControlState::default(),
@@ -1276,6 +1382,15 @@ pub struct CompositorState {
     
     /// Pending frame callbacks per surface.
     pub frame_callbacks: HashMap<u32, Vec<WlCallback>>,
+
+    /// Commits held back by FIFO barriers or commit-timing targets
+    pub commit_queue: crate::core::surface::commit_queue::CommitQueue,
+
+    /// Holds back or slows frame callbacks of surfaces nobody can see
+    pub frame_throttle: crate::core::surface::frame_throttle::FrameThrottle,
+
+    /// Surfaces committed since the runtime last synced its output clocks
+    pub committed_since_sync: Vec<u32>,
     
     // =========================================================================
     // Configuration
@@ -1283,6 +1398,13 @@ pub struct CompositorState {
     
     /// Decoration policy
     pub decoration_policy: DecorationPolicy,
+
+    /// Server-side decorations drawn by the core
+    pub decorations: crate::core::window::decoration::DecorationState,
+
+    /// Run interactive move/resize in the core instead of asking the
+    /// platform's window manager. Always on when headless.
+    pub core_window_grabs: bool,
     
     /// Keyboard repeat rate (Hz)
     pub keyboard_repeat_rate: i32,
@@ -1344,6 +1466,13 @@ pub struct CompositorState {
     
     /// Global scene graph
     pub scene: Scene,
+
+    /// Set when something the scene is built from may have changed since
+    /// the last build; see [`refresh_scene`](Self::refresh_scene)
+    pub scene_dirty: bool,
+
+    /// Surfaces the last built scene shows on each output
+    output_surfaces: Option<HashMap<u32, Vec<u32>>>,
     
     /// Global damage tracking
     pub scene_damage: SceneDamage,
@@ -1354,15 +1483,20 @@ pub struct CompositorState {
 
 impl CompositorState {
     pub fn new(config: Option<crate::core::compositor::CompositorConfig>) -> Self {
-        let (decoration_policy, advertise_fullscreen_shell) = if let Some(cfg) = config {
+        let mut data = DataDeviceState::default();
+        data.clipboard_cache.enabled = config.as_ref().map_or(false, |c| c.clipboard_persistence);
+
+        let headless = config.is_none();
+        let (decoration_policy, advertise_fullscreen_shell, core_window_grabs) = if let Some(cfg) = config {
              let policy = if cfg.force_ssd {
                  DecorationPolicy::ForceServer
              } else {
                  DecorationPolicy::default()
              };
-             (policy, cfg.advertise_fullscreen_shell)
+             (policy, cfg.advertise_fullscreen_shell, cfg.core_window_grabs)
         } else {
-            (DecorationPolicy::default(), false)
+            // Headless: no platform window manager to hand grabs to
+            (DecorationPolicy::default(), false, true)
         };
 
         Self {
@@ -1384,7 +1518,16 @@ impl CompositorState {
             output_id_by_resource: HashMap::new(),
             image_capture_source_output: HashMap::new(),
             frame_callbacks: HashMap::new(),
+            commit_queue: Default::default(),
+            frame_throttle: Default::default(),
+            committed_since_sync: Vec::new(),
             decoration_policy,
+            // Headless: no frontend to draw frames either
+            decorations: crate::core::window::decoration::DecorationState {
+                enabled: headless,
+                ..Default::default()
+            },
+            core_window_grabs,
             keyboard_repeat_rate: 33,
             keyboard_repeat_delay: 500,
             advertise_fullscreen_shell,
@@ -1396,7 +1539,7 @@ impl CompositorState {
             xdg: XdgState::default(),
             ext: ExtProtocolState::default(),
             wlr: WlrState::default(),
-            data: DataDeviceState::default(),
+            data,
             seat_resources: HashMap::new(),
             
             pending_compositor_events: Vec::new(),
@@ -1404,6 +1547,8 @@ impl CompositorState {
             regions: HashMap::new(),
             
             scene: Scene::new(),
+            scene_dirty: true,
+            output_surfaces: None,
             scene_damage: SceneDamage::new(),
             next_node_id: 1,
         }
@@ -1427,6 +1572,9 @@ impl CompositorState {
     /// Returns Some(window_id) for toplevels, popups, and subsurfaces (resolved
     /// via their parent chain); None for layer surfaces or unmapped surfaces.
     pub fn resolve_window_id_for_surface(&self, surface_id: u32) -> Option<u32> {
+        if let Some((wid, _)) = crate::core::window::decoration::DecorationPiece::from_surface_id(surface_id) {
+            return Some(wid);
+        }
         if let Some(&wid) = self.surface_to_window.get(&surface_id) {
             return Some(wid);
         }
@@ -1460,6 +1608,7 @@ impl CompositorState {
     
     /// Flush all pending frame callbacks for a surface.
     pub fn flush_frame_callbacks(&mut self, surface_id: u32, timestamp: Option<u32>) {
+        self.commit_queue.forget_callbacks(surface_id);
         if let Some(callbacks) = self.frame_callbacks.remove(&surface_id) {
             let _count = callbacks.len();
             let timestamp = timestamp.unwrap_or_else(Self::get_timestamp_ms);
@@ -1471,23 +1620,46 @@ impl CompositorState {
         }
     }
     
-    /// Flush all pending frame callbacks for all surfaces.
+    /// Flush pending frame callbacks for all surfaces.
+    ///
+    /// Callbacks of hidden surfaces are held back or slowed down according
+    /// to `frame_throttle`.
     pub fn flush_all_frame_callbacks(&mut self) {
-        let timestamp = Self::get_timestamp_ms();
-        let mut total = 0;
-        
-        for (_surface_id, callbacks) in self.frame_callbacks.drain() {
-            total += callbacks.len();
-            for callback in callbacks {
-                callback.done(timestamp);
-            }
+        if self.frame_callbacks.is_empty() {
+            return;
         }
+        let timestamp = Self::get_timestamp_ms();
+        let visibility = self.compute_surface_visibility();
+        let surface_ids: Vec<u32> = self.frame_callbacks.keys().copied().collect();
+        let total = self.flush_throttled_frame_callbacks(&surface_ids, &visibility, timestamp);
         
         if total > 0 {
             tracing::trace!("Flushed {} total frame callbacks", total);
         }
     }
     
+    /// Flush the callbacks of `surface_ids` that the throttle lets through.
+    /// Returns the number of callbacks fired.
+    pub(crate) fn flush_throttled_frame_callbacks(
+        &mut self,
+        surface_ids: &[u32],
+        visibility: &HashMap<u32, SurfaceVisibility>,
+        timestamp: u32,
+    ) -> usize {
+        let now = Instant::now();
+        let mut total = 0;
+        for &surface_id in surface_ids {
+            let vis = visibility.get(&surface_id).copied().unwrap_or(SurfaceVisibility::Visible);
+            let pending = self.frame_callbacks.get(&surface_id).map_or(0, |c| c.len());
+            if pending == 0 || !self.frame_throttle.should_fire(surface_id, vis, now) {
+                continue;
+            }
+            total += pending;
+            self.flush_frame_callbacks(surface_id, Some(timestamp));
+        }
+        total
+    }
+    
     /// Check if there are pending frame callbacks
     pub fn has_pending_frame_callbacks(&self) -> bool {
         self.frame_callbacks.values().any(|v| !v.is_empty())
@@ -1505,6 +1677,7 @@ impl CompositorState {
     /// Update primary output configuration
     pub fn update_primary_output(&mut self, width: u32, height: u32, scale: f32) {
         let index = self.primary_output;
+        self.scene_dirty = true;
         if let Some(output) = self.outputs.get_mut(index) {
             output.update(width, height, scale);
             crate::wlog!(crate::util::logging::STATE, "Updated primary output: {}x{} @ {}x", width, height, scale);
@@ -1518,6 +1691,7 @@ impl CompositorState {
     
     /// Set output size
     pub fn set_output_size(&mut self, width: u32, height: u32, scale: f32) {
+        self.scene_dirty = true;
         let output = self.primary_output_mut();
         
         let safe_scale = if scale < 1.0 { 1.0 } else { scale };
@@ -1626,6 +1800,80 @@ mod tests {
         assert_eq!(focus.focus_history, vec![2, 1]);
     }
 
+    #[test]
+    fn test_positioner_place() {
+        use crate::util::geometry::Rect;
+
+        // Anchor and gravity values
+        const LEFT: u32 = 3;
+        const RIGHT: u32 = 4;
+        const BOTTOM_LEFT: u32 = 6;
+        const BOTTOM_RIGHT: u32 = 8;
+        const SLIDE: u32 = SLIDE_X | SLIDE_Y;
+        const FLIP: u32 = FLIP_X | FLIP_Y;
+        const RESIZE: u32 = RESIZE_X | RESIZE_Y;
+
+        let bounds = Rect::new(0, 0, 1000, 800);
+        // (size, anchor rect, anchor, gravity, adjustment, offset, expected)
+        #[allow(clippy::type_complexity)]
+        let cases: &[((i32, i32), (i32, i32, i32, i32), u32, u32, u32, (i32, i32), (i32, i32, i32, i32))] = &[
+            // Unconstrained: a menu below its button, left edges aligned
+            ((200, 300), (100, 100, 80, 20), BOTTOM_LEFT, BOTTOM_RIGHT, FLIP | SLIDE, (0, 0), (100, 120, 200, 300)),
+            // Centered anchor and gravity
+            ((100, 50), (400, 400, 200, 100), 0, 0, 0, (0, 0), (450, 425, 100, 50)),
+            // Offset applies before constraining
+            ((100, 50), (0, 0, 10, 10), BOTTOM_LEFT, BOTTOM_RIGHT, 0, (5, -3), (5, 7, 100, 50)),
+            // No adjustment allowed: left sticking out
+            ((200, 300), (900, 700, 80, 20), BOTTOM_LEFT, BOTTOM_RIGHT, 0, (0, 0), (900, 720, 200, 300)),
+            // Flip up above the button near the bottom edge
+            ((200, 300), (100, 700, 80, 20), BOTTOM_LEFT, BOTTOM_RIGHT, FLIP, (0, 0), (100, 400, 200, 300)),
+            // Submenu flips to the left of its item near the right edge
+            ((200, 100), (880, 100, 100, 20), RIGHT, RIGHT, FLIP, (0, 0), (680, 60, 200, 100)),
+            // A flip that doesn't fit either is undone, then slide
+            ((200, 500), (100, 400, 80, 20), BOTTOM_LEFT, BOTTOM_RIGHT, FLIP | SLIDE, (0, 0), (100, 300, 200, 500)),
+            // Slide in from the right and from the left
+            ((200, 100), (900, 100, 50, 20), BOTTOM_LEFT, BOTTOM_RIGHT, SLIDE, (0, 0), (800, 120, 200, 100)),
+            ((200, 100), (-50, 100, 50, 20), BOTTOM_LEFT, BOTTOM_LEFT, SLIDE, (0, 0), (0, 120, 200, 100)),
+            // Too tall to slide in: the top stays visible
+            ((200, 1000), (100, 100, 80, 20), BOTTOM_LEFT, BOTTOM_RIGHT, SLIDE, (0, 0), (100, 0, 200, 1000)),
+            // A long dropdown is cut down to the space below its button
+            ((200, 1000), (100, 100, 80, 20), BOTTOM_LEFT, BOTTOM_RIGHT, RESIZE, (0, 0), (100, 120, 200, 680)),
+            // Slide first, then resize what still doesn't fit
+            ((200, 1000), (100, 100, 80, 20), BOTTOM_LEFT, BOTTOM_RIGHT, SLIDE | RESIZE, (0, 0), (100, 0, 200, 800)),
+            // Flip wins over slide and resize when it fits
+            ((200, 300), (100, 700, 80, 20), BOTTOM_LEFT, BOTTOM_RIGHT, FLIP | SLIDE | RESIZE, (0, 0), (100, 400, 200, 300)),
+            // Axes are independent: flip on x only, resize on y only
+            ((300, 900), (900, 50, 50, 20), RIGHT, BOTTOM_RIGHT, FLIP_X | RESIZE_Y, (0, 0), (600, 60, 300, 740)),
+            // Entirely outside with nothing left after resizing: unchanged
+            ((100, 100), (2000, 100, 10, 10), LEFT, LEFT, RESIZE, (0, 0), (1900, 55, 100, 100)),
+        ];
+        for (i, &(size, anchor_rect, anchor, gravity, adjustment, offset, expected)) in cases.iter().enumerate() {
+            let positioner = XdgPositionerData {
+                width: size.0,
+                height: size.1,
+                anchor_rect,
+                anchor,
+                gravity,
+                constraint_adjustment: adjustment,
+                offset,
+                ..Default::default()
+            };
+            assert_eq!(positioner.place(bounds), expected, "case {}", i);
+        }
+
+        // Without bounds nothing is adjusted
+        let positioner = XdgPositionerData {
+            width: 200,
+            height: 300,
+            anchor_rect: (900, 700, 80, 20),
+            anchor: BOTTOM_LEFT,
+            gravity: BOTTOM_RIGHT,
+            constraint_adjustment: FLIP | SLIDE | RESIZE,
+            ..Default::default()
+        };
+        assert_eq!(positioner.place(Rect::zero()), (900, 720, 200, 300));
+    }
+
     #[test]
     fn test_decoration_mode_for_new_window() {
         use crate::core::window::DecorationMode;
@@ -1660,6 +1908,47 @@ mod tests {
             DecorationMode::ServerSide
         );
     }
+
+    /// Keyboard grab that takes every key, noting (key, pressed)
+    #[derive(Debug, Default)]
+    struct KeyGrab(Arc<std::sync::Mutex<Vec<(u32, bool)>>>);
+
+    impl crate::core::input::grab::InputGrab for KeyGrab {
+        fn kind(&self) -> crate::core::input::grab::GrabKind {
+            crate::core::input::grab::GrabKind::InputMethod
+        }
+
+        fn key(&mut self, _state: &mut CompositorState, key: &crate::core::input::grab::GrabKey) -> crate::core::input::grab::GrabStatus {
+            self.0.lock().unwrap().push((key.key, key.state == wl_keyboard::KeyState::Pressed));
+            crate::core::input::grab::GrabStatus::Consumed
+        }
+
+        fn as_any(&self) -> &dyn std::any::Any {
+            self
+        }
+    }
+
+    #[test]
+    fn test_key_repeat_under_keyboard_grab() {
+        let mut state = CompositorState::new(None);
+        state.seat.keyboard.compositor_repeat = true;
+        let grab = KeyGrab::default();
+        let keys = grab.0.clone();
+
+        // A key pressed before the grab and released into it stops repeating
+        state.inject_key(30, wl_keyboard::KeyState::Pressed, 1000);
+        assert!(state.seat.keyboard.next_repeat_at().is_some());
+        state.start_grab(Box::new(grab)).unwrap();
+        state.inject_key(30, wl_keyboard::KeyState::Released, 1100);
+        assert_eq!(*keys.lock().unwrap(), vec![(30, false)]);
+        assert!(state.seat.keyboard.next_repeat_at().is_none());
+
+        // Repeats of a key the grab took go to the grab too
+        state.inject_key(31, wl_keyboard::KeyState::Pressed, 1200);
+        let due = state.seat.keyboard.next_repeat_at().unwrap();
+        state.dispatch_key_repeat(due);
+        assert_eq!(*keys.lock().unwrap(), vec![(30, false), (31, true), (31, true)]);
+    }
 }
 
 impl CompositorState {
@@ -1667,37 +1956,18 @@ impl CompositorState {
     /// Only sends `presented` for feedbacks whose surface has been committed;
     /// uncommitted feedbacks are retained for the next cycle.
     pub fn fire_presentation_feedback(&mut self) {
-        let now = SystemTime::now();
-        let duration = now.duration_since(UNIX_EPOCH).unwrap();
-
-        let tv_sec_hi = (duration.as_secs() >> 32) as u32;
-        let tv_sec_lo = (duration.as_secs() & 0xFFFFFFFF) as u32;
-        let tv_nsec = duration.subsec_nanos();
-
-        let refresh_nsec = 16_666_666; // 60Hz default
-
-        use wayland_protocols::wp::presentation_time::server::wp_presentation_feedback;
-        let flags = wp_presentation_feedback::Kind::Vsync;
-
-        let mut remaining = Vec::new();
-
-        for feedback in self.ext.presentation.feedbacks.drain(..) {
-            if feedback.committed {
-                feedback.callback.presented(
-                    tv_sec_hi,
-                    tv_sec_lo,
-                    tv_nsec,
-                    refresh_nsec,
-                    0, // seq_hi
-                    0, // seq_lo
-                    flags,
-                );
-            } else {
-                remaining.push(feedback);
-            }
+        // Platforms that report real presentations drive feedback from
+        // their vblank; this is only the fallback for headless setups.
+        if self.ext.presentation.platform_driven {
+            return;
         }
-
-        self.ext.presentation.feedbacks = remaining;
+        let refresh_ns = self.output_refresh_ns();
+        self.present_feedback(
+            None,
+            crate::core::time::monotonic_ns(),
+            refresh_ns,
+            wp_presentation_feedback::Kind::empty(),
+        );
     }
 }
 
@@ -1790,6 +2060,8 @@ impl ProtocolState for SeatState {
             }
         });
 
+        self.popup_grab_stack.retain(|(cid, _)| cid != &client);
+
         // Focus surface cleanup is handled at the CompositorState level
         // in client_disconnected(), which has access to the surface map
         // to resolve surface ownership.
@@ -1803,6 +2075,7 @@ impl ProtocolState for CompositorState {
         self.xdg.client_disconnected(client.clone());
         self.data.client_disconnected(client.clone());
         self.seat.client_disconnected(client);
+        self.scene_dirty = true;
     }
 }
 
@@ -5,21 +5,135 @@
 //! as well as repositioning layer surfaces and computing usable output area.
 
 use super::*;
+use crate::core::window::decoration::{DecorationLayout, DecorationPiece};
 
 impl CompositorState {
-    /// Report presentation feedback
+    /// Report presentation feedback for a frame the platform displayed at
+    /// `timestamp` (refresh in mHz, 0 = use the output's rate)
     pub fn report_presentation_feedback(&mut self, timestamp: std::time::Instant, refresh_mhz: u32) {
-        let seq = (timestamp.elapsed().as_millis() / 16) as u64; 
-        
+        self.ext.presentation.platform_driven = true;
+
         let refresh_ns = if refresh_mhz > 0 {
-            1_000_000_000_000 / refresh_mhz as u64
+            (1_000_000_000_000 / refresh_mhz as u64) as u32
         } else {
-            16_666_666
+            self.output_refresh_ns()
         };
-        
-        let ts_ns = crate::core::Compositor::timestamp_ms() as u64 * 1_000_000;
-        
-        self.ext.presentation.send_presented_events(ts_ns, refresh_ns, seq);
+        let ts_ns = crate::core::time::instant_to_monotonic_ns(timestamp);
+
+        self.present_feedback(None, ts_ns, refresh_ns, wp_presentation_feedback::Kind::Vsync);
+        self.lift_fifo_barriers();
+    }
+
+    /// Work out which surfaces can be seen.
+    ///
+    /// Surfaces of minimized windows and of windows on inactive workspaces
+    /// (with their subsurfaces and popups) are hidden outright; the rest of
+    /// the scene is checked against the outputs and against the opaque
+    /// regions of the surfaces stacked above. Surfaces not in the scene at
+    /// all (cursors, drag icons) are left out and count as visible.
+    pub fn compute_surface_visibility(&mut self) -> HashMap<u32, SurfaceVisibility> {
+        self.build_scene();
+        let flattened = self.scene.flatten_unculled();
+        self.surface_visibility(&flattened)
+    }
+
+    /// [`compute_surface_visibility`](Self::compute_surface_visibility)
+    /// for an unculled flattening of the current scene
+    pub(crate) fn surface_visibility(
+        &self,
+        flattened: &[crate::core::render::scene::FlattenedSurface],
+    ) -> HashMap<u32, SurfaceVisibility> {
+        use crate::core::render::visibility::{classify, PlacedSurface};
+        use crate::util::geometry::Rect;
+
+        let mut hidden: HashMap<u32, SurfaceVisibility> = HashMap::new();
+        for window in self.windows.values() {
+            let window = window.read().unwrap();
+            let reason = if window.minimized {
+                SurfaceVisibility::Minimized
+            } else if window.workspace.map_or(false, |ws| !self.ext.workspace.is_active(ws)) {
+                SurfaceVisibility::InactiveWorkspace
+            } else {
+                continue;
+            };
+            let mut roots = vec![window.surface_id];
+            roots.extend(
+                self.xdg.popups.values()
+                    .filter(|p| p.parent_id == Some(window.id))
+                    .map(|p| p.surface_id),
+            );
+            while let Some(surface_id) = roots.pop() {
+                hidden.insert(surface_id, reason);
+                if let Some(children) = self.subsurface_children.get(&surface_id) {
+                    roots.extend(children.iter().copied());
+                }
+            }
+        }
+
+        let placed: Vec<PlacedSurface> = flattened.iter()
+            .filter(|s| !hidden.contains_key(&s.surface_id))
+            .map(|s| PlacedSurface { surface_id: s.surface_id, rect: s.rect(), opaque: s.opaque_region.clone() })
+            .collect();
+        let outputs: Vec<Rect> = self.outputs.iter()
+            .map(|o| Rect::new(o.x, o.y, o.width, o.height))
+            .collect();
+
+        let mut visibility = classify(&placed, &outputs);
+        visibility.extend(hidden);
+        visibility
+    }
+
+    /// Compute this frame's scene damage from the flattened scene.
+    ///
+    /// Consumes the damage clients committed since the last frame. Culled
+    /// surfaces lose theirs too: when they are uncovered the exposed area
+    /// is damaged as a whole.
+    pub fn update_scene_damage(&mut self, flattened: &[crate::core::render::scene::FlattenedSurface]) {
+        use crate::core::render::transform::SourceRect;
+        use crate::core::surface::damage::DamageRegion;
+        use crate::util::geometry::Rect;
+        use crate::util::region::Region;
+
+        let mut posted: HashMap<u32, Region> = HashMap::new();
+        for surface in self.surfaces.values() {
+            let mut surface = surface.write().unwrap();
+            if surface.current.damage.is_empty() && surface.current.buffer_damage.is_empty() {
+                continue;
+            }
+            let mut damage = std::mem::take(&mut surface.current.damage);
+            let buffer_damage = std::mem::take(&mut surface.current.buffer_damage);
+            let placed = match flattened.iter().find(|s| s.surface_id == surface.id) {
+                Some(placed) => placed,
+                None => continue,
+            };
+            // Buffer damage goes back through transform, scale and viewport
+            let (width, height) = (surface.current.width, surface.current.height);
+            damage.extend(buffer_damage.iter()
+                .map(|d| {
+                    let r = placed.buffer.buffer_rect_to_surface(
+                        SourceRect::new(d.x as f64, d.y as f64, d.width as f64, d.height as f64),
+                    );
+                    let (x, y) = (r.x.floor() as i32, r.y.floor() as i32);
+                    DamageRegion::new(x, y, (r.x + r.width).ceil() as i32 - x, (r.y + r.height).ceil() as i32 - y)
+                })
+                .map(|d| if width > 0 && height > 0 { d.clamp(width, height) } else { d })
+                .filter(DamageRegion::is_valid));
+            // The node origin sits at the window geometry offset within the surface
+            let gx = (placed.content_rect.x * surface.current.width as f32) as i32;
+            let gy = (placed.content_rect.y * surface.current.height as f32) as i32;
+            let region = Region::from_rects(damage.iter().map(|d| Rect::new(
+                placed.x + ((d.x - gx) as f32 * placed.scale) as i32,
+                placed.y + ((d.y - gy) as f32 * placed.scale) as i32,
+                (d.width.max(0) as f32 * placed.scale).ceil() as u32,
+                (d.height.max(0) as f32 * placed.scale).ceil() as u32,
+            )));
+            posted.insert(surface.id, region);
+        }
+
+        let outputs: Vec<(u32, Rect)> = self.outputs.iter()
+            .map(|o| (o.id, Rect::new(o.x, o.y, o.width, o.height)))
+            .collect();
+        self.scene_damage.update(flattened, &posted, &outputs);
     }
 
     /// Send a configure event to an xdg_toplevel and its associated xdg_surface.
@@ -56,6 +170,10 @@ impl CompositorState {
                 if toplevel_data.pending_fullscreen {
                     states.extend_from_slice(&(wayland_protocols::xdg::shell::server::xdg_toplevel::State::Fullscreen as u32).to_ne_bytes());
                 }
+
+                if toplevel_data.pending_resizing {
+                    states.extend_from_slice(&(wayland_protocols::xdg::shell::server::xdg_toplevel::State::Resizing as u32).to_ne_bytes());
+                }
                 
                 crate::wlog!(crate::util::logging::COMPOSITOR, "Configuring xdg_toplevel {} with states: {:?}, size={}x{}", toplevel_id, states, final_w, final_h);
                 to_send = Some((resource.clone(), toplevel_data.xdg_surface_id, states, final_w, final_h));
@@ -84,10 +202,14 @@ impl CompositorState {
         serial
     }
 
-    /// Dismiss the active popup grab and all its child popups
+    /// Dismiss the active popup grab and all its child popups, topmost
+    /// first. Keyboard focus goes back to the window.
     pub fn dismiss_popup_grab(&mut self) {
+        self.end_grab(crate::core::input::grab::GrabKind::Popup);
+        let mut had_focus = false;
         while let Some((cid, pid)) = self.seat.popup_grab_stack.pop() {
             if let Some(data) = self.xdg.popups.get(&(cid.clone(), pid)) {
+                had_focus |= self.seat.keyboard.focus == Some(data.surface_id);
                 let resource = data.resource.clone();
                 if let Some(res) = resource {
                     tracing::debug!("Dismissing popup {} for client {:?}", pid, cid);
@@ -95,6 +217,22 @@ impl CompositorState {
                 }
             }
         }
+        if had_focus {
+            self.restore_window_keyboard_focus();
+        }
+    }
+
+    /// Whether `surface_id` is part of the popup grab chain: one of the
+    /// grabbing popups or the surface the chain hangs off, with their
+    /// subsurfaces
+    pub fn on_popup_grab_chain(&self, surface_id: u32) -> bool {
+        let window_id = self.resolve_window_id_for_surface(surface_id);
+        self.seat.popup_grab_stack.iter()
+            .filter_map(|key| self.xdg.popups.get(key))
+            .any(|popup| {
+                window_id.map_or(false, |wid| popup.window_id == wid || popup.parent_id == Some(wid))
+                    || popup.parent_layer == Some(surface_id)
+            })
     }
 
     /// Get the geometry of an output (x, y, width, height)
@@ -151,6 +289,15 @@ impl CompositorState {
         id
     }
 
+    /// Rebuild the scene if something it's built from changed since the
+    /// last build. For readers that don't need the scene any fresher than
+    /// the last commit, request or frame.
+    pub fn refresh_scene(&mut self) {
+        if self.scene_dirty {
+            self.build_scene();
+        }
+    }
+
     /// Rebuild the scene graph from windows and layers
     pub fn build_scene(&mut self) {
         let mut new_scene = Scene::new();
@@ -163,6 +310,14 @@ impl CompositorState {
         
         new_scene.add_node(root);
         new_scene.set_root(root_id);
+
+        // Nothing but the lock screen is shown while the session is locked
+        #[cfg(feature = "desktop-protocols")]
+        if self.ext.session_lock.is_locked() {
+            self.add_lock_surfaces_to_scene(&mut new_scene, root_id);
+            self.scene = new_scene;
+            return;
+        }
         
         self.add_layer_to_scene(&mut new_scene, root_id, 0);
         self.add_layer_to_scene(&mut new_scene, root_id, 1);
@@ -174,6 +329,10 @@ impl CompositorState {
                 .collect();
 
         for window_id in self.windows.keys().copied().collect::<Vec<_>>() {
+            let decoration = self.update_window_decoration(window_id).map(|d| {
+                let pieces = DecorationPiece::ALL.map(|piece| (piece, d.piece_buffer_size(piece)));
+                (d.layout(), pieces)
+            });
             if let Some(window) = self.get_window(window_id) {
                 let window = window.read().unwrap();
                 let node_id = self.next_node_id();
@@ -205,21 +364,32 @@ impl CompositorState {
                 let alpha = self.ext.alpha_modifier.get_alpha_f64(window.surface_id) as f32;
                 node.opacity = alpha;
                 
-                new_scene.add_node(node);
-                new_scene.add_child(root_id, node_id);
-                
                 let geom_offset = geom_by_surface.get(&window.surface_id)
                     .map(|&(gx, gy, _, _)| (gx, gy))
                     .unwrap_or((0, 0));
+                node.opaque_region = self.node_opaque_region(window.surface_id, geom_offset);
+                node.buffer = self.buffer_mapping(window.surface_id);
+                
+                new_scene.add_node(node);
+                new_scene.add_child(root_id, node_id);
+
+                if let Some((layout, pieces)) = decoration {
+                    self.add_decoration_to_scene(&mut new_scene, node_id, window_id, &layout, &pieces);
+                }
+                
                 self.add_subsurfaces_to_scene(&mut new_scene, node_id, window.surface_id, geom_offset);
             }
         }
         
-        let popup_data_list: Vec<_> = self.xdg.popups.iter()
-            .map(|((cid, _), p)| (cid.clone(), p.surface_id, p.geometry, p.parent_id))
+        // Parents go before their child popups, which are placed relative
+        // to them
+        let mut popup_data_list: Vec<_> = self.xdg.popups.values()
+            .filter(|p| p.parent_layer.is_none())
+            .map(|p| (self.popup_depth(p.window_id), p.surface_id, p.geometry, p.parent_id))
             .collect();
+        popup_data_list.sort_by_key(|&(depth, ..)| depth);
 
-        for (cid, popup_surface_id, geometry, parent_window_id) in popup_data_list {
+        for (_, popup_surface_id, geometry, parent_window_id) in popup_data_list {
             let node_id = self.next_node_id();
             let mut node = SceneNode::new(node_id)
                 .with_surface(popup_surface_id);
@@ -229,13 +399,17 @@ impl CompositorState {
             
             let alpha = self.ext.alpha_modifier.get_alpha_f64(popup_surface_id) as f32;
             node.opacity = alpha;
+            node.opaque_region = self.node_opaque_region(popup_surface_id, (0, 0));
+            node.buffer = self.buffer_mapping(popup_surface_id);
             
             new_scene.add_node(node);
             
             let mut parent_node_id = root_id;
             if let Some(pwid) = parent_window_id {
-                if let Some(parent_window) = self.get_window(pwid) {
-                    let parent_surf_id = parent_window.read().unwrap().surface_id;
+                let parent_surface = self.get_window(pwid)
+                    .map(|window| window.read().unwrap().surface_id)
+                    .or_else(|| self.xdg.popups.values().find(|p| p.window_id == pwid).map(|p| p.surface_id));
+                if let Some(parent_surf_id) = parent_surface {
                     for n in new_scene.nodes.values() {
                         if n.surface_id == Some(parent_surf_id) {
                             parent_node_id = n.id;
@@ -252,12 +426,102 @@ impl CompositorState {
         
         self.add_layer_to_scene(&mut new_scene, root_id, 2);
         self.add_layer_to_scene(&mut new_scene, root_id, 3);
+        self.add_layer_popups_to_scene(&mut new_scene, root_id);
         
         self.scene = new_scene;
+        self.add_input_popups_to_scene(root_id);
+        self.scene_dirty = false;
+        self.output_surfaces = None;
+    }
+
+    /// How many popups deep the popup with window id `window_id` is nested
+    fn popup_depth(&self, window_id: u32) -> usize {
+        let mut depth = 0;
+        let mut current = window_id;
+        while let Some(parent) = self.xdg.popups.values().find(|p| p.window_id == current).and_then(|p| p.parent_id) {
+            depth += 1;
+            current = parent;
+            if depth > self.xdg.popups.len() {
+                break;
+            }
+        }
+        depth
+    }
+
+    /// Colour the renderer fills the outputs with while the session is
+    /// locked, beneath the lock surfaces
+    pub fn lock_fill_color(&self) -> Option<u32> {
+        #[cfg(feature = "desktop-protocols")]
+        if self.ext.session_lock.is_locked() {
+            return Some(crate::core::wayland::ext::session_lock::LOCK_FALLBACK_COLOR);
+        }
+        None
+    }
+
+    /// Place each lock surface over its output.
+    #[cfg(feature = "desktop-protocols")]
+    fn add_lock_surfaces_to_scene(&mut self, scene: &mut Scene, root_id: u32) {
+        let placements: Vec<_> = self.ext.session_lock.lock_surfaces.values()
+            .filter(|data| data.mapped)
+            .filter_map(|data| {
+                let output = self.outputs.iter().find(|o| o.id == data.output_id)?;
+                Some((data.surface_id, output.x, output.y, output.width, output.height))
+            })
+            .collect();
+        for (surface_id, x, y, width, height) in placements {
+            let node_id = self.next_node_id();
+            let mut node = SceneNode::new(node_id).with_surface(surface_id);
+            node.set_position(x, y);
+            node.set_size(width, height);
+            node.opaque_region = self.node_opaque_region(surface_id, (0, 0));
+            node.buffer = self.buffer_mapping(surface_id);
+
+            scene.add_node(node);
+            scene.add_child(root_id, node_id);
+            self.add_subsurfaces_to_scene(scene, node_id, surface_id, (0, 0));
+        }
+    }
+
+    /// Put input method popups (candidate windows) on top of the scene,
+    /// next to the active text input's cursor. Placement needs the rest of
+    /// the scene, so this runs once it is built.
+    fn add_input_popups_to_scene(&mut self, root_id: u32) {
+        for surface_id in self.ext.text_input.popup_surfaces.clone() {
+            let size = match self.get_surface(surface_id) {
+                Some(surface) => {
+                    let surface = surface.read().unwrap();
+                    (surface.current.width.max(0) as u32, surface.current.height.max(0) as u32)
+                }
+                None => continue,
+            };
+            // Popups are only shown while a text input is active
+            let (x, y) = match self.input_popup_position(size) {
+                Some(position) => position,
+                None => continue,
+            };
+            let node_id = self.next_node_id();
+            let mut node = SceneNode::new(node_id).with_surface(surface_id);
+            node.set_position(x, y);
+            node.set_size(size.0, size.1);
+            node.opaque_region = self.node_opaque_region(surface_id, (0, 0));
+            node.buffer = self.buffer_mapping(surface_id);
+
+            let mut scene = std::mem::take(&mut self.scene);
+            scene.add_node(node);
+            scene.add_child(root_id, node_id);
+            self.add_subsurfaces_to_scene(&mut scene, node_id, surface_id, (0, 0));
+            self.scene = scene;
+        }
     }
 
-    /// Reposition all layer surfaces and update output usable areas.
+    /// Arrange all layer surfaces and update output usable areas.
+    ///
+    /// Surfaces are arranged per output in creation order, and a configure
+    /// is sent to every surface whose arranged size changed.
     pub fn reposition_layer_surfaces(&mut self) {
+        use crate::core::wayland::wlr::layer_shell::{arrange_layer_surfaces, LayerArrangement};
+
+        let mut resized = Vec::new();
         let output_count = self.outputs.len();
         for i in 0..output_count {
             let (output_id, ox, oy, ow, oh) = {
@@ -284,86 +548,39 @@ impl CompositorState {
                 usable.width = (usable.width as i32 - sa_right).max(0) as u32;
             }
             
-            let ls_refs: Vec<_> = self.wlr.layer_surfaces.values()
-                .filter(|ls| ls.read().unwrap().output_id == output_id)
+            // Surfaces take part once they made their initial commit
+            let mut ls_refs: Vec<_> = self.wlr.layer_surfaces.values()
+                .filter(|ls| {
+                    let ls = ls.read().unwrap();
+                    ls.output_id == output_id && ls.committed_state.is_some()
+                })
                 .cloned()
                 .collect();
-                
-            for layer in 0..4 {
-                for ls_lock in &ls_refs {
-                    let ls_read = ls_lock.read().unwrap();
-                    if ls_read.layer != layer || ls_read.exclusive_zone <= 0 {
-                        continue;
-                    }
-                    
-                    let zone = ls_read.exclusive_zone;
-                    let anchor = ls_read.anchor;
-                    
-                    // Anchor bits: 1=top, 2=bottom, 4=left, 8=right
-                    if (anchor & 1) != 0 && (anchor & 4) != 0 && (anchor & 8) != 0 {
-                        usable.y += zone;
-                        usable.height = (usable.height as i32 - zone).max(0) as u32;
-                    } else if (anchor & 2) != 0 && (anchor & 4) != 0 && (anchor & 8) != 0 {
-                        usable.height = (usable.height as i32 - zone).max(0) as u32;
-                    } else if (anchor & 4) != 0 && (anchor & 1) != 0 && (anchor & 2) != 0 {
-                        usable.x += zone;
-                        usable.width = (usable.width as i32 - zone).max(0) as u32;
-                    } else if (anchor & 8) != 0 && (anchor & 1) != 0 && (anchor & 2) != 0 {
-                        usable.width = (usable.width as i32 - zone).max(0) as u32;
-                    } else if anchor == 1 {
-                         usable.y += zone;
-                         usable.height = (usable.height as i32 - zone).max(0) as u32;
-                    } else if anchor == 2 {
-                         usable.height = (usable.height as i32 - zone).max(0) as u32;
-                    } else if anchor == 4 {
-                         usable.x += zone;
-                         usable.width = (usable.width as i32 - zone).max(0) as u32;
-                    } else if anchor == 8 {
-                         usable.width = (usable.width as i32 - zone).max(0) as u32;
-                    }
-                }
-            }
+            ls_refs.sort_by_key(|ls| ls.read().unwrap().sequence);
+
+            let arrangement: Vec<LayerArrangement> = ls_refs.iter()
+                .filter_map(|ls| ls.read().unwrap().committed_state)
+                .collect();
+            let output_rect = crate::util::geometry::Rect::new(ox, oy, ow as u32, oh as u32);
+            let (placed, usable) = arrange_layer_surfaces(output_rect, usable, &arrangement);
             
             self.outputs[i].usable_area = usable;
             
-            for ls_lock in &ls_refs {
+            for (ls_lock, rect) in ls_refs.iter().zip(placed) {
                 let mut ls = ls_lock.write().unwrap();
-                let anchor = ls.anchor;
-                let margin = ls.margin;
-                let mut w = ls.width;
-                let mut h = ls.height;
-                
-                let x;
-                let y;
-                
-                if (anchor & 4) != 0 && (anchor & 8) != 0 {
-                    w = (ow - margin.1 - margin.3).max(0) as u32;
-                    x = ox + margin.3;
-                } else if (anchor & 8) != 0 {
-                    x = ox + ow - w as i32 - margin.1;
-                } else if (anchor & 4) != 0 {
-                    x = ox + margin.3;
-                } else {
-                    x = ox + (ow - w as i32) / 2;
-                }
-                
-                if (anchor & 1) != 0 && (anchor & 2) != 0 {
-                    h = (oh - margin.0 - margin.2).max(0) as u32;
-                    y = oy + margin.0;
-                } else if (anchor & 2) != 0 {
-                    y = oy + oh - h as i32 - margin.2;
-                } else if (anchor & 1) != 0 {
-                    y = oy + margin.0;
-                } else {
-                    y = oy + (oh - h as i32) / 2;
+                ls.x = rect.x;
+                ls.y = rect.y;
+                ls.width = rect.width;
+                ls.height = rect.height;
+                if ls.configured_size != Some((rect.width, rect.height)) {
+                    resized.push((ls.surface_id, rect.width, rect.height));
                 }
-                
-                ls.x = x;
-                ls.y = y;
-                ls.width = w;
-                ls.height = h;
             }
         }
+
+        for (surface_id, width, height) in resized {
+            self.configure_layer_surface(surface_id, width, height);
+        }
     }
 
     fn add_layer_to_scene(&mut self, scene: &mut Scene, root_id: u32, layer: u32) {
@@ -384,6 +601,8 @@ impl CompositorState {
             
             node.set_position(x, y);
             node.set_size(width, height);
+            node.opaque_region = self.node_opaque_region(surface_id, (0, 0));
+            node.buffer = self.buffer_mapping(surface_id);
             
             scene.add_node(node);
             scene.add_child(root_id, node_id);
@@ -392,6 +611,103 @@ impl CompositorState {
         }
     }
 
+    /// Place popups of layer surfaces above every layer, so that menus of
+    /// background and bottom panels are not covered by windows.
+    fn add_layer_popups_to_scene(&mut self, scene: &mut Scene, root_id: u32) {
+        let placements: Vec<_> = self.xdg.popups.iter()
+            .filter_map(|((cid, _), p)| {
+                let layer = self.get_layer_surface(cid.clone(), p.parent_layer?)?;
+                let layer = layer.read().unwrap();
+                let (x, y, width, height) = p.geometry;
+                Some((p.surface_id, layer.x + x, layer.y + y, width, height))
+            })
+            .collect();
+        for (surface_id, x, y, width, height) in placements {
+            let node_id = self.next_node_id();
+            let mut node = SceneNode::new(node_id).with_surface(surface_id);
+            node.set_position(x, y);
+            node.set_size(width.max(0) as u32, height.max(0) as u32);
+            node.opacity = self.ext.alpha_modifier.get_alpha_f64(surface_id) as f32;
+            node.opaque_region = self.node_opaque_region(surface_id, (0, 0));
+            node.buffer = self.buffer_mapping(surface_id);
+
+            scene.add_node(node);
+            scene.add_child(root_id, node_id);
+            self.add_subsurfaces_to_scene(scene, node_id, surface_id, (0, 0));
+        }
+    }
+
+    /// Opaque region of a surface in node-local coordinates.
+    ///
+    /// `geometry_offset` is the window geometry origin within the surface,
+    /// which sits at the node origin when the buffer is cropped to it.
+    fn node_opaque_region(&self, surface_id: u32, geometry_offset: (i32, i32)) -> crate::util::region::Region {
+        let surface = match self.get_surface(surface_id) {
+            Some(surface) => surface,
+            None => return Default::default(),
+        };
+        let surface = surface.read().unwrap();
+        crate::util::region::Region::from_rects(
+            surface.current.opaque_region.iter().flatten()
+                .map(|r| crate::util::geometry::Rect::new(
+                    r.x - geometry_offset.0,
+                    r.y - geometry_offset.1,
+                    r.width.max(0) as u32,
+                    r.height.max(0) as u32,
+                )),
+        )
+    }
+
+    /// Add the pieces of a core-drawn decoration as children of a window
+    /// node. Each piece is a pseudo-surface whose pixels the frontend
+    /// fetches like any other buffer; borders stretch a single pixel.
+    fn add_decoration_to_scene(
+        &mut self,
+        scene: &mut Scene,
+        window_node_id: u32,
+        window_id: u32,
+        layout: &DecorationLayout,
+        pieces: &[(DecorationPiece, (u32, u32))],
+    ) {
+        for &(piece, (buffer_w, buffer_h)) in pieces {
+            let rect = layout.piece_rect(piece);
+            let Some(surface_id) = piece.surface_id(window_id) else { continue };
+            if rect.is_empty() {
+                continue;
+            }
+            let node_id = self.next_node_id();
+            let mut node = SceneNode::new(node_id).with_surface(surface_id);
+            node.set_position(rect.x, rect.y);
+            node.set_size(rect.width, rect.height);
+            node.opaque_region = crate::util::region::Region::from_rects(
+                [crate::util::geometry::Rect::new(0, 0, rect.width, rect.height)],
+            );
+            node.buffer = crate::core::render::transform::BufferMapping {
+                buffer_size: (buffer_w as i32, buffer_h as i32),
+                surface_size: (buffer_w as i32, buffer_h as i32),
+                ..Default::default()
+            };
+            scene.add_node(node);
+            scene.add_child(window_node_id, node_id);
+        }
+    }
+
+    /// Buffer transform, scale and viewport of a surface's committed state
+    fn buffer_mapping(&self, surface_id: u32) -> crate::core::render::transform::BufferMapping {
+        let surface = match self.get_surface(surface_id) {
+            Some(surface) => surface,
+            None => return Default::default(),
+        };
+        let surface = surface.read().unwrap();
+        crate::core::render::transform::BufferMapping {
+            transform: surface.current.transform,
+            scale: surface.current.scale,
+            buffer_size: surface.current.buffer.dimensions().unwrap_or((0, 0)),
+            source: surface.current.viewport_source,
+            surface_size: (surface.current.width, surface.current.height),
+        }
+    }
+
     /// Build subsurface scene nodes for `parent_surface_id`.
     ///
     /// `geometry_offset` is subtracted from the positions of **direct**
@@ -422,6 +738,8 @@ impl CompositorState {
                         let surface = surface_ref.read().unwrap();
                         node.set_size(surface.current.width.max(0) as u32, surface.current.height.max(0) as u32);
                     }
+                    node.opaque_region = self.node_opaque_region(child_surface_id, (0, 0));
+                    node.buffer = self.buffer_mapping(child_surface_id);
                     
                     scene.add_node(node);
                     scene.add_child(parent_node_id, node_id);
@@ -4,6 +4,9 @@
 //! wl_subsurface relationships, buffer handling, and surface commit logic.
 
 use super::*;
+use crate::core::surface::SurfaceState;
+use crate::core::surface::commit_queue::QueuedCommit;
+use crate::core::wayland::ext::presentation_time::PresentationFeedback;
 
 impl CompositorState {
     // =========================================================================
@@ -29,6 +32,13 @@ impl CompositorState {
     pub fn remove_surface(&mut self, surface_id: u32) {
         self.surfaces.remove(&surface_id);
         self.frame_callbacks.remove(&surface_id);
+        self.commit_queue.remove_surface(surface_id);
+        self.ext.presentation.discard_surface(surface_id);
+        self.ext.fifo.remove_surface(surface_id);
+        self.ext.commit_timing.target_times.remove(&surface_id);
+        self.ext.content_type.remove_surface(surface_id);
+        self.frame_throttle.remove_surface(surface_id);
+        self.ext.tearing_control.surface_hints.remove(&surface_id);
         
         if self.focus.grabbed_surface == Some(surface_id) {
             self.focus.grabbed_surface = None;
@@ -115,19 +125,117 @@ impl CompositorState {
     }
 
     /// Handle a surface commit request
+    ///
+    /// Commits that carry a FIFO wait while the surface's barrier is up, or a
+    /// commit-timing target, are snapshotted into the commit queue and applied
+    /// later by `process_commit_queue`.
     pub fn handle_surface_commit(&mut self, surface_id: u32) {
+        #[cfg(feature = "desktop-protocols")]
+        if !self.accept_lock_surface_commit(surface_id) {
+            return;
+        }
+
+        let fifo = self.ext.fifo.take_pending(surface_id);
+        let target_ns = self.ext.commit_timing.consume(surface_id);
         let is_sync = self.is_effectively_sync(surface_id);
-        
+
+        let must_queue = !is_sync
+            && (self.commit_queue.has_queued(surface_id)
+                || (fifo.wait_barrier && self.ext.fifo.has_barrier(surface_id))
+                || target_ns.is_some());
+
+        if must_queue {
+            let Some(surface) = self.get_surface(surface_id) else { return };
+            let state = surface.write().unwrap().commit_queued();
+            let frame_callbacks = match self.frame_callbacks.get_mut(&surface_id) {
+                Some(callbacks) => {
+                    let mark = self.commit_queue.callback_mark(surface_id).min(callbacks.len());
+                    callbacks.split_off(mark)
+                }
+                None => Vec::new(),
+            };
+            let presentation_feedback = self.ext.presentation.take_pending(surface_id);
+            self.commit_queue.push(surface_id, QueuedCommit {
+                state,
+                set_barrier: fifo.set_barrier,
+                wait_barrier: fifo.wait_barrier,
+                target_ns,
+                frame_callbacks,
+                presentation_feedback,
+            });
+            tracing::debug!("Surface {} commit queued (fifo={:?}, target={:?})", surface_id, fifo, target_ns);
+            return;
+        }
+
+        self.apply_surface_commit(surface_id, None, is_sync);
+        if fifo.set_barrier {
+            self.ext.fifo.set_barrier(surface_id);
+        }
+    }
+
+    /// Apply queued commits that are ready for presentation at `next_vblank`.
+    ///
+    /// Called by the runtime once per loop iteration, after client requests
+    /// have been dispatched. Barriers are lifted by presentation feedback;
+    /// barriers on surfaces that are never presented expire on their own.
+    pub fn process_commit_queue(&mut self, next_vblank: std::time::Instant) {
+        let now = std::time::Instant::now();
+        self.ext.fifo.expire_barriers(now);
+
+        let vblank_ns = crate::core::time::instant_to_monotonic_ns(next_vblank);
+        for surface_id in self.commit_queue.surfaces() {
+            while let Some(commit) = self.commit_queue.pop_ready(
+                surface_id,
+                self.ext.fifo.has_barrier(surface_id),
+                vblank_ns,
+            ) {
+                if !commit.frame_callbacks.is_empty() {
+                    self.frame_callbacks.entry(surface_id).or_default().extend(commit.frame_callbacks);
+                }
+                self.apply_surface_commit(surface_id, Some((commit.state, commit.presentation_feedback)), false);
+                if commit.set_barrier {
+                    self.ext.fifo.set_barrier(surface_id);
+                }
+            }
+        }
+    }
+
+    /// Lift FIFO barriers after a frame has been presented
+    pub fn lift_fifo_barriers(&mut self) {
+        self.ext.fifo.lift_barriers();
+    }
+
+    /// Lift FIFO barriers of the surfaces shown on `output_id` after that
+    /// output presented a frame; other outputs keep pacing their own
+    pub fn lift_output_fifo_barriers(&mut self, output_id: u32) {
+        let surfaces = self.surfaces_on_output(output_id);
+        self.ext.fifo.lift_barriers_of(&surfaces);
+    }
+
+    /// Apply a commit: either the surface's pending state or a state taken
+    /// from the commit queue, with the presentation feedback queued with it.
+    fn apply_surface_commit(
+        &mut self,
+        surface_id: u32,
+        queued: Option<(SurfaceState, Vec<PresentationFeedback>)>,
+        is_sync: bool,
+    ) {
+        let (queued, queued_feedback) = match queued {
+            Some((state, feedback)) => (Some(state), Some(feedback)),
+            None => (None, None),
+        };
         let release_id = if let Some(surface) = self.get_surface(surface_id) {
             let mut surface = surface.write().unwrap();
-            if is_sync {
-                surface.commit_sync()
-            } else {
-                surface.commit()
+            match queued {
+                Some(state) => surface.apply_queued(state),
+                None if is_sync => surface.commit_sync(),
+                None => surface.commit(),
             }
         } else {
             None
         };
+        let callback_count = self.frame_callbacks.get(&surface_id).map_or(0, |c| c.len());
+        self.commit_queue.mark_callbacks(surface_id, callback_count);
 
         let client_id = if let Some(surface) = self.get_surface(surface_id) {
             surface.read().unwrap().client_id.clone()
@@ -151,7 +259,12 @@ impl CompositorState {
             }
         }
         
-        self.ext.presentation.mark_committed(surface_id);
+        match queued_feedback {
+            Some(feedback) => self.ext.presentation.commit_feedback(surface_id, feedback),
+            None => self.ext.presentation.mark_committed(surface_id),
+        }
+        self.committed_since_sync.push(surface_id);
+        self.scene_dirty = true;
         self.finalize_surface_commit(surface_id);
     }
 
@@ -266,6 +379,10 @@ impl CompositorState {
                     size_changed = true;
                 }
             }
+            if size_changed {
+                self.anchor_resizing_window(wid);
+                self.reposition_reactive_popups(wid);
+            }
 
             // Notify the platform when the committed surface size differs from
             // the window size the platform created.  Fullscreen-shell windows
@@ -294,7 +411,7 @@ impl CompositorState {
                     buffer_id,
                 }
             );
-        } else if layer_id.is_some() {
+        } else if let Some(layer_id) = layer_id {
             let buffer_id = surface.current.buffer_id.map(|id| id as u64);
             self.pending_compositor_events.push(
                 crate::core::compositor::CompositorEvent::LayerSurfaceCommitted {
@@ -303,6 +420,41 @@ impl CompositorState {
                     buffer_id,
                 }
             );
+            drop(surface);
+
+            let Some(ls) = self.get_layer_surface(client_id, layer_id) else { return };
+            let (mapped_changed, state_changed) = {
+                let mut ls = ls.write().unwrap();
+                let state = ls.arrangement();
+                if let Err((error, message)) = state.validate() {
+                    if let Some(resource) = &ls.resource {
+                        resource.post_error(error, message);
+                    }
+                    return;
+                }
+                let was_mapped = std::mem::replace(&mut ls.mapped, buffer_id.is_some());
+                if was_mapped && !ls.mapped {
+                    // Unmapping resets the surface; the next commit is an
+                    // initial commit again and gets a fresh configure
+                    ls.committed_state = None;
+                    ls.configured = false;
+                    ls.configured_size = None;
+                    (true, true)
+                } else {
+                    let state_changed = ls.committed_state != Some(state);
+                    ls.committed_state = Some(state);
+                    (was_mapped != ls.mapped, state_changed)
+                }
+            };
+            // Changed state can move other layer surfaces and the usable
+            // area; configures go out to whatever changed size
+            if state_changed {
+                self.reposition_layer_surfaces();
+            }
+            // Mapping or unmapping can grant or release keyboard focus
+            if mapped_changed {
+                self.update_layer_keyboard_focus(layer_id);
+            }
         } else if is_cursor {
             let buffer_id = surface.current.buffer_id.map(|id| id as u64);
             self.pending_compositor_events.push(
@@ -3,9 +3,15 @@
 //! Contains `CompositorState` methods for window lifecycle, decoration
 //! reconfiguration, clipboard selection, and drag-and-drop operations.
 
+use std::os::unix::io::AsFd;
+
 use wayland_server::protocol::wl_data_device_manager::DndAction;
 
 use super::*;
+use crate::core::input::grab::{GrabKind, GrabSerial};
+use crate::core::window::decoration::{DecorationHit, DecorationLayout, DecorationPiece, Pixmap, WindowDecoration};
+use crate::core::window::grab::{self, WindowGrab, WindowGrabKind};
+use crate::core::window::resize::ResizeEdge;
 
 impl CompositorState {
     /// Add a window (Legacy - delegating to register_window)
@@ -143,6 +149,10 @@ impl CompositorState {
                 }
             }
             
+            self.decorations.remove(window_id);
+            if self.seat.grabs.get::<WindowGrab>().map_or(false, |g| g.window_id == window_id) {
+                self.end_grab(GrabKind::Window);
+            }
             tracing::info!("Destroyed window {}", window_id);
             
             self.pending_compositor_events.push(crate::core::compositor::CompositorEvent::WindowDestroyed {
@@ -151,6 +161,478 @@ impl CompositorState {
         }
     }
 
+    /// Ask the client of a window to close it
+    pub fn close_window(&mut self, window_id: u32) {
+        let resource = self.xdg.toplevels.values()
+            .find(|tl| tl.window_id == window_id)
+            .and_then(|tl| tl.resource.clone());
+        if let Some(resource) = resource {
+            resource.close();
+        }
+    }
+
+    /// Maximize a toplevel to the usable area of its output.
+    pub fn maximize_toplevel(&mut self, client_id: ClientId, toplevel_id: u32) {
+        // 1. Determine target output and calculate geometry
+        let (output_id, usable_origin, width, height) = {
+            let output_id = if let Some(tl_data) = self.xdg.toplevels.get(&(client_id.clone(), toplevel_id)) {
+                 if let Some(window) = self.get_window(tl_data.window_id) {
+                     let window = window.read().unwrap();
+                     window.outputs.first().copied().unwrap_or(
+                         self.outputs.get(self.primary_output).map(|o| o.id).unwrap_or(0)
+                     )
+                 } else {
+                     self.outputs.get(self.primary_output).map(|o| o.id).unwrap_or(0)
+                 }
+            } else {
+                self.outputs.get(self.primary_output).map(|o| o.id).unwrap_or(0)
+            };
+
+            let (x, y, w, h) = if let Some((x, y, w, h)) = self.get_usable_region(output_id) {
+                (x, y, w as i32, h as i32)
+            } else {
+                (0, 0, 0, 0)
+            };
+            (output_id, (x, y), w, h)
+        };
+        // A core-drawn title bar takes its share of the output
+        let window_id = self.xdg.toplevels.get(&(client_id.clone(), toplevel_id)).map(|t| t.window_id);
+        let (top, right, bottom, left) = window_id
+            .and_then(|wid| self.core_decoration_layout(wid, true))
+            .map_or((0, 0, 0, 0), |layout| layout.insets());
+        let (width, height) = ((width - left - right).max(0), (height - top - bottom).max(0));
+
+        // 2. Save current geometry and update Window state
+        if let Some(wid) = window_id {
+            if let Some(window) = self.get_window(wid) {
+                if let Ok(mut w) = window.write() {
+                    // Save geometry before maximizing (only if not already saved)
+                    if let Some(tl_data) = self.xdg.toplevels.get_mut(&(client_id.clone(), toplevel_id)) {
+                        if tl_data.saved_geometry.is_none() {
+                            tl_data.saved_geometry = Some((w.x, w.y, w.width as u32, w.height as u32));
+                        }
+                    }
+                    w.maximized = true;
+                    if top != 0 {
+                        w.x = usable_origin.0 + left;
+                        w.y = usable_origin.1 + top;
+                    }
+                }
+            }
+        }
+
+        // 3. Update Toplevel state and clamp to size constraints
+        let (clamped_w, clamped_h) = if let Some(tl_data) = self.xdg.toplevels.get_mut(&(client_id.clone(), toplevel_id)) {
+            tl_data.pending_maximized = true;
+            tl_data.clamp_size(width as u32, height as u32)
+        } else {
+            (width as u32, height as u32)
+        };
+
+        // 4. Send configure
+        tracing::debug!("Maximized to {}x{} on output {}", clamped_w, clamped_h, output_id);
+        self.send_toplevel_configure(client_id.clone(), toplevel_id, clamped_w, clamped_h);
+        
+        // 5. Push event for platform
+        if let Some(wid) = window_id {
+            self.pending_compositor_events.push(
+                crate::core::compositor::CompositorEvent::WindowMaximized {
+                    window_id: wid,
+                    maximized: true,
+                }
+            );
+        }
+    }
+
+    /// Restore a maximized toplevel to the geometry it had before.
+    pub fn unmaximize_toplevel(&mut self, client_id: ClientId, toplevel_id: u32) {
+        // Restore saved geometry
+        let saved = self.xdg.toplevels.get_mut(&(client_id.clone(), toplevel_id)).and_then(|tl| {
+            tl.pending_maximized = false;
+            tl.saved_geometry.take()
+        });
+
+        let (restore_w, restore_h) = if let Some((x, y, w, h)) = saved {
+            // Restore window position
+            let window_id = self.xdg.toplevels.get(&(client_id.clone(), toplevel_id)).map(|t| t.window_id);
+            if let Some(wid) = window_id {
+                if let Some(window) = self.get_window(wid) {
+                    if let Ok(mut win) = window.write() {
+                        win.maximized = false;
+                        win.x = x;
+                        win.y = y;
+                    }
+                }
+            }
+            (w, h)
+        } else {
+            let window_id = self.xdg.toplevels.get(&(client_id.clone(), toplevel_id)).map(|t| t.window_id);
+            if let Some(wid) = window_id {
+                if let Some(window) = self.get_window(wid) {
+                    if let Ok(mut win) = window.write() {
+                        win.maximized = false;
+                    }
+                }
+            }
+            (0, 0)
+        };
+        
+        self.send_toplevel_configure(client_id.clone(), toplevel_id, restore_w, restore_h);
+        
+        // Push event for platform
+        let window_id = self.xdg.toplevels.get(&(client_id.clone(), toplevel_id)).map(|t| t.window_id);
+        if let Some(wid) = window_id {
+            self.pending_compositor_events.push(
+                crate::core::compositor::CompositorEvent::WindowMaximized {
+                    window_id: wid,
+                    maximized: false,
+                }
+            );
+        }
+    }
+
+    // =========================================================================
+    // Interactive Move & Resize
+    // =========================================================================
+
+    /// Handle a request to move a window. When `core_window_grabs` is set
+    /// the core runs the grab itself, honouring it only while the button
+    /// press or touch point with `serial` is held over the window; otherwise
+    /// the request goes to the platform, which checks it.
+    pub fn request_window_move(&mut self, window_id: u32, seat_id: u32, serial: u32) {
+        if !self.core_window_grabs {
+            self.pending_compositor_events.push(
+                crate::core::compositor::CompositorEvent::WindowMoveRequested { window_id, seat_id, serial }
+            );
+            return;
+        }
+        let Some((source, pointer)) = self.window_grab_source(window_id, serial) else {
+            tracing::debug!("Ignoring move of window {} with stale serial {}", window_id, serial);
+            return;
+        };
+
+        let Some(window) = self.get_window(window_id) else { return };
+        let (x, y, width, maximized, fullscreen) = {
+            let window = window.read().unwrap();
+            (window.x, window.y, window.width, window.maximized, window.fullscreen)
+        };
+        if fullscreen {
+            return;
+        }
+        if maximized {
+            // Dragging a maximized window restores it, keeping the pointer
+            // at the same fraction of the title bar
+            let key = self.xdg.toplevels.iter()
+                .find(|(_, tl)| tl.window_id == window_id)
+                .map(|(key, tl)| (key.clone(), tl.saved_geometry));
+            if let Some(((client_id, toplevel_id), saved)) = key {
+                self.unmaximize_toplevel(client_id, toplevel_id);
+                if let Some((_, _, saved_w, _)) = saved {
+                    let offset = (pointer.0 - x) as i64 * saved_w as i64 / width.max(1) as i64;
+                    let mut window = window.write().unwrap();
+                    window.x = pointer.0 - offset as i32;
+                    window.y = y;
+                }
+            }
+        }
+
+        let origin = {
+            let window = window.read().unwrap();
+            (window.x, window.y)
+        };
+        self.begin_window_grab(WindowGrab::new_move(window_id, serial, pointer, origin).driven_by(source));
+    }
+
+    /// Handle a request to resize a window; see
+    /// [`request_window_move`](Self::request_window_move).
+    pub fn request_window_resize(&mut self, window_id: u32, seat_id: u32, serial: u32, edge: ResizeEdge) {
+        if !self.core_window_grabs {
+            self.pending_compositor_events.push(
+                crate::core::compositor::CompositorEvent::WindowResizeRequested {
+                    window_id,
+                    seat_id,
+                    serial,
+                    edges: edge.xdg_edges(),
+                }
+            );
+            return;
+        }
+        let Some((source, pointer)) = self.window_grab_source(window_id, serial) else {
+            tracing::debug!("Ignoring resize of window {} with stale serial {}", window_id, serial);
+            return;
+        };
+
+        let Some(window) = self.get_window(window_id) else { return };
+        let rect = {
+            let window = window.read().unwrap();
+            if window.maximized || window.fullscreen || edge == ResizeEdge::None {
+                return;
+            }
+            crate::util::geometry::Rect::new(window.x, window.y, window.width.max(1) as u32, window.height.max(1) as u32)
+        };
+        if let Some(tl) = self.xdg.toplevels.values_mut().find(|tl| tl.window_id == window_id) {
+            tl.pending_resizing = true;
+        }
+        self.begin_window_grab(WindowGrab::new_resize(window_id, serial, edge, pointer, rect).driven_by(source));
+    }
+
+    /// The held input a move/resize of a window requested with `serial`
+    /// would follow, and where it is now, or `None` if the request isn't
+    /// allowed. The press or touch must be on the window.
+    fn window_grab_source(&mut self, window_id: u32, serial: u32) -> Option<(GrabSerial, (i32, i32))> {
+        if !self.seat.grabs.admits(GrabKind::Window) {
+            return None;
+        }
+        let source = self.seat.grab_serial(serial)?;
+        let (surface_id, x, y) = match source {
+            GrabSerial::Pointer => (self.seat.pointer.focus?, self.seat.pointer.x, self.seat.pointer.y),
+            GrabSerial::Touch(id) => {
+                let point = self.seat.touch.active_points.get(&id)?.clone();
+                let (x, y) = self.surface_in_scene(point.surface_id)?.surface_to_scene(point.x, point.y);
+                (point.surface_id, x, y)
+            }
+            // Nothing to follow: a window can't be dragged with keys
+            GrabSerial::Key => return None,
+        };
+        if self.resolve_window_id_for_surface(surface_id) != Some(window_id) {
+            return None;
+        }
+        Some((source, (x.floor() as i32, y.floor() as i32)))
+    }
+
+    /// Take the pointer, or the touch point driving it, away from clients
+    /// for a move or resize
+    fn begin_window_grab(&mut self, grab: WindowGrab) {
+        let window_id = grab.window_id;
+        let by_pointer = grab.touch_id.is_none();
+        if self.start_grab(Box::new(grab)).is_err() {
+            return;
+        }
+        let focus = if by_pointer { self.seat.pointer.focus.take() } else { None };
+        if let Some(focus) = focus {
+            if let Some(surface) = self.get_surface(focus) {
+                let surface = surface.read().unwrap();
+                if let Some(res) = &surface.resource {
+                    let serial = self.next_serial();
+                    self.seat.pointer.broadcast_leave(serial, res);
+                }
+            }
+        }
+        self.decorations.hovered = None;
+        self.decorations.pressed = None;
+        self.set_focused_window(Some(window_id));
+        self.window_tree.bring_to_front(window_id);
+    }
+
+    /// Move or resize the grabbed window for the pointer at (x, y)
+    pub(crate) fn update_window_grab(&mut self, grab: &WindowGrab, x: f64, y: f64) {
+        let Some(window) = self.get_window(grab.window_id) else { return };
+        let pointer = (x.floor() as i32, y.floor() as i32);
+        let insets = self.core_decoration_layout(grab.window_id, false)
+            .map_or((0, 0, 0, 0), |layout| layout.insets());
+        let areas: Vec<_> = self.outputs.iter()
+            .filter_map(|o| self.get_usable_region(o.id))
+            .map(|(x, y, w, h)| crate::util::geometry::Rect::new(x, y, w, h))
+            .collect();
+
+        match grab.kind {
+            WindowGrabKind::Move { offset_x, offset_y } => {
+                let moved = {
+                    let mut window = window.write().unwrap();
+                    let size = (window.width, window.height);
+                    let origin = grab::move_origin((offset_x, offset_y), pointer, size, insets, &areas);
+                    let moved = origin != (window.x, window.y);
+                    (window.x, window.y) = origin;
+                    moved
+                };
+                if moved {
+                    self.reposition_reactive_popups(grab.window_id);
+                }
+            }
+            WindowGrabKind::Resize(resize) => {
+                let (width, height) = grab::resize_size(&resize, pointer, insets, &areas);
+                let Some(((client_id, toplevel_id), current)) = self.xdg.toplevels.iter()
+                    .find(|(_, tl)| tl.window_id == grab.window_id)
+                    .map(|(key, tl)| (key.clone(), (tl.width, tl.height)))
+                else {
+                    return;
+                };
+                let (width, height) = self.xdg.toplevels[&(client_id.clone(), toplevel_id)]
+                    .clamp_size(width as u32, height as u32);
+                if (width, height) != current {
+                    self.send_toplevel_configure(client_id, toplevel_id, width, height);
+                }
+            }
+        }
+    }
+
+    /// Keep the edges opposite the dragged ones in place when a window
+    /// being resized commits a new size
+    pub(crate) fn anchor_resizing_window(&mut self, window_id: u32) {
+        let Some(resize) = self.seat.grabs.get::<WindowGrab>()
+            .filter(|g| g.window_id == window_id)
+            .and_then(|g| g.resize().copied())
+        else {
+            return;
+        };
+        if let Some(window) = self.get_window(window_id) {
+            let mut window = window.write().unwrap();
+            (window.x, window.y) = resize.origin_for(window.width, window.height);
+        }
+    }
+
+    /// Finish a move or resize; a resize gets its final configure without
+    /// the resizing state.
+    pub(crate) fn finish_window_grab(&mut self, grab: &WindowGrab) {
+        if grab.resize().is_none() {
+            return;
+        }
+        let key = self.xdg.toplevels.iter_mut()
+            .find(|(_, tl)| tl.window_id == grab.window_id)
+            .map(|(key, tl)| {
+                tl.pending_resizing = false;
+                (key.clone(), (tl.width, tl.height))
+            });
+        if let Some(((client_id, toplevel_id), (width, height))) = key {
+            self.send_toplevel_configure(client_id, toplevel_id, width, height);
+        }
+    }
+
+    // =========================================================================
+    // Server-Side Decorations
+    // =========================================================================
+
+    /// Frame layout of a window whose decorations the core draws, or `None`
+    /// when the client or the frontend decorates it. `maximized` overrides
+    /// the window's own state, for sizing a window about to be maximized.
+    pub fn core_decoration_layout(&self, window_id: u32, maximized: bool) -> Option<DecorationLayout> {
+        if !self.decorations.enabled {
+            return None;
+        }
+        let window = self.get_window(window_id)?;
+        let window = window.read().unwrap();
+        if window.decoration_mode != DecorationMode::ServerSide || window.fullscreen {
+            return None;
+        }
+        Some(DecorationLayout::new(window.width, window.height, maximized || window.maximized))
+    }
+
+    /// Size to suggest in the first configure of a new toplevel, given the
+    /// space the platform offers it. A frame the core draws comes out of
+    /// that space. A frame the platform draws (a native title bar) takes
+    /// an amount only the platform knows, so the size is left to the
+    /// client until the platform reports the content area.
+    pub fn initial_toplevel_size(&self, window_id: u32, width: u32, height: u32) -> (u32, u32) {
+        if let Some(layout) = self.core_decoration_layout(window_id, false) {
+            let (top, right, bottom, left) = layout.insets();
+            return (
+                width.saturating_sub((left + right) as u32),
+                height.saturating_sub((top + bottom) as u32),
+            );
+        }
+        match self.decoration_policy {
+            DecorationPolicy::ForceServer => (0, 0),
+            _ => (width, height),
+        }
+    }
+
+    /// Bring the decoration of a window up to date for drawing.
+    pub(crate) fn update_window_decoration(&mut self, window_id: u32) -> Option<&WindowDecoration> {
+        let Some(layout) = self.core_decoration_layout(window_id, false) else {
+            self.decorations.remove(window_id);
+            return None;
+        };
+        let (title, active) = {
+            let window = self.get_window(window_id)?;
+            let window = window.read().unwrap();
+            (window.title.clone(), window.activated)
+        };
+        Some(self.decorations.update(window_id, layout, &title, active))
+    }
+
+    /// Pixels of a decoration piece, for frontends to upload
+    pub fn decoration_pixels(&self, surface_id: u32) -> Option<Pixmap> {
+        let (window_id, piece) = DecorationPiece::from_surface_id(surface_id)?;
+        self.decorations.windows.get(&window_id).map(|d| d.piece_pixels(piece))
+    }
+
+    /// Track the decoration part under the pointer, redrawing the hovered
+    /// button and telling the frontend which cursor to show.
+    pub(crate) fn hover_decoration(&mut self, hit: Option<(u32, DecorationHit)>) {
+        use wayland_protocols::wp::cursor_shape::v1::server::wp_cursor_shape_device_v1::Shape;
+
+        if self.decorations.hovered == hit {
+            return;
+        }
+        self.decorations.hovered = hit;
+        // Leaving the decoration puts the default cursor back until a
+        // client surface under the pointer sets its own
+        let shape = match hit.map(|(_, hit)| hit) {
+            Some(DecorationHit::Resize(ResizeEdge::Top)) => Shape::NResize,
+            Some(DecorationHit::Resize(ResizeEdge::Bottom)) => Shape::SResize,
+            Some(DecorationHit::Resize(ResizeEdge::Left)) => Shape::WResize,
+            Some(DecorationHit::Resize(ResizeEdge::Right)) => Shape::EResize,
+            Some(DecorationHit::Resize(ResizeEdge::TopLeft)) => Shape::NwResize,
+            Some(DecorationHit::Resize(ResizeEdge::TopRight)) => Shape::NeResize,
+            Some(DecorationHit::Resize(ResizeEdge::BottomLeft)) => Shape::SwResize,
+            Some(DecorationHit::Resize(ResizeEdge::BottomRight)) => Shape::SeResize,
+            _ => Shape::Default,
+        };
+        self.pending_compositor_events.push(
+            crate::core::compositor::CompositorEvent::CursorShapeChanged { shape: shape as u32 }
+        );
+    }
+
+    /// Act on a click on a window decoration: buttons act when released
+    /// over the button they were pressed on, the title bar and border
+    /// start a move or resize as soon as they are pressed.
+    pub(crate) fn handle_decoration_button(&mut self, pressed: bool, serial: u32) {
+        let hovered = self.decorations.hovered;
+        if pressed {
+            self.decorations.pressed = hovered;
+            let Some((window_id, hit)) = hovered else { return };
+            self.set_focused_window(Some(window_id));
+            self.window_tree.bring_to_front(window_id);
+            match hit {
+                DecorationHit::Titlebar => self.request_window_move(window_id, 0, serial),
+                DecorationHit::Resize(edge) => self.request_window_resize(window_id, 0, serial, edge),
+                _ => {}
+            }
+            return;
+        }
+
+        let Some((window_id, hit)) = self.decorations.pressed.take() else { return };
+        if hovered != Some((window_id, hit)) {
+            return;
+        }
+        let Some((client_id, toplevel_id)) = self.xdg.toplevels.iter()
+            .find(|(_, tl)| tl.window_id == window_id)
+            .map(|(key, _)| key.clone())
+        else {
+            return;
+        };
+        match hit {
+            DecorationHit::Close => self.close_window(window_id),
+            DecorationHit::Maximize => {
+                let maximized = self.get_window(window_id).map_or(false, |w| w.read().unwrap().maximized);
+                if maximized {
+                    self.unmaximize_toplevel(client_id, toplevel_id);
+                } else {
+                    self.maximize_toplevel(client_id, toplevel_id);
+                }
+            }
+            DecorationHit::Minimize => {
+                if let Some(window) = self.get_window(window_id) {
+                    window.write().unwrap().minimized = true;
+                }
+                self.pending_compositor_events.push(
+                    crate::core::compositor::CompositorEvent::WindowMinimized { window_id, minimized: true }
+                );
+            }
+            _ => {}
+        }
+    }
+
     // =========================================================================
     // Clipboard & Drag-and-Drop
     // =========================================================================
@@ -158,6 +640,30 @@ impl CompositorState {
     /// Set the current clipboard source
     pub fn set_clipboard_source(&mut self, dh: &wayland_server::DisplayHandle, source: Option<SelectionSource>) {
         tracing::debug!("Clipboard source set to: {:?}", source);
+
+        // Start copying the new selection into the clipboard cache so it can
+        // outlive its owner. Taking over with `Cached` keeps the data as is.
+        match &source {
+            Some(SelectionSource::Wayland(s)) => {
+                let offered = self.data.sources.get(&s.id().protocol_id())
+                    .map(|d| d.mime_types.clone())
+                    .unwrap_or_default();
+                for (mime, fd) in self.data.clipboard_cache.begin(&offered) {
+                    s.send(mime, fd.as_fd());
+                }
+            }
+            Some(SelectionSource::Wlr(s)) => {
+                let offered = self.wlr.data_control.sources.get(&s.id().protocol_id())
+                    .map(|d| d.mime_types.clone())
+                    .unwrap_or_default();
+                for (mime, fd) in self.data.clipboard_cache.begin(&offered) {
+                    s.send(mime, fd.as_fd());
+                }
+            }
+            Some(SelectionSource::Cached) => {}
+            None => self.data.clipboard_cache.clear(),
+        }
+
         self.seat.current_selection = source;
         
         let devices: Vec<wayland_server::protocol::wl_data_device::WlDataDevice> = self.data.devices.values()
@@ -200,6 +706,12 @@ impl CompositorState {
                              }
                              (Some(id), actions)
                          }
+                         SelectionSource::Cached => {
+                             for mime in self.data.clipboard_cache.cached_mime_types() {
+                                 offer.offer(mime);
+                             }
+                             (None, DndAction::empty())
+                         }
                      };
                      if offer.version() >= 3 {
                          offer.source_actions(source_dnd_actions);
@@ -220,14 +732,49 @@ impl CompositorState {
         }
     }
 
-    /// Start a drag-and-drop operation
+    /// Drive the clipboard cache: drain pending reads from the selection
+    /// source and, once its owner has gone away, re-announce the selection
+    /// as a compositor-owned source backed by the cached data.
+    pub fn service_clipboard_cache(&mut self, dh: &wayland_server::DisplayHandle) {
+        if !self.data.clipboard_cache.enabled {
+            return;
+        }
+
+        let settled = self.data.clipboard_cache.poll();
+        let owner_gone = match &self.seat.current_selection {
+            Some(SelectionSource::Wayland(s)) => !s.is_alive(),
+            Some(SelectionSource::Wlr(s)) => !s.is_alive(),
+            Some(SelectionSource::Cached) | None => false,
+        };
+        if !owner_gone || !settled {
+            return;
+        }
+
+        if self.data.clipboard_cache.has_entries() {
+            tracing::info!(
+                "Selection owner went away; serving {:?} from the clipboard cache",
+                self.data.clipboard_cache.cached_mime_types()
+            );
+            self.set_clipboard_source(dh, Some(SelectionSource::Cached));
+        } else {
+            self.set_clipboard_source(dh, None);
+        }
+    }
+
+    /// Start a drag-and-drop operation. Returns false when another grab,
+    /// such as an interactive move, holds the pointer and refuses it.
     pub fn start_drag(
         &mut self,
         source_id: Option<u32>,
         origin_surface_id: u32,
         icon_surface_id: Option<u32>,
-    ) {
-        use crate::core::wayland::ext::data_device::DragState;
+    ) -> bool {
+        use crate::core::wayland::ext::data_device::{DragGrab, DragState};
+
+        if self.start_grab(Box::new(DragGrab)).is_err() {
+            tracing::debug!("Drag refused: the pointer is grabbed");
+            return false;
+        }
 
         let serial = self.serial;
         self.data.drag = Some(DragState {
@@ -243,6 +790,61 @@ impl CompositorState {
             "Drag started: source={:?}, origin={}, icon={:?}, serial={}",
             source_id, origin_surface_id, icon_surface_id, serial
         );
+        true
+    }
+
+    /// Follow the pointer at (x, y) with the drag in progress: send
+    /// wl_data_device enter/leave/motion and move an attached toplevel.
+    pub(crate) fn update_drag(&mut self, x: f64, y: f64, time: u32) {
+        let Some(drag_focus) = self.data.drag.as_ref().map(|d| d.focus_surface_id) else { return };
+        let picking_res = self.find_surface_at(x, y);
+        let new_surface_id = picking_res.as_ref().map(|(sid, _, _)| *sid);
+
+        if drag_focus != new_surface_id {
+            if drag_focus.is_some() {
+                for device_data in self.data.devices.values() {
+                    if device_data.resource.is_alive() {
+                        device_data.resource.leave();
+                    }
+                }
+            }
+
+            if let Some((sid, lx, ly)) = &picking_res {
+                if let Some(surface) = self.get_surface(*sid) {
+                    let surface = surface.read().unwrap();
+                    if let Some(res) = &surface.resource {
+                        let serial = self.next_serial();
+                        for device_data in self.data.devices.values() {
+                            if device_data.resource.is_alive() {
+                                device_data.resource.enter(serial, res, *lx, *ly, None);
+                            }
+                        }
+                    }
+                }
+            }
+
+            if let Some(drag) = &mut self.data.drag {
+                drag.focus_surface_id = new_surface_id;
+            }
+        } else if let Some((_sid, lx, ly)) = &picking_res {
+            for device_data in self.data.devices.values() {
+                if device_data.resource.is_alive() {
+                    device_data.resource.motion(time, *lx, *ly);
+                }
+            }
+        }
+
+        if let Some(attachment) = &self.xdg.toplevel_drag.active {
+            if let Some(wid) = attachment.window_id {
+                let new_x = x as i32 + attachment.x_offset;
+                let new_y = y as i32 + attachment.y_offset;
+                if let Some(window) = self.get_window(wid) {
+                    let mut window = window.write().unwrap();
+                    window.x = new_x;
+                    window.y = new_y;
+                }
+            }
+        }
     }
 
     /// Check if a drag-and-drop operation is currently active
@@ -252,6 +854,7 @@ impl CompositorState {
 
     /// End the current drag-and-drop operation
     pub fn end_drag(&mut self, dropped: bool) {
+        self.end_grab(crate::core::input::grab::GrabKind::Drag);
         self.xdg.toplevel_drag.active = None;
 
         let drag = match self.data.drag.take() {
@@ -360,11 +963,194 @@ impl CompositorState {
     // Presentation Time
     // =========================================================================
     
-    /// Get next presentation sequence number
-    pub fn next_presentation_seq(&mut self) -> u64 {
-        let seq = self.ext.presentation.next_seq;
-        self.ext.presentation.next_seq = self.ext.presentation.next_seq.wrapping_add(1);
-        seq
+    /// Get the presentation sequence number (MSC) of `output_id` for a frame
+    /// presented at `timestamp_ns`, counting every refresh cycle since that
+    /// output's previous one
+    pub fn next_presentation_seq(&mut self, output_id: u32, timestamp_ns: u64, refresh_ns: u32) -> u64 {
+        self.ext.presentation.advance_msc(output_id, timestamp_ns, refresh_ns)
+    }
+
+    /// Refresh period of the primary output in nanoseconds (0 if unknown)
+    pub fn output_refresh_ns(&self) -> u32 {
+        let refresh_mhz = self.outputs.get(self.primary_output).map_or(0, |o| o.refresh);
+        if refresh_mhz == 0 {
+            0
+        } else {
+            (1_000_000_000_000u64 / refresh_mhz as u64) as u32
+        }
+    }
+
+    /// Send wp_presentation feedback for committed content.
+    ///
+    /// `timestamp_ns` is CLOCK_MONOTONIC, the clock advertised to clients.
+    /// `surface_id` limits delivery to one surface. Each feedback gets
+    /// `sync_output` for the client's wl_output objects of the output the
+    /// surface was shown on, followed by `presented`.
+    pub fn present_feedback(
+        &mut self,
+        surface_id: Option<u32>,
+        timestamp_ns: u64,
+        refresh_ns: u32,
+        flags: wp_presentation_feedback::Kind,
+    ) {
+        use crate::core::wayland::ext::presentation_time::{send_presented, PresentInfo};
+
+        let output_id = self.outputs.get(self.primary_output).map(|o| o.id);
+        let seq = self.next_presentation_seq(output_id.unwrap_or(0), timestamp_ns, refresh_ns);
+        let info = PresentInfo { timestamp_ns, refresh_ns, seq, flags };
+
+        for feedback in self.ext.presentation.take_committed(surface_id) {
+            self.sync_feedback_output(&feedback, output_id);
+            send_presented(&feedback, &info);
+        }
+    }
+
+    /// Send wp_presentation feedback for the surfaces shown on one output.
+    ///
+    /// Like [`present_feedback`](Self::present_feedback), but for platforms
+    /// that report vblanks per display: only committed content visible on
+    /// `output_id` is marked presented, with that output's refresh period
+    /// and vblank counter.
+    pub fn present_output_feedback(
+        &mut self,
+        output_id: u32,
+        timestamp_ns: u64,
+        refresh_ns: u32,
+        flags: wp_presentation_feedback::Kind,
+    ) {
+        use crate::core::wayland::ext::presentation_time::{send_presented, PresentInfo};
+
+        let surfaces = self.surfaces_on_output(output_id);
+        if surfaces.is_empty() {
+            return;
+        }
+        let seq = self.next_presentation_seq(output_id, timestamp_ns, refresh_ns);
+        let info = PresentInfo { timestamp_ns, refresh_ns, seq, flags };
+
+        for surface_id in surfaces {
+            for feedback in self.ext.presentation.take_committed(Some(surface_id)) {
+                self.sync_feedback_output(&feedback, Some(output_id));
+                send_presented(&feedback, &info);
+            }
+        }
+    }
+
+    /// Send `sync_output` for each of the feedback client's wl_output
+    /// objects bound to `output_id`
+    fn sync_feedback_output(
+        &self,
+        feedback: &crate::core::wayland::ext::presentation_time::PresentationFeedback,
+        output_id: Option<u32>,
+    ) {
+        let client = match feedback.callback.client() {
+            Some(client) => client,
+            None => return,
+        };
+        for (object_id, output) in &self.output_resources {
+            if output.client().as_ref() == Some(&client)
+                && self.output_id_by_resource.get(object_id).copied() == output_id
+            {
+                feedback.callback.sync_output(output);
+            }
+        }
+    }
+
+    // =========================================================================
+    // Per-Output Frame Scheduling
+    // =========================================================================
+
+    /// Internal ids of the surfaces the scene shows on each output.
+    ///
+    /// A surface spanning several outputs is listed under each of them.
+    /// Worked out once per scene build; the scene is only rebuilt when
+    /// something changed.
+    pub fn visible_surfaces_by_output(&mut self) -> &HashMap<u32, Vec<u32>> {
+        use crate::util::geometry::Rect;
+
+        self.refresh_scene();
+        if self.output_surfaces.is_none() {
+            let flattened = self.scene.flatten();
+            let by_output = self.outputs.iter()
+                .map(|output| {
+                    let output_rect = Rect::new(output.x, output.y, output.width, output.height);
+                    let surfaces: Vec<u32> = flattened
+                        .iter()
+                        .filter(|s| Rect::new(s.x, s.y, s.width, s.height).intersects(&output_rect))
+                        .map(|s| s.surface_id)
+                        .collect();
+                    (output.id, surfaces)
+                })
+                .collect();
+            self.output_surfaces = Some(by_output);
+        }
+        self.output_surfaces.get_or_insert_with(HashMap::new)
+    }
+
+    /// Internal ids of the surfaces the scene shows on `output_id`
+    pub fn surfaces_on_output(&mut self, output_id: u32) -> Vec<u32> {
+        self.visible_surfaces_by_output()
+            .get(&output_id)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Combined content-type / tearing-control hint for a set of surfaces
+    pub fn refresh_hint_for(&self, surfaces: &[u32]) -> crate::core::time::RefreshHint {
+        use crate::core::time::RefreshHint;
+        use crate::core::wayland::ext::content_type::ContentType;
+
+        surfaces
+            .iter()
+            .map(|&id| {
+                if self.ext.tearing_control.prefers_tearing(id) {
+                    RefreshHint::Tearing
+                } else {
+                    match self.ext.content_type.get(id) {
+                        ContentType::Game => RefreshHint::Game,
+                        ContentType::Video => RefreshHint::Video,
+                        ContentType::Photo | ContentType::None => RefreshHint::Default,
+                    }
+                }
+            })
+            .max()
+            .unwrap_or_default()
+    }
+
+    /// Flush frame callbacks for the surfaces visible on one output.
+    ///
+    /// Called on that output's vblank so that clients drawing to a 120 Hz
+    /// display are not held to the rate of a slower one. Surfaces visible on
+    /// several outputs run at the rate of the fastest. Hidden surfaces and
+    /// surfaces outside the scene (cursors, drag icons) belong to no output
+    /// and are serviced on the primary output's vblank, the hidden ones at
+    /// the rate `frame_throttle` allows.
+    pub fn flush_output_frame_callbacks(&mut self, output_id: u32, timestamp: Option<u32>) {
+        use crate::util::geometry::Rect;
+
+        if self.frame_callbacks.is_empty() {
+            return;
+        }
+        let timestamp = timestamp.unwrap_or_else(Self::get_timestamp_ms);
+        // One flattening serves both the visibility and the output check
+        self.refresh_scene();
+        let flattened = self.scene.flatten_unculled();
+        let visibility = self.surface_visibility(&flattened);
+        let output_rect = self.outputs.iter()
+            .find(|o| o.id == output_id)
+            .map(|o| Rect::new(o.x, o.y, o.width, o.height));
+        let mut surface_ids: Vec<u32> = crate::core::render::scene::Scene::cull(flattened)
+            .iter()
+            .filter(|s| output_rect.map_or(false, |r| Rect::new(s.x, s.y, s.width, s.height).intersects(&r)))
+            .map(|s| s.surface_id)
+            .collect();
+        if self.outputs.get(self.primary_output).map(|o| o.id) == Some(output_id) {
+            surface_ids.extend(
+                self.frame_callbacks.keys()
+                    .filter(|id| visibility.get(id).map_or(true, |v| !v.is_visible()))
+                    .copied(),
+            );
+        }
+        self.flush_throttled_frame_callbacks(&surface_ids, &visibility, timestamp);
     }
 
     // =========================================================================
@@ -426,12 +1212,28 @@ impl CompositorState {
         }
 
         if changed {
+            self.scene_dirty = true;
             crate::core::wayland::wayland::output::notify_output_change(self, output_id);
+            #[cfg(feature = "desktop-protocols")]
+            self.session_lock_outputs_changed();
         }
 
         true
     }
     
+    /// Set the adaptive-sync range of an output. `min_refresh` is in mHz;
+    /// 0 marks the output as fixed-rate.
+    pub fn set_output_vrr_range(&mut self, output_id: u32, min_refresh: u32) -> bool {
+        match self.outputs.iter_mut().find(|o| o.id == output_id) {
+            Some(output) => {
+                output.min_refresh = min_refresh;
+                tracing::info!("Output {} adaptive sync: min {}mHz", output_id, min_refresh);
+                true
+            }
+            None => false,
+        }
+    }
+    
     // =========================================================================
     // Idle Inhibition
     // =========================================================================
@@ -441,8 +1243,10 @@ impl CompositorState {
     // =========================================================================
     
     /// Add a layer surface
-    pub fn add_layer_surface(&mut self, client_id: ClientId, surface: LayerSurface) -> u32 {
+    pub fn add_layer_surface(&mut self, client_id: ClientId, mut surface: LayerSurface) -> u32 {
         let id = surface.surface_id;
+        surface.sequence = self.wlr.next_layer_sequence;
+        self.wlr.next_layer_sequence += 1;
         self.wlr.layer_surfaces.insert((client_id.clone(), id), Arc::new(RwLock::new(surface)));
         tracing::debug!("Added layer surface {}", id);
         id
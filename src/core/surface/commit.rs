@@ -1,5 +1,6 @@
 use crate::core::surface::surface::SurfaceState;
 use crate::core::surface::damage::DamageRegion;
+use crate::core::render::transform::swaps_axes;
 
 /// Validates and clamps region rectangles to surface bounds.
 /// Returns None for regions that pass validation, or clamps out-of-bounds ones.
@@ -26,6 +27,29 @@ fn validate_regions(regions: &Option<Vec<DamageRegion>>, width: i32, height: i32
     })
 }
 
+/// Size in surface coordinates that `state`'s buffer gives the surface,
+/// after buffer scale, transform and viewport; None without a buffer.
+pub fn surface_size(state: &SurfaceState) -> Option<(i32, i32)> {
+    let (buffer_width, buffer_height) = state.buffer.dimensions()?;
+    let scale = state.scale.max(1);
+
+    // The viewport destination wins; a source crop alone sets the
+    // size to the (integral) source size
+    if let Some(size) = state.viewport_destination {
+        return Some(size);
+    }
+    if let Some(source) = state.viewport_source {
+        return Some((source.width as i32, source.height as i32));
+    }
+
+    // Handle transforms that swap width/height
+    if swaps_axes(state.transform) {
+        Some((buffer_height / scale, buffer_width / scale))
+    } else {
+        Some((buffer_width / scale, buffer_height / scale))
+    }
+}
+
 /// Performs the atomic update of a surface state.
 /// Returns the ID of the buffer that was replaced and should be released, if any.
 pub fn apply_commit(pending: &mut SurfaceState, current: &mut SurfaceState) -> Option<u32> {
@@ -40,32 +64,12 @@ pub fn apply_commit(pending: &mut SurfaceState, current: &mut SurfaceState) -> O
     current.buffer = pending.buffer.clone();
     current.buffer_id = pending.buffer_id;
     
-    // 2. Update dimensions based on buffer size, scale and transform
-    if let Some((buffer_width, buffer_height)) = current.buffer.dimensions() {
-        let scale = pending.scale.max(1);
-        
-        // Handle transforms that swap width/height
-        let swapped = match pending.transform {
-            wayland_server::protocol::wl_output::Transform::_90 |
-            wayland_server::protocol::wl_output::Transform::_270 |
-            wayland_server::protocol::wl_output::Transform::Flipped90 |
-            wayland_server::protocol::wl_output::Transform::Flipped270 => true,
-            _ => false,
-        };
-        
-        if swapped {
-            current.width = buffer_height / scale;
-            current.height = buffer_width / scale;
-        } else {
-            current.width = buffer_width / scale;
-            current.height = buffer_height / scale;
-        }
-    } else {
-        current.width = 0;
-        current.height = 0;
-    }
+    // 2. Update dimensions based on buffer size, scale, transform and viewport
+    current.viewport_source = pending.viewport_source;
+    current.viewport_destination = pending.viewport_destination;
+    (current.width, current.height) = surface_size(pending).unwrap_or((0, 0));
     
-    // 3. Accumulate damage (clamp to surface bounds)
+    // 3. Accumulate damage (clamp to surface or buffer bounds)
     for region in pending.damage.drain(..) {
         if current.width > 0 && current.height > 0 {
             let clamped = region.clamp(current.width, current.height);
@@ -79,6 +83,17 @@ pub fn apply_commit(pending: &mut SurfaceState, current: &mut SurfaceState) -> O
         }
     }
     
+    let buffer_size = current.buffer.dimensions();
+    for region in pending.buffer_damage.drain(..) {
+        let region = match buffer_size {
+            Some((width, height)) if width > 0 && height > 0 => region.clamp(width, height),
+            _ => region,
+        };
+        if region.is_valid() {
+            current.buffer_damage.push(region);
+        }
+    }
+    
     // 4. Update other attributes
     current.opaque = pending.opaque;
     current.scale = pending.scale;
@@ -0,0 +1,148 @@
+//! Per-surface queue of commits held back by a wp_fifo_v1 barrier or a
+//! wp_commit_timing_v1 target time.
+//!
+//! Commits on one surface are applied strictly in order: once a commit is
+//! held, every later commit on that surface queues behind it.
+
+use std::collections::{HashMap, VecDeque};
+use wayland_server::protocol::wl_callback::WlCallback;
+
+use super::surface::SurfaceState;
+use crate::core::wayland::ext::presentation_time::PresentationFeedback;
+
+/// A commit whose state has been snapshotted but not yet applied
+#[derive(Debug)]
+pub struct QueuedCommit {
+    pub state: SurfaceState,
+    /// Raise a FIFO barrier once applied
+    pub set_barrier: bool,
+    /// Wait for the surface's FIFO barrier to be lifted
+    pub wait_barrier: bool,
+    /// Earliest presentation time, CLOCK_MONOTONIC nanoseconds
+    pub target_ns: Option<u64>,
+    /// Frame callbacks requested for this commit
+    pub frame_callbacks: Vec<WlCallback>,
+    /// wp_presentation feedback requested for this commit
+    pub presentation_feedback: Vec<PresentationFeedback>,
+}
+
+impl QueuedCommit {
+    /// Whether the commit may be applied for presentation at `vblank_ns`.
+    pub fn is_ready(&self, barrier_up: bool, vblank_ns: u64) -> bool {
+        if self.wait_barrier && barrier_up {
+            return false;
+        }
+        !matches!(self.target_ns, Some(target) if target > vblank_ns)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct CommitQueue {
+    queues: HashMap<u32, VecDeque<QueuedCommit>>,
+    /// Number of frame callbacks that already belong to applied commits
+    callback_marks: HashMap<u32, usize>,
+}
+
+impl CommitQueue {
+    /// Whether a surface has commits waiting
+    pub fn has_queued(&self, surface_id: u32) -> bool {
+        self.queues.get(&surface_id).is_some_and(|q| !q.is_empty())
+    }
+
+    pub fn push(&mut self, surface_id: u32, commit: QueuedCommit) {
+        self.queues.entry(surface_id).or_default().push_back(commit);
+    }
+
+    /// Pop the oldest commit of a surface if it is ready.
+    pub fn pop_ready(&mut self, surface_id: u32, barrier_up: bool, vblank_ns: u64) -> Option<QueuedCommit> {
+        let queue = self.queues.get_mut(&surface_id)?;
+        if !queue.front()?.is_ready(barrier_up, vblank_ns) {
+            return None;
+        }
+        let commit = queue.pop_front();
+        if queue.is_empty() {
+            self.queues.remove(&surface_id);
+        }
+        commit
+    }
+
+    /// Surfaces with queued commits
+    pub fn surfaces(&self) -> Vec<u32> {
+        self.queues.keys().copied().collect()
+    }
+
+    /// Earliest target time among queued commits, for scheduling wakeups
+    pub fn next_target_ns(&self) -> Option<u64> {
+        self.queues.values().filter_map(|q| q.front()).filter_map(|c| c.target_ns).min()
+    }
+
+    /// Drop everything queued for a destroyed surface
+    pub fn remove_surface(&mut self, surface_id: u32) -> Option<VecDeque<QueuedCommit>> {
+        self.callback_marks.remove(&surface_id);
+        self.queues.remove(&surface_id)
+    }
+
+    /// Record how many of a surface's frame callbacks belong to applied commits
+    pub fn mark_callbacks(&mut self, surface_id: u32, count: usize) {
+        self.callback_marks.insert(surface_id, count);
+    }
+
+    /// Frame callbacks before this index belong to applied commits
+    pub fn callback_mark(&self, surface_id: u32) -> usize {
+        self.callback_marks.get(&surface_id).copied().unwrap_or(0)
+    }
+
+    /// The surface's frame callbacks were flushed
+    pub fn forget_callbacks(&mut self, surface_id: u32) {
+        self.callback_marks.remove(&surface_id);
+    }
+
+    pub fn forget_all_callbacks(&mut self) {
+        self.callback_marks.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn commit(wait_barrier: bool, target_ns: Option<u64>) -> QueuedCommit {
+        QueuedCommit {
+            state: SurfaceState::default(),
+            set_barrier: false,
+            wait_barrier,
+            target_ns,
+            frame_callbacks: Vec::new(),
+            presentation_feedback: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_barrier_holds_commit() {
+        let mut queue = CommitQueue::default();
+        queue.push(1, commit(true, None));
+        assert!(queue.pop_ready(1, true, 0).is_none());
+        assert!(queue.pop_ready(1, false, 0).is_some());
+        assert!(!queue.has_queued(1));
+    }
+
+    #[test]
+    fn test_target_time_holds_commit() {
+        let mut queue = CommitQueue::default();
+        queue.push(1, commit(false, Some(2_000)));
+        assert_eq!(queue.next_target_ns(), Some(2_000));
+        assert!(queue.pop_ready(1, false, 1_999).is_none());
+        assert!(queue.pop_ready(1, false, 2_000).is_some());
+    }
+
+    #[test]
+    fn test_commits_apply_in_order() {
+        let mut queue = CommitQueue::default();
+        queue.push(1, commit(false, Some(5_000)));
+        queue.push(1, commit(false, None));
+        // The untimed commit must not overtake the timed one
+        assert!(queue.pop_ready(1, false, 1_000).is_none());
+        assert!(queue.pop_ready(1, false, 5_000).unwrap().target_ns.is_some());
+        assert!(queue.pop_ready(1, false, 5_000).unwrap().target_ns.is_none());
+    }
+}
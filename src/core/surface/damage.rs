@@ -1,4 +1,7 @@
 
+use crate::util::geometry::Rect;
+use crate::util::region::Region;
+
 #[derive(Debug, Clone, Default, Copy, PartialEq, Eq)]
 pub struct DamageRegion {
     pub x: i32,
@@ -52,6 +55,15 @@ impl DamageRegion {
         px >= self.x && px < self.x + self.width && py >= self.y && py < self.y + self.height
     }
 
+    /// Convert to a geometry rect (negative sizes become empty)
+    pub fn to_rect(&self) -> Rect {
+        Rect::new(self.x, self.y, self.width.max(0) as u32, self.height.max(0) as u32)
+    }
+
+    pub fn from_rect(rect: &Rect) -> Self {
+        Self::new(rect.x, rect.y, rect.width as i32, rect.height as i32)
+    }
+
     /// Clamp this region to the given bounds (0,0,max_w,max_h)
     pub fn clamp(&self, max_width: i32, max_height: i32) -> DamageRegion {
         let x = self.x.max(0);
@@ -70,7 +82,7 @@ impl DamageRegion {
 /// Tracks accumulated damage over multiple commits
 #[derive(Debug, Clone, Default)]
 pub struct DamageHistory {
-    pub regions: Vec<DamageRegion>,
+    region: Region,
 }
 
 impl DamageHistory {
@@ -78,57 +90,31 @@ impl DamageHistory {
         if !region.is_valid() {
             return;
         }
-
-        // Try to merge with an existing touching/overlapping region
-        for existing in self.regions.iter_mut() {
-            if existing.touches(&region) {
-                *existing = existing.union(&region);
-                return;
-            }
-        }
-        self.regions.push(region);
-        self.merge_pass();
-    }
-
-    /// Run a merge pass to collapse any regions that now overlap after additions
-    fn merge_pass(&mut self) {
-        let mut merged = true;
-        while merged {
-            merged = false;
-            let mut i = 0;
-            while i < self.regions.len() {
-                let mut j = i + 1;
-                while j < self.regions.len() {
-                    if self.regions[i].touches(&self.regions[j]) {
-                        let combined = self.regions[i].union(&self.regions[j]);
-                        self.regions[i] = combined;
-                        self.regions.swap_remove(j);
-                        merged = true;
-                    } else {
-                        j += 1;
-                    }
-                }
-                i += 1;
-            }
-        }
+        self.region.add_rect(region.to_rect());
     }
 
-    /// Add multiple regions, merging as we go
+    /// Add multiple regions
     pub fn add_regions(&mut self, regions: &[DamageRegion]) {
         for region in regions {
-            if region.is_valid() {
-                self.regions.push(*region);
-            }
+            self.add(*region);
         }
-        self.merge_pass();
+    }
+
+    /// The accumulated damage as non-overlapping rectangles
+    pub fn regions(&self) -> Vec<DamageRegion> {
+        self.region.rects().iter().map(DamageRegion::from_rect).collect()
+    }
+
+    pub fn region(&self) -> &Region {
+        &self.region
     }
 
     pub fn clear(&mut self) {
-        self.regions.clear();
+        self.region.clear();
     }
 
     pub fn is_empty(&self) -> bool {
-        self.regions.is_empty()
+        self.region.is_empty()
     }
 }
 
@@ -141,8 +127,10 @@ mod tests {
         let mut history = DamageHistory::default();
         history.add(DamageRegion::new(0, 0, 100, 100));
         history.add(DamageRegion::new(50, 50, 100, 100));
-        assert_eq!(history.regions.len(), 1);
-        assert_eq!(history.regions[0], DamageRegion::new(0, 0, 150, 150));
+        // Exact union: the overlap is counted once, the corners stay clean
+        assert_eq!(history.region().area(), 17_500);
+        assert_eq!(history.region().bounding_box(), Some(Rect::new(0, 0, 150, 150)));
+        assert!(!history.region().contains_point(140, 10));
     }
 
     #[test]
@@ -150,8 +138,7 @@ mod tests {
         let mut history = DamageHistory::default();
         history.add(DamageRegion::new(0, 0, 100, 100));
         history.add(DamageRegion::new(100, 0, 100, 100));
-        assert_eq!(history.regions.len(), 1);
-        assert_eq!(history.regions[0], DamageRegion::new(0, 0, 200, 100));
+        assert_eq!(history.regions(), vec![DamageRegion::new(0, 0, 200, 100)]);
     }
 
     #[test]
@@ -159,7 +146,7 @@ mod tests {
         let mut history = DamageHistory::default();
         history.add(DamageRegion::new(0, 0, 50, 50));
         history.add(DamageRegion::new(200, 200, 50, 50));
-        assert_eq!(history.regions.len(), 2);
+        assert_eq!(history.regions().len(), 2);
     }
 
     #[test]
@@ -167,7 +154,7 @@ mod tests {
         let mut history = DamageHistory::default();
         history.add(DamageRegion::new(0, 0, 0, 100));
         history.add(DamageRegion::new(0, 0, -5, 10));
-        assert!(history.regions.is_empty());
+        assert!(history.is_empty());
     }
 
     #[test]
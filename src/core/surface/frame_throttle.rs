@@ -0,0 +1,115 @@
+//! Frame callback throttling for surfaces that cannot be seen.
+//!
+//! Clients draw when their `wl_surface.frame` callback fires. Firing those
+//! for minimized, occluded or off-screen surfaces makes them render frames
+//! nobody sees, so callbacks for hidden surfaces are held back or slowed
+//! down according to a [`FrameThrottleConfig`].
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use crate::core::render::visibility::SurfaceVisibility;
+
+/// What to do with frame callbacks of a hidden surface
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HiddenFramePolicy {
+    /// Fire them like for visible surfaces
+    FullRate,
+    /// Fire them at most once per `interval`
+    Throttle { interval: Duration },
+    /// Hold them until the surface becomes visible again
+    Hold,
+}
+
+/// Policy per reason a surface is hidden
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameThrottleConfig {
+    pub occluded: HiddenFramePolicy,
+    pub minimized: HiddenFramePolicy,
+    pub inactive_workspace: HiddenFramePolicy,
+    pub off_screen: HiddenFramePolicy,
+}
+
+impl Default for FrameThrottleConfig {
+    fn default() -> Self {
+        // Occluded and off-screen surfaces keep ticking slowly so clients
+        // that wait on frame callbacks before tearing down still progress.
+        let slow = HiddenFramePolicy::Throttle { interval: Duration::from_secs(1) };
+        Self {
+            occluded: slow,
+            minimized: HiddenFramePolicy::Hold,
+            inactive_workspace: HiddenFramePolicy::Hold,
+            off_screen: slow,
+        }
+    }
+}
+
+impl FrameThrottleConfig {
+    pub fn policy_for(&self, visibility: SurfaceVisibility) -> HiddenFramePolicy {
+        match visibility {
+            SurfaceVisibility::Visible => HiddenFramePolicy::FullRate,
+            SurfaceVisibility::Occluded => self.occluded,
+            SurfaceVisibility::Minimized => self.minimized,
+            SurfaceVisibility::InactiveWorkspace => self.inactive_workspace,
+            SurfaceVisibility::OffScreen => self.off_screen,
+        }
+    }
+}
+
+/// Decides per surface whether pending frame callbacks may fire now
+#[derive(Debug, Default)]
+pub struct FrameThrottle {
+    pub config: FrameThrottleConfig,
+    /// When callbacks of each throttled surface last fired
+    last_fired: HashMap<u32, Instant>,
+}
+
+impl FrameThrottle {
+    /// Whether callbacks for `surface_id` may fire at `now`. Records the
+    /// firing when they may.
+    pub fn should_fire(&mut self, surface_id: u32, visibility: SurfaceVisibility, now: Instant) -> bool {
+        match self.config.policy_for(visibility) {
+            HiddenFramePolicy::FullRate => {
+                self.last_fired.remove(&surface_id);
+                true
+            }
+            HiddenFramePolicy::Hold => false,
+            HiddenFramePolicy::Throttle { interval } => {
+                let due = self
+                    .last_fired
+                    .get(&surface_id)
+                    .map_or(true, |&last| now.saturating_duration_since(last) >= interval);
+                if due {
+                    self.last_fired.insert(surface_id, now);
+                }
+                due
+            }
+        }
+    }
+
+    pub fn remove_surface(&mut self, surface_id: u32) {
+        self.last_fired.remove(&surface_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_policies() {
+        let mut throttle = FrameThrottle::default();
+        let now = Instant::now();
+
+        assert!(throttle.should_fire(1, SurfaceVisibility::Visible, now));
+        assert!(!throttle.should_fire(1, SurfaceVisibility::Minimized, now));
+
+        // Throttled surfaces fire once, then wait out the interval
+        assert!(throttle.should_fire(2, SurfaceVisibility::Occluded, now));
+        assert!(!throttle.should_fire(2, SurfaceVisibility::Occluded, now + Duration::from_millis(500)));
+        assert!(throttle.should_fire(2, SurfaceVisibility::Occluded, now + Duration::from_secs(1)));
+
+        throttle.config.minimized = HiddenFramePolicy::FullRate;
+        assert!(throttle.should_fire(1, SurfaceVisibility::Minimized, now));
+    }
+}
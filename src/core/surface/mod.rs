@@ -2,6 +2,8 @@ pub mod surface;
 pub mod buffer;
 pub mod role;
 pub mod commit;
+pub mod commit_queue;
+pub mod frame_throttle;
 pub mod damage;
 
 pub use surface::{Surface, SurfaceState};
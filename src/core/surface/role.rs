@@ -6,6 +6,7 @@ pub enum SurfaceRole {
     Subsurface,
     Cursor,
     Layer,
+    SessionLock,
 }
 
 impl Default for SurfaceRole {
@@ -27,6 +28,7 @@ impl SurfaceRole {
             SurfaceRole::Subsurface => "subsurface",
             SurfaceRole::Cursor => "cursor",
             SurfaceRole::Layer => "layer",
+            SurfaceRole::SessionLock => "session_lock",
         }
     }
 }
@@ -13,12 +13,19 @@ pub struct SurfaceState {
     pub width: i32,
     pub height: i32,
     pub offset: (i32, i32),
+    /// Surface-local damage (`wl_surface.damage`)
     pub damage: Vec<DamageRegion>,
+    /// Damage in buffer pixels (`wl_surface.damage_buffer`)
+    pub buffer_damage: Vec<DamageRegion>,
     pub input_region: Option<Vec<crate::core::surface::damage::DamageRegion>>, // Using DamageRegion as Rect equivalent for now
     pub opaque_region: Option<Vec<crate::core::surface::damage::DamageRegion>>,
     pub opaque: bool,
     pub scale: i32,
     pub transform: wayland_server::protocol::wl_output::Transform, // Using the protocol enum directly
+    /// wp_viewport source crop (None = whole buffer)
+    pub viewport_source: Option<crate::core::render::transform::SourceRect>,
+    /// wp_viewport destination size (None = derived from the source or buffer)
+    pub viewport_destination: Option<(i32, i32)>,
 }
 
 /// Represents a Wayland Surface.
@@ -86,6 +93,7 @@ impl Surface {
         let new_cached = self.pending.clone();
         // Reset pending damage for next commit
         self.pending.damage.clear();
+        self.pending.buffer_damage.clear();
         
         self.cached = Some(new_cached);
         
@@ -97,6 +105,28 @@ impl Surface {
         old_buffer_id
     }
 
+    /// Snapshot the pending state for a commit that has to wait in the
+    /// commit queue (FIFO barrier or commit-timing target).
+    pub fn commit_queued(&mut self) -> SurfaceState {
+        let snapshot = self.pending.clone();
+        self.pending.damage.clear();
+        self.pending.buffer_damage.clear();
+        snapshot
+    }
+
+    /// Apply a state previously taken with `commit_queued`.
+    /// Returns the ID of the buffer to release, if any.
+    pub fn apply_queued(&mut self, mut state: SurfaceState) -> Option<u32> {
+        let release_id = super::commit::apply_commit(&mut state, &mut self.current);
+
+        tracing::debug!(
+            "Surface {} applied queued state: {}x{}, buffer={:?}",
+            self.id, self.current.width, self.current.height, self.current.buffer
+        );
+
+        release_id
+    }
+
     /// Apply the cached state to current
     /// Returns the ID of the buffer to release, if any.
     pub fn apply_cached(&mut self) -> Option<u32> {
@@ -124,11 +154,14 @@ impl Default for SurfaceState {
             height: 0,
             offset: (0, 0),
             damage: Vec::new(),
+            buffer_damage: Vec::new(),
             input_region: None, // None means infinite (accept all input)
             opaque_region: None, // None means empty (fully transparent)
             opaque: false, // Legacy field, might be redundant with opaque_region but kept for now
             scale: 1,
             transform: wayland_server::protocol::wl_output::Transform::Normal,
+            viewport_source: None,
+            viewport_destination: None,
         }
     }
 }
@@ -70,6 +70,49 @@ fn test_surface_damage() {
     assert_eq!(surface.current.damage[1], region2);
 }
 
+#[test]
+fn test_surface_buffer_damage_kept_apart() {
+    let mut surface = Surface::new(3, None);
+    surface.pending.damage.push(DamageRegion::new(0, 0, 10, 10));
+    surface.pending.buffer_damage.push(DamageRegion::new(20, 20, 40, 40));
+    surface.commit();
+
+    assert_eq!(surface.current.damage, vec![DamageRegion::new(0, 0, 10, 10)]);
+    assert_eq!(surface.current.buffer_damage, vec![DamageRegion::new(20, 20, 40, 40)]);
+    assert!(surface.pending.buffer_damage.is_empty());
+}
+
+#[test]
+fn test_surface_size_with_transform_and_viewport() {
+    use crate::core::render::transform::SourceRect;
+    use crate::core::surface::buffer::{BufferType, ShmBufferData};
+    use wayland_server::protocol::wl_output::Transform;
+
+    let mut surface = Surface::new(4, None);
+    surface.pending.buffer = BufferType::Shm(ShmBufferData {
+        width: 200,
+        height: 100,
+        stride: 800,
+        format: 0,
+        offset: 0,
+        pool_id: 1,
+    });
+    surface.pending.buffer_id = Some(1);
+    surface.pending.transform = Transform::_90;
+    surface.commit();
+    assert_eq!((surface.current.width, surface.current.height), (100, 200));
+
+    // A source crop alone sizes the surface to the crop
+    surface.pending.viewport_source = Some(SourceRect::new(0.0, 0.0, 50.0, 60.0));
+    surface.commit();
+    assert_eq!((surface.current.width, surface.current.height), (50, 60));
+
+    // The destination wins over both
+    surface.pending.viewport_destination = Some((300, 300));
+    surface.commit();
+    assert_eq!((surface.current.width, surface.current.height), (300, 300));
+}
+
 #[test]
 fn test_surface_role() {
     let mut surface = Surface::new(3, None);
@@ -105,6 +105,17 @@ impl FrameClock {
         target_vblank.checked_sub(total_margin).unwrap_or_else(Instant::now)
     }
     
+    /// Replace the refresh interval, e.g. after an output mode change
+    pub fn set_refresh_interval(&mut self, interval: Duration) {
+        self.refresh_interval = interval;
+        self.interval_history.clear();
+    }
+    
+    /// Timestamp of the last known VBlank
+    pub fn last_vblank(&self) -> Instant {
+        self.last_vblank
+    }
+    
     /// Get the estimated refresh interval
     pub fn refresh_interval(&self) -> Duration {
         self.refresh_interval
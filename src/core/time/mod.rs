@@ -1,3 +1,7 @@
 pub mod frame_clock;
+pub mod monotonic;
+pub mod output_clock;
 
 pub use frame_clock::FrameClock;
+pub use monotonic::{instant_to_monotonic_ns, monotonic_ns, monotonic_ns_to_instant};
+pub use output_clock::{OutputClock, RefreshHint};
@@ -0,0 +1,48 @@
+//! Conversions between `Instant` and CLOCK_MONOTONIC nanosecond timestamps,
+//! the clock domain used on the wire by wp_presentation and
+//! wp_commit_timing_v1.
+
+use std::time::{Duration, Instant};
+
+/// Current CLOCK_MONOTONIC time in nanoseconds.
+pub fn monotonic_ns() -> u64 {
+    let mut ts = libc::timespec { tv_sec: 0, tv_nsec: 0 };
+    // SAFETY: `ts` is a valid, writable timespec.
+    unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut ts) };
+    ts.tv_sec as u64 * 1_000_000_000 + ts.tv_nsec as u64
+}
+
+/// Convert an `Instant` into CLOCK_MONOTONIC nanoseconds.
+pub fn instant_to_monotonic_ns(instant: Instant) -> u64 {
+    let now = Instant::now();
+    let now_ns = monotonic_ns();
+    if instant >= now {
+        now_ns.saturating_add((instant - now).as_nanos() as u64)
+    } else {
+        now_ns.saturating_sub((now - instant).as_nanos() as u64)
+    }
+}
+
+/// Convert CLOCK_MONOTONIC nanoseconds into an `Instant`.
+pub fn monotonic_ns_to_instant(ns: u64) -> Instant {
+    let now = Instant::now();
+    let now_ns = monotonic_ns();
+    if ns >= now_ns {
+        now + Duration::from_nanos(ns - now_ns)
+    } else {
+        now.checked_sub(Duration::from_nanos(now_ns - ns)).unwrap_or(now)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let target = Instant::now() + Duration::from_millis(20);
+        let back = monotonic_ns_to_instant(instant_to_monotonic_ns(target));
+        let error = if back > target { back - target } else { target - back };
+        assert!(error < Duration::from_millis(1));
+    }
+}
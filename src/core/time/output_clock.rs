@@ -0,0 +1,221 @@
+use std::time::{Duration, Instant};
+
+use super::FrameClock;
+
+/// How long an adaptive-sync output has to go without damage before it
+/// ramps down to its minimum refresh rate.
+pub const VRR_IDLE_THRESHOLD: Duration = Duration::from_millis(250);
+
+/// Damage gaps longer than this are treated as idle time rather than as the
+/// cadence of the content.
+const CADENCE_MAX_GAP: Duration = Duration::from_millis(100);
+
+/// Scheduling hint derived from the surfaces visible on an output.
+///
+/// Ordered by priority: when several surfaces share an output the highest
+/// hint wins.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum RefreshHint {
+    /// No hint — ramp down when idle on adaptive-sync outputs
+    #[default]
+    Default,
+    /// Video — follow the content's frame cadence
+    Video,
+    /// Game — run at the highest rate the output supports
+    Game,
+    /// A surface asked for async (tearing) presentation
+    Tearing,
+}
+
+/// Frame clock for a single output.
+///
+/// Wraps a [`FrameClock`] that follows the output's nominal refresh and adds
+/// a variable-refresh policy on top: outputs with an adaptive-sync range can
+/// stretch the interval between frames, down to their minimum rate, depending
+/// on the [`RefreshHint`] of what they show.
+#[derive(Debug, Clone)]
+pub struct OutputClock {
+    clock: FrameClock,
+    /// Nominal (maximum) refresh rate in mHz
+    refresh_mhz: u32,
+    /// Minimum refresh rate in mHz for adaptive-sync outputs
+    min_refresh_mhz: Option<u32>,
+    hint: RefreshHint,
+    last_damage: Option<Instant>,
+    /// Smoothed interval between damaged frames
+    content_interval: Option<Duration>,
+}
+
+impl OutputClock {
+    /// Create a clock for an output refreshing at `refresh_mhz` (0 = 60 Hz).
+    pub fn new(refresh_mhz: u32) -> Self {
+        let refresh_mhz = if refresh_mhz == 0 { 60_000 } else { refresh_mhz };
+        Self {
+            clock: FrameClock::new(interval_for_mhz(refresh_mhz)),
+            refresh_mhz,
+            min_refresh_mhz: None,
+            hint: RefreshHint::Default,
+            last_damage: None,
+            content_interval: None,
+        }
+    }
+
+    /// Follow a change of the output's nominal refresh rate.
+    pub fn set_refresh(&mut self, refresh_mhz: u32) {
+        if refresh_mhz == 0 || refresh_mhz == self.refresh_mhz {
+            return;
+        }
+        self.refresh_mhz = refresh_mhz;
+        self.clock.set_refresh_interval(interval_for_mhz(refresh_mhz));
+    }
+
+    /// Nominal refresh rate in mHz
+    pub fn refresh_mhz(&self) -> u32 {
+        self.refresh_mhz
+    }
+
+    /// Enable adaptive sync down to `min_refresh_mhz`, or disable it with
+    /// `None`. A minimum at or above the nominal rate disables it as well.
+    pub fn set_vrr_range(&mut self, min_refresh_mhz: Option<u32>) {
+        self.min_refresh_mhz = min_refresh_mhz.filter(|&min| min > 0 && min < self.refresh_mhz);
+    }
+
+    /// Whether the output supports variable refresh
+    pub fn is_variable(&self) -> bool {
+        self.min_refresh_mhz.is_some()
+    }
+
+    pub fn set_hint(&mut self, hint: RefreshHint) {
+        self.hint = hint;
+    }
+
+    pub fn hint(&self) -> RefreshHint {
+        self.hint
+    }
+
+    /// Whether frames on this output may be presented without waiting for
+    /// vblank
+    pub fn allows_tearing(&self) -> bool {
+        self.hint == RefreshHint::Tearing
+    }
+
+    /// Record that content on this output was damaged at `now`.
+    pub fn note_damage(&mut self, now: Instant) {
+        if let Some(last) = self.last_damage {
+            let gap = now.saturating_duration_since(last);
+            if !gap.is_zero() && gap <= CADENCE_MAX_GAP {
+                self.content_interval = Some(match self.content_interval {
+                    Some(avg) => (avg * 3 + gap) / 4,
+                    None => gap,
+                });
+            }
+        }
+        self.last_damage = Some(now);
+    }
+
+    /// Feed a presentation reported by the platform.
+    pub fn report_presentation(&mut self, timestamp: Instant, refresh_mhz: u32) {
+        // On adaptive-sync outputs the reported rate is the instantaneous
+        // one; the nominal rate only changes through `set_refresh`.
+        let refresh_mhz = if self.is_variable() { 0 } else { refresh_mhz };
+        self.clock.update_vblank(timestamp, refresh_mhz);
+    }
+
+    /// Interval the next frame should be scheduled at.
+    ///
+    /// Fixed-rate outputs always use their nominal interval. Adaptive-sync
+    /// outputs run games and tearing surfaces at the fastest rate, lock video
+    /// to its own cadence and ramp down to the minimum rate once idle.
+    pub fn target_interval(&self, now: Instant) -> Duration {
+        let nominal = self.clock.refresh_interval();
+        let min_mhz = match self.min_refresh_mhz {
+            Some(min_mhz) => min_mhz,
+            None => return nominal,
+        };
+        let slowest = interval_for_mhz(min_mhz);
+
+        match self.hint {
+            RefreshHint::Game | RefreshHint::Tearing => nominal,
+            RefreshHint::Video => self
+                .content_interval
+                .map_or(nominal, |interval| interval.clamp(nominal, slowest)),
+            RefreshHint::Default => {
+                let idle = self
+                    .last_damage
+                    .map_or(true, |last| now.saturating_duration_since(last) >= VRR_IDLE_THRESHOLD);
+                if idle { slowest } else { nominal }
+            }
+        }
+    }
+
+    /// Predicted time of the next frame on this output.
+    pub fn next_frame(&self) -> Instant {
+        if !self.is_variable() {
+            return self.clock.next_vblank();
+        }
+        // With adaptive sync the display waits for us: the next frame is one
+        // target interval after the last one, or right away if that passed.
+        let now = Instant::now();
+        (self.clock.last_vblank() + self.target_interval(now)).max(now)
+    }
+
+    /// When rendering for the next frame should start, given how long
+    /// compositing is expected to take.
+    pub fn plan_render(&self, estimated_render_time: Duration) -> Instant {
+        if !self.is_variable() {
+            return self.clock.plan_render(estimated_render_time);
+        }
+        let margin = estimated_render_time + Duration::from_millis(2);
+        self.next_frame().checked_sub(margin).unwrap_or_else(Instant::now)
+    }
+}
+
+/// Duration of one refresh cycle at `refresh_mhz`
+pub fn interval_for_mhz(refresh_mhz: u32) -> Duration {
+    Duration::from_nanos(1_000_000_000_000 / refresh_mhz.max(1) as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fixed_rate_ignores_hints() {
+        let mut clock = OutputClock::new(120_000);
+        clock.set_hint(RefreshHint::Video);
+        assert_eq!(clock.target_interval(Instant::now()), interval_for_mhz(120_000));
+    }
+
+    #[test]
+    fn test_vrr_idle_ramp_down() {
+        let mut clock = OutputClock::new(120_000);
+        clock.set_vrr_range(Some(10_000));
+
+        let now = Instant::now();
+        clock.note_damage(now);
+        assert_eq!(clock.target_interval(now), interval_for_mhz(120_000));
+
+        let later = now + VRR_IDLE_THRESHOLD;
+        assert_eq!(clock.target_interval(later), interval_for_mhz(10_000));
+
+        // Games never ramp down
+        clock.set_hint(RefreshHint::Game);
+        assert_eq!(clock.target_interval(later), interval_for_mhz(120_000));
+    }
+
+    #[test]
+    fn test_vrr_video_follows_cadence() {
+        let mut clock = OutputClock::new(120_000);
+        clock.set_vrr_range(Some(24_000));
+        clock.set_hint(RefreshHint::Video);
+
+        // 24 fps content
+        let frame = Duration::from_nanos(41_666_667);
+        let start = Instant::now();
+        for i in 0..8 {
+            clock.note_damage(start + frame * i);
+        }
+        let interval = clock.target_interval(start + frame * 8);
+        assert!(interval > Duration::from_millis(40) && interval < Duration::from_millis(43));
+    }
+}
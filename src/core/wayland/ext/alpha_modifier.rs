@@ -7,7 +7,7 @@
 
 use std::collections::HashMap;
 use wayland_server::{
-    Client, DataInit, Dispatch, DisplayHandle, GlobalDispatch, New, Resource,
+    Client, DataInit, Dispatch, DisplayHandle, GlobalDispatch, New,
 };
 use wayland_protocols::wp::alpha_modifier::v1::server::{
     wp_alpha_modifier_v1::{self, WpAlphaModifierV1},
@@ -15,6 +15,7 @@ use wayland_protocols::wp::alpha_modifier::v1::server::{
 };
 
 use crate::core::state::CompositorState;
+use crate::core::wayland::wayland::compositor::internal_surface_id;
 
 // ============================================================================
 // Data Types
@@ -67,7 +68,7 @@ impl Dispatch<WpAlphaModifierV1, ()> for CompositorState {
     ) {
         match request {
             wp_alpha_modifier_v1::Request::GetSurface { id, surface } => {
-                let surface_id = surface.id().protocol_id();
+                let surface_id = internal_surface_id(&surface);
                 let _am = data_init.init(id, surface_id);
                 // Default: u32::MAX = 1.0 (no modification)
                 state.ext.alpha_modifier.surface_alpha.insert(surface_id, u32::MAX);
@@ -1,12 +1,13 @@
 //! Commit Timing protocol implementation.
 //!
 //! This protocol allows clients to specify a target presentation time
-//! for their next commit. The compositor can use this to schedule
-//! frame presentation more precisely (e.g., for video playback).
+//! for their next commit. The timestamp is consumed by the surface's next
+//! commit, which is held in the commit queue until the vblank that would
+//! present it no earlier than requested (e.g., for video playback).
 
 use std::collections::HashMap;
 use wayland_server::{
-    Client, DataInit, Dispatch, DisplayHandle, GlobalDispatch, New, Resource,
+    Client, DataInit, Dispatch, DisplayHandle, GlobalDispatch, New,
 };
 use wayland_protocols::wp::commit_timing::v1::server::{
     wp_commit_timing_manager_v1::{self, WpCommitTimingManagerV1},
@@ -14,6 +15,7 @@ use wayland_protocols::wp::commit_timing::v1::server::{
 };
 
 use crate::core::state::CompositorState;
+use crate::core::wayland::wayland::compositor::internal_surface_id;
 
 // ============================================================================
 // Data Types
@@ -32,7 +34,7 @@ impl CommitTimingState {
         self.target_times.get(&surface_id).copied()
     }
 
-    /// Clear the target time (after it's been consumed by the frame scheduler)
+    /// Take the target time for the commit being processed
     pub fn consume(&mut self, surface_id: u32) -> Option<u64> {
         self.target_times.remove(&surface_id)
     }
@@ -68,7 +70,7 @@ impl Dispatch<WpCommitTimingManagerV1, ()> for CompositorState {
     ) {
         match request {
             wp_commit_timing_manager_v1::Request::GetTimer { id, surface } => {
-                let surface_id = surface.id().protocol_id();
+                let surface_id = internal_surface_id(&surface);
                 let _timer = data_init.init(id, surface_id);
                 tracing::debug!("Created commit timer for surface {}", surface_id);
             }
@@ -5,7 +5,7 @@
 //! The compositor can use this hint to adjust frame scheduling and rendering.
 
 use wayland_server::{
-    Client, DataInit, Dispatch, DisplayHandle, GlobalDispatch, New, Resource,
+    Client, DataInit, Dispatch, DisplayHandle, GlobalDispatch, New,
 };
 use wayland_protocols::wp::content_type::v1::server::{
     wp_content_type_manager_v1::{self, WpContentTypeManagerV1},
@@ -13,6 +13,7 @@ use wayland_protocols::wp::content_type::v1::server::{
 };
 
 use crate::core::state::CompositorState;
+use crate::core::wayland::wayland::compositor::internal_surface_id;
 use std::collections::HashMap;
 
 // ============================================================================
@@ -85,7 +86,7 @@ impl Dispatch<WpContentTypeManagerV1, ()> for CompositorState {
     ) {
         match request {
             wp_content_type_manager_v1::Request::GetSurfaceContentType { id, surface } => {
-                let surface_id = surface.id().protocol_id();
+                let surface_id = internal_surface_id(&surface);
                 let _ct: wp_content_type_v1::WpContentTypeV1 = data_init.init(id, surface_id);
                 tracing::debug!("Created content type for surface {}", surface_id);
             }
@@ -7,7 +7,10 @@
 //! - MIME type negotiation
 
 
-use std::os::unix::io::AsFd;
+use std::io::{Read, Write};
+use std::os::unix::io::{AsFd, AsRawFd, FromRawFd, OwnedFd};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use wayland_server::{
     protocol::{
         wl_data_device::{self, WlDataDevice},
@@ -19,6 +22,7 @@ use wayland_server::{
     Client, DataInit, Dispatch, DisplayHandle, GlobalDispatch, New, Resource,
 };
 
+use crate::core::input::grab::{GrabKind, GrabStatus, InputGrab};
 use crate::core::state::CompositorState;
 
 use std::collections::HashMap;
@@ -80,6 +84,270 @@ pub struct DragState {
     pub serial: u32,
 }
 
+/// Pointer grab of a drag in progress: motion goes to data devices and
+/// releasing the last button drops
+#[derive(Debug)]
+pub struct DragGrab;
+
+impl InputGrab for DragGrab {
+    fn kind(&self) -> GrabKind {
+        GrabKind::Drag
+    }
+
+    fn pointer_motion(&mut self, state: &mut CompositorState, x: f64, y: f64, time: u32) -> GrabStatus {
+        if state.data.drag.is_none() {
+            return GrabStatus::End { consumed: false };
+        }
+        state.update_drag(x, y, time);
+        GrabStatus::Consumed
+    }
+
+    fn pointer_button(&mut self, state: &mut CompositorState, _button: u32, _pressed: bool, _time: u32) -> GrabStatus {
+        let Some(drag) = &state.data.drag else {
+            return GrabStatus::End { consumed: false };
+        };
+        if state.seat.pointer.button_count > 0 {
+            return GrabStatus::Consumed;
+        }
+        let has_focus = drag.focus_surface_id.is_some();
+        state.end_drag(has_focus);
+        GrabStatus::End { consumed: true }
+    }
+
+    fn cancel(&mut self, state: &mut CompositorState) {
+        state.end_drag(false);
+    }
+
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+}
+
+/// MIME types copied into the clipboard cache by default, most preferred first.
+pub const DEFAULT_CACHED_MIME_TYPES: &[&str] = &[
+    "text/plain;charset=utf-8",
+    "UTF8_STRING",
+    "text/plain",
+    "STRING",
+    "TEXT",
+    "text/uri-list",
+    "text/html",
+    "image/png",
+];
+
+/// A read of one MIME type from the selection source that has not hit EOF yet.
+#[derive(Debug)]
+struct PendingClipboardRead {
+    mime_type: String,
+    pipe: std::fs::File,
+    data: Vec<u8>,
+}
+
+/// Compositor-side clipboard cache.
+///
+/// When enabled, the preferred MIME types of every new selection source are
+/// read eagerly into memory. Once the owning client destroys the source or
+/// disconnects, the compositor takes over the selection and serves the cached
+/// bytes itself, so copied data survives the client exiting.
+#[derive(Debug)]
+pub struct ClipboardCache {
+    /// Whether the cache is active (opt-in)
+    pub enabled: bool,
+    /// Upper bound on the bytes held across all cached MIME types
+    pub max_bytes: usize,
+    /// MIME types worth caching, most preferred first
+    pub mime_types: Vec<String>,
+    /// How long a source gets to deliver its data before reads are abandoned
+    pub read_timeout: Duration,
+    /// Completed entries (mime type, contents) in preference order
+    entries: Vec<(String, Arc<[u8]>)>,
+    /// Reads still in flight
+    pending: Vec<PendingClipboardRead>,
+    /// When the current round of reads started
+    started_at: Option<Instant>,
+}
+
+impl Default for ClipboardCache {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_bytes: 8 * 1024 * 1024,
+            mime_types: DEFAULT_CACHED_MIME_TYPES.iter().map(|m| m.to_string()).collect(),
+            read_timeout: Duration::from_secs(2),
+            entries: Vec::new(),
+            pending: Vec::new(),
+            started_at: None,
+        }
+    }
+}
+
+impl ClipboardCache {
+    /// Drop all cached data and abandon in-flight reads.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.pending.clear();
+        self.started_at = None;
+    }
+
+    /// Start caching a new selection offering `offered` MIME types.
+    ///
+    /// Returns the write ends of the pipes that must be handed to the source
+    /// via its `send` event, one per MIME type selected for caching.
+    pub fn begin(&mut self, offered: &[String]) -> Vec<(String, OwnedFd)> {
+        self.clear();
+        if !self.enabled {
+            return Vec::new();
+        }
+
+        let mut writers = Vec::new();
+        for mime in &self.mime_types {
+            if !offered.contains(mime) {
+                continue;
+            }
+            match clipboard_pipe() {
+                Ok((read, write)) => {
+                    self.pending.push(PendingClipboardRead {
+                        mime_type: mime.clone(),
+                        pipe: std::fs::File::from(read),
+                        data: Vec::new(),
+                    });
+                    writers.push((mime.clone(), write));
+                }
+                Err(e) => {
+                    tracing::warn!("Clipboard cache: failed to create pipe for {}: {}", mime, e);
+                }
+            }
+        }
+
+        if !writers.is_empty() {
+            self.started_at = Some(Instant::now());
+        }
+        writers
+    }
+
+    /// Drain whatever the source has written so far without blocking.
+    ///
+    /// Returns true once no reads remain in flight.
+    pub fn poll(&mut self) -> bool {
+        if self.pending.is_empty() {
+            return true;
+        }
+
+        let timed_out = self.started_at
+            .map(|t| t.elapsed() >= self.read_timeout)
+            .unwrap_or(false);
+
+        let mut cached: usize = self.entries.iter().map(|(_, d)| d.len()).sum();
+        let mut buf = [0u8; 16 * 1024];
+        let mut still_pending = Vec::new();
+
+        for mut read in self.pending.drain(..) {
+            let done = loop {
+                match read.pipe.read(&mut buf) {
+                    Ok(0) => break true,
+                    Ok(n) => {
+                        if cached + read.data.len() + n > self.max_bytes {
+                            tracing::warn!(
+                                "Clipboard cache: {} exceeds the {} byte limit, not caching it",
+                                read.mime_type, self.max_bytes
+                            );
+                            read.data.clear();
+                            break true;
+                        }
+                        read.data.extend_from_slice(&buf[..n]);
+                    }
+                    Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                    Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => break false,
+                    Err(e) => {
+                        tracing::debug!("Clipboard cache: read of {} failed: {}", read.mime_type, e);
+                        read.data.clear();
+                        break true;
+                    }
+                }
+            };
+
+            if done {
+                if !read.data.is_empty() {
+                    cached += read.data.len();
+                    self.entries.push((read.mime_type, Arc::from(read.data)));
+                }
+            } else if timed_out {
+                tracing::debug!("Clipboard cache: gave up waiting for {}", read.mime_type);
+            } else {
+                still_pending.push(read);
+            }
+        }
+
+        self.pending = still_pending;
+        // Keep entries in preference order regardless of completion order
+        let order = &self.mime_types;
+        self.entries.sort_by_key(|(mime, _)| order.iter().position(|m| m == mime));
+        self.pending.is_empty()
+    }
+
+    /// True if there is at least one complete entry to serve.
+    pub fn has_entries(&self) -> bool {
+        !self.entries.is_empty()
+    }
+
+    /// MIME types available from the cache, most preferred first.
+    pub fn cached_mime_types(&self) -> Vec<String> {
+        self.entries.iter().map(|(m, _)| m.clone()).collect()
+    }
+
+    /// Cached contents for `mime_type`, if any.
+    pub fn get(&self, mime_type: &str) -> Option<Arc<[u8]>> {
+        self.entries.iter()
+            .find(|(m, _)| m == mime_type)
+            .map(|(_, d)| d.clone())
+    }
+
+    /// Write the cached contents for `mime_type` into `fd`.
+    ///
+    /// The write happens on a helper thread so a slow reader cannot stall
+    /// the compositor; the fd is closed when the write finishes.
+    pub fn serve(&self, mime_type: &str, fd: OwnedFd) {
+        let Some(data) = self.get(mime_type) else {
+            tracing::debug!("Clipboard cache: no data for {}", mime_type);
+            return;
+        };
+        std::thread::spawn(move || {
+            let mut file = std::fs::File::from(fd);
+            let mut written = 0;
+            while written < data.len() {
+                match file.write(&data[written..]) {
+                    Ok(0) => break,
+                    Ok(n) => written += n,
+                    Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+                    Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                        std::thread::sleep(Duration::from_millis(5));
+                    }
+                    Err(_) => break,
+                }
+            }
+        });
+    }
+}
+
+/// Create a close-on-exec pipe whose read end is non-blocking.
+fn clipboard_pipe() -> std::io::Result<(OwnedFd, OwnedFd)> {
+    let mut fds = [0 as libc::c_int; 2];
+    // pipe2() is unavailable on Apple platforms, so set the flags by hand.
+    if unsafe { libc::pipe(fds.as_mut_ptr()) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    let (read, write) = unsafe { (OwnedFd::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1])) };
+    unsafe {
+        libc::fcntl(read.as_raw_fd(), libc::F_SETFD, libc::FD_CLOEXEC);
+        libc::fcntl(write.as_raw_fd(), libc::F_SETFD, libc::FD_CLOEXEC);
+        let flags = libc::fcntl(read.as_raw_fd(), libc::F_GETFL);
+        if libc::fcntl(read.as_raw_fd(), libc::F_SETFL, flags | libc::O_NONBLOCK) != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+    }
+    Ok((read, write))
+}
+
 /// Data device protocol state — clipboard, drag-and-drop,
 /// data sources, offers, and devices.
 pub struct DataDeviceState {
@@ -91,6 +359,8 @@ pub struct DataDeviceState {
     pub devices: HashMap<u32, DataDeviceData>,
     /// Active drag-and-drop operation (None when no drag is in progress)
     pub drag: Option<DragState>,
+    /// Opt-in cache that keeps the selection alive after its owner exits
+    pub clipboard_cache: ClipboardCache,
 }
 
 impl Default for DataDeviceState {
@@ -100,6 +370,7 @@ impl Default for DataDeviceState {
             offers: HashMap::new(),
             devices: HashMap::new(),
             drag: None,
+            clipboard_cache: ClipboardCache::default(),
         }
     }
 }
@@ -221,11 +492,16 @@ impl Dispatch<WlDataDevice, ()> for CompositorState {
                     icon.is_some()
                 );
                 
-                state.start_drag(
+                let started = state.start_drag(
                     source.as_ref().map(|s| s.id().protocol_id()),
                     origin.id().protocol_id(),
                     icon.as_ref().map(|i| i.id().protocol_id()),
                 );
+                if !started {
+                    if let Some(source) = &source {
+                        source.cancelled();
+                    }
+                }
             }
             wl_data_device::Request::SetSelection { source, serial } => {
                 tracing::debug!(
@@ -296,19 +572,19 @@ impl Dispatch<WlDataOffer, ()> for CompositorState {
                 // Look up the source and ask it to send data
                 let offer_id = resource.id().protocol_id();
                 if let Some(offer_data) = state.data.offers.get(&offer_id) {
-                    if let Some(source_id) = offer_data.source_id {
+                    match (&state.seat.current_selection, offer_data.source_id) {
                         // Find the wl_data_source resource for this source
-                        if let Some(selection) = &state.seat.current_selection {
-                            match selection {
-                                crate::core::state::SelectionSource::Wayland(src) => {
-                                    if src.id().protocol_id() == source_id {
-                                        src.send(mime_type, fd.as_fd());
-                                        tracing::debug!("Forwarded receive to wl_data_source {}", source_id);
-                                    }
-                                }
-                                _ => {}
+                        (Some(crate::core::state::SelectionSource::Wayland(src)), Some(source_id)) => {
+                            if src.id().protocol_id() == source_id {
+                                src.send(mime_type, fd.as_fd());
+                                tracing::debug!("Forwarded receive to wl_data_source {}", source_id);
                             }
                         }
+                        (Some(crate::core::state::SelectionSource::Cached), None) => {
+                            state.data.clipboard_cache.serve(&mime_type, fd);
+                            return;
+                        }
+                        _ => {}
                     }
                 }
                 drop(fd);
@@ -378,3 +654,85 @@ impl Dispatch<WlDataOffer, ()> for CompositorState {
 pub fn register_data_device_manager(display: &DisplayHandle) -> wayland_server::backend::GlobalId {
     display.create_global::<CompositorState, WlDataDeviceManager, ()>(3, ())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn enabled_cache() -> ClipboardCache {
+        ClipboardCache { enabled: true, ..Default::default() }
+    }
+
+    fn offered(mimes: &[&str]) -> Vec<String> {
+        mimes.iter().map(|m| m.to_string()).collect()
+    }
+
+    /// Play the source: write `data` for each MIME type and close the pipe
+    fn deliver(writers: Vec<(String, OwnedFd)>, data: &[(&str, &[u8])]) {
+        for (mime, fd) in writers {
+            let mut file = std::fs::File::from(fd);
+            if let Some((_, bytes)) = data.iter().find(|(m, _)| *m == mime) {
+                file.write_all(bytes).unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn test_clipboard_cache_selects_preferred_mime_types() {
+        let mut cache = enabled_cache();
+        let writers = cache.begin(&offered(&["image/jpeg", "text/plain", "application/x-private", "text/plain;charset=utf-8"]));
+        let mimes: Vec<&str> = writers.iter().map(|(m, _)| m.as_str()).collect();
+        assert_eq!(mimes, vec!["text/plain;charset=utf-8", "text/plain"]);
+
+        deliver(writers, &[("text/plain", b"plain"), ("text/plain;charset=utf-8", b"utf8")]);
+        assert!(cache.poll());
+        assert_eq!(cache.cached_mime_types(), vec!["text/plain;charset=utf-8", "text/plain"]);
+        assert_eq!(cache.get("text/plain").as_deref(), Some(&b"plain"[..]));
+
+        // A source with nothing worth caching isn't read at all
+        assert!(cache.begin(&offered(&["application/x-private"])).is_empty());
+        assert!(!cache.has_entries());
+
+        // Nor is anything read while the cache is off
+        let mut disabled = ClipboardCache::default();
+        assert!(disabled.begin(&offered(&["text/plain"])).is_empty());
+    }
+
+    #[test]
+    fn test_clipboard_cache_size_limit() {
+        let mut cache = ClipboardCache { max_bytes: 8, ..enabled_cache() };
+
+        // An entry over the limit on its own is dropped, smaller ones stay
+        let writers = cache.begin(&offered(&["text/plain;charset=utf-8", "text/plain"]));
+        deliver(writers, &[("text/plain;charset=utf-8", b"far too long"), ("text/plain", b"short")]);
+        assert!(cache.poll());
+        assert_eq!(cache.cached_mime_types(), vec!["text/plain"]);
+
+        // The limit covers all entries together
+        let writers = cache.begin(&offered(&["text/plain;charset=utf-8", "text/plain"]));
+        deliver(writers, &[("text/plain;charset=utf-8", b"12345"), ("text/plain", b"67890")]);
+        assert!(cache.poll());
+        assert_eq!(cache.cached_mime_types(), vec!["text/plain;charset=utf-8"]);
+    }
+
+    #[test]
+    fn test_clipboard_cache_serve() {
+        let mut cache = enabled_cache();
+        let writers = cache.begin(&offered(&["text/plain"]));
+        deliver(writers, &[("text/plain", b"kept")]);
+        assert!(cache.poll());
+
+        let (mut reader, writer) = std::os::unix::net::UnixStream::pair().unwrap();
+        cache.serve("text/plain", OwnedFd::from(writer));
+        let mut served = Vec::new();
+        reader.read_to_end(&mut served).unwrap();
+        assert_eq!(served, b"kept");
+
+        // An uncached type closes the fd without data
+        let (mut reader, writer) = std::os::unix::net::UnixStream::pair().unwrap();
+        cache.serve("text/html", OwnedFd::from(writer));
+        let mut served = Vec::new();
+        reader.read_to_end(&mut served).unwrap();
+        assert!(served.is_empty());
+    }
+}
@@ -1,13 +1,15 @@
 //! FIFO protocol implementation.
 //!
 //! This protocol allows clients to request FIFO (first-in-first-out)
-//! buffer presentation ordering. When a barrier is set, the compositor
-//! should stall the client's next commit until the previous frame has
-//! been presented. When wait_barrier is called, the client blocks.
+//! buffer presentation ordering. Both requests are double-buffered: a
+//! commit carrying `set_barrier` raises a barrier once it is applied, and a
+//! commit carrying `wait_barrier` is held in the surface's commit queue until
+//! the barrier is lifted by the next presentation.
 
 use std::collections::HashMap;
+use std::time::{Duration, Instant};
 use wayland_server::{
-    Client, DataInit, Dispatch, DisplayHandle, GlobalDispatch, New, Resource,
+    Client, DataInit, Dispatch, DisplayHandle, GlobalDispatch, New,
 };
 use wayland_protocols::wp::fifo::v1::server::{
     wp_fifo_manager_v1::{self, WpFifoManagerV1},
@@ -15,22 +17,70 @@ use wayland_protocols::wp::fifo::v1::server::{
 };
 
 use crate::core::state::CompositorState;
+use crate::core::wayland::wayland::compositor::internal_surface_id;
 
 // ============================================================================
 // Data Types
 // ============================================================================
 
+/// Barriers on surfaces that are never presented (hidden, occluded) are
+/// lifted after this long so their clients keep making progress.
+pub const FIFO_BARRIER_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// FIFO requests made since the surface's last commit
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FifoPending {
+    pub set_barrier: bool,
+    pub wait_barrier: bool,
+}
+
 /// Per-surface FIFO barrier state
 #[derive(Debug, Clone, Default)]
 pub struct FifoState {
-    /// surface_id → whether a barrier is currently set
-    pub barriers: HashMap<u32, bool>,
+    /// surface_id → when the currently active barrier was raised
+    pub barriers: HashMap<u32, Instant>,
+    /// surface_id → requests waiting for the next commit
+    pub pending: HashMap<u32, FifoPending>,
 }
 
 impl FifoState {
     /// Check if a surface has an active FIFO barrier
     pub fn has_barrier(&self, surface_id: u32) -> bool {
-        self.barriers.get(&surface_id).copied().unwrap_or(false)
+        self.barriers.contains_key(&surface_id)
+    }
+
+    /// Take the double-buffered requests for the commit being processed
+    pub fn take_pending(&mut self, surface_id: u32) -> FifoPending {
+        self.pending.remove(&surface_id).unwrap_or_default()
+    }
+
+    /// Raise a barrier; called when a commit carrying set_barrier is applied
+    pub fn set_barrier(&mut self, surface_id: u32) {
+        self.barriers.insert(surface_id, Instant::now());
+    }
+
+    /// Lift every barrier; called when a frame has been presented
+    pub fn lift_barriers(&mut self) {
+        self.barriers.clear();
+    }
+
+    /// Lift the barriers of `surfaces`; called when the output showing them
+    /// has presented a frame
+    pub fn lift_barriers_of(&mut self, surfaces: &[u32]) {
+        for surface_id in surfaces {
+            self.barriers.remove(surface_id);
+        }
+    }
+
+    /// Lift barriers older than `FIFO_BARRIER_TIMEOUT`
+    pub fn expire_barriers(&mut self, now: Instant) {
+        self.barriers.retain(|_, raised| now.duration_since(*raised) < FIFO_BARRIER_TIMEOUT);
+    }
+
+    /// Forget a surface whose fifo object was destroyed
+    pub fn remove_surface(&mut self, surface_id: u32) {
+        self.barriers.remove(&surface_id);
+        self.pending.remove(&surface_id);
     }
 }
 
@@ -64,9 +114,9 @@ impl Dispatch<WpFifoManagerV1, ()> for CompositorState {
     ) {
         match request {
             wp_fifo_manager_v1::Request::GetFifo { id, surface } => {
-                let surface_id = surface.id().protocol_id();
+                let surface_id = internal_surface_id(&surface);
                 let _fifo = data_init.init(id, surface_id);
-                state.ext.fifo.barriers.insert(surface_id, false);
+                state.ext.fifo.remove_surface(surface_id);
                 tracing::debug!("Created FIFO for surface {}", surface_id);
             }
             wp_fifo_manager_v1::Request::Destroy => {
@@ -93,17 +143,16 @@ impl Dispatch<WpFifoV1, u32> for CompositorState {
     ) {
         match request {
             wp_fifo_v1::Request::SetBarrier => {
-                state.ext.fifo.barriers.insert(*surface_id, true);
-                tracing::debug!("FIFO barrier set for surface {}", surface_id);
+                state.ext.fifo.pending.entry(*surface_id).or_default().set_barrier = true;
+                tracing::debug!("FIFO barrier requested for surface {}", surface_id);
             }
             wp_fifo_v1::Request::WaitBarrier => {
-                // The barrier is cleared when the frame is presented.
-                // For now, immediately clear it (since we present every frame).
-                state.ext.fifo.barriers.insert(*surface_id, false);
-                tracing::debug!("FIFO barrier waited (cleared) for surface {}", surface_id);
+                // Applied on commit: the commit is queued while a barrier is up.
+                state.ext.fifo.pending.entry(*surface_id).or_default().wait_barrier = true;
+                tracing::debug!("FIFO wait requested for surface {}", surface_id);
             }
             wp_fifo_v1::Request::Destroy => {
-                state.ext.fifo.barriers.remove(surface_id);
+                state.ext.fifo.remove_surface(*surface_id);
                 tracing::debug!("FIFO destroyed for surface {}", surface_id);
             }
             _ => {}
@@ -115,3 +164,19 @@ impl Dispatch<WpFifoV1, u32> for CompositorState {
 pub fn register_fifo(display: &DisplayHandle) -> wayland_server::backend::GlobalId {
     display.create_global::<CompositorState, WpFifoManagerV1, ()>(1, ())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lift_barriers_of() {
+        let mut fifo = FifoState::default();
+        fifo.set_barrier(1);
+        fifo.set_barrier(2);
+        // Only the surfaces of the output that presented are released
+        fifo.lift_barriers_of(&[2, 3]);
+        assert!(fifo.has_barrier(1));
+        assert!(!fifo.has_barrier(2));
+    }
+}
@@ -8,7 +8,7 @@
 
 
 use wayland_server::{
-    Client, DataInit, Dispatch, DisplayHandle, GlobalDispatch, New, Resource,
+    Client, DataInit, Dispatch, DisplayHandle, GlobalDispatch, New,
 };
 use wayland_protocols::wp::idle_inhibit::zv1::server::{
     zwp_idle_inhibit_manager_v1::{self, ZwpIdleInhibitManagerV1},
@@ -17,6 +17,7 @@ use wayland_protocols::wp::idle_inhibit::zv1::server::{
 
 
 use crate::core::state::CompositorState;
+use crate::core::wayland::wayland::compositor::internal_surface_id;
 
 // ============================================================================
 // Data Types
@@ -37,6 +38,14 @@ pub struct IdleInhibitState {
     pub next_id: u32,
 }
 
+impl IdleInhibitState {
+    /// Whether any inhibitor's surface is among `visible` surfaces. An
+    /// inhibitor only takes effect while its surface is shown.
+    pub fn inhibits(&self, visible: &[u32]) -> bool {
+        self.inhibitors.values().any(|sid| visible.contains(sid))
+    }
+}
+
 
 // ============================================================================
 // zwp_idle_inhibit_manager_v1
@@ -68,24 +77,18 @@ impl Dispatch<ZwpIdleInhibitManagerV1, ()> for CompositorState {
     ) {
         match request {
             zwp_idle_inhibit_manager_v1::Request::CreateInhibitor { id, surface } => {
-                let surface_id = surface.id().protocol_id();
+                let surface_id = internal_surface_id(&surface);
                 let inhibitor_id = state.ext.idle_inhibit.next_id;
                 state.ext.idle_inhibit.next_id += 1;
 
-                
-                // let data = IdleInhibitorData {
-                //     surface_id,
-                //     inhibitor_id,
-                // };
-                
-                let _inhibitor = data_init.init(id, ());
-                
+                data_init.init(id, IdleInhibitorData {
+                    surface_id,
+                    inhibitor_id,
+                });
 
-                
                 // Register the inhibitor
                 state.ext.idle_inhibit.inhibitors.insert(inhibitor_id, surface_id);
 
-                
                 tracing::debug!(
                     "Created idle inhibitor {} for surface {}",
                     inhibitor_id, surface_id
@@ -103,26 +106,33 @@ impl Dispatch<ZwpIdleInhibitManagerV1, ()> for CompositorState {
 // zwp_idle_inhibitor_v1
 // ============================================================================
 
-impl Dispatch<ZwpIdleInhibitorV1, ()> for CompositorState {
+impl Dispatch<ZwpIdleInhibitorV1, IdleInhibitorData> for CompositorState {
     fn request(
         _state: &mut Self,
         _client: &Client,
         _resource: &ZwpIdleInhibitorV1,
         request: zwp_idle_inhibitor_v1::Request,
-        _data: &(),
+        _data: &IdleInhibitorData,
         _dhandle: &DisplayHandle,
         _data_init: &mut DataInit<'_, Self>,
     ) {
         match request {
             zwp_idle_inhibitor_v1::Request::Destroy => {
-                // state.remove_idle_inhibitor(data.inhibitor_id);
-                tracing::debug!(
-                    "Idle inhibitor destroyed"
-                );
+                // Cleanup happens in destroyed()
             }
             _ => {}
         }
     }
+
+    fn destroyed(
+        state: &mut Self,
+        _client: wayland_server::backend::ClientId,
+        _resource: &ZwpIdleInhibitorV1,
+        data: &IdleInhibitorData,
+    ) {
+        state.ext.idle_inhibit.inhibitors.remove(&data.inhibitor_id);
+        tracing::debug!("Idle inhibitor {} destroyed", data.inhibitor_id);
+    }
 }
 
 /// Register zwp_idle_inhibit_manager_v1 global
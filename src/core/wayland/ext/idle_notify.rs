@@ -3,8 +3,14 @@
 //! This protocol allows clients to be notified when the user becomes idle
 //! (no input events for a configurable timeout). When the user resumes
 //! activity, a `resumed` event is sent.
+//!
+//! Every notification runs its own timer on the runtime clock. Visible idle
+//! inhibitors (`zwp_idle_inhibit_manager_v1`) hold the timers of
+//! `get_idle_notification`, while `get_input_idle_notification` (v2) only
+//! looks at input. The host gets its own timer, reported through
+//! `CompositorEvent::IdleStateChanged`, to dim or sleep the display.
 
-use std::time::Instant;
+use std::time::{Duration, Instant};
 use wayland_server::{
     Client, DataInit, Dispatch, DisplayHandle, GlobalDispatch, New, Resource,
 };
@@ -19,13 +25,54 @@ use crate::core::state::CompositorState;
 // Data Types
 // ============================================================================
 
+/// An idle timeout, restarted by input
+#[derive(Debug, Clone)]
+pub struct IdleTimer {
+    pub timeout: Duration,
+    /// Held while a visible surface inhibits idleness
+    pub respects_inhibitors: bool,
+    /// When the timer last (re)started
+    since: Instant,
+    /// Whether the timeout has elapsed
+    pub is_idle: bool,
+}
+
+impl IdleTimer {
+    pub fn new(timeout: Duration, respects_inhibitors: bool, now: Instant) -> Self {
+        Self { timeout, respects_inhibitors, since: now, is_idle: false }
+    }
+
+    /// When the timer fires, or None if it is idle already or held
+    pub fn deadline(&self, inhibited: bool) -> Option<Instant> {
+        if self.is_idle || (inhibited && self.respects_inhibitors) {
+            return None;
+        }
+        Some(self.since + self.timeout)
+    }
+
+    /// Returns true if the timer became idle at `now`
+    fn poll(&mut self, now: Instant, inhibited: bool) -> bool {
+        match self.deadline(inhibited) {
+            Some(deadline) if now >= deadline => {
+                self.is_idle = true;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Restart the timer. Returns true if it was idle.
+    fn restart(&mut self, now: Instant) -> bool {
+        self.since = now;
+        std::mem::replace(&mut self.is_idle, false)
+    }
+}
+
 /// Tracks an individual idle notification subscription
 #[derive(Debug, Clone)]
 pub struct IdleNotification {
     pub resource: ExtIdleNotificationV1,
-    pub timeout_ms: u32,
-    /// Whether the `idled` event has been sent
-    pub is_idle: bool,
+    pub timer: IdleTimer,
 }
 
 /// Tracks all idle notification subscriptions and user activity
@@ -34,6 +81,12 @@ pub struct IdleNotifyState {
     pub notifications: Vec<IdleNotification>,
     /// Time of last user input
     pub last_activity: Instant,
+    /// Whether a visible surface currently inhibits idleness
+    pub inhibited: bool,
+    /// The host's own idle timer, if it asked for one
+    pub host: Option<IdleTimer>,
+    /// Host idleness last reported through `IdleStateChanged`
+    host_reported_idle: bool,
 }
 
 impl Default for IdleNotifyState {
@@ -41,6 +94,9 @@ impl Default for IdleNotifyState {
         Self {
             notifications: Vec::new(),
             last_activity: Instant::now(),
+            inhibited: false,
+            host: None,
+            host_reported_idle: false,
         }
     }
 }
@@ -48,33 +104,73 @@ impl Default for IdleNotifyState {
 impl IdleNotifyState {
     /// Record user activity (call on any input event)
     pub fn record_activity(&mut self) {
-        let was_idle = self.notifications.iter().any(|n| n.is_idle);
-        self.last_activity = Instant::now();
+        let now = Instant::now();
+        self.last_activity = now;
 
         // Send resumed to any notifications that were idle
-        if was_idle {
-            for notif in &mut self.notifications {
-                if notif.is_idle && notif.resource.is_alive() {
-                    notif.resource.resumed();
-                    notif.is_idle = false;
-                }
+        for notif in &mut self.notifications {
+            if notif.timer.restart(now) && notif.resource.is_alive() {
+                notif.resource.resumed();
             }
         }
+        if let Some(host) = &mut self.host {
+            host.restart(now);
+        }
     }
 
-    /// Check for idle timeouts and send `idled` events.
-    /// Call this periodically (e.g., once per second from the event loop).
-    pub fn check_idle(&mut self) {
-        let elapsed = self.last_activity.elapsed();
-        let elapsed_ms = elapsed.as_millis() as u32;
+    /// Update whether idleness is inhibited. Timers held by an inhibitor
+    /// start over once it goes away.
+    pub fn set_inhibited(&mut self, inhibited: bool, now: Instant) {
+        if self.inhibited && !inhibited {
+            let timers = self.notifications.iter_mut().map(|n| &mut n.timer).chain(self.host.as_mut());
+            for timer in timers.filter(|t| t.respects_inhibitors && !t.is_idle) {
+                timer.since = now;
+            }
+        }
+        self.inhibited = inhibited;
+    }
 
+    /// Send `idled` for the timers that came due by `now`
+    pub fn check_idle(&mut self, now: Instant) {
+        let inhibited = self.inhibited;
         for notif in &mut self.notifications {
-            if !notif.is_idle && elapsed_ms >= notif.timeout_ms && notif.resource.is_alive() {
+            if notif.timer.poll(now, inhibited) && notif.resource.is_alive() {
                 notif.resource.idled();
-                notif.is_idle = true;
-                tracing::debug!("User idle for {}ms (timeout={}ms)", elapsed_ms, notif.timeout_ms);
+                tracing::debug!("User idle (timeout={}ms)", notif.timer.timeout.as_millis());
             }
         }
+        if let Some(host) = &mut self.host {
+            host.poll(now, inhibited);
+        }
+    }
+
+    /// The earliest time a timer fires
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.notifications.iter()
+            .map(|n| &n.timer)
+            .chain(self.host.as_ref())
+            .filter_map(|t| t.deadline(self.inhibited))
+            .min()
+    }
+
+    /// Set (or with None, clear) the host's idle timeout
+    pub fn set_host_timeout(&mut self, timeout: Option<Duration>) {
+        self.host = timeout.map(|t| IdleTimer::new(t, true, Instant::now()));
+    }
+
+    /// Whether the host's idle timer has fired
+    pub fn host_idle(&self) -> bool {
+        self.host.as_ref().map_or(false, |t| t.is_idle)
+    }
+
+    /// The host's idleness, if it changed since last asked
+    pub fn take_host_change(&mut self) -> Option<bool> {
+        let idle = self.host_idle();
+        if idle == self.host_reported_idle {
+            return None;
+        }
+        self.host_reported_idle = idle;
+        Some(idle)
     }
 
     /// Remove dead resources
@@ -117,11 +213,20 @@ impl Dispatch<ExtIdleNotifierV1, ()> for CompositorState {
                 let notification_res = data_init.init(id, ());
                 state.ext.idle_notify.notifications.push(IdleNotification {
                     resource: notification_res,
-                    timeout_ms: timeout,
-                    is_idle: false,
+                    timer: IdleTimer::new(Duration::from_millis(timeout as u64), true, Instant::now()),
                 });
                 tracing::debug!("Created idle notification: timeout={}ms", timeout);
             }
+            ext_idle_notifier_v1::Request::GetInputIdleNotification { id, timeout, seat } => {
+                let _seat_id = seat.id().protocol_id();
+                let notification_res = data_init.init(id, ());
+                // Only input counts; inhibitors are ignored
+                state.ext.idle_notify.notifications.push(IdleNotification {
+                    resource: notification_res,
+                    timer: IdleTimer::new(Duration::from_millis(timeout as u64), false, Instant::now()),
+                });
+                tracing::debug!("Created input idle notification: timeout={}ms", timeout);
+            }
             ext_idle_notifier_v1::Request::Destroy => {
                 tracing::debug!("ext_idle_notifier_v1 destroyed");
             }
@@ -157,5 +262,30 @@ impl Dispatch<ExtIdleNotificationV1, ()> for CompositorState {
 
 /// Register ext_idle_notifier_v1 global
 pub fn register_idle_notify(display: &DisplayHandle) -> wayland_server::backend::GlobalId {
-    display.create_global::<CompositorState, ExtIdleNotifierV1, ()>(1, ())
+    display.create_global::<CompositorState, ExtIdleNotifierV1, ()>(2, ())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_idle_timer_inhibition() {
+        let start = Instant::now();
+        let second = Duration::from_secs(1);
+        let mut held = IdleTimer::new(second, true, start);
+        let mut input_only = IdleTimer::new(second, false, start);
+
+        // An inhibitor holds only the timer that respects it
+        assert_eq!(held.deadline(true), None);
+        assert_eq!(input_only.deadline(true), Some(start + second));
+        assert!(!held.poll(start + second * 2, true));
+        assert!(input_only.poll(start + second * 2, true));
+        assert!(!input_only.poll(start + second * 3, true));
+
+        // Input restarts the timer and reports that it was idle
+        assert!(input_only.restart(start + second * 3));
+        assert_eq!(input_only.deadline(false), Some(start + second * 4));
+        assert!(!held.restart(start + second * 3));
+    }
 }
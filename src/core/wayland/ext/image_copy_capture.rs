@@ -185,6 +185,10 @@ impl Dispatch<ExtImageCopyCaptureFrameV1, CaptureFrameData> for CompositorState
                     }
                 };
                 *data.captured.lock().unwrap() = true;
+                if state.session_locked() {
+                    resource.failed(ext_image_copy_capture_frame_v1::FailureReason::Unknown);
+                    return;
+                }
 
                 let client_id = _client.id();
                 let buffer_guard = match state.buffers.get(&(client_id.clone(), buffer_id)) {
@@ -16,6 +16,7 @@ use wayland_protocols::wp::input_method::zv1::server::{
 };
 
 use crate::core::state::CompositorState;
+use crate::core::wayland::wayland::compositor::internal_surface_id;
 
 // ============================================================================
 // zwp_input_panel_v1 (always available)
@@ -83,6 +84,20 @@ pub fn register_input_panel(display: &DisplayHandle) -> wayland_server::backend:
     display.create_global::<CompositorState, ZwpInputPanelV1, ()>(1, ())
 }
 
+impl CompositorState {
+    /// Whether `client` is the bound input method. Keys it sends through a
+    /// virtual keyboard bypass its own keyboard grab.
+    pub fn is_input_method_client(&self, _client: &Client) -> bool {
+        #[cfg(feature = "desktop-protocols")]
+        {
+            if let Some(im) = &self.ext.input_method.resource {
+                return im.client().map_or(false, |c| c.id() == _client.id());
+            }
+        }
+        false
+    }
+}
+
 // ============================================================================
 // zwp_input_method_manager_v2 / zwp_input_method_v2  (desktop-only)
 // ============================================================================
@@ -90,6 +105,8 @@ pub fn register_input_panel(display: &DisplayHandle) -> wayland_server::backend:
 #[cfg(feature = "desktop-protocols")]
 mod input_method_v2 {
     use super::*;
+    use wayland_server::protocol::wl_keyboard;
+    use crate::core::input::grab::{GrabKey, GrabKind, GrabStatus, InputGrab};
     use crate::core::wayland::protocol::server::zwp_input_method_v2::{
         zwp_input_method_manager_v2::{self, ZwpInputMethodManagerV2},
         zwp_input_method_v2::{self, ZwpInputMethodV2},
@@ -101,6 +118,36 @@ mod input_method_v2 {
     // State
     // ------------------------------------------------------------------
 
+    /// Keyboard grab of the input method: keys go to it while it is active,
+    /// except on the lock screen
+    #[derive(Debug)]
+    pub struct InputMethodGrab;
+
+    impl InputGrab for InputMethodGrab {
+        fn kind(&self) -> GrabKind {
+            GrabKind::InputMethod
+        }
+
+        fn key(&mut self, state: &mut CompositorState, key: &GrabKey) -> GrabStatus {
+            if state.session_locked() {
+                return GrabStatus::Pass;
+            }
+            let serial = state.next_serial();
+            let im = &state.ext.input_method;
+            if !im.grab_key(serial, key.time, key.key, key.state) {
+                return GrabStatus::Pass;
+            }
+            if let Some(mods) = key.modifiers {
+                im.grab_modifiers(serial, mods);
+            }
+            GrabStatus::Consumed
+        }
+
+        fn as_any(&self) -> &dyn std::any::Any {
+            self
+        }
+    }
+
     /// Per-seat input method state.
     #[derive(Debug, Default)]
     pub struct InputMethodState {
@@ -117,6 +164,11 @@ mod input_method_v2 {
         pub pending_preedit: Option<(String, i32, i32)>,
         /// Pending delete_surrounding from the IME.
         pub pending_delete: Option<(u32, u32)>,
+        /// Candidate popups of the IME, with their internal surface ids.
+        pub popups: Vec<(ZwpInputPopupSurfaceV2, u32)>,
+        /// Keyboard grab: while the IME is active, keys go here first and
+        /// the IME passes on what it doesn't consume via virtual keyboard.
+        pub keyboard_grab: Option<ZwpInputMethodKeyboardGrabV2>,
     }
 
     impl InputMethodState {
@@ -163,6 +215,39 @@ mod input_method_v2 {
             }
         }
 
+        /// Tell the IME's popups where the text cursor is, in coordinates
+        /// local to the focused surface.
+        pub fn text_input_rectangle(&self, rect: (i32, i32, i32, i32)) {
+            for (popup, _) in &self.popups {
+                if popup.is_alive() {
+                    popup.text_input_rectangle(rect.0, rect.1, rect.2, rect.3);
+                }
+            }
+        }
+
+        /// Route a key through the keyboard grab. Returns false when there
+        /// is no grab to take it and the key should go to the focused client.
+        pub fn grab_key(&self, serial: u32, time: u32, key: u32, state: wl_keyboard::KeyState) -> bool {
+            match &self.keyboard_grab {
+                Some(grab) if self.active && grab.is_alive() => {
+                    grab.key(serial, time, key, state);
+                    true
+                }
+                _ => false,
+            }
+        }
+
+        /// Route modifiers through the keyboard grab, see [`grab_key`](Self::grab_key).
+        pub fn grab_modifiers(&self, serial: u32, mods: (u32, u32, u32, u32)) -> bool {
+            match &self.keyboard_grab {
+                Some(grab) if self.active && grab.is_alive() => {
+                    grab.modifiers(serial, mods.0, mods.1, mods.2, mods.3);
+                    true
+                }
+                _ => false,
+            }
+        }
+
         /// Send the done event to the IME, applying double-buffered state.
         pub fn done(&mut self) {
             if let Some(ref res) = self.resource {
@@ -298,15 +383,34 @@ mod input_method_v2 {
                     }
                 }
                 zwp_input_method_v2::Request::GetInputPopupSurface { id, surface } => {
-                    let _popup = data_init.init(id, ());
-                    let sid = surface.id().protocol_id();
+                    let sid = internal_surface_id(&surface);
+                    let popup = data_init.init(id, sid);
+                    if let Some(instance) = state.ext.text_input.active() {
+                        let (x, y, w, h) = instance.cursor_rect;
+                        popup.text_input_rectangle(x, y, w, h);
+                    }
+                    state.ext.input_method.popups.push((popup, sid));
+                    state.ext.text_input.popup_surfaces.push(sid);
                     tracing::debug!(
                         "Input method popup surface created for surface {}",
                         sid
                     );
                 }
                 zwp_input_method_v2::Request::GrabKeyboard { keyboard } => {
-                    let _grab = data_init.init(keyboard, ());
+                    let grab = data_init.init(keyboard, ());
+                    let serial = state.next_serial();
+                    let kbd = &state.seat.keyboard;
+                    if let Some(xkb) = &kbd.xkb_state {
+                        if let Ok(xkb) = xkb.lock() {
+                            use std::os::unix::io::AsFd;
+                            grab.keymap(wl_keyboard::KeymapFormat::XkbV1, xkb.keymap_file().as_fd(), xkb.keymap_size);
+                        }
+                    }
+                    let (rate, delay) = kbd.advertised_repeat_info();
+                    grab.repeat_info(rate, delay);
+                    grab.modifiers(serial, kbd.mods_depressed, kbd.mods_latched, kbd.mods_locked, kbd.mods_group);
+                    state.ext.input_method.keyboard_grab = Some(grab);
+                    let _ = state.start_grab(Box::new(InputMethodGrab));
                     tracing::debug!("Input method keyboard grab created");
                 }
                 zwp_input_method_v2::Request::Destroy => {
@@ -319,6 +423,8 @@ mod input_method_v2 {
                     {
                         state.ext.input_method.resource = None;
                         state.ext.input_method.active = false;
+                        state.ext.input_method.keyboard_grab = None;
+                        state.end_grab(GrabKind::InputMethod);
                     }
                     tracing::debug!("Input method destroyed");
                 }
@@ -328,42 +434,52 @@ mod input_method_v2 {
     }
 
     // ------------------------------------------------------------------
-    // zwp_input_popup_surface_v2 (stub)
+    // zwp_input_popup_surface_v2 — user data is the popup's surface id
     // ------------------------------------------------------------------
 
-    impl Dispatch<ZwpInputPopupSurfaceV2, ()> for CompositorState {
+    impl Dispatch<ZwpInputPopupSurfaceV2, u32> for CompositorState {
         fn request(
-            _state: &mut Self,
+            state: &mut Self,
             _client: &Client,
-            _resource: &ZwpInputPopupSurfaceV2,
+            resource: &ZwpInputPopupSurfaceV2,
             request: zwp_input_popup_surface_v2::Request,
-            _data: &(),
+            surface_id: &u32,
             _dhandle: &DisplayHandle,
             _data_init: &mut DataInit<'_, Self>,
         ) {
             match request {
-                zwp_input_popup_surface_v2::Request::Destroy => {}
+                zwp_input_popup_surface_v2::Request::Destroy => {
+                    state.ext.input_method.popups.retain(|(popup, _)| popup.id() != resource.id());
+                    state.ext.text_input.popup_surfaces.retain(|sid| sid != surface_id);
+                }
                 _ => {}
             }
         }
     }
 
     // ------------------------------------------------------------------
-    // zwp_input_method_keyboard_grab_v2 (stub)
+    // zwp_input_method_keyboard_grab_v2
     // ------------------------------------------------------------------
 
     impl Dispatch<ZwpInputMethodKeyboardGrabV2, ()> for CompositorState {
         fn request(
-            _state: &mut Self,
+            state: &mut Self,
             _client: &Client,
-            _resource: &ZwpInputMethodKeyboardGrabV2,
+            resource: &ZwpInputMethodKeyboardGrabV2,
             request: zwp_input_method_keyboard_grab_v2::Request,
             _data: &(),
             _dhandle: &DisplayHandle,
             _data_init: &mut DataInit<'_, Self>,
         ) {
             match request {
-                zwp_input_method_keyboard_grab_v2::Request::Release => {}
+                zwp_input_method_keyboard_grab_v2::Request::Release => {
+                    let grab = &mut state.ext.input_method.keyboard_grab;
+                    if grab.as_ref().map_or(false, |g| g.id() == resource.id()) {
+                        *grab = None;
+                        state.end_grab(GrabKind::InputMethod);
+                    }
+                    tracing::debug!("Input method keyboard grab released");
+                }
                 _ => {}
             }
         }
@@ -10,6 +10,8 @@ pub mod pointer_constraints;
 pub mod pointer_gestures;
 pub mod tablet;
 pub mod text_input;
+pub mod text_input_v1;
+pub mod text_input_v2;
 pub mod keyboard_shortcuts_inhibit;
 pub mod cursor_shape;
 pub mod primary_selection;
@@ -74,6 +76,8 @@ pub fn register(_state: &mut CompositorState, dh: &DisplayHandle) {
     pointer_gestures::register_pointer_gestures(dh);
     idle_inhibit::register_idle_inhibit_manager(dh);
     text_input::register_text_input_manager(dh);
+    text_input_v1::register_text_input_manager_v1(dh);
+    text_input_v2::register_text_input_manager_v2(dh);
     keyboard_shortcuts_inhibit::register_keyboard_shortcuts_inhibit_manager(dh);
     linux_dmabuf::register_linux_dmabuf(dh);
     linux_explicit_sync::register_linux_explicit_sync(dh);
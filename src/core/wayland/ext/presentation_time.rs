@@ -3,10 +3,13 @@
 
 use crate::core::wayland::protocol::server::wp::presentation_time::server::{wp_presentation, wp_presentation_feedback};
 use wayland_server::{
-    Dispatch, DisplayHandle, GlobalDispatch, Resource,
+    Dispatch, DisplayHandle, GlobalDispatch,
 };
 
+use std::collections::HashMap;
+
 use crate::core::state::CompositorState;
+use crate::core::wayland::wayland::compositor::internal_surface_id;
 
 /// Presentation time global
 pub struct PresentationTimeGlobal;
@@ -19,42 +22,124 @@ pub struct PresentationFeedback {
     pub committed: bool,
 }
 
+/// Timing of one presented frame, on CLOCK_MONOTONIC as advertised
+/// through `clock_id`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PresentInfo {
+    pub timestamp_ns: u64,
+    /// Refresh period of the output, 0 if unknown or variable
+    pub refresh_ns: u32,
+    /// Output vblank counter (MSC)
+    pub seq: u64,
+    pub flags: wp_presentation_feedback::Kind,
+}
+
+/// Vblank counter of one output
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct OutputMsc {
+    /// Vblank counter of the most recent presentation
+    pub msc: u64,
+    /// Timestamp of the most recent presentation (CLOCK_MONOTONIC ns)
+    pub last_present_ns: Option<u64>,
+}
+
+impl OutputMsc {
+    /// Advance the MSC to the refresh cycle containing `timestamp_ns` and
+    /// return it. Reports less than half a refresh apart are the same vblank;
+    /// longer gaps count every refresh cycle that elapsed.
+    pub fn advance(&mut self, timestamp_ns: u64, refresh_ns: u32) -> u64 {
+        let refresh_ns = refresh_ns as u64;
+        match self.last_present_ns {
+            Some(last) if timestamp_ns <= last => {}
+            Some(last) => {
+                let elapsed = timestamp_ns - last;
+                let cycles = if refresh_ns == 0 {
+                    1
+                } else {
+                    (elapsed + refresh_ns / 2) / refresh_ns
+                };
+                if cycles > 0 {
+                    self.msc += cycles;
+                    self.last_present_ns = Some(timestamp_ns);
+                }
+            }
+            None => {
+                self.msc += 1;
+                self.last_present_ns = Some(timestamp_ns);
+            }
+        }
+        self.msc
+    }
+}
+
 #[derive(Debug)]
 pub struct PresentationState {
     pub feedbacks: Vec<PresentationFeedback>,
-    pub next_seq: u64,
+    /// Vblank counters by output id; every display refreshes on its own clock
+    pub msc: HashMap<u32, OutputMsc>,
+    /// Set once the platform reports real presentations; from then on the
+    /// dispatch-time fallback in `fire_presentation_feedback` stays quiet.
+    pub platform_driven: bool,
 }
 
 impl PresentationState {
+    /// Attach pending feedback to the commit that was just applied.
     pub fn mark_committed(&mut self, surface_id: u32) {
-        for feedback in &mut self.feedbacks {
-            if feedback.surface_id == surface_id {
-                feedback.committed = true;
+        let pending = self.take_pending(surface_id);
+        self.commit_feedback(surface_id, pending);
+    }
+
+    /// Remove the feedback requested since the surface's last commit, so it
+    /// can travel with a commit that is queued rather than applied.
+    pub fn take_pending(&mut self, surface_id: u32) -> Vec<PresentationFeedback> {
+        let (pending, remaining) = self.feedbacks.drain(..).partition(|f| {
+            f.surface_id == surface_id && !f.committed
+        });
+        self.feedbacks = remaining;
+        pending
+    }
+
+    /// Attach `feedback` to the commit of `surface_id` that was just applied.
+    ///
+    /// Feedback still waiting from an earlier commit of the same surface is
+    /// discarded: that content was replaced before it could be shown.
+    pub fn commit_feedback(&mut self, surface_id: u32, feedback: Vec<PresentationFeedback>) {
+        self.feedbacks.retain(|feedback| {
+            if feedback.surface_id == surface_id && feedback.committed {
+                feedback.callback.discarded();
+                false
+            } else {
+                true
             }
-        }
+        });
+        self.feedbacks.extend(feedback.into_iter().map(|f| PresentationFeedback { committed: true, ..f }));
     }
 
-    pub fn send_presented_events(&mut self, timestamp_ns: u64, refresh_ns: u64, seq: u64) {
-        let tv_sec = (timestamp_ns / 1_000_000_000) as u32;
-        let tv_nsec = (timestamp_ns % 1_000_000_000) as u32;
-        
-        let mut i = 0;
-        while i < self.feedbacks.len() {
-            if self.feedbacks[i].committed {
-                let feedback = self.feedbacks.remove(i);
-                feedback.callback.presented(
-                    tv_sec,
-                    tv_nsec,
-                    refresh_ns as u32,
-                    (seq >> 32) as u32,
-                    (seq & 0xFFFFFFFF) as u32,
-                    0, // flags
-                    wp_presentation_feedback::Kind::empty(),
-                );
+    /// Discard all feedback for a surface that is going away.
+    pub fn discard_surface(&mut self, surface_id: u32) {
+        self.feedbacks.retain(|feedback| {
+            if feedback.surface_id == surface_id {
+                feedback.callback.discarded();
+                false
             } else {
-                i += 1;
+                true
             }
-        }
+        });
+    }
+
+    /// Advance the MSC of `output_id` to the refresh cycle containing
+    /// `timestamp_ns` and return it.
+    pub fn advance_msc(&mut self, output_id: u32, timestamp_ns: u64, refresh_ns: u32) -> u64 {
+        self.msc.entry(output_id).or_default().advance(timestamp_ns, refresh_ns)
+    }
+
+    /// Remove and return committed feedback, for one surface or all of them.
+    pub fn take_committed(&mut self, surface_id: Option<u32>) -> Vec<PresentationFeedback> {
+        let (ready, remaining) = self.feedbacks.drain(..).partition(|f| {
+            f.committed && surface_id.map_or(true, |id| id == f.surface_id)
+        });
+        self.feedbacks = remaining;
+        ready
     }
 }
 
@@ -62,11 +147,26 @@ impl Default for PresentationState {
     fn default() -> Self {
         Self {
             feedbacks: Vec::new(),
-            next_seq: 1,
+            msc: HashMap::new(),
+            platform_driven: false,
         }
     }
 }
 
+/// Send `presented` for one feedback object
+pub fn send_presented(feedback: &PresentationFeedback, info: &PresentInfo) {
+    let tv_sec = info.timestamp_ns / 1_000_000_000;
+    feedback.callback.presented(
+        (tv_sec >> 32) as u32,
+        (tv_sec & 0xFFFF_FFFF) as u32,
+        (info.timestamp_ns % 1_000_000_000) as u32,
+        info.refresh_ns,
+        (info.seq >> 32) as u32,
+        (info.seq & 0xFFFF_FFFF) as u32,
+        info.flags,
+    );
+}
+
 
 
 impl GlobalDispatch<wp_presentation::WpPresentation, ()> for CompositorState {
@@ -79,10 +179,11 @@ impl GlobalDispatch<wp_presentation::WpPresentation, ()> for CompositorState {
         data_init: &mut wayland_server::DataInit<'_, Self>,
     ) {
         let presentation = data_init.init(resource, ());
-        // CLOCK_MONOTONIC = 1 on Linux/Darwin.  The protocol requires
+        // Timestamps come from CLOCK_MONOTONIC, whose id differs between
+        // platforms (1 on Linux, 6 on Darwin). The protocol requires
         // clock_id to be sent before any presented event so clients
         // (notably waypipe) know which clock domain timestamps use.
-        presentation.clock_id(1);
+        presentation.clock_id(libc::CLOCK_MONOTONIC as u32);
     }
 }
 
@@ -98,9 +199,8 @@ impl Dispatch<wp_presentation::WpPresentation, ()> for CompositorState {
     ) {
         match request {
             wp_presentation::Request::Feedback { surface, callback } => {
-                let surface_id = surface.id().protocol_id();
+                let surface_id = internal_surface_id(&surface);
 
-                
                 let feedback_resource: wp_presentation_feedback::WpPresentationFeedback = data_init.init(callback, ());
                 
                 state.ext.presentation.feedbacks.push(PresentationFeedback {
@@ -136,3 +236,37 @@ impl Dispatch<wp_presentation_feedback::WpPresentationFeedback, ()> for Composit
 pub fn register_presentation_time(display: &DisplayHandle) -> wayland_server::backend::GlobalId {
     display.create_global::<CompositorState, wp_presentation::WpPresentation, ()>(1, ())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_advance_msc() {
+        let mut state = PresentationState::default();
+        assert_eq!(state.advance_msc(1, 1_000_000, 16_000_000), 1);
+        // Within half a refresh: the same vblank
+        assert_eq!(state.advance_msc(1, 8_000_000, 16_000_000), 1);
+        // One refresh later, then a gap of three
+        assert_eq!(state.advance_msc(1, 17_000_000, 16_000_000), 2);
+        assert_eq!(state.advance_msc(1, 65_000_000, 16_000_000), 5);
+        // Timestamps going backwards don't move the counter
+        assert_eq!(state.advance_msc(1, 40_000_000, 16_000_000), 5);
+        // Without a known refresh every later report is a new vblank
+        assert_eq!(state.advance_msc(1, 66_000_000, 0), 6);
+        assert_eq!(state.msc[&1].last_present_ns, Some(66_000_000));
+    }
+
+    #[test]
+    fn test_advance_msc_per_output() {
+        let mut state = PresentationState::default();
+        // A 120 Hz output reporting in between doesn't move a 60 Hz one
+        assert_eq!(state.advance_msc(1, 16_000_000, 16_000_000), 1);
+        assert_eq!(state.advance_msc(2, 20_000_000, 8_000_000), 1);
+        assert_eq!(state.advance_msc(2, 28_000_000, 8_000_000), 2);
+        assert_eq!(state.advance_msc(1, 32_000_000, 16_000_000), 2);
+        assert_eq!(state.advance_msc(2, 36_000_000, 8_000_000), 3);
+        assert_eq!(state.msc[&1].last_present_ns, Some(32_000_000));
+        assert_eq!(state.msc[&2].last_present_ns, Some(36_000_000));
+    }
+}
@@ -1,8 +1,12 @@
 //! Session Lock protocol implementation.
 //!
 //! This protocol allows clients to lock the user session and display a lock screen.
-//! When locked, all other surfaces are hidden. Lock surfaces receive configure
-//! events with the output dimensions. UnlockAndDestroy releases the lock.
+//! Every output gets its own lock surface, sized to that output; `locked` is
+//! only sent once each output shows a committed lock surface.
+//! Locking is fail-secure: while locked, only lock surfaces are in the scene
+//! and receive input, and screen capture is refused. If the locker goes away
+//! without `unlock_and_destroy` (e.g. it crashed) the session stays locked
+//! behind a solid colour until a new locker takes over and unlocks.
 
 use std::collections::HashMap;
 use wayland_server::{
@@ -14,7 +18,13 @@ use crate::core::wayland::protocol::server::ext::session_lock::v1::server::{
     ext_session_lock_surface_v1::{self, ExtSessionLockSurfaceV1},
 };
 
+use crate::core::input::grab::{GrabKind, InputGrab};
 use crate::core::state::CompositorState;
+use crate::core::wayland::wayland::compositor::internal_surface_id;
+use crate::core::surface::SurfaceRole;
+
+/// Colour (ARGB8888) shown where no lock surface covers an output
+pub const LOCK_FALLBACK_COLOR: u32 = 0xff00_0000;
 
 // ============================================================================
 // Data Types
@@ -23,16 +33,56 @@ use crate::core::state::CompositorState;
 /// Lock surface data — tracks which output and surface this covers
 #[derive(Debug, Clone)]
 pub struct SessionLockSurfaceData {
+    pub resource: ExtSessionLockSurfaceV1,
+    /// Internal output id
     pub output_id: u32,
     pub surface_id: u32,
-    pub pending_serial: u32,
+    /// Configures sent but not acked yet, as (serial, size), oldest first
+    pub pending_configures: Vec<(u32, (u32, u32))>,
+    /// Size of the last configure sent
+    pub configured_size: (u32, u32),
+    /// Size of the latest configure the client acked; None before the first
+    /// ack. Committed buffers must match it.
+    pub acked_size: Option<(u32, u32)>,
+    /// A buffer has been committed after the first ack
+    pub mapped: bool,
+}
+
+/// Where the session is in the lock lifecycle
+#[derive(Debug, Clone, Default)]
+pub enum LockStatus {
+    #[default]
+    Unlocked,
+    /// Locked by a live locker that hasn't covered every output yet; input is
+    /// already isolated but `locked` has not been sent
+    Pending(ExtSessionLockV1),
+    /// Locked by a live locker and `locked` has been sent
+    Locked(ExtSessionLockV1),
+    /// The locker went away without unlocking. The session stays locked; a
+    /// new locker may take over.
+    Abandoned,
+}
+
+/// Grab held while the session is locked. It takes no events itself, as
+/// delivery is already limited to the locker, but cancels pointer grabs
+/// when the lock starts and refuses new ones until unlock.
+#[derive(Debug)]
+pub struct SessionLockGrab;
+
+impl InputGrab for SessionLockGrab {
+    fn kind(&self) -> GrabKind {
+        GrabKind::SessionLock
+    }
+
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
 }
 
 /// Compositor-wide session lock state
 #[derive(Debug, Default)]
 pub struct SessionLockState {
-    /// Whether the session is currently locked
-    pub locked: bool,
+    pub status: LockStatus,
     /// Active lock surfaces (lock_surface_id → data)
     pub lock_surfaces: HashMap<u32, SessionLockSurfaceData>,
     /// Serial counter for lock surface configures
@@ -44,6 +94,36 @@ impl SessionLockState {
         self.next_serial = self.next_serial.wrapping_add(1);
         self.next_serial
     }
+
+    /// Whether the session is locked, with or without a live locker
+    pub fn is_locked(&self) -> bool {
+        !matches!(self.status, LockStatus::Unlocked)
+    }
+
+    /// Whether `lock` is the lock object currently holding the session
+    fn is_current(&self, lock: &ExtSessionLockV1) -> bool {
+        matches!(
+            &self.status,
+            LockStatus::Pending(current) | LockStatus::Locked(current) if current.id() == lock.id()
+        )
+    }
+
+    pub fn is_lock_surface(&self, surface_id: u32) -> bool {
+        self.lock_surfaces.values().any(|data| data.surface_id == surface_id)
+    }
+
+    /// The locker client, if one is alive
+    pub fn lock_client(&self) -> Option<Client> {
+        match &self.status {
+            LockStatus::Pending(lock) | LockStatus::Locked(lock) => lock.client(),
+            _ => None,
+        }
+    }
+
+    /// The lock surface covering `output_id`, if any
+    fn surface_for_output(&self, output_id: u32) -> Option<&SessionLockSurfaceData> {
+        self.lock_surfaces.values().find(|data| data.output_id == output_id)
+    }
 }
 
 // ============================================================================
@@ -78,11 +158,23 @@ impl Dispatch<ExtSessionLockManagerV1, ()> for CompositorState {
             ext_session_lock_manager_v1::Request::Lock { id } => {
                 let lock = data_init.init(id, ());
 
-                state.ext.session_lock.locked = true;
-                // Acknowledge the lock
-                lock.locked();
-
-                tracing::info!("Session locked");
+                match state.ext.session_lock.status {
+                    LockStatus::Pending(_) | LockStatus::Locked(_) => {
+                        // Only one locker at a time
+                        lock.finished();
+                        tracing::warn!("Rejected session lock: already locked");
+                    }
+                    LockStatus::Abandoned => {
+                        // `locked` follows once the new locker covers every output
+                        state.ext.session_lock.status = LockStatus::Pending(lock);
+                        tracing::info!("New locker is taking over the abandoned session lock");
+                    }
+                    LockStatus::Unlocked => {
+                        state.ext.session_lock.status = LockStatus::Pending(lock);
+                        state.begin_session_lock();
+                        tracing::info!("Session locking, waiting for lock surfaces");
+                    }
+                }
             }
             ext_session_lock_manager_v1::Request::Destroy => {
                 tracing::debug!("ext_session_lock_manager_v1 destroyed");
@@ -100,7 +192,7 @@ impl Dispatch<ExtSessionLockV1, ()> for CompositorState {
     fn request(
         state: &mut Self,
         _client: &Client,
-        _resource: &ExtSessionLockV1,
+        resource: &ExtSessionLockV1,
         request: ext_session_lock_v1::Request,
         _data: &(),
         _dhandle: &DisplayHandle,
@@ -108,73 +200,266 @@ impl Dispatch<ExtSessionLockV1, ()> for CompositorState {
     ) {
         match request {
             ext_session_lock_v1::Request::GetLockSurface { id, surface, output } => {
-                let output_id = output.id().protocol_id();
-                let surface_id = surface.id().protocol_id();
-                let serial = state.ext.session_lock.next_serial();
+                let lock_surface = data_init.init(id, ());
+                // A rejected lock has nothing to show
+                if !state.ext.session_lock.is_current(resource) {
+                    return;
+                }
+                let output_id = state.output_id_by_resource.get(&output.id()).copied()
+                    .unwrap_or_else(|| state.primary_output().id);
+                let surface_id = internal_surface_id(&surface);
 
-                let lock_surface = data_init.init(id, output_id);
+                if state.ext.session_lock.surface_for_output(output_id).is_some() {
+                    resource.post_error(ext_session_lock_v1::Error::DuplicateOutput, "output already has a lock surface");
+                    return;
+                }
+                if let Some(surf) = state.get_surface(surface_id) {
+                    let mut surf = surf.write().unwrap();
+                    if surf.current.buffer_id.is_some() || surf.pending.buffer_id.is_some() {
+                        resource.post_error(ext_session_lock_v1::Error::AlreadyConstructed, "surface already has a buffer");
+                        return;
+                    }
+                    if surf.set_role(SurfaceRole::SessionLock).is_err() {
+                        resource.post_error(ext_session_lock_v1::Error::Role, "surface already has a role");
+                        return;
+                    }
+                }
+
+                let (width, height) = state.outputs.iter().find(|o| o.id == output_id)
+                    .map(|o| (o.width, o.height))
+                    .unwrap_or_else(|| (state.primary_output().width, state.primary_output().height));
+                let serial = state.ext.session_lock.next_serial();
                 let ls_id = lock_surface.id().protocol_id();
 
+                lock_surface.configure(serial, width, height);
                 state.ext.session_lock.lock_surfaces.insert(ls_id, SessionLockSurfaceData {
+                    resource: lock_surface,
                     output_id,
                     surface_id,
-                    pending_serial: serial,
+                    pending_configures: vec![(serial, (width, height))],
+                    configured_size: (width, height),
+                    acked_size: None,
+                    mapped: false,
                 });
 
-                // Send configure with the output dimensions
-                let output = state.primary_output();
-                lock_surface.configure(serial, output.width, output.height);
-
                 tracing::debug!(
                     "Created lock surface {} for output {} (surface {}), configure {}x{} serial {}",
-                    ls_id, output_id, surface_id, output.width, output.height, serial
+                    ls_id, output_id, surface_id, width, height, serial
                 );
             }
             ext_session_lock_v1::Request::UnlockAndDestroy => {
-                state.ext.session_lock.locked = false;
-                state.ext.session_lock.lock_surfaces.clear();
-                tracing::info!("Session unlocked");
+                if matches!(&state.ext.session_lock.status, LockStatus::Pending(lock) if lock.id() == resource.id()) {
+                    resource.post_error(ext_session_lock_v1::Error::InvalidUnlock, "unlock before the locked event");
+                    return;
+                }
+                if state.ext.session_lock.is_current(resource) {
+                    state.ext.session_lock.status = LockStatus::Unlocked;
+                    state.ext.session_lock.lock_surfaces.clear();
+                    state.end_grab(GrabKind::SessionLock);
+                    state.restore_window_keyboard_focus();
+                    tracing::info!("Session unlocked");
+                }
             }
             ext_session_lock_v1::Request::Destroy => {
-                state.ext.session_lock.locked = false;
-                state.ext.session_lock.lock_surfaces.clear();
+                if matches!(&state.ext.session_lock.status, LockStatus::Locked(lock) if lock.id() == resource.id()) {
+                    resource.post_error(ext_session_lock_v1::Error::InvalidDestroy, "destroy after the locked event, use unlock_and_destroy");
+                    return;
+                }
                 tracing::debug!("ext_session_lock_v1 destroyed");
             }
             _ => {}
         }
     }
+
+    fn destroyed(
+        state: &mut Self,
+        _client: wayland_server::backend::ClientId,
+        resource: &ExtSessionLockV1,
+        _data: &(),
+    ) {
+        // Destroyed without unlocking, or the locker died: stay locked. Its
+        // lock surfaces are no longer used.
+        if state.ext.session_lock.is_current(resource) {
+            state.ext.session_lock.status = LockStatus::Abandoned;
+            state.ext.session_lock.lock_surfaces.clear();
+            state.set_keyboard_focus_surface(None);
+            tracing::warn!("Session locker went away without unlocking; session stays locked");
+        }
+    }
 }
 
 // ============================================================================
-// ext_session_lock_surface_v1 — user data is output_id: u32
+// ext_session_lock_surface_v1
 // ============================================================================
 
-impl Dispatch<ExtSessionLockSurfaceV1, u32> for CompositorState {
+impl Dispatch<ExtSessionLockSurfaceV1, ()> for CompositorState {
     fn request(
         state: &mut Self,
         _client: &Client,
         resource: &ExtSessionLockSurfaceV1,
         request: ext_session_lock_surface_v1::Request,
-        _output_id: &u32,
+        _data: &(),
         _dhandle: &DisplayHandle,
         _data_init: &mut DataInit<'_, Self>,
     ) {
         let ls_id = resource.id().protocol_id();
         match request {
             ext_session_lock_surface_v1::Request::AckConfigure { serial } => {
-                if let Some(data) = state.ext.session_lock.lock_surfaces.get(&ls_id) {
-                    if data.pending_serial == serial {
-                        tracing::debug!("Lock surface {} ack configure serial {}", ls_id, serial);
-                    }
+                if let Some(data) = state.ext.session_lock.lock_surfaces.get_mut(&ls_id) {
+                    // Acking a configure drops the ones sent before it
+                    let Some(index) = data.pending_configures.iter().position(|(s, _)| *s == serial) else {
+                        resource.post_error(ext_session_lock_surface_v1::Error::InvalidSerial, "ack_configure with a serial never sent");
+                        return;
+                    };
+                    let (_, size) = data.pending_configures.drain(..=index).last().unwrap();
+                    data.acked_size = Some(size);
+                    tracing::debug!("Lock surface {} ack configure serial {} ({}x{})", ls_id, serial, size.0, size.1);
                 }
             }
             ext_session_lock_surface_v1::Request::Destroy => {
-                state.ext.session_lock.lock_surfaces.remove(&ls_id);
                 tracing::debug!("Lock surface {} destroyed", ls_id);
             }
             _ => {}
         }
     }
+
+    fn destroyed(
+        state: &mut Self,
+        _client: wayland_server::backend::ClientId,
+        resource: &ExtSessionLockSurfaceV1,
+        _data: &(),
+    ) {
+        let removed = state.ext.session_lock.lock_surfaces.remove(&resource.id().protocol_id());
+        // Keyboard focus moves to another lock surface, if any is left
+        if let Some(data) = removed {
+            if state.seat.keyboard.focus == Some(data.surface_id) {
+                let next = state.ext.session_lock.lock_surfaces.values()
+                    .find(|d| d.mapped)
+                    .map(|d| d.surface_id);
+                state.set_keyboard_focus_surface(next);
+            }
+        }
+    }
+}
+
+impl CompositorState {
+    /// Check a commit on a lock surface. Commits before the first ack, and
+    /// buffers of another size than the acked configure, are protocol
+    /// errors; the first buffer after the ack maps the surface and may
+    /// complete the lock. Returns false if the commit must be dropped.
+    pub(crate) fn accept_lock_surface_commit(&mut self, surface_id: u32) -> bool {
+        let Some((&ls_id, data)) = self.ext.session_lock.lock_surfaces.iter()
+            .find(|(_, data)| data.surface_id == surface_id)
+        else {
+            return true;
+        };
+        let Some(acked_size) = data.acked_size else {
+            data.resource.post_error(
+                ext_session_lock_surface_v1::Error::CommitBeforeFirstAck,
+                "lock surface committed before the first ack_configure",
+            );
+            return false;
+        };
+
+        let size = self.get_surface(surface_id)
+            .and_then(|s| crate::core::surface::commit::surface_size(&s.read().unwrap().pending));
+        if let Some((width, height)) = size {
+            if (width as u32, height as u32) != acked_size {
+                data.resource.post_error(
+                    ext_session_lock_surface_v1::Error::DimensionsMismatch,
+                    format!("buffer is {}x{}, configured {}x{}", width, height, acked_size.0, acked_size.1),
+                );
+                return false;
+            }
+        }
+        if data.mapped {
+            return true;
+        }
+
+        if size.is_some() {
+            if let Some(data) = self.ext.session_lock.lock_surfaces.get_mut(&ls_id) {
+                data.mapped = true;
+            }
+            if !self.seat.keyboard.focus.map_or(false, |sid| self.ext.session_lock.is_lock_surface(sid)) {
+                self.set_keyboard_focus_surface(Some(surface_id));
+            }
+            self.confirm_session_lock();
+        }
+        true
+    }
+
+    /// Resize lock surfaces after the output layout changed and re-check
+    /// whether every output is covered. Outputs without a lock surface stay
+    /// filled with [`LOCK_FALLBACK_COLOR`] until the locker, which sees the
+    /// new `wl_output`, creates one.
+    pub fn session_lock_outputs_changed(&mut self) {
+        if !self.ext.session_lock.is_locked() {
+            return;
+        }
+        let sizes: HashMap<u32, (u32, u32)> = self.outputs.iter()
+            .map(|o| (o.id, (o.width, o.height)))
+            .collect();
+        let mut reconfigure = Vec::new();
+        for (&ls_id, data) in &self.ext.session_lock.lock_surfaces {
+            if let Some(&size) = sizes.get(&data.output_id) {
+                if size != data.configured_size {
+                    reconfigure.push((ls_id, size));
+                }
+            }
+        }
+        for (ls_id, (width, height)) in reconfigure {
+            let serial = self.ext.session_lock.next_serial();
+            if let Some(data) = self.ext.session_lock.lock_surfaces.get_mut(&ls_id) {
+                data.pending_configures.push((serial, (width, height)));
+                data.configured_size = (width, height);
+                data.resource.configure(serial, width, height);
+                tracing::debug!("Reconfigured lock surface {} to {}x{} serial {}", ls_id, width, height, serial);
+            }
+        }
+        self.confirm_session_lock();
+    }
+
+    /// Send `locked` once every output shows a mapped lock surface
+    fn confirm_session_lock(&mut self) {
+        let lock = match &self.ext.session_lock.status {
+            LockStatus::Pending(lock) => lock.clone(),
+            _ => return,
+        };
+        let covered = self.outputs.iter().all(|output| {
+            self.ext.session_lock.surface_for_output(output.id).map_or(false, |data| data.mapped)
+        });
+        if covered {
+            lock.locked();
+            self.ext.session_lock.status = LockStatus::Locked(lock);
+            tracing::info!("Session locked on {} output(s)", self.outputs.len());
+        }
+    }
+
+    /// Isolate the session as it becomes locked: take keyboard, pointer and
+    /// touch focus away from all clients and refuse pending captures.
+    fn begin_session_lock(&mut self) {
+        let _ = self.start_grab(Box::new(SessionLockGrab));
+        self.set_keyboard_focus_surface(None);
+
+        if let Some(sid) = self.seat.pointer.focus.take() {
+            if let Some(res) = self.get_surface(sid).and_then(|s| s.read().unwrap().resource.clone()) {
+                let serial = self.next_serial();
+                self.seat.broadcast_pointer_leave(serial, &res);
+            }
+        }
+        if !self.seat.touch.active_points.is_empty() {
+            self.inject_touch_cancel();
+        }
+
+        let screencopies: Vec<u64> = self.wlr.pending_screencopies.iter().map(|p| p.capture_id).collect();
+        for capture_id in screencopies {
+            crate::core::wayland::wlr::screencopy::fail_screencopy(self, capture_id);
+        }
+        let image_copies: Vec<u64> = self.wlr.pending_image_copy_captures.iter().map(|p| p.capture_id).collect();
+        for capture_id in image_copies {
+            crate::core::wayland::ext::image_copy_capture::fail_image_copy_capture(self, capture_id);
+        }
+    }
 }
 
 /// Register ext_session_lock_manager_v1 global
@@ -18,6 +18,8 @@ use wayland_protocols::wp::tablet::zv2::server::{
     zwp_tablet_pad_group_v2::{self, ZwpTabletPadGroupV2},
 };
 
+use wayland_server::protocol::wl_surface::WlSurface;
+
 use crate::core::state::CompositorState;
 
 // ============================================================================
@@ -53,6 +55,451 @@ pub struct TabletPadStripData;
 #[derive(Debug, Clone, Default)]
 pub struct TabletPadGroupData;
 
+/// Physical kind of a tablet tool
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TabletToolKind {
+    #[default]
+    Pen,
+    Eraser,
+    Brush,
+    Pencil,
+    Airbrush,
+    Finger,
+    Mouse,
+    Lens,
+}
+
+impl TabletToolKind {
+    fn to_wl(self) -> zwp_tablet_tool_v2::Type {
+        match self {
+            Self::Pen => zwp_tablet_tool_v2::Type::Pen,
+            Self::Eraser => zwp_tablet_tool_v2::Type::Eraser,
+            Self::Brush => zwp_tablet_tool_v2::Type::Brush,
+            Self::Pencil => zwp_tablet_tool_v2::Type::Pencil,
+            Self::Airbrush => zwp_tablet_tool_v2::Type::Airbrush,
+            Self::Finger => zwp_tablet_tool_v2::Type::Finger,
+            Self::Mouse => zwp_tablet_tool_v2::Type::Mouse,
+            Self::Lens => zwp_tablet_tool_v2::Type::Lens,
+        }
+    }
+}
+
+/// Description of a physical tool, announced to clients on first use
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct TabletToolInfo {
+    /// Hardware serial identifying this tool (0 if unknown)
+    pub serial: u64,
+    pub kind: TabletToolKind,
+    pub has_pressure: bool,
+    pub has_tilt: bool,
+    pub has_distance: bool,
+    pub has_rotation: bool,
+    pub has_slider: bool,
+    pub has_wheel: bool,
+}
+
+/// A single tablet tool state change; a batch of these forms one frame.
+///
+/// Coordinates are global for `CompositorState::inject_tablet_tool` and
+/// surface-local for `inject_tablet_tool_on_surface`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TabletToolEvent {
+    ProximityIn { tool: TabletToolInfo, x: f64, y: f64 },
+    ProximityOut,
+    Motion { x: f64, y: f64 },
+    Down,
+    Up,
+    /// Normalized pressure, 0.0..=1.0
+    Pressure(f64),
+    /// Normalized hover distance, 0.0..=1.0
+    Distance(f64),
+    /// Tilt in degrees from the surface normal
+    Tilt { x: f64, y: f64 },
+    /// Rotation around the tool's own axis, degrees clockwise
+    Rotation(f64),
+    /// Slider position, -1.0..=1.0
+    Slider(f64),
+    Wheel { degrees: f64, clicks: i32 },
+    Button { button: u32, pressed: bool },
+}
+
+/// A tablet pad state change; each one is delivered as its own frame
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TabletPadEvent {
+    Button { button: u32, pressed: bool },
+    /// Ring angle in degrees, or None when the finger leaves the ring
+    Ring { ring: u32, angle: Option<f64> },
+    /// Normalized strip position, or None when the finger leaves the strip
+    Strip { strip: u32, position: Option<f64> },
+}
+
+/// Layout of the platform's tablet pad, if it has one
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct TabletPadLayout {
+    pub buttons: u32,
+    pub rings: u32,
+    pub strips: u32,
+}
+
+/// Protocol objects one pad has in one client
+#[derive(Debug, Clone)]
+struct PadObjects {
+    pad: ZwpTabletPadV2,
+    rings: Vec<ZwpTabletPadRingV2>,
+    strips: Vec<ZwpTabletPadStripV2>,
+}
+
+/// Device objects announced through one zwp_tablet_seat_v2
+#[derive(Debug, Clone)]
+struct SeatDevices {
+    seat: ZwpTabletSeatV2,
+    tablet: ZwpTabletV2,
+    /// Tool objects by hardware serial and kind, like `TabletState::tools`
+    tools: Vec<((u64, TabletToolKind), ZwpTabletToolV2)>,
+    pad: Option<PadObjects>,
+}
+
+/// Tool currently in proximity of a surface
+#[derive(Debug, Clone)]
+struct ActiveTool {
+    tool: TabletToolInfo,
+    surface_id: u32,
+    surface: WlSurface,
+    down: bool,
+}
+
+/// Tablet state: the single virtual tablet the platform drives, the tools
+/// seen so far and the objects announced to each client's tablet seat.
+#[derive(Debug, Default)]
+pub struct TabletState {
+    seats: Vec<SeatDevices>,
+    tools: Vec<TabletToolInfo>,
+    pad_layout: Option<TabletPadLayout>,
+    active: Option<ActiveTool>,
+    pad_focus: Option<(u32, WlSurface)>,
+}
+
+impl TabletState {
+    /// Announce the tablet, every known tool and the pad to a new tablet seat.
+    pub fn add_seat(&mut self, dh: &DisplayHandle, client: &Client, seat: ZwpTabletSeatV2) {
+        let version = seat.version();
+        let Ok(tablet) = client.create_resource::<ZwpTabletV2, (), CompositorState>(dh, version, ()) else {
+            return;
+        };
+        seat.tablet_added(&tablet);
+        tablet.name("Wawona Tablet".to_string());
+        tablet.done();
+
+        let mut devices = SeatDevices { seat, tablet, tools: Vec::new(), pad: None };
+        for info in &self.tools {
+            announce_tool(dh, client, &mut devices, info);
+        }
+        if let Some(layout) = self.pad_layout {
+            announce_pad(dh, client, &mut devices, layout);
+        }
+        self.seats.push(devices);
+    }
+
+    /// Forget a destroyed tablet seat and the devices announced through it.
+    pub fn remove_seat(&mut self, seat: &ZwpTabletSeatV2) {
+        self.seats.retain(|d| d.seat.id() != seat.id());
+    }
+
+    /// Drop everything belonging to dead clients.
+    pub fn cleanup(&mut self) {
+        self.seats.retain(|d| d.seat.is_alive());
+        if self.active.as_ref().is_some_and(|a| !a.surface.is_alive()) {
+            self.active = None;
+        }
+        if self.pad_focus.as_ref().is_some_and(|(_, s)| !s.is_alive()) {
+            self.pad_focus = None;
+        }
+    }
+
+    /// Register a tool, announcing it to every tablet seat the first time
+    /// it is seen.
+    pub fn ensure_tool(&mut self, info: &TabletToolInfo) {
+        if self.tools.iter().any(|t| t.serial == info.serial && t.kind == info.kind) {
+            return;
+        }
+        self.tools.push(info.clone());
+        for devices in &mut self.seats {
+            let (Some(client), Some(dh)) = (devices.seat.client(), devices.seat.handle().upgrade()) else {
+                continue;
+            };
+            announce_tool(&dh, &client, devices, info);
+        }
+    }
+
+    /// Replace the pad layout, removing the old pad and announcing the new one.
+    pub fn set_pad_layout(&mut self, layout: Option<TabletPadLayout>) {
+        if self.pad_layout == layout {
+            return;
+        }
+        self.pad_layout = layout;
+        self.pad_focus = None;
+        for devices in &mut self.seats {
+            if let Some(old) = devices.pad.take() {
+                old.pad.removed();
+            }
+            let Some(layout) = layout else { continue };
+            let (Some(client), Some(dh)) = (devices.seat.client(), devices.seat.handle().upgrade()) else {
+                continue;
+            };
+            announce_pad(&dh, &client, devices, layout);
+        }
+    }
+
+    /// The surface the tool in proximity is over, if any.
+    pub fn active_surface(&self) -> Option<u32> {
+        self.active.as_ref().map(|a| a.surface_id)
+    }
+
+    /// The tool in proximity.
+    pub fn active_tool_info(&self) -> Option<&TabletToolInfo> {
+        self.active.as_ref().map(|a| &a.tool)
+    }
+
+    /// Whether the tool in proximity has its tip down (implicit grab).
+    pub fn is_tool_down(&self) -> bool {
+        self.active.as_ref().is_some_and(|a| a.down)
+    }
+
+    fn devices_for(&self, client: &Client) -> Option<&SeatDevices> {
+        self.seats.iter().find(|d| d.seat.client().as_ref() == Some(client))
+    }
+
+    /// The active tool's resource and the tablet it belongs to.
+    fn active_tool(&self) -> Option<(&ZwpTabletV2, &ZwpTabletToolV2)> {
+        let active = self.active.as_ref()?;
+        let client = active.surface.client()?;
+        let devices = self.devices_for(&client)?;
+        let key = (active.tool.serial, active.tool.kind);
+        let tool = devices.tools.iter().find(|(k, _)| *k == key).map(|(_, t)| t)?;
+        Some((&devices.tablet, tool))
+    }
+
+    /// Move the tool into proximity of a surface, leaving the previous one.
+    pub fn proximity_in(&mut self, serial: u32, tool: &TabletToolInfo, surface_id: u32, surface: &WlSurface, time: u32) {
+        if self.active.as_ref().is_some_and(|a| {
+            a.surface_id == surface_id && (a.tool.serial, a.tool.kind) == (tool.serial, tool.kind)
+        }) {
+            return;
+        }
+        self.proximity_out(time);
+        self.active = Some(ActiveTool {
+            tool: tool.clone(),
+            surface_id,
+            surface: surface.clone(),
+            down: false,
+        });
+        if let Some((tablet, tool)) = self.active_tool() {
+            tool.proximity_in(serial, tablet, surface);
+        }
+    }
+
+    /// Take the tool out of proximity, lifting the tip first if needed.
+    ///
+    /// The leaving tool's frame is closed here: once `active` is cleared,
+    /// `tool_frame` no longer reaches it.
+    pub fn proximity_out(&mut self, time: u32) {
+        if self.is_tool_down() {
+            self.up();
+        }
+        if let Some((_, tool)) = self.active_tool() {
+            tool.proximity_out();
+            tool.frame(time);
+        }
+        self.active = None;
+    }
+
+    pub fn down(&mut self, serial: u32) {
+        let Some(active) = self.active.as_mut() else { return };
+        if active.down {
+            return;
+        }
+        active.down = true;
+        if let Some((_, tool)) = self.active_tool() {
+            tool.down(serial);
+        }
+    }
+
+    pub fn up(&mut self) {
+        let Some(active) = self.active.as_mut() else { return };
+        if !active.down {
+            return;
+        }
+        active.down = false;
+        if let Some((_, tool)) = self.active_tool() {
+            tool.up();
+        }
+    }
+
+    pub fn motion(&self, x: f64, y: f64) {
+        if let Some((_, tool)) = self.active_tool() {
+            tool.motion(x, y);
+        }
+    }
+
+    /// Send an axis or button event to the active tool (no-op when the
+    /// event does not apply to an in-proximity tool, or is an axis the
+    /// tool did not announce a capability for).
+    pub fn tool_event(&self, serial: u32, event: &TabletToolEvent) {
+        let Some(info) = self.active_tool_info() else { return };
+        let Some((_, tool)) = self.active_tool() else { return };
+        match *event {
+            TabletToolEvent::Pressure(p) if info.has_pressure => {
+                tool.pressure((p.clamp(0.0, 1.0) * 65535.0) as u32)
+            }
+            TabletToolEvent::Distance(d) if info.has_distance => {
+                tool.distance((d.clamp(0.0, 1.0) * 65535.0) as u32)
+            }
+            TabletToolEvent::Tilt { x, y } if info.has_tilt => tool.tilt(x, y),
+            TabletToolEvent::Rotation(degrees) if info.has_rotation => tool.rotation(degrees),
+            TabletToolEvent::Slider(pos) if info.has_slider => {
+                tool.slider((pos.clamp(-1.0, 1.0) * 65535.0) as i32)
+            }
+            TabletToolEvent::Wheel { degrees, clicks } if info.has_wheel => tool.wheel(degrees, clicks),
+            TabletToolEvent::Button { button, pressed } => {
+                let state = if pressed {
+                    zwp_tablet_tool_v2::ButtonState::Pressed
+                } else {
+                    zwp_tablet_tool_v2::ButtonState::Released
+                };
+                tool.button(serial, button, state);
+            }
+            _ => {}
+        }
+    }
+
+    /// Close the current tool frame.
+    pub fn tool_frame(&self, time: u32) {
+        if let Some((_, tool)) = self.active_tool() {
+            tool.frame(time);
+        }
+    }
+
+    /// Move pad focus to a surface (the keyboard focus), sending leave/enter.
+    pub fn pad_focus(&mut self, serial: u32, target: Option<(u32, WlSurface)>) {
+        if self.pad_focus.as_ref().map(|(id, _)| *id) == target.as_ref().map(|(id, _)| *id) {
+            return;
+        }
+        if let Some((_, old)) = self.pad_focus.take() {
+            if let Some(pad) = old.client().and_then(|c| self.devices_for(&c)).and_then(|d| d.pad.as_ref()) {
+                pad.pad.leave(serial, &old);
+            }
+        }
+        if let Some((_, surface)) = &target {
+            if let Some(devices) = surface.client().and_then(|c| self.devices_for(&c)) {
+                if let Some(pad) = &devices.pad {
+                    pad.pad.enter(serial, &devices.tablet, surface);
+                }
+            }
+        }
+        self.pad_focus = target;
+    }
+
+    /// Deliver a pad event to the focused surface's pad.
+    pub fn pad_event(&self, event: &TabletPadEvent, time: u32) {
+        let Some((_, surface)) = &self.pad_focus else { return };
+        let Some(pad) = surface.client().and_then(|c| self.devices_for(&c)).and_then(|d| d.pad.as_ref()) else {
+            return;
+        };
+        match *event {
+            TabletPadEvent::Button { button, pressed } => {
+                let state = if pressed {
+                    zwp_tablet_pad_v2::ButtonState::Pressed
+                } else {
+                    zwp_tablet_pad_v2::ButtonState::Released
+                };
+                pad.pad.button(time, button, state);
+            }
+            TabletPadEvent::Ring { ring, angle } => {
+                let Some(r) = pad.rings.get(ring as usize) else { return };
+                match angle {
+                    Some(angle) => {
+                        r.source(zwp_tablet_pad_ring_v2::Source::Finger);
+                        r.angle(angle.rem_euclid(360.0));
+                    }
+                    None => r.stop(),
+                }
+                r.frame(time);
+            }
+            TabletPadEvent::Strip { strip, position } => {
+                let Some(s) = pad.strips.get(strip as usize) else { return };
+                match position {
+                    Some(pos) => {
+                        s.source(zwp_tablet_pad_strip_v2::Source::Finger);
+                        s.position((pos.clamp(0.0, 1.0) * 65535.0) as u32);
+                    }
+                    None => s.stop(),
+                }
+                s.frame(time);
+            }
+        }
+    }
+}
+
+fn announce_tool(dh: &DisplayHandle, client: &Client, devices: &mut SeatDevices, info: &TabletToolInfo) {
+    let Ok(tool) = client.create_resource::<ZwpTabletToolV2, (), CompositorState>(dh, devices.seat.version(), ()) else {
+        return;
+    };
+    devices.seat.tool_added(&tool);
+    tool._type(info.kind.to_wl());
+    if info.serial != 0 {
+        tool.hardware_serial((info.serial >> 32) as u32, info.serial as u32);
+    }
+    let caps = [
+        (info.has_tilt, zwp_tablet_tool_v2::Capability::Tilt),
+        (info.has_pressure, zwp_tablet_tool_v2::Capability::Pressure),
+        (info.has_distance, zwp_tablet_tool_v2::Capability::Distance),
+        (info.has_rotation, zwp_tablet_tool_v2::Capability::Rotation),
+        (info.has_slider, zwp_tablet_tool_v2::Capability::Slider),
+        (info.has_wheel, zwp_tablet_tool_v2::Capability::Wheel),
+    ];
+    for (has, cap) in caps {
+        if has {
+            tool.capability(cap);
+        }
+    }
+    tool.done();
+    devices.tools.push(((info.serial, info.kind), tool));
+}
+
+fn announce_pad(dh: &DisplayHandle, client: &Client, devices: &mut SeatDevices, layout: TabletPadLayout) {
+    let version = devices.seat.version();
+    let Ok(pad) = client.create_resource::<ZwpTabletPadV2, (), CompositorState>(dh, version, ()) else {
+        return;
+    };
+    devices.seat.pad_added(&pad);
+    let Ok(group) = client.create_resource::<ZwpTabletPadGroupV2, (), CompositorState>(dh, version, ()) else {
+        return;
+    };
+    pad.group(&group);
+    // All buttons belong to the single group, as an array of u32 indices
+    let buttons: Vec<u8> = (0..layout.buttons).flat_map(|b| b.to_ne_bytes()).collect();
+    group.buttons(buttons);
+    let mut rings = Vec::new();
+    for _ in 0..layout.rings {
+        if let Ok(ring) = client.create_resource::<ZwpTabletPadRingV2, (), CompositorState>(dh, version, ()) {
+            group.ring(&ring);
+            rings.push(ring);
+        }
+    }
+    let mut strips = Vec::new();
+    for _ in 0..layout.strips {
+        if let Ok(strip) = client.create_resource::<ZwpTabletPadStripV2, (), CompositorState>(dh, version, ()) {
+            group.strip(&strip);
+            strips.push(strip);
+        }
+    }
+    group.modes(1);
+    group.done();
+    pad.buttons(layout.buttons);
+    pad.done();
+    devices.pad = Some(PadObjects { pad, rings, strips });
+}
+
 // ============================================================================
 // zwp_tablet_manager_v2
 // ============================================================================
@@ -73,19 +520,20 @@ impl GlobalDispatch<ZwpTabletManagerV2, ()> for CompositorState {
 
 impl Dispatch<ZwpTabletManagerV2, ()> for CompositorState {
     fn request(
-        _state: &mut Self,
-        _client: &Client,
+        state: &mut Self,
+        client: &Client,
         _resource: &ZwpTabletManagerV2,
         request: zwp_tablet_manager_v2::Request,
         _data: &(),
-        _dhandle: &DisplayHandle,
+        dhandle: &DisplayHandle,
         data_init: &mut DataInit<'_, Self>,
     ) {
         match request {
             zwp_tablet_manager_v2::Request::GetTabletSeat { tablet_seat, seat } => {
                 let seat_id = seat.id().protocol_id();
                 // let data = TabletSeatData { seat_id };
-                let _tablet_seat = data_init.init(tablet_seat, ());
+                let tablet_seat = data_init.init(tablet_seat, ());
+                state.ext.tablet.add_seat(dhandle, client, tablet_seat);
                 tracing::debug!("Created tablet seat for seat {}", seat_id);
             }
             zwp_tablet_manager_v2::Request::Destroy => {
@@ -102,9 +550,9 @@ impl Dispatch<ZwpTabletManagerV2, ()> for CompositorState {
 
 impl Dispatch<ZwpTabletSeatV2, ()> for CompositorState {
     fn request(
-        _state: &mut Self,
+        state: &mut Self,
         _client: &Client,
-        _resource: &ZwpTabletSeatV2,
+        resource: &ZwpTabletSeatV2,
         request: zwp_tablet_seat_v2::Request,
         _data: &(),
         _dhandle: &DisplayHandle,
@@ -112,6 +560,7 @@ impl Dispatch<ZwpTabletSeatV2, ()> for CompositorState {
     ) {
         match request {
             zwp_tablet_seat_v2::Request::Destroy => {
+                state.ext.tablet.remove_seat(resource);
                 tracing::debug!("zwp_tablet_seat_v2 destroyed");
             }
             _ => {}
@@ -7,7 +7,7 @@
 
 use std::collections::HashMap;
 use wayland_server::{
-    Client, DataInit, Dispatch, DisplayHandle, GlobalDispatch, New, Resource,
+    Client, DataInit, Dispatch, DisplayHandle, GlobalDispatch, New,
 };
 use wayland_protocols::wp::tearing_control::v1::server::{
     wp_tearing_control_manager_v1::{self, WpTearingControlManagerV1},
@@ -15,6 +15,7 @@ use wayland_protocols::wp::tearing_control::v1::server::{
 };
 
 use crate::core::state::CompositorState;
+use crate::core::wayland::wayland::compositor::internal_surface_id;
 
 // ============================================================================
 // Data Types
@@ -77,7 +78,7 @@ impl Dispatch<WpTearingControlManagerV1, ()> for CompositorState {
     ) {
         match request {
             wp_tearing_control_manager_v1::Request::GetTearingControl { id, surface } => {
-                let surface_id = surface.id().protocol_id();
+                let surface_id = internal_surface_id(&surface);
                 let _tc = data_init.init(id, surface_id);
                 // Default to vsync
                 state.ext.tearing_control.surface_hints.insert(surface_id, PresentationHint::Vsync);
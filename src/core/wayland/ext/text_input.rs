@@ -4,109 +4,311 @@
 //! The compositor tracks per-text-input state (surrounding text, content type,
 //! cursor rectangle, enabled/disabled) and sends enter/leave events on focus change.
 //! Commit strings and preedit are forwarded from the platform IME integration.
+//!
+//! Besides `zwp_text_input_v3`, the legacy v1 (`text_input_v1`) and KDE v2
+//! (`text_input_v2`) protocols share this state, so every client gets the
+//! same IME events in the dialect it speaks.
 
 use std::collections::HashMap;
 use wayland_server::{
-    Client, DataInit, Dispatch, DisplayHandle, GlobalDispatch, New, Resource,
+    backend::ObjectId, Client, DataInit, Dispatch, DisplayHandle, GlobalDispatch, New, Resource,
 };
+use wayland_protocols::wp::text_input::zv1::server::zwp_text_input_v1::ZwpTextInputV1;
 use wayland_protocols::wp::text_input::zv3::server::{
     zwp_text_input_manager_v3::{self, ZwpTextInputManagerV3},
     zwp_text_input_v3::{self, ZwpTextInputV3},
 };
+use crate::core::wayland::protocol::server::plasma::text_input::v2::server::zwp_text_input_v2::ZwpTextInputV2;
+
+use wayland_server::protocol::wl_surface::WlSurface;
 
 use crate::core::state::CompositorState;
+use crate::util::geometry::Rect;
 
 // ============================================================================
 // Data Types
 // ============================================================================
 
-/// Content type hint for the text input field
+/// Content type hint for the text input field.
+///
+/// Values use the `zwp_text_input_v3` numbering; legacy purposes are
+/// converted with [`legacy_content_purpose`].
 #[derive(Debug, Clone, Default)]
 pub struct ContentType {
     pub hint: u32,
     pub purpose: u32,
 }
 
+/// Convert a v1/v2 content purpose to v3, which inserted `pin` after
+/// `password`. The hint bits are the same in all versions.
+pub fn legacy_content_purpose(purpose: u32) -> u32 {
+    if purpose >= 9 {
+        purpose + 1
+    } else {
+        purpose
+    }
+}
+
+/// A text input object of any supported protocol version
+#[derive(Debug, Clone)]
+pub enum TextInputResource {
+    V1(ZwpTextInputV1),
+    V2(ZwpTextInputV2),
+    V3(ZwpTextInputV3),
+}
+
+impl TextInputResource {
+    pub fn id(&self) -> ObjectId {
+        match self {
+            TextInputResource::V1(resource) => resource.id(),
+            TextInputResource::V2(resource) => resource.id(),
+            TextInputResource::V3(resource) => resource.id(),
+        }
+    }
+
+    pub fn is_alive(&self) -> bool {
+        match self {
+            TextInputResource::V1(resource) => resource.is_alive(),
+            TextInputResource::V2(resource) => resource.is_alive(),
+            TextInputResource::V3(resource) => resource.is_alive(),
+        }
+    }
+}
+
 /// Per-text-input state tracked by the compositor
 #[derive(Debug, Clone)]
 pub struct TextInputInstance {
-    pub resource: ZwpTextInputV3,
+    pub resource: TextInputResource,
     pub seat_id: u32,
     pub enabled: bool,
+    /// Enable/disable requested since the last commit (v3 only)
+    pub pending_enabled: Option<bool>,
+    /// Surface passed to v1 `activate`; v1 text inputs only enter that one
+    pub activated_surface: Option<WlSurface>,
+    /// Surface this text input was sent `enter` for
+    pub entered: Option<WlSurface>,
     pub surrounding_text: String,
     pub surrounding_cursor: i32,
     pub surrounding_anchor: i32,
     pub content_type: ContentType,
     pub cursor_rect: (i32, i32, i32, i32),
+    /// v3: count of `commit` requests received. v1: serial of the last
+    /// `commit_state`. v2: serial of the last `enter`.
     pub serial: u32,
 }
 
+impl TextInputInstance {
+    pub fn new(resource: TextInputResource, seat_id: u32) -> Self {
+        Self {
+            resource,
+            seat_id,
+            enabled: false,
+            pending_enabled: None,
+            activated_surface: None,
+            entered: None,
+            surrounding_text: String::new(),
+            surrounding_cursor: 0,
+            surrounding_anchor: 0,
+            content_type: ContentType::default(),
+            cursor_rect: (0, 0, 0, 0),
+            serial: 0,
+        }
+    }
+
+    /// Whether this text input currently receives input method events
+    pub fn is_active(&self) -> bool {
+        self.enabled && self.entered.is_some() && self.resource.is_alive()
+    }
+
+    /// Whether this text input should get `enter` when `surface` takes focus
+    pub fn accepts_focus(&self, surface: &WlSurface) -> bool {
+        if !self.resource.is_alive() || !self.resource.id().same_client_as(&surface.id()) {
+            return false;
+        }
+        match self.resource {
+            // v1 text inputs only take focus on the surface they activated
+            TextInputResource::V1(_) => self.enabled && self.activated_surface.as_ref() == Some(surface),
+            _ => true,
+        }
+    }
+
+    /// Reset the client-provided state, as enabling does
+    pub fn reset_state(&mut self) {
+        self.surrounding_text.clear();
+        self.surrounding_cursor = 0;
+        self.surrounding_anchor = 0;
+        self.content_type = ContentType::default();
+        self.cursor_rect = (0, 0, 0, 0);
+    }
+
+    pub fn enter(&mut self, surface: &WlSurface) {
+        match &self.resource {
+            TextInputResource::V1(resource) => resource.enter(surface),
+            TextInputResource::V2(resource) => {
+                self.serial = self.serial.wrapping_add(1);
+                resource.enter(self.serial, surface);
+            }
+            TextInputResource::V3(resource) => resource.enter(surface),
+        }
+        self.entered = Some(surface.clone());
+    }
+
+    /// Send `leave` for the entered surface. Leaving implicitly disables
+    /// v2 and v3 text inputs; a v1 one stays activated for its surface
+    /// until it deactivates, and enters again when that surface regains focus.
+    pub fn leave(&mut self) {
+        if let Some(old) = self.entered.take() {
+            if self.resource.is_alive() && old.is_alive() {
+                match &self.resource {
+                    TextInputResource::V1(resource) => resource.leave(),
+                    TextInputResource::V2(resource) => resource.leave(self.serial, &old),
+                    TextInputResource::V3(resource) => resource.leave(&old),
+                }
+            }
+        }
+        if !matches!(self.resource, TextInputResource::V1(_)) {
+            self.enabled = false;
+        }
+        self.pending_enabled = None;
+    }
+
+    /// Per the zwp_text_input_v3 spec, the `done` serial is the number of
+    /// commits received, so a client can tell whether the events were
+    /// meant for the state it committed last.
+    fn done(&mut self) {
+        if let TextInputResource::V3(resource) = &self.resource {
+            resource.done(self.serial);
+        }
+    }
+
+    pub fn commit_string(&mut self, text: &str) {
+        match &self.resource {
+            TextInputResource::V1(resource) => resource.commit_string(self.serial, text.to_string()),
+            TextInputResource::V2(resource) => resource.commit_string(text.to_string()),
+            TextInputResource::V3(resource) => resource.commit_string(Some(text.to_string())),
+        }
+        self.done();
+    }
+
+    pub fn preedit_string(&mut self, text: &str, cursor_begin: i32, cursor_end: i32) {
+        // Legacy versions have a single preedit cursor and commit nothing
+        // when the preedit is reset; the platform IME commits explicitly
+        match &self.resource {
+            TextInputResource::V1(resource) => {
+                resource.preedit_cursor(cursor_begin);
+                resource.preedit_string(self.serial, text.to_string(), String::new());
+            }
+            TextInputResource::V2(resource) => {
+                resource.preedit_cursor(cursor_begin);
+                resource.preedit_string(text.to_string(), String::new());
+            }
+            TextInputResource::V3(resource) => {
+                resource.preedit_string(Some(text.to_string()), cursor_begin, cursor_end)
+            }
+        }
+        self.done();
+    }
+
+    pub fn delete_surrounding_text(&mut self, before_length: u32, after_length: u32) {
+        // Legacy versions apply the deletion with the next commit_string
+        match &self.resource {
+            TextInputResource::V1(resource) => {
+                resource.delete_surrounding_text(-(before_length as i32), before_length + after_length);
+                resource.commit_string(self.serial, String::new());
+            }
+            TextInputResource::V2(resource) => {
+                resource.delete_surrounding_text(before_length, after_length);
+                resource.commit_string(String::new());
+            }
+            TextInputResource::V3(resource) => resource.delete_surrounding_text(before_length, after_length),
+        }
+        self.done();
+    }
+}
+
 /// Compositor-wide text input state
 #[derive(Debug, Default)]
 pub struct TextInputState {
-    /// All active text input instances, keyed by resource protocol ID
-    pub instances: HashMap<u32, TextInputInstance>,
-    /// Currently focused text input (receives enter/leave)
+    /// All text input instances of every protocol version
+    pub instances: HashMap<ObjectId, TextInputInstance>,
+    /// Surface holding text-input focus; follows keyboard focus
     pub focused: Option<u32>,
+    /// Surfaces of input method popups (candidate windows), placed next to
+    /// the active text input's cursor rectangle
+    pub popup_surfaces: Vec<u32>,
 }
 
 impl TextInputState {
-    /// Send enter event to all text inputs associated with the focused surface
-    pub fn enter(&mut self, surface: &wayland_server::protocol::wl_surface::WlSurface) {
-        for (_id, instance) in &self.instances {
-            if instance.resource.is_alive() {
-                instance.resource.enter(surface);
-            }
+    /// Move text-input focus to `surface` (internal surface id alongside).
+    ///
+    /// Text inputs that had focus get `leave`; those of the new surface's
+    /// client get `enter`. Focus on another client's surface leaves this
+    /// client's text inputs without focus, so nothing reaches them.
+    pub fn set_focus(&mut self, surface: Option<(&WlSurface, u32)>) {
+        let surface_id = surface.map(|(_, id)| id);
+        if self.focused == surface_id {
+            return;
         }
-    }
+        self.focused = surface_id;
 
-    /// Send leave event to all text inputs associated with the focused surface
-    pub fn leave(&mut self, surface: &wayland_server::protocol::wl_surface::WlSurface) {
-        for (_id, instance) in &self.instances {
-            if instance.resource.is_alive() {
-                instance.resource.leave(surface);
+        for instance in self.instances.values_mut() {
+            if instance.entered.is_some() {
+                instance.leave();
+            }
+            if let Some((wl_surface, _)) = surface {
+                if instance.accepts_focus(wl_surface) {
+                    instance.enter(wl_surface);
+                }
             }
         }
     }
 
-    /// Forward a commit string from platform IME to the enabled text input.
-    ///
-    /// Per the zwp_text_input_v3 spec, the `done` serial must reflect the
-    /// compositor's own event sequence.  We increment on each `done` we send
-    /// so clients can detect missed events.
+    /// The enabled text input of the focused surface, if any
+    pub fn active(&self) -> Option<&TextInputInstance> {
+        self.instances.values().find(|instance| instance.is_active())
+    }
+
+    fn active_mut(&mut self) -> Option<&mut TextInputInstance> {
+        self.instances.values_mut().find(|instance| instance.is_active())
+    }
+
+    /// Forward a commit string from platform IME to the active text input.
     pub fn commit_string(&mut self, text: &str) {
-        for (_id, instance) in &mut self.instances {
-            if instance.enabled && instance.resource.is_alive() {
-                instance.serial = instance.serial.wrapping_add(1);
-                instance.resource.commit_string(Some(text.to_string()));
-                instance.resource.done(instance.serial);
-            }
+        if let Some(instance) = self.active_mut() {
+            instance.commit_string(text);
         }
     }
 
     /// Forward preedit from platform IME
     pub fn preedit_string(&mut self, text: &str, cursor_begin: i32, cursor_end: i32) {
-        for (_id, instance) in &mut self.instances {
-            if instance.enabled && instance.resource.is_alive() {
-                instance.serial = instance.serial.wrapping_add(1);
-                instance.resource.preedit_string(Some(text.to_string()), cursor_begin, cursor_end);
-                instance.resource.done(instance.serial);
-            }
+        if let Some(instance) = self.active_mut() {
+            instance.preedit_string(text, cursor_begin, cursor_end);
         }
     }
 
     /// Forward delete_surrounding_text from platform IME
     pub fn delete_surrounding_text(&mut self, before_length: u32, after_length: u32) {
-        for (_id, instance) in &mut self.instances {
-            if instance.enabled && instance.resource.is_alive() {
-                instance.serial = instance.serial.wrapping_add(1);
-                instance.resource.delete_surrounding_text(before_length, after_length);
-                instance.resource.done(instance.serial);
-            }
+        if let Some(instance) = self.active_mut() {
+            instance.delete_surrounding_text(before_length, after_length);
         }
     }
 }
 
+/// Place an input method popup of `size` next to `cursor` (scene
+/// coordinates): below the cursor rectangle, or above it when there is no
+/// room below, shifted horizontally to stay on `output`.
+pub fn place_input_popup(cursor: Rect, size: (u32, u32), output: Rect) -> (i32, i32) {
+    let (w, h) = (size.0 as i32, size.1 as i32);
+    let below = cursor.bottom();
+    let y = if below + h > output.bottom() && cursor.y - h >= output.y {
+        cursor.y - h
+    } else {
+        below
+    };
+    let x = cursor.x.min(output.right() - w).max(output.x);
+    (x, y)
+}
+
 // ============================================================================
 // zwp_text_input_manager_v3
 // ============================================================================
@@ -140,19 +342,7 @@ impl Dispatch<ZwpTextInputManagerV3, ()> for CompositorState {
                 let seat_id = seat.id().protocol_id();
                 let text_input = data_init.init(id, seat_id);
                 let ti_id = text_input.id().protocol_id();
-
-                state.ext.text_input.instances.insert(ti_id, TextInputInstance {
-                    resource: text_input,
-                    seat_id,
-                    enabled: false,
-                    surrounding_text: String::new(),
-                    surrounding_cursor: 0,
-                    surrounding_anchor: 0,
-                    content_type: ContentType::default(),
-                    cursor_rect: (0, 0, 0, 0),
-                    serial: 0,
-                });
-
+                state.add_text_input(TextInputResource::V3(text_input), seat_id);
                 tracing::debug!("Created text input {} for seat {}", ti_id, seat_id);
             }
             zwp_text_input_manager_v3::Request::Destroy => {
@@ -177,33 +367,24 @@ impl Dispatch<ZwpTextInputV3, u32> for CompositorState {
         _dhandle: &DisplayHandle,
         _data_init: &mut DataInit<'_, Self>,
     ) {
-        let ti_id = resource.id().protocol_id();
+        let key = resource.id();
+        let ti_id = key.protocol_id();
         match request {
+            // Enable and disable are double-buffered and take effect on commit
             zwp_text_input_v3::Request::Enable => {
-                if let Some(instance) = state.ext.text_input.instances.get_mut(&ti_id) {
-                    instance.enabled = true;
-                    tracing::debug!("Text input {} enabled", ti_id);
-                }
-                // Notify input method engine (desktop only).
-                #[cfg(feature = "desktop-protocols")]
-                {
-                    state.ext.input_method.activate();
+                if let Some(instance) = state.ext.text_input.instances.get_mut(&key) {
+                    instance.pending_enabled = Some(true);
+                    // Enabling resets the state to its defaults
+                    instance.reset_state();
                 }
             }
             zwp_text_input_v3::Request::Disable => {
-                if let Some(instance) = state.ext.text_input.instances.get_mut(&ti_id) {
-                    instance.enabled = false;
-                    instance.surrounding_text.clear();
-                    tracing::debug!("Text input {} disabled", ti_id);
-                }
-                // Notify input method engine (desktop only).
-                #[cfg(feature = "desktop-protocols")]
-                {
-                    state.ext.input_method.deactivate();
+                if let Some(instance) = state.ext.text_input.instances.get_mut(&key) {
+                    instance.pending_enabled = Some(false);
                 }
             }
             zwp_text_input_v3::Request::SetSurroundingText { text, cursor, anchor } => {
-                if let Some(instance) = state.ext.text_input.instances.get_mut(&ti_id) {
+                if let Some(instance) = state.ext.text_input.instances.get_mut(&key) {
                     instance.surrounding_text = text;
                     instance.surrounding_cursor = cursor;
                     instance.surrounding_anchor = anchor;
@@ -213,44 +394,32 @@ impl Dispatch<ZwpTextInputV3, u32> for CompositorState {
                 // Stored implicitly — the change cause applies to the next commit
             }
             zwp_text_input_v3::Request::SetContentType { hint, purpose } => {
-                if let Some(instance) = state.ext.text_input.instances.get_mut(&ti_id) {
+                if let Some(instance) = state.ext.text_input.instances.get_mut(&key) {
                     instance.content_type.hint = hint.into();
                     instance.content_type.purpose = purpose.into();
                 }
             }
             zwp_text_input_v3::Request::SetCursorRectangle { x, y, width, height } => {
-                if let Some(instance) = state.ext.text_input.instances.get_mut(&ti_id) {
+                if let Some(instance) = state.ext.text_input.instances.get_mut(&key) {
                     instance.cursor_rect = (x, y, width, height);
                 }
             }
             zwp_text_input_v3::Request::Commit => {
-                // Extract the values we need before releasing the borrow
-                // on `instances`, so we can also borrow `input_method`.
-                let _im_data = state.ext.text_input.instances.get_mut(&ti_id).map(|instance| {
+                if let Some(instance) = state.ext.text_input.instances.get_mut(&key) {
                     instance.serial = instance.serial.wrapping_add(1);
-                    tracing::debug!("Text input {} commit (serial {})", ti_id, instance.serial);
-                    (
-                        instance.surrounding_text.clone(),
-                        instance.surrounding_cursor as u32,
-                        instance.surrounding_anchor as u32,
-                        instance.content_type.hint,
-                        instance.content_type.purpose,
-                    )
-                });
-
-                // Forward double-buffered state to the input method engine.
-                #[cfg(feature = "desktop-protocols")]
-                if let Some((text, cursor, anchor, hint, purpose)) = _im_data {
-                    let im = &mut state.ext.input_method;
-                    if im.active {
-                        im.surrounding_text(&text, cursor, anchor);
-                        im.content_type(hint, purpose);
-                        im.done();
+                    if let Some(enabled) = instance.pending_enabled.take() {
+                        instance.enabled = enabled;
+                        if !enabled {
+                            instance.surrounding_text.clear();
+                        }
+                        tracing::debug!("Text input {} {}", ti_id, if enabled { "enabled" } else { "disabled" });
                     }
+                    tracing::debug!("Text input {} commit (serial {})", ti_id, instance.serial);
                 }
+                state.sync_input_method();
             }
             zwp_text_input_v3::Request::Destroy => {
-                state.ext.text_input.instances.remove(&ti_id);
+                state.remove_text_input(&key);
                 tracing::debug!("Text input {} destroyed", ti_id);
             }
             _ => {}
@@ -258,7 +427,127 @@ impl Dispatch<ZwpTextInputV3, u32> for CompositorState {
     }
 }
 
+impl CompositorState {
+    /// Track a new text input. One created while its client has focus
+    /// enters right away (v1 waits for `activate`).
+    pub(crate) fn add_text_input(&mut self, resource: TextInputResource, seat_id: u32) {
+        let mut instance = TextInputInstance::new(resource, seat_id);
+        let focused = self.ext.text_input.focused
+            .and_then(|sid| self.get_surface(sid))
+            .and_then(|surface| surface.read().unwrap().resource.clone());
+        if let Some(surface) = focused.filter(|surface| instance.accepts_focus(surface)) {
+            instance.enter(&surface);
+        }
+        self.ext.text_input.instances.insert(instance.resource.id(), instance);
+    }
+
+    /// Forget a destroyed text input, deactivating the input method if it
+    /// was the active one.
+    pub(crate) fn remove_text_input(&mut self, id: &ObjectId) {
+        let was_active = self.ext.text_input.instances.remove(id)
+            .map_or(false, |instance| instance.is_active());
+        if was_active {
+            self.sync_input_method();
+        }
+    }
+
+    /// Move text-input focus to the keyboard focus and let the input method
+    /// follow.
+    pub fn update_text_input_focus(&mut self) {
+        let focus = self.seat.keyboard.focus;
+        if self.ext.text_input.focused == focus {
+            return;
+        }
+        let surface = focus
+            .and_then(|sid| self.get_surface(sid))
+            .and_then(|surface| surface.read().unwrap().resource.clone());
+        match (&surface, focus) {
+            (Some(wl_surface), Some(sid)) => self.ext.text_input.set_focus(Some((wl_surface, sid))),
+            _ => self.ext.text_input.set_focus(None),
+        }
+        self.sync_input_method();
+    }
+
+    /// Bring the input method in line with the active text input: activate
+    /// it with the text input's state, or deactivate it when no enabled text
+    /// input has focus.
+    pub fn sync_input_method(&mut self) {
+        #[cfg(feature = "desktop-protocols")]
+        {
+            let active = self.ext.text_input.active().map(|instance| (
+                instance.surrounding_text.clone(),
+                instance.surrounding_cursor.max(0) as u32,
+                instance.surrounding_anchor.max(0) as u32,
+                instance.content_type.clone(),
+                instance.cursor_rect,
+            ));
+            let im = &mut self.ext.input_method;
+            match active {
+                Some((text, cursor, anchor, content_type, cursor_rect)) => {
+                    if !im.active {
+                        im.activate();
+                    }
+                    im.surrounding_text(&text, cursor, anchor);
+                    im.content_type(content_type.hint, content_type.purpose);
+                    im.done();
+                    im.text_input_rectangle(cursor_rect);
+                }
+                None if im.active => {
+                    im.deactivate();
+                    im.done();
+                }
+                None => {}
+            }
+        }
+    }
+
+    /// Scene position of an input method popup of `size`, next to the
+    /// active text input's cursor rectangle.
+    pub fn input_popup_position(&self, size: (u32, u32)) -> Option<(i32, i32)> {
+        let instance = self.ext.text_input.active()?;
+        let surface_id = self.ext.text_input.focused?;
+        let placed = self.scene.flatten_unculled().into_iter().find(|s| s.surface_id == surface_id)?;
+        let (cx, cy, cw, ch) = instance.cursor_rect;
+        let (x, y) = placed.surface_to_scene(cx as f64, cy as f64);
+        let cursor = Rect::new(
+            x as i32,
+            y as i32,
+            (cw.max(0) as f32 * placed.scale) as u32,
+            (ch.max(0) as f32 * placed.scale) as u32,
+        );
+        let output = self.outputs.iter()
+            .map(|o| Rect::new(o.x, o.y, o.width, o.height))
+            .find(|o| o.contains_point(cursor.x, cursor.y))
+            .or_else(|| self.outputs.get(self.primary_output).map(|o| Rect::new(o.x, o.y, o.width, o.height)))?;
+        Some(place_input_popup(cursor, size, output))
+    }
+}
+
 /// Register zwp_text_input_manager_v3 global
 pub fn register_text_input_manager(display: &DisplayHandle) -> wayland_server::backend::GlobalId {
     display.create_global::<CompositorState, ZwpTextInputManagerV3, ()>(1, ())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_place_input_popup() {
+        let output = Rect::new(0, 0, 1000, 800);
+        // Below the cursor
+        assert_eq!(place_input_popup(Rect::new(100, 100, 2, 20), (300, 200), output), (100, 120));
+        // Flipped above when it would run off the bottom
+        assert_eq!(place_input_popup(Rect::new(100, 700, 2, 20), (300, 200), output), (100, 500));
+        // Shifted left to stay on the output
+        assert_eq!(place_input_popup(Rect::new(900, 100, 2, 20), (300, 200), output), (700, 120));
+    }
+
+    #[test]
+    fn test_legacy_content_purpose() {
+        // password, then date: v3 has pin in between
+        assert_eq!(legacy_content_purpose(8), 8);
+        assert_eq!(legacy_content_purpose(9), 10);
+        assert_eq!(legacy_content_purpose(12), 13);
+    }
+}
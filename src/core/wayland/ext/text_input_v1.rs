@@ -0,0 +1,149 @@
+//! Text Input v1 protocol implementation.
+//!
+//! `zwp_text_input_v1` is spoken by older toolkits (Qt5, older
+//! Chromium/Electron). Unlike v3, a text input is activated for an explicit
+//! surface and carries the client's state serial in every event. Instances
+//! live in the shared [`TextInputState`](super::text_input::TextInputState).
+
+use wayland_server::{
+    Client, DataInit, Dispatch, DisplayHandle, GlobalDispatch, New, Resource,
+};
+use wayland_protocols::wp::text_input::zv1::server::{
+    zwp_text_input_manager_v1::{self, ZwpTextInputManagerV1},
+    zwp_text_input_v1::{self, ZwpTextInputV1},
+};
+
+use crate::core::state::CompositorState;
+use crate::core::wayland::wayland::compositor::internal_surface_id;
+use crate::core::wayland::ext::text_input::{legacy_content_purpose, TextInputResource};
+
+// ============================================================================
+// zwp_text_input_manager_v1
+// ============================================================================
+
+impl GlobalDispatch<ZwpTextInputManagerV1, ()> for CompositorState {
+    fn bind(
+        _state: &mut Self,
+        _handle: &DisplayHandle,
+        _client: &Client,
+        resource: New<ZwpTextInputManagerV1>,
+        _global_data: &(),
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        data_init.init(resource, ());
+        tracing::debug!("Bound zwp_text_input_manager_v1");
+    }
+}
+
+impl Dispatch<ZwpTextInputManagerV1, ()> for CompositorState {
+    fn request(
+        state: &mut Self,
+        _client: &Client,
+        _resource: &ZwpTextInputManagerV1,
+        request: zwp_text_input_manager_v1::Request,
+        _data: &(),
+        _dhandle: &DisplayHandle,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        if let zwp_text_input_manager_v1::Request::CreateTextInput { id } = request {
+            let text_input = data_init.init(id, ());
+            let ti_id = text_input.id().protocol_id();
+            // The seat is only known once the text input is activated
+            state.add_text_input(TextInputResource::V1(text_input), 0);
+            tracing::debug!("Created text input v1 {}", ti_id);
+        }
+    }
+}
+
+// ============================================================================
+// zwp_text_input_v1
+// ============================================================================
+
+impl Dispatch<ZwpTextInputV1, ()> for CompositorState {
+    fn request(
+        state: &mut Self,
+        _client: &Client,
+        resource: &ZwpTextInputV1,
+        request: zwp_text_input_v1::Request,
+        _data: &(),
+        _dhandle: &DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+        let key = resource.id();
+        match request {
+            zwp_text_input_v1::Request::Activate { seat, surface } => {
+                let surface_id = internal_surface_id(&surface);
+                let has_focus = state.ext.text_input.focused == Some(surface_id);
+                if let Some(instance) = state.ext.text_input.instances.get_mut(&key) {
+                    if instance.entered.is_some() {
+                        instance.leave();
+                    }
+                    instance.seat_id = seat.id().protocol_id();
+                    instance.enabled = true;
+                    instance.activated_surface = Some(surface.clone());
+                    instance.reset_state();
+                    // Activation for a surface without focus waits for it
+                    if has_focus {
+                        instance.enter(&surface);
+                    }
+                }
+                state.sync_input_method();
+                tracing::debug!("Text input v1 {} activated on surface {}", key.protocol_id(), surface_id);
+            }
+            zwp_text_input_v1::Request::Deactivate { .. } => {
+                if let Some(instance) = state.ext.text_input.instances.get_mut(&key) {
+                    instance.leave();
+                    instance.enabled = false;
+                    instance.activated_surface = None;
+                }
+                state.sync_input_method();
+            }
+            zwp_text_input_v1::Request::SetSurroundingText { text, cursor, anchor } => {
+                if let Some(instance) = state.ext.text_input.instances.get_mut(&key) {
+                    instance.surrounding_text = text;
+                    instance.surrounding_cursor = cursor as i32;
+                    instance.surrounding_anchor = anchor as i32;
+                }
+            }
+            zwp_text_input_v1::Request::SetContentType { hint, purpose } => {
+                if let Some(instance) = state.ext.text_input.instances.get_mut(&key) {
+                    instance.content_type.hint = hint.into();
+                    instance.content_type.purpose = legacy_content_purpose(purpose.into());
+                }
+            }
+            zwp_text_input_v1::Request::SetCursorRectangle { x, y, width, height } => {
+                if let Some(instance) = state.ext.text_input.instances.get_mut(&key) {
+                    instance.cursor_rect = (x, y, width, height);
+                }
+            }
+            zwp_text_input_v1::Request::CommitState { serial } => {
+                if let Some(instance) = state.ext.text_input.instances.get_mut(&key) {
+                    // Events carry the serial of the latest state we know
+                    instance.serial = serial;
+                }
+                state.sync_input_method();
+            }
+            zwp_text_input_v1::Request::Reset => {
+                state.sync_input_method();
+            }
+            // Input panel visibility, language and preedit actions are left
+            // to the platform IME
+            _ => {}
+        }
+    }
+
+    fn destroyed(
+        state: &mut Self,
+        _client: wayland_server::backend::ClientId,
+        resource: &ZwpTextInputV1,
+        _data: &(),
+    ) {
+        state.remove_text_input(&resource.id());
+        tracing::debug!("Text input v1 {} destroyed", resource.id().protocol_id());
+    }
+}
+
+/// Register zwp_text_input_manager_v1 global
+pub fn register_text_input_manager_v1(display: &DisplayHandle) -> wayland_server::backend::GlobalId {
+    display.create_global::<CompositorState, ZwpTextInputManagerV1, ()>(1, ())
+}
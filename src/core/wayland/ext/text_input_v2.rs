@@ -0,0 +1,135 @@
+//! KDE Text Input v2 protocol implementation.
+//!
+//! `zwp_text_input_v2` comes from plasma-wayland-protocols and is used by Qt
+//! and GTK3 on some distros. Focus follows the keyboard like v3, but
+//! enable/disable take effect immediately and state is sent with
+//! `update_state`. Instances live in the shared
+//! [`TextInputState`](super::text_input::TextInputState).
+
+use wayland_server::{
+    Client, DataInit, Dispatch, DisplayHandle, GlobalDispatch, New, Resource,
+};
+
+use crate::core::state::CompositorState;
+use crate::core::wayland::ext::text_input::{legacy_content_purpose, TextInputResource};
+use crate::core::wayland::protocol::server::plasma::text_input::v2::server::{
+    zwp_text_input_manager_v2::{self, ZwpTextInputManagerV2},
+    zwp_text_input_v2::{self, ZwpTextInputV2},
+};
+
+// ============================================================================
+// zwp_text_input_manager_v2
+// ============================================================================
+
+impl GlobalDispatch<ZwpTextInputManagerV2, ()> for CompositorState {
+    fn bind(
+        _state: &mut Self,
+        _handle: &DisplayHandle,
+        _client: &Client,
+        resource: New<ZwpTextInputManagerV2>,
+        _global_data: &(),
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        data_init.init(resource, ());
+        tracing::debug!("Bound zwp_text_input_manager_v2");
+    }
+}
+
+impl Dispatch<ZwpTextInputManagerV2, ()> for CompositorState {
+    fn request(
+        state: &mut Self,
+        _client: &Client,
+        _resource: &ZwpTextInputManagerV2,
+        request: zwp_text_input_manager_v2::Request,
+        _data: &(),
+        _dhandle: &DisplayHandle,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        match request {
+            zwp_text_input_manager_v2::Request::GetTextInput { id, seat } => {
+                let seat_id = seat.id().protocol_id();
+                let text_input = data_init.init(id, seat_id);
+                let ti_id = text_input.id().protocol_id();
+                state.add_text_input(TextInputResource::V2(text_input), seat_id);
+                tracing::debug!("Created text input v2 {} for seat {}", ti_id, seat_id);
+            }
+            zwp_text_input_manager_v2::Request::Destroy => {
+                tracing::debug!("zwp_text_input_manager_v2 destroyed");
+            }
+            _ => {}
+        }
+    }
+}
+
+// ============================================================================
+// zwp_text_input_v2 — user data is seat_id: u32
+// ============================================================================
+
+impl Dispatch<ZwpTextInputV2, u32> for CompositorState {
+    fn request(
+        state: &mut Self,
+        _client: &Client,
+        resource: &ZwpTextInputV2,
+        request: zwp_text_input_v2::Request,
+        _seat_id: &u32,
+        _dhandle: &DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+        let key = resource.id();
+        match request {
+            zwp_text_input_v2::Request::Enable { surface } => {
+                if let Some(instance) = state.ext.text_input.instances.get_mut(&key) {
+                    // Only the entered surface can be enabled
+                    if instance.entered.as_ref() == Some(&surface) {
+                        instance.enabled = true;
+                    }
+                }
+                state.sync_input_method();
+            }
+            zwp_text_input_v2::Request::Disable { surface } => {
+                if let Some(instance) = state.ext.text_input.instances.get_mut(&key) {
+                    if instance.entered.as_ref() == Some(&surface) {
+                        instance.enabled = false;
+                    }
+                }
+                state.sync_input_method();
+            }
+            zwp_text_input_v2::Request::SetSurroundingText { text, cursor, anchor } => {
+                if let Some(instance) = state.ext.text_input.instances.get_mut(&key) {
+                    instance.surrounding_text = text;
+                    instance.surrounding_cursor = cursor;
+                    instance.surrounding_anchor = anchor;
+                }
+            }
+            zwp_text_input_v2::Request::SetContentType { hint, purpose } => {
+                if let Some(instance) = state.ext.text_input.instances.get_mut(&key) {
+                    instance.content_type.hint = hint.into();
+                    instance.content_type.purpose = legacy_content_purpose(purpose.into());
+                }
+            }
+            zwp_text_input_v2::Request::SetCursorRectangle { x, y, width, height } => {
+                if let Some(instance) = state.ext.text_input.instances.get_mut(&key) {
+                    instance.cursor_rect = (x, y, width, height);
+                }
+            }
+            zwp_text_input_v2::Request::UpdateState { serial, reason: _ } => {
+                let current = state.ext.text_input.instances.get(&key).map(|instance| instance.serial);
+                // State for an older enter is stale
+                if current == Some(serial) {
+                    state.sync_input_method();
+                }
+            }
+            zwp_text_input_v2::Request::Destroy => {
+                state.remove_text_input(&key);
+                tracing::debug!("Text input v2 {} destroyed", key.protocol_id());
+            }
+            // Input panel visibility and language are left to the platform IME
+            _ => {}
+        }
+    }
+}
+
+/// Register zwp_text_input_manager_v2 global
+pub fn register_text_input_manager_v2(display: &DisplayHandle) -> wayland_server::backend::GlobalId {
+    display.create_global::<CompositorState, ZwpTextInputManagerV2, ()>(1, ())
+}
@@ -13,7 +13,9 @@ use crate::core::wayland::protocol::server::wp::viewporter::server::{
 };
 
 
+use crate::core::render::transform::SourceRect;
 use crate::core::state::CompositorState;
+use crate::core::wayland::wayland::compositor::internal_surface_id;
 use std::collections::HashMap;
 
 // ============================================================================
@@ -78,8 +80,8 @@ impl Dispatch<WpViewporter, ()> for CompositorState {
     ) {
         match request {
             wp_viewporter::Request::GetViewport { id, surface } => {
-                let surface_id = surface.id().protocol_id();
-                
+                let surface_id = internal_surface_id(&surface);
+
                 let viewport_data = ViewportData::new(surface_id);
                 let viewport: wp_viewport::WpViewport = data_init.init(id, ());
                 state.ext.viewporter.viewports.insert(viewport.id().protocol_id(), viewport_data);
@@ -164,12 +166,29 @@ impl Dispatch<WpViewport, ()> for CompositorState {
             }
             wp_viewport::Request::Destroy => {
                 if let Some(data) = state.ext.viewporter.viewports.remove(&viewport_id) {
+                    // Source and destination are dropped on the next commit
+                    state.set_pending_viewport(&ViewportData::new(data.surface_id));
                     tracing::debug!("Viewport destroyed for surface {}", data.surface_id);
-
                 }
             }
             _ => {}
         }
+
+        if let Some(data) = state.ext.viewporter.viewports.get(&viewport_id).cloned() {
+            state.set_pending_viewport(&data);
+        }
+    }
+}
+
+impl CompositorState {
+    /// Stage viewport state in the surface's pending state; it takes
+    /// effect on the next wl_surface.commit.
+    fn set_pending_viewport(&mut self, data: &ViewportData) {
+        if let Some(surface) = self.get_surface(data.surface_id) {
+            let mut surface = surface.write().unwrap();
+            surface.pending.viewport_source = data.source.map(|s| SourceRect::new(s.x, s.y, s.width, s.height));
+            surface.pending.viewport_destination = data.destination;
+        }
     }
 }
 
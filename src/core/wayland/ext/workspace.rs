@@ -37,6 +37,12 @@ impl WorkspaceState {
         self.next_id += 1;
         self.next_id
     }
+
+    /// Whether a workspace is shown. Unknown workspaces count as active so
+    /// windows never disappear because of a stale id.
+    pub fn is_active(&self, workspace_id: u32) -> bool {
+        self.workspaces.get(&workspace_id).map_or(true, |w| w.active)
+    }
 }
 
 #[derive(Debug, Clone, Default)]
@@ -2,6 +2,8 @@
 //!
 //! Allows XWayland to grab keyboard input for a surface. When a grab is
 //! active, keyboard events are exclusively delivered to the grabbed surface.
+//! All of the client's grabs share one [`XwaylandKeyboardInputGrab`] on the
+//! seat, which holds keyboard focus on the grabbed surface.
 
 use std::collections::HashMap;
 use wayland_server::{
@@ -12,12 +14,13 @@ use wayland_protocols::xwayland::keyboard_grab::zv1::server::{
     zwp_xwayland_keyboard_grab_v1::{self, ZwpXwaylandKeyboardGrabV1},
 };
 
+use crate::core::input::grab::{GrabKey, GrabKind, GrabStatus, InputGrab};
 use crate::core::state::CompositorState;
 
 /// Compositor-wide XWayland keyboard grab state
 #[derive(Debug, Default)]
 pub struct XwaylandKeyboardGrabState {
-    /// Active grabs: grab_id → surface_id
+    /// Active grabs: grab_id → internal surface_id
     pub grabs: HashMap<u32, u32>,
 }
 
@@ -33,6 +36,35 @@ impl XwaylandKeyboardGrabState {
     }
 }
 
+/// Keyboard grab for the surfaces Xwayland grabbed: keys go to the grabbed
+/// surface, wherever keyboard focus was, except on the lock screen
+#[derive(Debug)]
+pub struct XwaylandKeyboardInputGrab;
+
+impl InputGrab for XwaylandKeyboardInputGrab {
+    fn kind(&self) -> GrabKind {
+        GrabKind::XwaylandKeyboard
+    }
+
+    fn key(&mut self, state: &mut CompositorState, _key: &GrabKey) -> GrabStatus {
+        if state.session_locked() {
+            return GrabStatus::Pass;
+        }
+        let Some(surface_id) = state.ext.xwayland_keyboard_grab.grabbed_surface() else {
+            return GrabStatus::End { consumed: false };
+        };
+        let focusable = state.get_surface(surface_id).is_some() && state.accepts_keyboard_focus(surface_id);
+        if focusable && state.seat.keyboard.focus != Some(surface_id) {
+            state.set_keyboard_focus_surface(Some(surface_id));
+        }
+        GrabStatus::Pass
+    }
+
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+}
+
 impl GlobalDispatch<ZwpXwaylandKeyboardGrabManagerV1, ()> for CompositorState {
     fn bind(
         _state: &mut Self,
@@ -50,7 +82,7 @@ impl GlobalDispatch<ZwpXwaylandKeyboardGrabManagerV1, ()> for CompositorState {
 impl Dispatch<ZwpXwaylandKeyboardGrabManagerV1, ()> for CompositorState {
     fn request(
         state: &mut Self,
-        _client: &Client,
+        client: &Client,
         _resource: &ZwpXwaylandKeyboardGrabManagerV1,
         request: zwp_xwayland_keyboard_grab_manager_v1::Request,
         _data: &(),
@@ -59,12 +91,17 @@ impl Dispatch<ZwpXwaylandKeyboardGrabManagerV1, ()> for CompositorState {
     ) {
         match request {
             zwp_xwayland_keyboard_grab_manager_v1::Request::GrabKeyboard { id, surface, seat: _ } => {
-                let surface_id = surface.id().protocol_id();
-                let grab = data_init.init(id, surface_id);
+                let surface_id = state.protocol_to_internal_surface
+                    .get(&(client.id(), surface.id().protocol_id()))
+                    .copied();
+                let grab = data_init.init(id, surface_id.unwrap_or(0));
                 let grab_id = grab.id().protocol_id();
 
-                state.ext.xwayland_keyboard_grab.grabs.insert(grab_id, surface_id);
-                tracing::info!("XWayland keyboard grab {} for surface {}", grab_id, surface_id);
+                if let Some(surface_id) = surface_id {
+                    state.ext.xwayland_keyboard_grab.grabs.insert(grab_id, surface_id);
+                    let _ = state.start_grab(Box::new(XwaylandKeyboardInputGrab));
+                    tracing::info!("XWayland keyboard grab {} for surface {}", grab_id, surface_id);
+                }
             }
             zwp_xwayland_keyboard_grab_manager_v1::Request::Destroy => {}
             _ => {}
@@ -86,6 +123,10 @@ impl Dispatch<ZwpXwaylandKeyboardGrabV1, u32> for CompositorState {
             zwp_xwayland_keyboard_grab_v1::Request::Destroy => {
                 let grab_id = resource.id().protocol_id();
                 state.ext.xwayland_keyboard_grab.grabs.remove(&grab_id);
+                if !state.ext.xwayland_keyboard_grab.is_grabbed() {
+                    state.end_grab(GrabKind::XwaylandKeyboard);
+                    state.restore_window_keyboard_focus();
+                }
                 tracing::debug!("XWayland keyboard grab {} released", grab_id);
             }
             _ => {}
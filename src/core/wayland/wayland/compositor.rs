@@ -9,6 +9,13 @@ use crate::core::surface::Surface;
 
 pub struct CompositorGlobal;
 
+/// The compositor's own id for a wl_surface, as given to it in
+/// wl_compositor.create_surface. Protocol extensions that take a surface
+/// argument use this to find its state.
+pub fn internal_surface_id(surface: &wl_surface::WlSurface) -> u32 {
+    surface.data::<u32>().copied().unwrap_or_else(|| surface.id().protocol_id())
+}
+
 impl GlobalDispatch<wl_compositor::WlCompositor, ()> for CompositorState {
     #[allow(unreachable_code)]
     fn bind(
@@ -129,7 +136,7 @@ impl Dispatch<wl_surface::WlSurface, u32> for CompositorState {
                 crate::wtrace!(crate::util::logging::COMPOSITOR, "Surface {} damage (buffer): x={}, y={}, width={}, height={}", id, x, y, width, height);
                 if let Some(surface) = state.get_surface(id) {
                     let mut surface = surface.write().unwrap();
-                    surface.pending.damage.push(crate::core::surface::damage::DamageRegion {
+                    surface.pending.buffer_damage.push(crate::core::surface::damage::DamageRegion {
                         x, y, width, height
                     });
                 }
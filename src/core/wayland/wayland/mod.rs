@@ -28,8 +28,8 @@ pub fn register(state: &mut CompositorState, dh: &DisplayHandle) {
     }
     crate::wlog!(crate::util::logging::COMPOSITOR, "Registered {} wl_output(s) v3", state.outputs.len());
     
-    dh.create_global::<CompositorState, wl_seat::WlSeat, SeatGlobal>(8, SeatGlobal::default());
-    crate::wlog!(crate::util::logging::COMPOSITOR, "Registered wl_seat v8");
+    dh.create_global::<CompositorState, wl_seat::WlSeat, SeatGlobal>(9, SeatGlobal::default());
+    crate::wlog!(crate::util::logging::COMPOSITOR, "Registered wl_seat v9");
     
     dh.create_global::<CompositorState, wl_subcompositor::WlSubcompositor, _>(1, ());
     crate::wlog!(crate::util::logging::COMPOSITOR, "Registered wl_subcompositor v1");
@@ -206,6 +206,11 @@ impl Dispatch<zwlr_data_control_offer_v1::ZwlrDataControlOfferV1, ()> for Compos
                             src.send(mime_type, fd.as_fd());
                             tracing::debug!("Forwarded data_control receive to wlr source");
                         }
+                        crate::core::state::SelectionSource::Cached => {
+                            _state.data.clipboard_cache.serve(&mime_type, fd);
+                            tracing::debug!("Served data_control receive from clipboard cache");
+                            return;
+                        }
                     }
                 }
                 drop(fd);
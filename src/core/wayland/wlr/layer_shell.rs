@@ -15,15 +15,23 @@
 //! 1. Must send `configure` event after `get_layer_surface`
 //! 2. Must track surface IDs correctly (not hardcode to 0)
 //! 3. Must set surface role to Layer
+//!
+//! ## Keyboard interactivity and popups
+//!
+//! A mapped top or overlay surface with `exclusive` interactivity holds
+//! keyboard focus until it unmaps; `on_demand` surfaces take focus when
+//! clicked. Popups created with `get_popup` are placed relative to their
+//! layer surface and constrained to its output.
 
 use wayland_server::{
-    Dispatch, Resource, DisplayHandle, GlobalDispatch,
+    backend::ClientId, Dispatch, Resource, DisplayHandle, GlobalDispatch,
 };
 use crate::core::wayland::protocol::wlroots::wlr_layer_shell_unstable_v1::{
-    zwlr_layer_shell_v1, zwlr_layer_surface_v1,
+    zwlr_layer_shell_v1, zwlr_layer_surface_v1::{self, KeyboardInteractivity},
 };
 
 use crate::core::state::{CompositorState, LayerSurface};
+use crate::util::geometry::Rect;
 use crate::core::surface::role::SurfaceRole;
 
 // ============================================================================
@@ -143,30 +151,12 @@ impl Dispatch<zwlr_layer_shell_v1::ZwlrLayerShellV1, ()> for CompositorState {
                 // Initialize the layer surface resource with our data
                 let layer_surface_resource: zwlr_layer_surface_v1::ZwlrLayerSurfaceV1 = data_init.init(id, layer_surface_data);
                 
-                // CRITICAL: Send initial configure event!
-                // wlroots clients BLOCK until they receive this configure event.
-                // Get output dimensions for the configure
-                let (output_width, output_height) = state.outputs
-                    .get(state.primary_output)
-                    .map(|o| (o.width, o.height))
-                    .unwrap_or((1920, 1080));
-                
-                let serial = state.next_serial();
-                
-                // Update layer surface state with resource and pending serial
+                // The first configure is sent once the client made its
+                // initial commit and the surface has been arranged
                 if let Some(ls) = state.get_layer_surface(client_id.clone(), surface_id) {
                     let mut ls = ls.write().unwrap();
-                    ls.pending_serial = serial;
-                    ls.resource = Some(layer_surface_resource.clone());
+                    ls.resource = Some(layer_surface_resource);
                 }
-                
-                // Send configure event with output dimensions
-                layer_surface_resource.configure(serial, output_width, output_height);
-                
-                tracing::info!(
-                    "Layer shell: Sent configure (serial={}) with dimensions {}x{} to surface {}",
-                    serial, output_width, output_height, surface_id
-                );
             }
             zwlr_layer_shell_v1::Request::Destroy => {
                 tracing::debug!("zwlr_layer_shell_v1 destroyed");
@@ -184,7 +174,7 @@ impl Dispatch<zwlr_layer_surface_v1::ZwlrLayerSurfaceV1, LayerSurfaceData> for C
     fn request(
         state: &mut Self,
         _client: &wayland_server::Client,
-        _resource: &zwlr_layer_surface_v1::ZwlrLayerSurfaceV1,
+        resource: &zwlr_layer_surface_v1::ZwlrLayerSurfaceV1,
         request: zwlr_layer_surface_v1::Request,
         data: &LayerSurfaceData,
         _dhandle: &DisplayHandle,
@@ -199,10 +189,8 @@ impl Dispatch<zwlr_layer_surface_v1::ZwlrLayerSurfaceV1, LayerSurfaceData> for C
                 tracing::debug!("Layer surface {}: set_size {}x{}", surface_id, width, height);
                 if let Some(ls) = state.get_layer_surface(client_id.clone(), surface_id) {
                     let mut ls = ls.write().unwrap();
-                    ls.width = width;
-                    ls.height = height;
+                    ls.requested_size = (width, height);
                 }
-                state.reposition_layer_surfaces();
             }
             zwlr_layer_surface_v1::Request::SetAnchor { anchor } => {
                 let anchor_val = match anchor {
@@ -214,7 +202,6 @@ impl Dispatch<zwlr_layer_surface_v1::ZwlrLayerSurfaceV1, LayerSurfaceData> for C
                     let mut ls = ls.write().unwrap();
                     ls.anchor = anchor_val;
                 }
-                state.reposition_layer_surfaces();
             }
             zwlr_layer_surface_v1::Request::SetExclusiveZone { zone } => {
                 tracing::debug!("Layer surface {}: set_exclusive_zone {}", surface_id, zone);
@@ -222,7 +209,6 @@ impl Dispatch<zwlr_layer_surface_v1::ZwlrLayerSurfaceV1, LayerSurfaceData> for C
                     let mut ls = ls.write().unwrap();
                     ls.exclusive_zone = zone;
                 }
-                state.reposition_layer_surfaces();
             }
             zwlr_layer_surface_v1::Request::SetMargin { top, right, bottom, left } => {
                 tracing::debug!("Layer surface {}: set_margin t={} r={} b={} l={}", 
@@ -231,7 +217,6 @@ impl Dispatch<zwlr_layer_surface_v1::ZwlrLayerSurfaceV1, LayerSurfaceData> for C
                     let mut ls = ls.write().unwrap();
                     ls.margin = (top, right, bottom, left);
                 }
-                state.reposition_layer_surfaces();
             }
             zwlr_layer_surface_v1::Request::SetKeyboardInteractivity { keyboard_interactivity } => {
                 let interactivity_val = match keyboard_interactivity {
@@ -243,10 +228,21 @@ impl Dispatch<zwlr_layer_surface_v1::ZwlrLayerSurfaceV1, LayerSurfaceData> for C
                     let mut ls = ls.write().unwrap();
                     ls.interactivity = interactivity_val;
                 }
+                state.update_layer_keyboard_focus(surface_id);
             }
-            zwlr_layer_surface_v1::Request::GetPopup { popup: _popup } => {
+            zwlr_layer_surface_v1::Request::GetPopup { popup } => {
                 tracing::debug!("Layer surface {}: get_popup", surface_id);
-                // popup is already initialized in this protocol version/implementation
+                let popup_id = popup.id().protocol_id();
+                let is_orphan = state.xdg.popups.get(&(client_id.clone(), popup_id))
+                    .map_or(false, |p| p.parent_id.is_none() && p.parent_layer.is_none());
+                if !is_orphan {
+                    tracing::warn!("Layer surface {}: get_popup for popup {} that already has a parent", surface_id, popup_id);
+                    return;
+                }
+                if let Some(data) = state.xdg.popups.get_mut(&(client_id.clone(), popup_id)) {
+                    data.parent_layer = Some(surface_id);
+                }
+                state.configure_popup(&client_id, popup_id, None);
             }
             zwlr_layer_surface_v1::Request::AckConfigure { serial } => {
                 tracing::info!("Layer surface {}: ack_configure serial={}", surface_id, serial);
@@ -259,6 +255,16 @@ impl Dispatch<zwlr_layer_surface_v1::ZwlrLayerSurfaceV1, LayerSurfaceData> for C
                 tracing::info!("Layer surface {}: destroy", surface_id);
                 state.remove_layer_surface(client_id.clone(), surface_id);
                 state.wlr.surface_to_layer.remove(&(client_id, surface_id));
+                // Give its exclusive zone back to the others
+                state.reposition_layer_surfaces();
+
+                // Its popups have nothing left to be placed against
+                for popup in state.xdg.popups.values().filter(|p| p.parent_layer == Some(surface_id)) {
+                    if let Some(res) = &popup.resource {
+                        res.popup_done();
+                    }
+                }
+                state.update_layer_keyboard_focus(surface_id);
             }
             zwlr_layer_surface_v1::Request::SetLayer { layer } => {
                 let layer_val = match layer {
@@ -270,13 +276,177 @@ impl Dispatch<zwlr_layer_surface_v1::ZwlrLayerSurfaceV1, LayerSurfaceData> for C
                     let mut ls = ls.write().unwrap();
                     ls.layer = layer_val;
                 }
-                state.reposition_layer_surfaces();
+            }
+            zwlr_layer_surface_v1::Request::SetExclusiveEdge { edge } => {
+                let edge_val = match edge {
+                    wayland_server::backend::protocol::WEnum::Value(v) => v.bits(),
+                    wayland_server::backend::protocol::WEnum::Unknown(v) => v,
+                };
+                tracing::debug!("Layer surface {}: set_exclusive_edge 0x{:x}", surface_id, edge_val);
+                // Zero resets to the edge deduced from the anchor
+                let single_edge = edge_val.is_power_of_two() && edge_val <= ANCHOR_RIGHT;
+                if edge_val != 0 && !single_edge {
+                    resource.post_error(
+                        zwlr_layer_surface_v1::Error::InvalidExclusiveEdge,
+                        "exclusive edge must be a single edge",
+                    );
+                    return;
+                }
+                if let Some(ls) = state.get_layer_surface(client_id, surface_id) {
+                    let mut ls = ls.write().unwrap();
+                    ls.exclusive_edge = edge_val;
+                }
             }
             _ => {}
         }
     }
 }
 
+// ============================================================================
+// Arrangement
+// ============================================================================
+
+const ANCHOR_TOP: u32 = 1;
+const ANCHOR_BOTTOM: u32 = 2;
+const ANCHOR_LEFT: u32 = 4;
+const ANCHOR_RIGHT: u32 = 8;
+const HORIZONTAL: u32 = ANCHOR_LEFT | ANCHOR_RIGHT;
+const VERTICAL: u32 = ANCHOR_TOP | ANCHOR_BOTTOM;
+
+/// The parts of a layer surface's state that decide where it goes
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct LayerArrangement {
+    pub layer: u32,
+    pub anchor: u32,
+    pub exclusive_zone: i32,
+    /// Explicit exclusive edge (0 = deduce from the anchor)
+    pub exclusive_edge: u32,
+    /// Margin (top, right, bottom, left)
+    pub margin: (i32, i32, i32, i32),
+    /// Requested size (0 = stretch between anchors)
+    pub size: (u32, u32),
+}
+
+impl LayerArrangement {
+    /// Check committed state: a zero size stretches between anchors on
+    /// both ends of its axis, and an explicit exclusive edge must be one
+    /// of the anchors.
+    pub fn validate(&self) -> Result<(), (zwlr_layer_surface_v1::Error, &'static str)> {
+        if self.size.0 == 0 && self.anchor & HORIZONTAL != HORIZONTAL {
+            return Err((zwlr_layer_surface_v1::Error::InvalidSize, "width 0 needs both left and right anchors"));
+        }
+        if self.size.1 == 0 && self.anchor & VERTICAL != VERTICAL {
+            return Err((zwlr_layer_surface_v1::Error::InvalidSize, "height 0 needs both top and bottom anchors"));
+        }
+        if self.exclusive_edge != 0 && self.anchor & self.exclusive_edge == 0 {
+            return Err((zwlr_layer_surface_v1::Error::InvalidExclusiveEdge, "exclusive edge must be one of the anchors"));
+        }
+        Ok(())
+    }
+
+    /// Edge the exclusive zone applies to. Without an explicit edge a
+    /// surface must be anchored to one edge, or to one edge and both
+    /// edges perpendicular to it.
+    fn exclusive_edge(&self) -> Option<u32> {
+        if self.exclusive_edge != 0 && self.anchor & self.exclusive_edge != 0 {
+            return Some(self.exclusive_edge);
+        }
+        match self.anchor {
+            ANCHOR_TOP => Some(ANCHOR_TOP),
+            a if a == ANCHOR_TOP | HORIZONTAL => Some(ANCHOR_TOP),
+            ANCHOR_BOTTOM => Some(ANCHOR_BOTTOM),
+            a if a == ANCHOR_BOTTOM | HORIZONTAL => Some(ANCHOR_BOTTOM),
+            ANCHOR_LEFT => Some(ANCHOR_LEFT),
+            a if a == ANCHOR_LEFT | VERTICAL => Some(ANCHOR_LEFT),
+            ANCHOR_RIGHT => Some(ANCHOR_RIGHT),
+            a if a == ANCHOR_RIGHT | VERTICAL => Some(ANCHOR_RIGHT),
+            _ => None,
+        }
+    }
+
+    /// Place the surface within `bounds`.
+    fn place(&self, bounds: Rect) -> Rect {
+        let (top, right, bottom, left) = self.margin;
+        let (x, width) = place_axis(
+            bounds.x, bounds.width as i32, self.size.0 as i32,
+            self.anchor & ANCHOR_LEFT != 0, self.anchor & ANCHOR_RIGHT != 0, left, right,
+        );
+        let (y, height) = place_axis(
+            bounds.y, bounds.height as i32, self.size.1 as i32,
+            self.anchor & ANCHOR_TOP != 0, self.anchor & ANCHOR_BOTTOM != 0, top, bottom,
+        );
+        Rect::new(x, y, width.max(0) as u32, height.max(0) as u32)
+    }
+}
+
+/// Place a surface along one axis. A zero size, which only a surface
+/// anchored to both ends may ask for, stretches between the margins; a
+/// surface anchored to both ends with a fixed size is centred and its
+/// margins are ignored.
+fn place_axis(start: i32, extent: i32, size: i32, at_start: bool, at_end: bool, margin_start: i32, margin_end: i32) -> (i32, i32) {
+    if size == 0 {
+        return (start + margin_start, extent - margin_start - margin_end);
+    }
+    match (at_start, at_end) {
+        (true, false) => (start + margin_start, size),
+        (false, true) => (start + extent - size - margin_end, size),
+        _ => (start + (extent - size) / 2, size),
+    }
+}
+
+/// Take an exclusive zone, plus the margin on its edge, off `usable`.
+fn claim_exclusive_zone(usable: &mut Rect, edge: u32, zone: i32) {
+    let shrink = |extent: u32| (extent as i32 - zone).max(0) as u32;
+    match edge {
+        ANCHOR_TOP => {
+            usable.y += zone;
+            usable.height = shrink(usable.height);
+        }
+        ANCHOR_BOTTOM => usable.height = shrink(usable.height),
+        ANCHOR_LEFT => {
+            usable.x += zone;
+            usable.width = shrink(usable.width);
+        }
+        ANCHOR_RIGHT => usable.width = shrink(usable.width),
+        _ => {}
+    }
+}
+
+/// Arrange the layer surfaces of one output.
+///
+/// `surfaces` must be in creation order. Surfaces with an exclusive zone
+/// are placed first, from the overlay layer down, each in the area the
+/// ones before it left and then shrinking it further, so panels on the
+/// same edge stack. The others are then placed in what remains, except
+/// that an exclusive zone of -1 places a surface against the full output.
+/// Returns each surface's rect and the usable area left for windows.
+pub fn arrange_layer_surfaces(output: Rect, usable: Rect, surfaces: &[LayerArrangement]) -> (Vec<Rect>, Rect) {
+    let mut usable = usable;
+    let mut placed = vec![Rect::zero(); surfaces.len()];
+    for exclusive in [true, false] {
+        for layer in (0..4).rev() {
+            for (i, surface) in surfaces.iter().enumerate() {
+                if surface.layer != layer || (surface.exclusive_zone > 0) != exclusive {
+                    continue;
+                }
+                let bounds = if surface.exclusive_zone == -1 { output } else { usable };
+                placed[i] = surface.place(bounds);
+                if let Some(edge) = surface.exclusive_edge().filter(|_| exclusive) {
+                    let (top, right, bottom, left) = surface.margin;
+                    let margin = match edge {
+                        ANCHOR_TOP => top,
+                        ANCHOR_BOTTOM => bottom,
+                        ANCHOR_LEFT => left,
+                        _ => right,
+                    };
+                    claim_exclusive_zone(&mut usable, edge, surface.exclusive_zone + margin);
+                }
+            }
+        }
+    }
+    (placed, usable)
+}
+
 // ============================================================================
 // Helper function to send configure events for size changes
 // ============================================================================
@@ -285,19 +455,83 @@ impl CompositorState {
     /// Send a configure event to a layer surface with new dimensions
     pub fn configure_layer_surface(&mut self, surface_id: u32, width: u32, height: u32) {
         let client_id = self.surfaces.get(&surface_id).and_then(|s| s.read().unwrap().client_id.clone());
-        if let Some(cid) = client_id {
-            if let Some(ls) = self.get_layer_surface(cid, surface_id) {
-            let serial = self.next_serial();
-            let mut ls = ls.write().unwrap();
-            ls.pending_serial = serial;
-            ls.width = width;
-            ls.height = height;
-            // Note: We'd need to store the resource to send the event
-            // This would require additional tracking in CompositorState
-            tracing::debug!(
-                "Layer surface {}: would send configure serial={} {}x{}",
-                surface_id, serial, width, height
-            );
+        let Some(ls) = client_id.and_then(|cid| self.get_layer_surface(cid, surface_id)) else { return };
+        let serial = self.next_serial();
+        let mut ls = ls.write().unwrap();
+        ls.pending_serial = serial;
+        ls.configured_size = Some((width, height));
+        if let Some(resource) = &ls.resource {
+            resource.configure(serial, width, height);
+        }
+        tracing::debug!("Layer surface {}: configure serial={} {}x{}", surface_id, serial, width, height);
+    }
+
+    /// Mapped state and keyboard interactivity of a layer surface
+    fn layer_interactivity(&self, surface_id: u32) -> Option<(bool, u32)> {
+        self.wlr.layer_surfaces.values()
+            .map(|ls| ls.read().unwrap())
+            .find(|ls| ls.surface_id == surface_id)
+            .map(|ls| (ls.mapped, ls.interactivity))
+    }
+
+    /// The mapped top or overlay layer surface with exclusive keyboard
+    /// interactivity, overlay first. It holds keyboard focus while mapped.
+    pub fn exclusive_keyboard_layer(&self) -> Option<u32> {
+        self.wlr.layer_surfaces.values()
+            .map(|ls| ls.read().unwrap())
+            .filter(|ls| {
+                ls.mapped
+                    && ls.layer >= zwlr_layer_shell_v1::Layer::Top as u32
+                    && ls.interactivity == KeyboardInteractivity::Exclusive as u32
+            })
+            .max_by_key(|ls| ls.layer)
+            .map(|ls| ls.surface_id)
+    }
+
+    /// Re-evaluate keyboard focus after a layer surface mapped, unmapped,
+    /// was destroyed or changed its keyboard interactivity.
+    pub fn update_layer_keyboard_focus(&mut self, surface_id: u32) {
+        if self.session_locked() {
+            return;
+        }
+        if let Some(exclusive) = self.exclusive_keyboard_layer() {
+            if self.seat.keyboard.focus != Some(exclusive) {
+                self.set_keyboard_focus_surface(Some(exclusive));
+            }
+            return;
+        }
+        // A layer surface that can no longer take focus gives it back
+        let keeps_focus = self.layer_interactivity(surface_id)
+            .map_or(false, |(mapped, interactivity)| mapped && interactivity != KeyboardInteractivity::None as u32);
+        if self.seat.keyboard.focus == Some(surface_id) && !keeps_focus {
+            self.restore_window_keyboard_focus();
+        }
+    }
+
+    /// Click-to-focus for layer surfaces: a click on an interactive layer
+    /// surface (or one of its subsurfaces) focuses it, a click elsewhere
+    /// gives focus back from an on-demand layer surface.
+    pub fn focus_layer_on_click(&mut self, surface_id: u32) {
+        if self.session_locked() || self.exclusive_keyboard_layer().is_some() {
+            return;
+        }
+        let mut root = surface_id;
+        while let Some(sub) = self.subsurfaces.get(&root) {
+            root = sub.parent_id;
+        }
+        match self.layer_interactivity(root) {
+            Some((true, interactivity)) if interactivity != KeyboardInteractivity::None as u32 => {
+                if self.seat.keyboard.focus != Some(root) {
+                    self.set_keyboard_focus_surface(Some(root));
+                }
+            }
+            Some(_) => {}
+            None => {
+                let layer_focused = self.seat.keyboard.focus
+                    .map_or(false, |sid| self.layer_interactivity(sid).is_some());
+                if layer_focused {
+                    self.restore_window_keyboard_focus();
+                }
             }
         }
     }
@@ -306,5 +540,82 @@ impl CompositorState {
 
 /// Register zwlr_layer_shell_v1 global
 pub fn register_layer_shell(display: &DisplayHandle) -> wayland_server::backend::GlobalId {
-    display.create_global::<CompositorState, zwlr_layer_shell_v1::ZwlrLayerShellV1, ()>(4, ())
+    display.create_global::<CompositorState, zwlr_layer_shell_v1::ZwlrLayerShellV1, ()>(5, ())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OUTPUT: Rect = Rect { x: 0, y: 0, width: 1000, height: 800 };
+
+    fn panel(anchor: u32, height: u32, zone: i32) -> LayerArrangement {
+        LayerArrangement {
+            layer: zwlr_layer_shell_v1::Layer::Top as u32,
+            anchor,
+            exclusive_zone: zone,
+            size: (0, height),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_arrange_layer_surfaces() {
+        let horizontal = ANCHOR_LEFT | ANCHOR_RIGHT;
+        let mut corner = panel(ANCHOR_TOP | ANCHOR_LEFT, 50, 100);
+        corner.size = (100, 50);
+        corner.exclusive_edge = ANCHOR_LEFT;
+        let mut wallpaper = panel(horizontal | ANCHOR_TOP | ANCHOR_BOTTOM, 0, -1);
+        wallpaper.layer = zwlr_layer_shell_v1::Layer::Background as u32;
+        let mut centred = panel(0, 100, 0);
+        centred.size = (200, 100);
+        let mut margined = panel(ANCHOR_BOTTOM | horizontal, 20, 20);
+        margined.margin = (0, 10, 5, 10);
+
+        let surfaces = [
+            panel(ANCHOR_TOP | horizontal, 30, 30),
+            panel(ANCHOR_TOP | horizontal, 40, 40),
+            wallpaper,
+            corner,
+            centred,
+            margined,
+        ];
+        let (placed, usable) = arrange_layer_surfaces(OUTPUT, OUTPUT, &surfaces);
+
+        // Panels on the same edge stack in creation order
+        assert_eq!(placed[0], Rect::new(0, 0, 1000, 30));
+        assert_eq!(placed[1], Rect::new(0, 30, 1000, 40));
+        // -1 ignores the exclusive zones of others
+        assert_eq!(placed[2], OUTPUT);
+        // A corner surface needs an explicit edge to reserve space
+        assert_eq!(placed[3], Rect::new(0, 70, 100, 50));
+        // Margins apply within the remaining area and count towards the zone
+        assert_eq!(placed[5], Rect::new(110, 775, 880, 20));
+        assert_eq!(usable, Rect::new(100, 70, 900, 705));
+        // Surfaces without a zone are placed in what is left
+        assert_eq!(placed[4], Rect::new(100 + 350, 70 + 302, 200, 100));
+        assert!(surfaces.iter().all(|s| s.validate().is_ok()));
+    }
+
+    #[test]
+    fn test_validate_layer_state() {
+        let error = |state: LayerArrangement| state.validate().unwrap_err().0;
+
+        // Stretching needs anchors on both ends of the axis
+        let unanchored = panel(ANCHOR_TOP | ANCHOR_LEFT, 30, 0);
+        assert_eq!(error(unanchored), zwlr_layer_surface_v1::Error::InvalidSize);
+        let mut sized = unanchored;
+        sized.size = (0, 0);
+        sized.anchor = ANCHOR_TOP | HORIZONTAL;
+        assert_eq!(error(sized), zwlr_layer_surface_v1::Error::InvalidSize);
+        sized.anchor |= ANCHOR_BOTTOM;
+        assert!(sized.validate().is_ok());
+
+        // The exclusive edge is one the surface is anchored to
+        let mut bar = panel(ANCHOR_TOP | HORIZONTAL, 30, 30);
+        bar.exclusive_edge = ANCHOR_BOTTOM;
+        assert_eq!(error(bar), zwlr_layer_surface_v1::Error::InvalidExclusiveEdge);
+        bar.exclusive_edge = ANCHOR_TOP;
+        assert!(bar.validate().is_ok());
+    }
 }
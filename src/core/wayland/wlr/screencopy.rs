@@ -107,6 +107,11 @@ impl Dispatch<zwlr_screencopy_frame_v1::ZwlrScreencopyFrameV1, ()> for Composito
         match request {
             zwlr_screencopy_frame_v1::Request::Copy { buffer }
             | zwlr_screencopy_frame_v1::Request::CopyWithDamage { buffer } => {
+                // Screen capture is refused while the session is locked
+                if state.session_locked() {
+                    resource.failed();
+                    return;
+                }
                 let buffer_id = buffer.id().protocol_id();
                 let client_id = _client.id();
                 let buffer_guard = match state.buffers.get(&(client_id.clone(), buffer_id)) {
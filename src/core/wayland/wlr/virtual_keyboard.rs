@@ -83,7 +83,11 @@ impl Dispatch<zwp_virtual_keyboard_v1::ZwpVirtualKeyboardV1, ()> for CompositorS
                     1 => wayland_server::protocol::wl_keyboard::KeyState::Pressed,
                     _ => wayland_server::protocol::wl_keyboard::KeyState::Released,
                 };
-                state.inject_key(key, key_state_val, time);
+                if state.is_input_method_client(_client) {
+                    state.inject_key_ungrabbed(key, key_state_val, time);
+                } else {
+                    state.inject_key(key, key_state_val, time);
+                }
             }
             zwp_virtual_keyboard_v1::Request::Modifiers { mods_depressed, mods_latched, mods_locked, group } => {
                 tracing::debug!(
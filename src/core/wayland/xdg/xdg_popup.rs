@@ -5,11 +5,259 @@
 
 
 use wayland_server::{
-    Dispatch, DisplayHandle, Resource,
+    backend::ClientId, Dispatch, DisplayHandle, Resource,
 };
-use crate::core::wayland::protocol::server::xdg::shell::server::xdg_popup;
+use crate::core::wayland::protocol::server::xdg::shell::server::{xdg_popup, xdg_wm_base};
 
-use crate::core::state::CompositorState;
+use crate::core::input::grab::{GrabKind, GrabStatus, InputGrab};
+use crate::core::state::{CompositorState, XdgPopupData, XdgPositionerData};
+use crate::core::window::grab::WindowGrab;
+use crate::util::geometry::Rect;
+
+/// Pointer and touch grab of an xdg_popup.grab chain: a press outside the
+/// chain dismisses it top-down and goes on to whatever is under it
+#[derive(Debug)]
+pub struct PopupGrab;
+
+impl InputGrab for PopupGrab {
+    fn kind(&self) -> GrabKind {
+        GrabKind::Popup
+    }
+
+    fn pointer_button(&mut self, state: &mut CompositorState, _button: u32, pressed: bool, _time: u32) -> GrabStatus {
+        if !pressed {
+            return GrabStatus::Pass;
+        }
+        let focus = state.seat.pointer.focus;
+        self.dismiss_unless_on_chain(state, focus)
+    }
+
+    fn touch_down(&mut self, state: &mut CompositorState, _id: i32, x: f64, y: f64, _time: u32) -> GrabStatus {
+        let target = state.find_surface_at(x, y).map(|(surface_id, _, _)| surface_id);
+        self.dismiss_unless_on_chain(state, target)
+    }
+
+    fn cancel(&mut self, state: &mut CompositorState) {
+        state.dismiss_popup_grab();
+    }
+
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+}
+
+impl PopupGrab {
+    fn dismiss_unless_on_chain(&self, state: &mut CompositorState, target: Option<u32>) -> GrabStatus {
+        if state.seat.popup_grab_stack.is_empty() {
+            return GrabStatus::End { consumed: false };
+        }
+        if target.map_or(false, |sid| state.on_popup_grab_chain(sid)) {
+            return GrabStatus::Pass;
+        }
+        state.dismiss_popup_grab();
+        GrabStatus::End { consumed: false }
+    }
+}
+
+impl CompositorState {
+    /// Give the seat's popup grab to a popup. The grab needs the serial of
+    /// a press that is still held, and a nested popup must be the child of
+    /// the topmost grabbing popup; a popup of a toplevel starts a new chain,
+    /// dismissing the old one. The popup gets keyboard focus. Returns false
+    /// when the grab is denied and the popup is to be dismissed.
+    pub fn start_popup_grab(&mut self, popup: (ClientId, u32), parent_popup: Option<(ClientId, u32)>, serial: u32) -> bool {
+        if !self.xdg.popups.contains_key(&popup) || self.seat.grab_serial(serial).is_none() {
+            return false;
+        }
+        // A popup can't take the pointer from a drag or a move; the open
+        // chain stays as it is
+        if !self.seat.grabs.admits(GrabKind::Popup) {
+            return false;
+        }
+        match &parent_popup {
+            Some(parent) if self.seat.popup_grab_stack.last() != Some(parent) => return false,
+            Some(_) => {}
+            None => self.dismiss_popup_grab(),
+        }
+        if self.start_grab(Box::new(PopupGrab)).is_err() {
+            return false;
+        }
+        if let Some(data) = self.xdg.popups.get_mut(&popup) {
+            data.grabbed = true;
+        }
+        if !self.seat.popup_grab_stack.contains(&popup) {
+            self.seat.popup_grab_stack.push(popup);
+        }
+        self.focus_popup_grab();
+        true
+    }
+
+    /// Move keyboard focus to the topmost grabbing popup, or back to the
+    /// window once the chain is gone
+    fn focus_popup_grab(&mut self) {
+        let top = self.seat.popup_grab_stack.last()
+            .and_then(|key| self.xdg.popups.get(key))
+            .map(|p| p.surface_id);
+        match top {
+            Some(surface_id) => {
+                if self.seat.keyboard.focus != Some(surface_id) && self.accepts_keyboard_focus(surface_id) {
+                    self.set_keyboard_focus_surface(Some(surface_id));
+                }
+            }
+            None => self.restore_window_keyboard_focus(),
+        }
+    }
+
+    /// The popup with window id `window_id`
+    fn popup_for_window(&self, window_id: u32) -> Option<(ClientId, u32)> {
+        self.xdg.popups.iter()
+            .find(|(_, p)| p.window_id == window_id)
+            .map(|(key, _)| key.clone())
+    }
+
+    /// Global position of the surface a popup is placed relative to, and
+    /// the output of the toplevel or layer surface its chain hangs off.
+    /// A positioner with `set_parent_size` is placed for the size the
+    /// parent window is being resized to.
+    fn popup_parent_origin(&self, client_id: &ClientId, popup: &XdgPopupData) -> ((i32, i32), Option<u32>) {
+        let mut offset = (0, 0);
+        let mut current = popup;
+        for _ in 0..=self.xdg.popups.len() {
+            if let Some(layer_id) = current.parent_layer {
+                let Some(ls) = self.get_layer_surface(client_id.clone(), layer_id) else { break };
+                let ls = ls.read().unwrap();
+                return ((ls.x + offset.0, ls.y + offset.1), Some(ls.output_id));
+            }
+            let Some(parent_id) = current.parent_id else { break };
+            if let Some(window) = self.get_window(parent_id) {
+                let window = window.read().unwrap();
+                let origin = if std::ptr::eq(current, popup) {
+                    self.resized_parent_origin(parent_id, &popup.positioner)
+                } else {
+                    None
+                };
+                let (x, y) = origin.unwrap_or((window.x, window.y));
+                return ((x + offset.0, y + offset.1), window.outputs.first().copied());
+            }
+            let Some(parent) = self.xdg.popups.values().find(|p| p.window_id == parent_id) else { break };
+            offset = (offset.0 + parent.geometry.0, offset.1 + parent.geometry.1);
+            current = parent;
+        }
+        (offset, None)
+    }
+
+    /// Where the window being interactively resized will be once it has
+    /// the parent size of `positioner`, if the positioner answers the
+    /// window's latest configure
+    fn resized_parent_origin(&self, window_id: u32, positioner: &XdgPositionerData) -> Option<(i32, i32)> {
+        let (width, height) = positioner.parent_size?;
+        let resize = self.seat.grabs.get::<WindowGrab>()
+            .filter(|g| g.window_id == window_id)
+            .and_then(|g| g.resize().copied())?;
+        if let Some(serial) = positioner.parent_configure {
+            let pending = self.xdg.toplevels.values()
+                .find(|tl| tl.window_id == window_id)
+                .map(|tl| tl.pending_serial);
+            if pending != Some(serial) {
+                return None;
+            }
+        }
+        Some(resize.origin_for(width, height))
+    }
+
+    /// Geometry of a popup from its positioner, relative to its parent and
+    /// constrained to the usable area of the parent's output
+    pub fn place_popup(&self, key: &(ClientId, u32)) -> Option<(i32, i32, i32, i32)> {
+        let popup = self.xdg.popups.get(key)?;
+        let (origin, output_id) = self.popup_parent_origin(&key.0, popup);
+        let output_id = output_id
+            .or_else(|| {
+                self.outputs.iter()
+                    .find(|o| Rect::new(o.x, o.y, o.width, o.height).contains_point(origin.0, origin.1))
+                    .map(|o| o.id)
+            })
+            .unwrap_or(self.primary_output().id);
+        let bounds = self.get_usable_region(output_id)
+            .map(|(x, y, width, height)| Rect::new(x - origin.0, y - origin.1, width, height))
+            .unwrap_or_else(Rect::zero);
+        Some(popup.positioner.place(bounds))
+    }
+
+    /// Place a popup from its positioner and send the configure. `token`
+    /// is the reposition token, if this answers `xdg_popup.reposition`.
+    /// The platform gets popups of layer surfaces in global coordinates
+    /// and the others relative to their parent.
+    pub fn configure_popup(&mut self, client_id: &ClientId, popup_id: u32, token: Option<u32>) {
+        let key = (client_id.clone(), popup_id);
+        let Some((x, y, width, height)) = self.place_popup(&key) else { return };
+        let Some(data) = self.xdg.popups.get_mut(&key) else { return };
+        data.geometry = (x, y, width, height);
+        data.anchor_rect = data.positioner.anchor_rect;
+        if token.is_some() {
+            data.repositioned_token = token;
+        }
+        let (window_id, xdg_surface_id, resource) = (data.window_id, data.xdg_surface_id, data.resource.clone());
+
+        if let Some(popup) = resource {
+            if let Some(token) = token {
+                popup.repositioned(token);
+            }
+            popup.configure(x, y, width, height);
+        }
+        let serial = self.next_serial();
+        if let Some(surface_resource) = self.xdg.surfaces.get(&(client_id.clone(), xdg_surface_id)).and_then(|s| s.resource.clone()) {
+            surface_resource.configure(serial);
+        }
+
+        let (ex, ey) = match self.xdg.popups.get(&key) {
+            Some(popup) if popup.parent_layer.is_some() => {
+                let (origin, _) = self.popup_parent_origin(client_id, popup);
+                (origin.0 + x, origin.1 + y)
+            }
+            _ => (x, y),
+        };
+        self.pending_compositor_events.push(crate::core::compositor::CompositorEvent::PopupRepositioned {
+            window_id,
+            x: ex,
+            y: ey,
+            width: width.max(1) as u32,
+            height: height.max(1) as u32,
+        });
+        tracing::debug!("Popup {} placed at {},{} {}x{}", popup_id, x, y, width, height);
+    }
+
+    /// Re-place the reactive popups hanging off a window that moved or
+    /// resized, parents before their children
+    pub fn reposition_reactive_popups(&mut self, window_id: u32) {
+        let mut parents = vec![window_id];
+        for _ in 0..=self.xdg.popups.len() {
+            if parents.is_empty() {
+                break;
+            }
+            let children: Vec<_> = self.xdg.popups.iter()
+                .filter(|(_, p)| p.parent_id.map_or(false, |pid| parents.contains(&pid)))
+                .map(|(key, p)| (key.clone(), p.window_id, p.positioner.reactive, p.geometry))
+                .collect();
+            for (key, _, reactive, geometry) in &children {
+                if *reactive && self.place_popup(key) != Some(*geometry) {
+                    self.configure_popup(&key.0, key.1, None);
+                }
+            }
+            parents = children.into_iter().map(|(_, wid, ..)| wid).collect();
+        }
+    }
+
+    /// Post an xdg_wm_base error to the client, on the xdg_wm_base it
+    /// bound
+    fn post_wm_base_error(&self, client_id: &ClientId, error: xdg_wm_base::Error, message: &str) {
+        let shell = self.xdg.shell_resources.iter()
+            .find(|((cid, _), _)| cid == client_id)
+            .map(|(_, shell)| shell);
+        if let Some(shell) = shell {
+            shell.post_error(error, message);
+        }
+    }
+}
 
 impl Dispatch<xdg_popup::XdgPopup, u32> for CompositorState {
     fn request(
@@ -27,12 +275,32 @@ impl Dispatch<xdg_popup::XdgPopup, u32> for CompositorState {
         match request {
             xdg_popup::Request::Destroy => {
                 tracing::debug!("xdg_popup destroyed: {}", popup_id);
-                if let Some(data) = state.xdg.popups.remove(&(client_id.clone(), popup_id)) {
+                let key = (client_id.clone(), popup_id);
+                let grabbing = state.seat.popup_grab_stack.contains(&key);
+                // Nested popups go away topmost first, whether they grabbed
+                // or not
+                let window_id = state.xdg.popups.get(&key).map(|p| p.window_id);
+                let has_children = window_id.is_some_and(|wid| {
+                    state.xdg.popups.values().any(|p| p.parent_id == Some(wid))
+                });
+                if has_children {
+                    state.post_wm_base_error(&client_id, xdg_wm_base::Error::NotTheTopmostPopup, "destroyed a popup that is not the topmost one");
+                    return;
+                }
+                if let Some(data) = state.xdg.popups.remove(&key) {
                     // Clean up surface_to_window mapping
                     state.surface_to_window.remove(&data.surface_id);
                     
-                    // Remove from grab stack if present
-                    state.seat.popup_grab_stack.retain(|(ref cid, ref pid)| cid != &client_id || *pid != popup_id);
+                    // The grab returns to the parent popup, if any
+                    if grabbing {
+                        state.seat.popup_grab_stack.pop();
+                        if state.seat.popup_grab_stack.is_empty() {
+                            state.end_grab(GrabKind::Popup);
+                        }
+                        if state.seat.keyboard.focus == Some(data.surface_id) {
+                            state.focus_popup_grab();
+                        }
+                    }
                     
                     // CRITICAL: Emit event for FFI layer cleanup
                     state.pending_compositor_events.push(crate::core::compositor::CompositorEvent::WindowDestroyed {
@@ -40,15 +308,30 @@ impl Dispatch<xdg_popup::XdgPopup, u32> for CompositorState {
                     });
                 }
             }
-            xdg_popup::Request::Grab { seat: _, serial: _ } => {
+            xdg_popup::Request::Grab { seat: _, serial } => {
                 tracing::debug!("xdg_popup.grab requested for popup {}", popup_id);
-                if let Some(data) = state.xdg.popups.get_mut(&(client_id.clone(), popup_id)) {
-                    data.grabbed = true;
-                    // Push to grab stack
-                    if !state.seat.popup_grab_stack.contains(&(client_id.clone(), popup_id)) {
-                        state.seat.popup_grab_stack.push((client_id.clone(), popup_id));
+                let key = (client_id.clone(), popup_id);
+                let Some((surface_id, parent_id)) = state.xdg.popups.get(&key).map(|p| (p.surface_id, p.parent_id)) else {
+                    return;
+                };
+                let mapped = state.get_surface(surface_id)
+                    .map_or(false, |s| s.read().unwrap().current.buffer_id.is_some());
+                if mapped {
+                    resource.post_error(xdg_popup::Error::InvalidGrab, "xdg_popup.grab after the popup was mapped");
+                    return;
+                }
+                let parent_popup = parent_id.and_then(|wid| state.popup_for_window(wid));
+                if let Some(parent) = &parent_popup {
+                    if !state.xdg.popups.get(parent).map_or(false, |p| p.grabbed) {
+                        state.post_wm_base_error(&client_id, xdg_wm_base::Error::InvalidPopupParent, "the parent of a grabbing popup must have a grab");
+                        return;
                     }
+                }
+                if state.start_popup_grab(key, parent_popup, serial) {
                     tracing::debug!("Popup {} added to grab stack", popup_id);
+                } else {
+                    tracing::debug!("Popup grab denied, dismissing popup {}", popup_id);
+                    resource.popup_done();
                 }
             }
             xdg_popup::Request::Reposition { positioner, token } => {
@@ -59,52 +342,11 @@ impl Dispatch<xdg_popup::XdgPopup, u32> for CompositorState {
                     .get(&(client_id.clone(), positioner.id().protocol_id()))
                     .cloned()
                     .unwrap_or_default();
-                    
-                // Get output dimensions
-                let (ox, oy, initial_width, initial_height, _) = {
-                    let output = state.primary_output();
-                    (output.x, output.y, output.width, output.height, output.scale)
-                };
-                let output_rect = crate::util::geometry::Rect::new(ox, oy, initial_width, initial_height);
-
-                let surface_id = if let Some(data) = state.xdg.popups.get_mut(&(client_id.clone(), popup_id)) {
-                    // Calculate new position using anchor/gravity rules
-                    let (px, py) = positioner_data.calculate_position(output_rect);
-                    
-                    // Update geometry
-                    data.geometry = (px, py, positioner_data.width, positioner_data.height);
-                    data.anchor_rect = positioner_data.anchor_rect;
-                    data.repositioned_token = Some(token);
-                    
-                    // CRITICAL: Emit event for FFI layer to reposition the platform window
-                    state.pending_compositor_events.push(crate::core::compositor::CompositorEvent::PopupRepositioned {
-                        window_id: data.window_id,
-                        x: px,
-                        y: py,
-                        width: data.geometry.2 as u32,
-                        height: data.geometry.3 as u32,
-                    });
- 
-                    // Send repositioned event
-                    resource.repositioned(token);
-                    
-                    // Send configure
-                    resource.configure(px, py, data.geometry.2, data.geometry.3);
-                    
-                    Some(data.surface_id)
-                } else {
-                    None
-                };
 
-                // Trigger surface configure to apply changes
-                if let Some(sid) = surface_id {
-                     let serial = state.next_serial();
-                     if let Some(surface_data) = state.xdg.surfaces.get(&(client_id, sid)) {
-                        if let Some(surface_resource) = &surface_data.resource {
-                             surface_resource.configure(serial);
-                        }
-                    }
+                if let Some(data) = state.xdg.popups.get_mut(&(client_id.clone(), popup_id)) {
+                    data.positioner = positioner_data;
                 }
+                state.configure_popup(&client_id, popup_id, Some(token));
             }
             _ => {}
         }
@@ -81,6 +81,24 @@ impl Dispatch<xdg_positioner::XdgPositioner, ()> for CompositorState {
                     data.offset = (x, y);
                 }));
             }
+            xdg_positioner::Request::SetReactive => {
+                tracing::trace!("xdg_positioner.set_reactive");
+                update_positioner(state, client_id.clone(), positioner_id, Box::new(|data| {
+                    data.reactive = true;
+                }));
+            }
+            xdg_positioner::Request::SetParentSize { parent_width, parent_height } => {
+                tracing::trace!("xdg_positioner.set_parent_size: {}x{}", parent_width, parent_height);
+                update_positioner(state, client_id.clone(), positioner_id, Box::new(move |data| {
+                    data.parent_size = Some((parent_width, parent_height));
+                }));
+            }
+            xdg_positioner::Request::SetParentConfigure { serial } => {
+                tracing::trace!("xdg_positioner.set_parent_configure: {}", serial);
+                update_positioner(state, client_id.clone(), positioner_id, Box::new(move |data| {
+                    data.parent_configure = Some(serial);
+                }));
+            }
             xdg_positioner::Request::Destroy => {
                 tracing::trace!("xdg_positioner destroyed");
                 state.xdg.positioners.remove(&(client_id, positioner_id));
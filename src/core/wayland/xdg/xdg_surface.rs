@@ -41,6 +41,7 @@ impl Dispatch<xdg_surface::XdgSurface, u32> for CompositorState {
 
                     window.width = initial_width as i32;
                     window.height = initial_height as i32;
+                    window.decoration_mode = state.decoration_mode_for_new_window();
                     
                     // Create toplevel data (wl_surface_id, xdg_surface_id)
                     let mut toplevel_data = XdgToplevelData::new(window_id, data.surface_id, surface_id);
@@ -61,25 +62,20 @@ impl Dispatch<xdg_surface::XdgSurface, u32> for CompositorState {
                     state.add_window(window);
                     
                     // Send initial configure.
-                    // When Force SSD is active the platform window has a native
-                    // titlebar that reduces the usable content area below the
-                    // full output size.  Sending (0, 0) here defers the size
-                    // decision to the client and avoids the nested compositor
-                    // latching onto the raw output dimensions before the correct
-                    // content-area size arrives via the subsequent injectWindowResize
-                    // that handleWindowCreated: fires after NSWindow is created.
-                    //
-                    // For non-SSD (CSD) windows we continue to send the output
-                    // size as a hint so the client can size itself reasonably.
+                    // With a native titlebar (Force SSD on a platform that
+                    // decorates) sending (0, 0) defers the size decision to
+                    // the client and avoids the nested compositor latching
+                    // onto the raw output dimensions before the correct
+                    // content-area size arrives via the subsequent
+                    // injectWindowResize that handleWindowCreated: fires
+                    // after NSWindow is created. Otherwise the output size,
+                    // less any frame the core draws, is the hint.
                     let serial = state.next_serial();
                     
                     let mut states: Vec<u8> = vec![];
                     states.extend_from_slice(&((wayland_protocols::xdg::shell::server::xdg_toplevel::State::Activated as u32).to_ne_bytes()));
                     
-                    let (configure_w, configure_h) = match state.decoration_policy {
-                        crate::core::state::DecorationPolicy::ForceServer => (0u32, 0u32),
-                        _ => (initial_width, initial_height),
-                    };
+                    let (configure_w, configure_h) = state.initial_toplevel_size(window_id, initial_width, initial_height);
 
                     crate::wlog!(crate::util::logging::COMPOSITOR, 
                         "Configuring xdg_toplevel: window={} surface={} size={}x{} (force_ssd={}) states={:?} serial={}", 
@@ -135,26 +131,23 @@ impl Dispatch<xdg_surface::XdgSurface, u32> for CompositorState {
                         .remove(&(client_id.clone(), positioner.id().protocol_id()))
                         .unwrap_or_default();
 
-                    // Get output dimensions for initial size
-                    let (ox, oy, initial_width, initial_height, _scale) = {
-                        let output = state.primary_output();
-                        (output.x, output.y, output.width, output.height, output.scale)
-                    };
-                    
-                    let output_rect = crate::util::geometry::Rect::new(ox, oy, initial_width, initial_height);
-                    
-                    // Calculate position using anchor/gravity rules
-                    let (px, py) = positioner_data.calculate_position(output_rect);
-                    
+                    // The parent toplevel or popup, by window id
+                    let parent_window_id = parent.as_ref().and_then(|parent| {
+                        state.xdg.surfaces.get(&(client_id.clone(), parent.id().protocol_id()))
+                            .and_then(|d| d.window_id)
+                    });
+
                     // Create popup state
                     let window_id = state.next_window_id();
                     let popup_data = XdgPopupData {
                         surface_id: data.surface_id,
-                        xdg_surface_id: surface_id,
+                        xdg_surface_id: resource.id().protocol_id(),
                         window_id,
-                        parent_id: parent.as_ref().map(|p| p.id().protocol_id()),
-                        geometry: (px, py, positioner_data.width, positioner_data.height),
+                        parent_id: parent_window_id,
+                        geometry: (0, 0, positioner_data.width, positioner_data.height),
                         anchor_rect: positioner_data.anchor_rect,
+                        positioner: positioner_data,
+                        parent_layer: None,
                         grabbed: false,
                         repositioned_token: None,
                         resource: None,
@@ -166,31 +159,25 @@ impl Dispatch<xdg_surface::XdgSurface, u32> for CompositorState {
                     let mut popup_data = popup_data;
                     popup_data.resource = Some(popup.clone());
                     
-                    state.xdg.popups.insert((client_id.clone(), popup.id().protocol_id()), popup_data);
+                    let key = (client_id.clone(), popup.id().protocol_id());
+                    state.xdg.popups.insert(key.clone(), popup_data);
+
+                    // Place it against its parent's output
+                    let (px, py, width, height) = state.place_popup(&key)
+                        .unwrap_or((0, 0, positioner_data.width, positioner_data.height));
+                    if let Some(data) = state.xdg.popups.get_mut(&key) {
+                        data.geometry = (px, py, width, height);
+                    }
                     
                     // CRITICAL: Register in surface_to_window for buffer routing
                     state.surface_to_window.insert(surface_id, window_id);
                     
                     // Update surface data with window_id
-                    if let Some(surface_data) = state.xdg.surfaces.get_mut(&(client_id.clone(), surface_id)) {
+                    if let Some(surface_data) = state.xdg.surfaces.get_mut(&(client_id.clone(), resource.id().protocol_id())) {
                         surface_data.window_id = Some(window_id);
                     }
                     
                     tracing::debug!("Created xdg_popup for surface {}, window_id={}", surface_id, window_id);
-                    
-                    // CRITICAL: Push PopupCreated event for FFI layer
-                    // We need to resolve parent popup/toplevel ID to a Window ID if possible
-                    // For now, pass 0 as parent if lookup fails, but we should try
-                    let parent_window_id = if let Some(parent_obj) = parent.as_ref() {
-                        // This logic is simplified; parent could be xdg_surface or xdg_toplevel
-                        // Typically it's the xdg_surface ID. We need its window ID.
-                        // Assuming parent is XdgSurface resource:
-                        state.xdg.surfaces.get(&(client_id.clone(), parent_obj.id().protocol_id()))
-                             .and_then(|d| d.window_id)
-                             .unwrap_or(0)
-                    } else {
-                        0
-                    };
 
                     // Send enter events for all bound outputs
                     let surface_res = if let Some(s) = state.get_surface(surface_id) {
@@ -213,20 +200,20 @@ impl Dispatch<xdg_surface::XdgSurface, u32> for CompositorState {
                             client_id: client_id.clone(),
                             window_id,
                             surface_id,
-                            parent_id: parent_window_id,
+                            parent_id: parent_window_id.unwrap_or(0),
                             x: px,
                             y: py,
-                            width: positioner_data.width.max(1) as u32,
-                            height: positioner_data.height.max(1) as u32,
+                            width: width.max(1) as u32,
+                            height: height.max(1) as u32,
                         }
                     );
 
                     // Send initial configure
                         let next_serial = state.next_serial();
                         crate::wlog!(crate::util::logging::COMPOSITOR, "Configuring xdg_popup: window={} surface={} x={} y={} w={} h={} serial={}", 
-                            window_id, surface_id, px, py, positioner_data.width, positioner_data.height, next_serial);
+                            window_id, surface_id, px, py, width, height, next_serial);
 
-                        popup.configure(px, py, positioner_data.width, positioner_data.height);
+                        popup.configure(px, py, width, height);
                         
                         // Send surface configure
                         resource.configure(next_serial);
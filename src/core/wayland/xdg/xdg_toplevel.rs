@@ -65,114 +65,11 @@ impl Dispatch<xdg_toplevel::XdgToplevel, u32> for CompositorState {
             }
             xdg_toplevel::Request::SetMaximized => {
                 tracing::debug!("xdg_toplevel.set_maximized for toplevel {}", toplevel_id);
-                
-                // 1. Determine target output and calculate geometry
-                let (output_id, width, height) = {
-                    let output_id = if let Some(tl_data) = state.xdg.toplevels.get(&(client_id.clone(), toplevel_id)) {
-                         if let Some(window) = state.get_window(tl_data.window_id) {
-                             let window = window.read().unwrap();
-                             window.outputs.first().copied().unwrap_or(
-                                 state.outputs.get(state.primary_output).map(|o| o.id).unwrap_or(0)
-                             )
-                         } else {
-                             state.outputs.get(state.primary_output).map(|o| o.id).unwrap_or(0)
-                         }
-                    } else {
-                        state.outputs.get(state.primary_output).map(|o| o.id).unwrap_or(0)
-                    };
-
-                    let (w, h) = if let Some((_, _, w, h)) = state.get_usable_region(output_id) {
-                        (w as i32, h as i32)
-                    } else {
-                        (0, 0)
-                    };
-                    (output_id, w, h)
-                };
-
-                // 2. Save current geometry and update Window state
-                let window_id = state.xdg.toplevels.get(&(client_id.clone(), toplevel_id)).map(|t| t.window_id);
-                if let Some(wid) = window_id {
-                    if let Some(window) = state.get_window(wid) {
-                        if let Ok(mut w) = window.write() {
-                            // Save geometry before maximizing (only if not already saved)
-                            if let Some(tl_data) = state.xdg.toplevels.get_mut(&(client_id.clone(), toplevel_id)) {
-                                if tl_data.saved_geometry.is_none() {
-                                    tl_data.saved_geometry = Some((w.x, w.y, w.width as u32, w.height as u32));
-                                }
-                            }
-                            w.maximized = true;
-                        }
-                    }
-                }
-
-                // 3. Update Toplevel state and clamp to size constraints
-                let (clamped_w, clamped_h) = if let Some(tl_data) = state.xdg.toplevels.get_mut(&(client_id.clone(), toplevel_id)) {
-                    tl_data.pending_maximized = true;
-                    tl_data.clamp_size(width as u32, height as u32)
-                } else {
-                    (width as u32, height as u32)
-                };
-
-                // 4. Send configure
-                tracing::debug!("Maximized to {}x{} on output {}", clamped_w, clamped_h, output_id);
-                state.send_toplevel_configure(client_id.clone(), toplevel_id, clamped_w, clamped_h);
-                
-                // 5. Push event for platform
-                if let Some(wid) = window_id {
-                    state.pending_compositor_events.push(
-                        crate::core::compositor::CompositorEvent::WindowMaximized {
-                            window_id: wid,
-                            maximized: true,
-                        }
-                    );
-                }
+                state.maximize_toplevel(client_id.clone(), toplevel_id);
             }
             xdg_toplevel::Request::UnsetMaximized => {
                 tracing::debug!("xdg_toplevel.unset_maximized for toplevel {}", toplevel_id);
-                
-                // Restore saved geometry
-                let saved = state.xdg.toplevels.get_mut(&(client_id.clone(), toplevel_id)).and_then(|tl| {
-                    tl.pending_maximized = false;
-                    tl.saved_geometry.take()
-                });
-
-                let (restore_w, restore_h) = if let Some((x, y, w, h)) = saved {
-                    // Restore window position
-                    let window_id = state.xdg.toplevels.get(&(client_id.clone(), toplevel_id)).map(|t| t.window_id);
-                    if let Some(wid) = window_id {
-                        if let Some(window) = state.get_window(wid) {
-                            if let Ok(mut win) = window.write() {
-                                win.maximized = false;
-                                win.x = x;
-                                win.y = y;
-                            }
-                        }
-                    }
-                    (w, h)
-                } else {
-                    let window_id = state.xdg.toplevels.get(&(client_id.clone(), toplevel_id)).map(|t| t.window_id);
-                    if let Some(wid) = window_id {
-                        if let Some(window) = state.get_window(wid) {
-                            if let Ok(mut win) = window.write() {
-                                win.maximized = false;
-                            }
-                        }
-                    }
-                    (0, 0)
-                };
-                
-                state.send_toplevel_configure(client_id.clone(), toplevel_id, restore_w, restore_h);
-                
-                // Push event for platform
-                let window_id = state.xdg.toplevels.get(&(client_id.clone(), toplevel_id)).map(|t| t.window_id);
-                if let Some(wid) = window_id {
-                    state.pending_compositor_events.push(
-                        crate::core::compositor::CompositorEvent::WindowMaximized {
-                            window_id: wid,
-                            maximized: false,
-                        }
-                    );
-                }
+                state.unmaximize_toplevel(client_id.clone(), toplevel_id);
             }
             xdg_toplevel::Request::SetFullscreen { output } => {
                 tracing::debug!("xdg_toplevel.set_fullscreen for toplevel {}", toplevel_id);
@@ -283,13 +180,7 @@ impl Dispatch<xdg_toplevel::XdgToplevel, u32> for CompositorState {
                 let seat_id = seat.id().protocol_id();
                 tracing::debug!("xdg_toplevel.move requested: seat={}, serial={}", seat_id, serial);
                 if let Some(data) = &data {
-                    state.pending_compositor_events.push(
-                        crate::core::compositor::CompositorEvent::WindowMoveRequested {
-                            window_id: data.window_id,
-                            seat_id,
-                            serial,
-                        }
-                    );
+                    state.request_window_move(data.window_id, seat_id, serial);
                 }
             }
             xdg_toplevel::Request::Resize { seat, serial, edges } => {
@@ -299,15 +190,15 @@ impl Dispatch<xdg_toplevel::XdgToplevel, u32> for CompositorState {
                     wayland_server::WEnum::Unknown(v) => v,
                 };
                 tracing::debug!("xdg_toplevel.resize requested: seat={}, serial={}, edges={}", seat_id, serial, edge_val);
-                if let Some(data) = &data {
-                    state.pending_compositor_events.push(
-                        crate::core::compositor::CompositorEvent::WindowResizeRequested {
-                            window_id: data.window_id,
-                            seat_id,
-                            serial,
-                            edges: edge_val,
-                        }
+                let Some(edge) = crate::core::window::resize::ResizeEdge::from_xdg_edges(edge_val) else {
+                    resource.post_error(
+                        xdg_toplevel::Error::InvalidResizeEdge,
+                        format!("invalid resize edge {}", edge_val),
                     );
+                    return;
+                };
+                if let Some(data) = &data {
+                    state.request_window_resize(data.window_id, seat_id, serial, edge);
                 }
             }
             xdg_toplevel::Request::ShowWindowMenu { seat: _, serial: _, x, y } => {
@@ -0,0 +1,407 @@
+//! Server-side window decorations drawn by the core.
+//!
+//! Every frontend gets the same chrome: a title bar with the window title
+//! and minimize, maximize and close buttons, and a border whose edges and
+//! corners resize the window. The frame is split into pieces that become
+//! scene nodes around the window's content. The title bar is rasterised
+//! here with the built-in 8x8 font; the border pieces are single-pixel
+//! buffers stretched over their rect.
+//!
+//! Pieces are named by surface ids from [`DECORATION_SURFACE_BASE`] up, so
+//! they flatten, hit-test and render like any other surface.
+
+use std::collections::HashMap;
+
+use crate::core::window::resize::ResizeEdge;
+use crate::util::font8x8::FONT;
+use crate::util::geometry::Rect;
+
+/// Height of the title bar
+pub const TITLEBAR_HEIGHT: i32 = 24;
+/// Width of the border, which is also the resize handle
+pub const BORDER_WIDTH: i32 = 4;
+/// How far the corner resize handles reach along the edges
+pub const CORNER_SIZE: i32 = 16;
+/// Width of each title bar button
+pub const BUTTON_WIDTH: i32 = 24;
+/// Glyphs are the 8x8 font doubled
+const GLYPH_SCALE: i32 = 2;
+const GLYPH_SIZE: i32 = 8 * GLYPH_SCALE;
+/// Space left of the title
+const TITLE_PADDING: i32 = 8;
+/// Size of the button icons
+const ICON_SIZE: i32 = 10;
+
+const TITLEBAR_ACTIVE: u32 = 0xFF2B_2B2B;
+const TITLEBAR_INACTIVE: u32 = 0xFF3C_3C3C;
+const TEXT_ACTIVE: u32 = 0xFFFF_FFFF;
+const TEXT_INACTIVE: u32 = 0xFF9A_9A9A;
+const BUTTON_HOVER: u32 = 0xFF50_5050;
+const CLOSE_HOVER: u32 = 0xFFC4_2B1C;
+
+/// Surface ids from here up name decoration pieces instead of client
+/// surfaces, four per window
+pub const DECORATION_SURFACE_BASE: u32 = 0xF000_0000;
+
+/// Buffer ids of decoration pieces have this bit set
+const DECORATION_BUFFER_BIT: u64 = 1 << 63;
+
+/// A piece of the frame around a window
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DecorationPiece {
+    /// The title bar, including the top border
+    Titlebar = 0,
+    Left = 1,
+    Right = 2,
+    /// The bottom border, including the bottom corners
+    Bottom = 3,
+}
+
+impl DecorationPiece {
+    pub const ALL: [DecorationPiece; 4] = [Self::Titlebar, Self::Left, Self::Right, Self::Bottom];
+
+    /// Surface id naming this piece of a window's frame, or `None` for a
+    /// window id too large to fit above [`DECORATION_SURFACE_BASE`]
+    pub fn surface_id(self, window_id: u32) -> Option<u32> {
+        window_id.checked_mul(4)?.checked_add(DECORATION_SURFACE_BASE)?.checked_add(self as u32)
+    }
+
+    /// The window and piece a decoration surface id names
+    pub fn from_surface_id(surface_id: u32) -> Option<(u32, DecorationPiece)> {
+        let n = surface_id.checked_sub(DECORATION_SURFACE_BASE)?;
+        Some((n / 4, Self::ALL[(n % 4) as usize]))
+    }
+}
+
+/// Whether a surface id names a decoration piece
+pub fn is_decoration_surface(surface_id: u32) -> bool {
+    surface_id >= DECORATION_SURFACE_BASE
+}
+
+/// Buffer id of a decoration piece; it changes whenever the pixels do, so
+/// frontends know to upload them again
+pub fn decoration_buffer_id(surface_id: u32, generation: u32) -> u64 {
+    DECORATION_BUFFER_BIT | (generation as u64) << 32 | surface_id as u64
+}
+
+/// Whether a buffer id names the pixels of a decoration piece
+pub fn is_decoration_buffer(buffer_id: u64) -> bool {
+    buffer_id & DECORATION_BUFFER_BIT != 0
+}
+
+/// The decoration surface a decoration buffer id belongs to
+pub fn decoration_buffer_surface(buffer_id: u64) -> u32 {
+    buffer_id as u32
+}
+
+/// Part of the decoration under a point
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecorationHit {
+    /// Dragging moves the window
+    Titlebar,
+    Close,
+    Maximize,
+    Minimize,
+    /// Dragging resizes the window from this edge
+    Resize(ResizeEdge),
+}
+
+/// Frame geometry around a window's content, relative to the content
+/// origin
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DecorationLayout {
+    pub width: i32,
+    pub height: i32,
+    /// Maximized windows have no border to resize with
+    pub maximized: bool,
+}
+
+impl DecorationLayout {
+    pub fn new(width: i32, height: i32, maximized: bool) -> Self {
+        Self { width: width.max(0), height: height.max(0), maximized }
+    }
+
+    fn border(&self) -> i32 {
+        if self.maximized { 0 } else { BORDER_WIDTH }
+    }
+
+    /// Space the frame takes around the content (top, right, bottom, left)
+    pub fn insets(&self) -> (i32, i32, i32, i32) {
+        let b = self.border();
+        (TITLEBAR_HEIGHT + b, b, b, b)
+    }
+
+    /// Rect of a piece, relative to the content origin
+    pub fn piece_rect(&self, piece: DecorationPiece) -> Rect {
+        let b = self.border();
+        let (w, h) = (self.width, self.height);
+        let (x, y, width, height) = match piece {
+            DecorationPiece::Titlebar => (-b, -TITLEBAR_HEIGHT - b, w + 2 * b, TITLEBAR_HEIGHT + b),
+            DecorationPiece::Left => (-b, 0, b, h),
+            DecorationPiece::Right => (w, 0, b, h),
+            DecorationPiece::Bottom => (-b, h, w + 2 * b, b),
+        };
+        Rect::new(x, y, width.max(0) as u32, height.max(0) as u32)
+    }
+
+    /// Rect of a title bar button, relative to the content origin. Close
+    /// sits at the right, then maximize, then minimize.
+    pub fn button_rect(&self, hit: DecorationHit) -> Option<Rect> {
+        let slot = match hit {
+            DecorationHit::Close => 1,
+            DecorationHit::Maximize => 2,
+            DecorationHit::Minimize => 3,
+            _ => return None,
+        };
+        let x = self.width - slot * BUTTON_WIDTH;
+        Some(Rect::new(x, -TITLEBAR_HEIGHT, BUTTON_WIDTH as u32, TITLEBAR_HEIGHT as u32))
+    }
+
+    /// What a point relative to the content origin hits. The content
+    /// itself and anything outside the frame hit nothing.
+    pub fn hit_test(&self, x: i32, y: i32) -> Option<DecorationHit> {
+        let b = self.border();
+        let (w, h) = (self.width, self.height);
+        let top_edge = -TITLEBAR_HEIGHT - b;
+        if x < -b || x >= w + b || y < top_edge || y >= h + b {
+            return None;
+        }
+        if x >= 0 && x < w && y >= 0 && y < h {
+            return None;
+        }
+
+        if !self.maximized {
+            let mut top = y < -TITLEBAR_HEIGHT;
+            let mut bottom = y >= h;
+            let mut left = x < 0;
+            let mut right = x >= w;
+            // Corners reach a little along both edges
+            if top || bottom {
+                left |= x < CORNER_SIZE - b;
+                right |= x >= w + b - CORNER_SIZE;
+            }
+            if left || right {
+                top |= y < top_edge + CORNER_SIZE;
+                bottom |= y >= h + b - CORNER_SIZE;
+            }
+            let edge = ResizeEdge::from_sides(top, bottom, left, right);
+            if edge != ResizeEdge::None {
+                return Some(DecorationHit::Resize(edge));
+            }
+        }
+
+        [DecorationHit::Close, DecorationHit::Maximize, DecorationHit::Minimize]
+            .into_iter()
+            .find(|&hit| self.button_rect(hit).map_or(false, |r| r.contains_point(x, y)))
+            .or(Some(DecorationHit::Titlebar))
+    }
+}
+
+/// ARGB8888 pixels
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pixmap {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u32>,
+}
+
+impl Pixmap {
+    pub fn new(width: u32, height: u32, color: u32) -> Self {
+        Self { width, height, pixels: vec![color; width as usize * height as usize] }
+    }
+
+    /// Pixel bytes in wl_shm ARGB8888 order (little endian)
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.pixels.iter().flat_map(|p| p.to_le_bytes()).collect()
+    }
+
+    /// Stride in bytes
+    pub fn stride(&self) -> u32 {
+        self.width * 4
+    }
+
+    pub fn pixel(&self, x: i32, y: i32) -> Option<u32> {
+        if x < 0 || y < 0 || x >= self.width as i32 || y >= self.height as i32 {
+            return None;
+        }
+        Some(self.pixels[y as usize * self.width as usize + x as usize])
+    }
+
+    fn put(&mut self, x: i32, y: i32, color: u32) {
+        if x >= 0 && y >= 0 && x < self.width as i32 && y < self.height as i32 {
+            self.pixels[y as usize * self.width as usize + x as usize] = color;
+        }
+    }
+
+    fn fill_rect(&mut self, rect: Rect, color: u32) {
+        for y in rect.y..rect.bottom() {
+            for x in rect.x..rect.right() {
+                self.put(x, y, color);
+            }
+        }
+    }
+
+    /// Draw ASCII text with the 8x8 font; other characters show as `?`
+    fn draw_text(&mut self, x: i32, y: i32, text: &str, color: u32) {
+        for (i, c) in text.chars().enumerate() {
+            let glyph = FONT[if c.is_ascii() { c as usize } else { b'?' as usize }];
+            let gx = x + i as i32 * GLYPH_SIZE;
+            for (row, bits) in glyph.iter().enumerate() {
+                for col in 0..8 {
+                    if bits & (0x80 >> col) != 0 {
+                        let rect = Rect::new(
+                            gx + col * GLYPH_SCALE,
+                            y + row as i32 * GLYPH_SCALE,
+                            GLYPH_SCALE as u32,
+                            GLYPH_SCALE as u32,
+                        );
+                        self.fill_rect(rect, color);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Colour of the border pieces
+pub fn border_color(active: bool) -> u32 {
+    if active { TITLEBAR_ACTIVE } else { TITLEBAR_INACTIVE }
+}
+
+/// Draw the title bar piece: title on the left, buttons on the right, the
+/// hovered button highlighted. Titles too long for the space left are cut
+/// short with `...`.
+pub fn render_titlebar(layout: &DecorationLayout, title: &str, active: bool, hovered: Option<DecorationHit>) -> Pixmap {
+    let piece = layout.piece_rect(DecorationPiece::Titlebar);
+    let mut pixmap = Pixmap::new(piece.width, piece.height, border_color(active));
+    let text_color = if active { TEXT_ACTIVE } else { TEXT_INACTIVE };
+    // Drawing happens in piece-local coordinates
+    let to_local = |r: Rect| Rect::new(r.x - piece.x, r.y - piece.y, r.width, r.height);
+
+    for hit in [DecorationHit::Close, DecorationHit::Maximize, DecorationHit::Minimize] {
+        let Some(rect) = layout.button_rect(hit).map(to_local) else { continue };
+        if hovered == Some(hit) {
+            pixmap.fill_rect(rect, if hit == DecorationHit::Close { CLOSE_HOVER } else { BUTTON_HOVER });
+        }
+        let ix = rect.x + (BUTTON_WIDTH - ICON_SIZE) / 2;
+        let iy = rect.y + (TITLEBAR_HEIGHT - ICON_SIZE) / 2;
+        match hit {
+            DecorationHit::Close => {
+                for i in 0..ICON_SIZE {
+                    pixmap.put(ix + i, iy + i, text_color);
+                    pixmap.put(ix + ICON_SIZE - 1 - i, iy + i, text_color);
+                }
+            }
+            DecorationHit::Maximize => {
+                for i in 0..ICON_SIZE {
+                    pixmap.put(ix + i, iy, text_color);
+                    pixmap.put(ix + i, iy + ICON_SIZE - 1, text_color);
+                    pixmap.put(ix, iy + i, text_color);
+                    pixmap.put(ix + ICON_SIZE - 1, iy + i, text_color);
+                }
+            }
+            _ => pixmap.fill_rect(Rect::new(ix, iy + ICON_SIZE - 2, ICON_SIZE as u32, 2), text_color),
+        }
+    }
+
+    let title_x = -piece.x + TITLE_PADDING;
+    let buttons_x = layout.width - 3 * BUTTON_WIDTH - piece.x;
+    let max_chars = ((buttons_x - title_x) / GLYPH_SIZE).max(0) as usize;
+    let text: String = if title.chars().count() > max_chars {
+        let kept = max_chars.saturating_sub(3);
+        title.chars().take(kept).chain("...".chars()).take(max_chars).collect()
+    } else {
+        title.to_string()
+    };
+    let title_y = -piece.y - TITLEBAR_HEIGHT + (TITLEBAR_HEIGHT - GLYPH_SIZE) / 2;
+    pixmap.draw_text(title_x, title_y, &text, text_color);
+    pixmap
+}
+
+/// What a window's title bar was last drawn from
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct TitlebarKey {
+    layout: DecorationLayout,
+    title: String,
+    active: bool,
+    hovered: Option<DecorationHit>,
+}
+
+/// Decoration of one window
+#[derive(Debug, Clone)]
+pub struct WindowDecoration {
+    key: TitlebarKey,
+    pub titlebar: Pixmap,
+    /// Bumped whenever the pixels of any piece change
+    pub generation: u32,
+}
+
+impl WindowDecoration {
+    pub fn layout(&self) -> DecorationLayout {
+        self.key.layout
+    }
+
+    pub fn active(&self) -> bool {
+        self.key.active
+    }
+
+    /// Pixels of a piece; borders are a single pixel stretched over their rect
+    pub fn piece_pixels(&self, piece: DecorationPiece) -> Pixmap {
+        match piece {
+            DecorationPiece::Titlebar => self.titlebar.clone(),
+            _ => Pixmap::new(1, 1, border_color(self.key.active)),
+        }
+    }
+
+    /// Size of a piece's buffer
+    pub fn piece_buffer_size(&self, piece: DecorationPiece) -> (u32, u32) {
+        match piece {
+            DecorationPiece::Titlebar => (self.titlebar.width, self.titlebar.height),
+            _ => (1, 1),
+        }
+    }
+}
+
+/// Core-drawn decorations of all server-side decorated windows
+#[derive(Debug, Default)]
+pub struct DecorationState {
+    /// Draw decorations in the core; when off they are left to the
+    /// frontend. On when headless, and for frontends without a window
+    /// manager to draw frames.
+    pub enabled: bool,
+    pub windows: HashMap<u32, WindowDecoration>,
+    /// Decoration part under the pointer
+    pub hovered: Option<(u32, DecorationHit)>,
+    /// Decoration part a button press started on
+    pub pressed: Option<(u32, DecorationHit)>,
+}
+
+impl DecorationState {
+    /// Bring a window's decoration up to date, redrawing the title bar
+    /// only when something shown on it changed.
+    pub fn update(&mut self, window_id: u32, layout: DecorationLayout, title: &str, active: bool) -> &WindowDecoration {
+        let hovered = self.hovered.filter(|(wid, _)| *wid == window_id).map(|(_, hit)| hit);
+        let key = TitlebarKey { layout, title: title.to_string(), active, hovered };
+        let decoration = self.windows.entry(window_id).or_insert_with(|| WindowDecoration {
+            key: key.clone(),
+            titlebar: render_titlebar(&layout, title, active, hovered),
+            generation: 0,
+        });
+        if decoration.key != key {
+            decoration.titlebar = render_titlebar(&layout, title, active, hovered);
+            decoration.key = key;
+            decoration.generation = decoration.generation.wrapping_add(1);
+        }
+        decoration
+    }
+
+    pub fn remove(&mut self, window_id: u32) {
+        self.windows.remove(&window_id);
+        if self.hovered.map_or(false, |(wid, _)| wid == window_id) {
+            self.hovered = None;
+        }
+        if self.pressed.map_or(false, |(wid, _)| wid == window_id) {
+            self.pressed = None;
+        }
+    }
+}
@@ -0,0 +1,213 @@
+//! Interactive move and resize grabs.
+//!
+//! After a validated `xdg_toplevel.move` or `.resize`, or a press on a
+//! core-drawn decoration, the pointer belongs to the grab until its
+//! buttons are released: motion moves or resizes the window instead of
+//! reaching clients. A request made with a touch down serial is driven by
+//! that touch point instead, until it is lifted.
+
+use crate::core::input::grab::{GrabKind, GrabSerial, GrabStatus, InputGrab};
+use crate::core::state::CompositorState;
+use crate::core::window::resize::{ResizeEdge, ResizeState};
+use crate::util::geometry::Rect;
+
+/// Distance within which a dragged window's frame sticks to output edges
+pub const SNAP_DISTANCE: i32 = 16;
+
+/// What a grab does with pointer motion
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WindowGrabKind {
+    /// Pointer offset from the window origin stays fixed
+    Move { offset_x: i32, offset_y: i32 },
+    Resize(ResizeState),
+}
+
+/// An interactive move or resize in progress
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WindowGrab {
+    pub window_id: u32,
+    /// Serial of the button press or touch down the grab was started with
+    pub serial: u32,
+    pub kind: WindowGrabKind,
+    /// Touch point driving the grab, or `None` for the pointer
+    pub touch_id: Option<i32>,
+}
+
+impl WindowGrab {
+    pub fn new_move(window_id: u32, serial: u32, pointer: (i32, i32), window_origin: (i32, i32)) -> Self {
+        Self {
+            window_id,
+            serial,
+            kind: WindowGrabKind::Move {
+                offset_x: pointer.0 - window_origin.0,
+                offset_y: pointer.1 - window_origin.1,
+            },
+            touch_id: None,
+        }
+    }
+
+    pub fn new_resize(window_id: u32, serial: u32, edge: ResizeEdge, pointer: (i32, i32), window: Rect) -> Self {
+        Self {
+            window_id,
+            serial,
+            kind: WindowGrabKind::Resize(ResizeState {
+                initial_width: window.width as i32,
+                initial_height: window.height as i32,
+                initial_x: pointer.0,
+                initial_y: pointer.1,
+                edge,
+                window_x: window.x,
+                window_y: window.y,
+            }),
+            touch_id: None,
+        }
+    }
+
+    /// Follow the held input the grab was requested with; `pointer` and
+    /// the window geometry passed in must be where it is
+    pub fn driven_by(mut self, source: GrabSerial) -> Self {
+        self.touch_id = match source {
+            GrabSerial::Touch(id) => Some(id),
+            GrabSerial::Pointer | GrabSerial::Key => None,
+        };
+        self
+    }
+
+    /// The resize in progress, if this is a resize grab
+    pub fn resize(&self) -> Option<&ResizeState> {
+        match &self.kind {
+            WindowGrabKind::Resize(resize) => Some(resize),
+            WindowGrabKind::Move { .. } => None,
+        }
+    }
+}
+
+impl InputGrab for WindowGrab {
+    fn kind(&self) -> GrabKind {
+        GrabKind::Window
+    }
+
+    fn pointer_motion(&mut self, state: &mut CompositorState, x: f64, y: f64, _time: u32) -> GrabStatus {
+        if self.touch_id.is_some() {
+            return GrabStatus::Pass;
+        }
+        state.update_window_grab(self, x, y);
+        GrabStatus::Consumed
+    }
+
+    fn pointer_button(&mut self, state: &mut CompositorState, _button: u32, _pressed: bool, time: u32) -> GrabStatus {
+        if self.touch_id.is_some() {
+            return GrabStatus::Pass;
+        }
+        if state.seat.pointer.button_count > 0 {
+            return GrabStatus::Consumed;
+        }
+        state.end_grab(GrabKind::Window);
+        state.finish_window_grab(self);
+        // Give the pointer back to whatever is under it now
+        let (x, y) = (state.seat.pointer.x, state.seat.pointer.y);
+        state.inject_pointer_motion_absolute(x, y, time);
+        GrabStatus::End { consumed: true }
+    }
+
+    fn touch_motion(&mut self, state: &mut CompositorState, id: i32, x: f64, y: f64, _time: u32) -> GrabStatus {
+        if self.touch_id != Some(id) {
+            return GrabStatus::Pass;
+        }
+        state.update_window_grab(self, x, y);
+        GrabStatus::Consumed
+    }
+
+    fn touch_up(&mut self, state: &mut CompositorState, id: i32, _time: u32) -> GrabStatus {
+        if self.touch_id != Some(id) {
+            return GrabStatus::Pass;
+        }
+        state.end_grab(GrabKind::Window);
+        state.finish_window_grab(self);
+        // The client still sees the point lifted, ending its sequence
+        GrabStatus::End { consumed: false }
+    }
+
+    fn cancel(&mut self, state: &mut CompositorState) {
+        state.finish_window_grab(self);
+    }
+
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+}
+
+/// Window origin for a move with the pointer at (x, y). The frame around
+/// the content, given as (top, right, bottom, left) insets, snaps to the
+/// edges of `areas` when it comes within [`SNAP_DISTANCE`] of them.
+pub fn move_origin(
+    offset: (i32, i32),
+    pointer: (i32, i32),
+    size: (i32, i32),
+    insets: (i32, i32, i32, i32),
+    areas: &[Rect],
+) -> (i32, i32) {
+    let (top, right, bottom, left) = insets;
+    let x = pointer.0 - offset.0;
+    let y = pointer.1 - offset.1;
+    let frame = Rect::new(
+        x - left,
+        y - top,
+        (size.0 + left + right).max(0) as u32,
+        (size.1 + top + bottom).max(0) as u32,
+    );
+    let (dx, dy) = snap_to_edges(frame, areas, SNAP_DISTANCE);
+    (x + dx, y + dy)
+}
+
+/// Size for a resize with the pointer at (x, y), before the client's
+/// constraints are applied. Dragged sides of the frame snap to the edges
+/// of `areas` like a moved window does.
+pub fn resize_size(
+    resize: &ResizeState,
+    pointer: (i32, i32),
+    insets: (i32, i32, i32, i32),
+    areas: &[Rect],
+) -> (i32, i32) {
+    let (top, right, bottom, left) = insets;
+    let (mut width, mut height) = resize.size_at(pointer.0, pointer.1);
+    let (x, y) = resize.origin_for(width, height);
+    let xs = || areas.iter().flat_map(|a| [a.x, a.right()]);
+    let ys = || areas.iter().flat_map(|a| [a.y, a.bottom()]);
+    if resize.edge.has_left() {
+        width -= nearest_edge(x - left, xs(), SNAP_DISTANCE).unwrap_or(0);
+    } else if resize.edge.has_right() {
+        width += nearest_edge(x + width + right, xs(), SNAP_DISTANCE).unwrap_or(0);
+    }
+    if resize.edge.has_top() {
+        height -= nearest_edge(y - top, ys(), SNAP_DISTANCE).unwrap_or(0);
+    } else if resize.edge.has_bottom() {
+        height += nearest_edge(y + height + bottom, ys(), SNAP_DISTANCE).unwrap_or(0);
+    }
+    (width.max(1), height.max(1))
+}
+
+/// Offset that moves `frame` onto the nearest edges of `areas` within
+/// `distance`, per axis. An area only pulls on an axis when the frame lies
+/// alongside it on the other one.
+pub fn snap_to_edges(frame: Rect, areas: &[Rect], distance: i32) -> (i32, i32) {
+    let beside_x = || areas.iter().filter(|a| frame.y < a.bottom() + distance && frame.bottom() > a.y - distance);
+    let beside_y = || areas.iter().filter(|a| frame.x < a.right() + distance && frame.right() > a.x - distance);
+    let xs = || beside_x().flat_map(|a| [a.x, a.right()]);
+    let ys = || beside_y().flat_map(|a| [a.y, a.bottom()]);
+    let closest = |a: Option<i32>, b: Option<i32>| {
+        [a, b].into_iter().flatten().min_by_key(|delta| delta.abs()).unwrap_or(0)
+    };
+    (
+        closest(nearest_edge(frame.x, xs(), distance), nearest_edge(frame.right(), xs(), distance)),
+        closest(nearest_edge(frame.y, ys(), distance), nearest_edge(frame.bottom(), ys(), distance)),
+    )
+}
+
+/// Distance from `value` to the nearest of `edges` within `distance`
+fn nearest_edge(value: i32, edges: impl Iterator<Item = i32>, distance: i32) -> Option<i32> {
+    edges
+        .map(|edge| edge - value)
+        .filter(|delta| delta.abs() <= distance)
+        .min_by_key(|delta| delta.abs())
+}
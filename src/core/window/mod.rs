@@ -3,6 +3,8 @@ pub mod tree;
 pub mod focus;
 pub mod resize;
 pub mod fullscreen;
+pub mod decoration;
+pub mod grab;
 mod tests;
 
 pub use window::{Window, DecorationMode};
@@ -14,13 +14,54 @@ pub enum ResizeEdge {
     BottomRight,
 }
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
 pub struct ResizeState {
     pub initial_width: i32,
     pub initial_height: i32,
     pub initial_x: i32, // Pointer X at start
     pub initial_y: i32, // Pointer Y at start
     pub edge: ResizeEdge,
+    pub window_x: i32, // Window X at start
+    pub window_y: i32, // Window Y at start
+}
+
+impl ResizeState {
+    /// Size asked for with the pointer at (x, y), before the client's
+    /// constraints are applied
+    pub fn size_at(&self, x: i32, y: i32) -> (i32, i32) {
+        let (dx, dy) = (x - self.initial_x, y - self.initial_y);
+        let width = if self.edge.has_left() {
+            self.initial_width - dx
+        } else if self.edge.has_right() {
+            self.initial_width + dx
+        } else {
+            self.initial_width
+        };
+        let height = if self.edge.has_top() {
+            self.initial_height - dy
+        } else if self.edge.has_bottom() {
+            self.initial_height + dy
+        } else {
+            self.initial_height
+        };
+        (width.max(1), height.max(1))
+    }
+
+    /// Window position for a new size, keeping the edges opposite the
+    /// dragged ones in place
+    pub fn origin_for(&self, width: i32, height: i32) -> (i32, i32) {
+        let x = if self.edge.has_left() {
+            self.window_x + self.initial_width - width
+        } else {
+            self.window_x
+        };
+        let y = if self.edge.has_top() {
+            self.window_y + self.initial_height - height
+        } else {
+            self.window_y
+        };
+        (x, y)
+    }
 }
 
 impl Default for ResizeEdge {
@@ -29,3 +70,68 @@ impl Default for ResizeEdge {
     }
 }
 
+impl ResizeEdge {
+    /// Edge from the sides being dragged; opposite sides cancel out
+    pub fn from_sides(top: bool, bottom: bool, left: bool, right: bool) -> Self {
+        match (top && !bottom, bottom && !top, left && !right, right && !left) {
+            (true, _, true, _) => Self::TopLeft,
+            (true, _, _, true) => Self::TopRight,
+            (_, true, true, _) => Self::BottomLeft,
+            (_, true, _, true) => Self::BottomRight,
+            (true, ..) => Self::Top,
+            (_, true, ..) => Self::Bottom,
+            (_, _, true, _) => Self::Left,
+            (.., true) => Self::Right,
+            _ => Self::None,
+        }
+    }
+
+    /// Edge from an xdg_toplevel.resize_edge value; `None` for values
+    /// outside the enum
+    pub fn from_xdg_edges(edges: u32) -> Option<Self> {
+        Some(match edges {
+            0 => Self::None,
+            1 => Self::Top,
+            2 => Self::Bottom,
+            4 => Self::Left,
+            5 => Self::TopLeft,
+            6 => Self::BottomLeft,
+            8 => Self::Right,
+            9 => Self::TopRight,
+            10 => Self::BottomRight,
+            _ => return None,
+        })
+    }
+
+    pub fn has_top(self) -> bool {
+        matches!(self, Self::Top | Self::TopLeft | Self::TopRight)
+    }
+
+    pub fn has_bottom(self) -> bool {
+        matches!(self, Self::Bottom | Self::BottomLeft | Self::BottomRight)
+    }
+
+    pub fn has_left(self) -> bool {
+        matches!(self, Self::Left | Self::TopLeft | Self::BottomLeft)
+    }
+
+    pub fn has_right(self) -> bool {
+        matches!(self, Self::Right | Self::TopRight | Self::BottomRight)
+    }
+
+    /// The xdg_toplevel.resize_edge value
+    pub fn xdg_edges(self) -> u32 {
+        match self {
+            Self::None => 0,
+            Self::Top => 1,
+            Self::Bottom => 2,
+            Self::Left => 4,
+            Self::TopLeft => 5,
+            Self::BottomLeft => 6,
+            Self::Right => 8,
+            Self::TopRight => 9,
+            Self::BottomRight => 10,
+        }
+    }
+}
+
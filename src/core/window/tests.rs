@@ -76,4 +76,139 @@ mod tests {
         // Usually history shouldn't contain current focus.
         
     }
+
+    #[test]
+    fn test_decoration_hit_test() {
+        use crate::core::window::decoration::{DecorationHit, DecorationLayout};
+        use crate::core::window::resize::ResizeEdge;
+
+        let layout = DecorationLayout::new(200, 100, false);
+        assert_eq!(layout.insets(), (28, 4, 4, 4));
+
+        // Border and corners resize
+        assert_eq!(layout.hit_test(-4, -28), Some(DecorationHit::Resize(ResizeEdge::TopLeft)));
+        assert_eq!(layout.hit_test(100, -27), Some(DecorationHit::Resize(ResizeEdge::Top)));
+        assert_eq!(layout.hit_test(-2, 50), Some(DecorationHit::Resize(ResizeEdge::Left)));
+        assert_eq!(layout.hit_test(-2, -20), Some(DecorationHit::Resize(ResizeEdge::TopLeft)));
+        assert_eq!(layout.hit_test(202, 103), Some(DecorationHit::Resize(ResizeEdge::BottomRight)));
+        assert_eq!(layout.hit_test(190, 102), Some(DecorationHit::Resize(ResizeEdge::BottomRight)));
+
+        // Buttons from the right, the rest of the bar moves
+        assert_eq!(layout.hit_test(180, -10), Some(DecorationHit::Close));
+        assert_eq!(layout.hit_test(160, -10), Some(DecorationHit::Maximize));
+        assert_eq!(layout.hit_test(130, -10), Some(DecorationHit::Minimize));
+        assert_eq!(layout.hit_test(50, -10), Some(DecorationHit::Titlebar));
+
+        // Content and outside hit nothing
+        assert_eq!(layout.hit_test(10, 10), None);
+        assert_eq!(layout.hit_test(300, 300), None);
+
+        // Maximized windows have no border to resize with
+        let maximized = DecorationLayout::new(200, 100, true);
+        assert_eq!(maximized.insets(), (24, 0, 0, 0));
+        assert_eq!(maximized.hit_test(0, -24), Some(DecorationHit::Titlebar));
+        assert_eq!(maximized.hit_test(-1, 50), None);
+    }
+
+    #[test]
+    fn test_decoration_surface_ids() {
+        use crate::core::window::decoration::{is_decoration_surface, DecorationPiece};
+
+        let id = DecorationPiece::Right.surface_id(7).unwrap();
+        assert!(is_decoration_surface(id));
+        assert_eq!(DecorationPiece::from_surface_id(id), Some((7, DecorationPiece::Right)));
+        // Window ids that don't fit above the base get no pieces rather
+        // than wrapping into client surface ids
+        assert_eq!(DecorationPiece::Titlebar.surface_id(0x0400_0000), None);
+        assert_eq!(DecorationPiece::Bottom.surface_id(u32::MAX), None);
+        assert!(DecorationPiece::Bottom.surface_id(0x03FF_FFFF).is_some());
+    }
+
+    #[test]
+    fn test_render_titlebar() {
+        use crate::core::window::decoration::{
+            border_color, render_titlebar, DecorationHit, DecorationLayout, DecorationPiece,
+        };
+
+        let layout = DecorationLayout::new(200, 100, false);
+        let rect = layout.piece_rect(DecorationPiece::Titlebar);
+        let background = border_color(true);
+        let drawn_in = |pixmap: &crate::core::window::decoration::Pixmap, x0: i32, x1: i32| {
+            (x0..x1).any(|x| (0..pixmap.height as i32).any(|y| pixmap.pixel(x, y) != Some(background)))
+        };
+
+        let short = render_titlebar(&layout, "Hi", true, None);
+        assert_eq!((short.width, short.height), (rect.width, rect.height));
+        assert!(drawn_in(&short, 12, 44));
+        assert!(!drawn_in(&short, 44, 132));
+
+        // Seven glyphs fit before the buttons; longer titles are cut short
+        let long = render_titlebar(&layout, "A very long window title", true, None);
+        assert!(drawn_in(&long, 108, 124));
+        assert!(!drawn_in(&long, 124, 132));
+
+        // Hovering a button highlights it
+        let hovered = render_titlebar(&layout, "Hi", true, Some(DecorationHit::Close));
+        assert_ne!(hovered.pixel(182, 5), short.pixel(182, 5));
+    }
+
+    #[test]
+    fn test_resize_from_edges() {
+        use crate::core::window::resize::{ResizeEdge, ResizeState};
+
+        assert_eq!(ResizeEdge::from_xdg_edges(9), Some(ResizeEdge::TopRight));
+        assert_eq!(ResizeEdge::from_xdg_edges(3), None);
+        assert_eq!(ResizeEdge::from_xdg_edges(ResizeEdge::BottomLeft.xdg_edges()), Some(ResizeEdge::BottomLeft));
+
+        let resize = |edge| ResizeState {
+            initial_width: 400,
+            initial_height: 300,
+            initial_x: 500,
+            initial_y: 400,
+            edge,
+            window_x: 100,
+            window_y: 100,
+        };
+
+        // Dragging the bottom-right corner grows away from the origin
+        let corner = resize(ResizeEdge::BottomRight);
+        assert_eq!(corner.size_at(550, 420), (450, 320));
+        assert_eq!(corner.origin_for(450, 320), (100, 100));
+
+        // Dragging the top-left corner keeps the bottom-right one in place
+        let corner = resize(ResizeEdge::TopLeft);
+        assert_eq!(corner.size_at(450, 380), (450, 320));
+        assert_eq!(corner.origin_for(450, 320), (50, 80));
+
+        // Edges only change their own axis, and never below one pixel
+        let left = resize(ResizeEdge::Left);
+        assert_eq!(left.size_at(1000, 0), (1, 300));
+    }
+
+    #[test]
+    fn test_window_grab_snapping() {
+        use crate::core::window::grab::{move_origin, resize_size, WindowGrab, WindowGrabKind};
+        use crate::core::window::resize::ResizeEdge;
+        use crate::util::geometry::Rect;
+
+        let outputs = [Rect::new(0, 0, 1920, 1080), Rect::new(1920, 0, 1280, 1024)];
+        let grab = WindowGrab::new_move(1, 7, (150, 110), (100, 100));
+        let WindowGrabKind::Move { offset_x, offset_y } = grab.kind else { panic!() };
+        let offset = (offset_x, offset_y);
+
+        // Far from any edge the window follows the pointer
+        assert_eq!(move_origin(offset, (450, 410), (400, 300), (0, 0, 0, 0), &outputs), (400, 400));
+        // Near the left and top edges it sticks to them
+        assert_eq!(move_origin(offset, (60, 20), (400, 300), (0, 0, 0, 0), &outputs), (0, 0));
+        // The frame snaps, not the content
+        assert_eq!(move_origin(offset, (60, 40), (400, 300), (28, 4, 4, 4), &outputs), (4, 28));
+        // The right edge sticks to the boundary between outputs
+        assert_eq!(move_origin(offset, (1570, 510), (400, 300), (0, 0, 0, 0), &outputs), (1520, 500));
+
+        // Resizing snaps the dragged edges only
+        let grab = WindowGrab::new_resize(1, 7, ResizeEdge::BottomRight, (500, 400), Rect::new(100, 100, 400, 300));
+        let resize = grab.resize().unwrap();
+        assert_eq!(resize_size(resize, (1910, 700), (0, 0, 0, 0), &outputs), (1820, 600));
+        assert_eq!(resize_size(resize, (800, 1075), (0, 0, 0, 0), &outputs), (700, 980));
+    }
 }
@@ -41,18 +41,4 @@ impl WindowTree {
     pub fn topmost(&self) -> Option<u32> {
         self.stacking_order.last().copied()
     }
-
-    /// Find the top-most window under the given point
-    pub fn window_under(&self, x: f64, y: f64, windows: &std::collections::HashMap<u32, std::sync::Arc<std::sync::RwLock<crate::core::window::Window>>>) -> Option<u32> {
-        // Iterate in reverse stacking order (top to bottom)
-        for &window_id in self.stacking_order.iter().rev() {
-            if let Some(window) = windows.get(&window_id) {
-                let window = window.read().unwrap();
-                if window.geometry().contains_point(x as i32, y as i32) {
-                    return Some(window_id);
-                }
-            }
-        }
-        None
-    }
 }
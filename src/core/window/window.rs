@@ -38,6 +38,13 @@ pub struct Window {
     
     /// IDs of outputs this window is visible on
     pub outputs: Vec<u32>,
+    
+    /// Workspace the window belongs to; `None` shows it on every workspace
+    pub workspace: Option<u32>,
+
+    /// Pointer and touch input pass through the window (and its
+    /// subsurfaces) to whatever is below
+    pub input_passthrough: bool,
 }
 
 impl Window {
@@ -61,6 +68,8 @@ impl Window {
             geometry_x: 0,
             geometry_y: 0,
             outputs: Vec::new(),
+            workspace: None,
+            input_passthrough: false,
         }
     }
 
@@ -20,6 +20,10 @@ use crate::core::{
     CompositorState,
 };
 
+use crate::core::window::decoration::{
+    decoration_buffer_id, decoration_buffer_surface, is_decoration_buffer, DecorationPiece,
+};
+
 use wayland_server::Resource;
 
 // Re-export types for convenience
@@ -56,6 +60,12 @@ pub struct WawonaCore {
 
     /// Whether to advertise zwp_fullscreen_shell_v1
     advertise_fullscreen_shell: RwLock<bool>,
+
+    /// Whether the clipboard outlives the client that set it
+    clipboard_persistence: RwLock<bool>,
+
+    /// Whether the core runs interactive move/resize
+    core_window_grabs: RwLock<bool>,
     
     /// FFI window info cache
     ffi_windows: RwLock<HashMap<u64, WindowInfo>>,
@@ -106,6 +116,81 @@ fn apply_geometry_offset(
     (x, y)
 }
 
+/// Convert an FFI scroll event into the core's per-frame axis description.
+fn axis_frame_from_event(event: &AxisEvent) -> crate::core::input::AxisFrame {
+    use wayland_server::protocol::wl_pointer::AxisSource as WlAxisSource;
+    crate::core::input::AxisFrame {
+        source: Some(match event.source {
+            AxisSource::Wheel => WlAxisSource::Wheel,
+            AxisSource::Finger => WlAxisSource::Finger,
+            AxisSource::Continuous => WlAxisSource::Continuous,
+            AxisSource::WheelTilt => WlAxisSource::WheelTilt,
+        }),
+        horizontal: event.horizontal,
+        vertical: event.vertical,
+        horizontal_v120: event.horizontal_v120,
+        vertical_v120: event.vertical_v120,
+        horizontal_stop: event.horizontal_stop,
+        vertical_stop: event.vertical_stop,
+        inverted: event.inverted,
+        time_ms: event.time_ms,
+    }
+}
+
+/// Convert an FFI tablet tool event, shifting coordinates by `(dx, dy)`.
+fn tablet_tool_event_to_core(
+    event: &TabletToolEvent,
+    dx: f64,
+    dy: f64,
+) -> crate::core::wayland::ext::tablet::TabletToolEvent {
+    use crate::core::wayland::ext::tablet::{TabletToolEvent as Core, TabletToolInfo, TabletToolKind};
+    match *event {
+        TabletToolEvent::ProximityIn { tool, x, y } => Core::ProximityIn {
+            tool: TabletToolInfo {
+                serial: tool.serial,
+                kind: match tool.tool_type {
+                    TabletToolType::Pen => TabletToolKind::Pen,
+                    TabletToolType::Eraser => TabletToolKind::Eraser,
+                    TabletToolType::Brush => TabletToolKind::Brush,
+                    TabletToolType::Pencil => TabletToolKind::Pencil,
+                    TabletToolType::Airbrush => TabletToolKind::Airbrush,
+                    TabletToolType::Finger => TabletToolKind::Finger,
+                    TabletToolType::Mouse => TabletToolKind::Mouse,
+                    TabletToolType::Lens => TabletToolKind::Lens,
+                },
+                has_pressure: tool.has_pressure,
+                has_tilt: tool.has_tilt,
+                has_distance: tool.has_distance,
+                has_rotation: tool.has_rotation,
+                has_slider: tool.has_slider,
+                has_wheel: tool.has_wheel,
+            },
+            x: x + dx,
+            y: y + dy,
+        },
+        TabletToolEvent::ProximityOut => Core::ProximityOut,
+        TabletToolEvent::Motion { x, y } => Core::Motion { x: x + dx, y: y + dy },
+        TabletToolEvent::Down => Core::Down,
+        TabletToolEvent::Up => Core::Up,
+        TabletToolEvent::Pressure { pressure } => Core::Pressure(pressure),
+        TabletToolEvent::Distance { distance } => Core::Distance(distance),
+        TabletToolEvent::Tilt { x, y } => Core::Tilt { x, y },
+        TabletToolEvent::Rotation { degrees } => Core::Rotation(degrees),
+        TabletToolEvent::Slider { position } => Core::Slider(position),
+        TabletToolEvent::Wheel { degrees, clicks } => Core::Wheel { degrees, clicks },
+        TabletToolEvent::Button { button, state } => Core::Button { button, pressed: state.is_pressed() },
+    }
+}
+
+fn tablet_pad_event_to_core(event: &TabletPadEvent) -> crate::core::wayland::ext::tablet::TabletPadEvent {
+    use crate::core::wayland::ext::tablet::TabletPadEvent as Core;
+    match *event {
+        TabletPadEvent::Button { button, state } => Core::Button { button, pressed: state.is_pressed() },
+        TabletPadEvent::Ring { ring, angle } => Core::Ring { ring, angle },
+        TabletPadEvent::Strip { strip, position } => Core::Strip { strip, position },
+    }
+}
+
 #[uniffi::export]
 impl WawonaCore {
     // =========================================================================
@@ -124,6 +209,8 @@ impl WawonaCore {
             output_size: RwLock::new((1920, 1080, 1.0)),
             force_ssd: RwLock::new(false),
             advertise_fullscreen_shell: RwLock::new(false),
+            clipboard_persistence: RwLock::new(false),
+            core_window_grabs: RwLock::new(false),
             ffi_windows: RwLock::new(HashMap::new()),
             ffi_surfaces: RwLock::new(HashMap::new()),
             ffi_clients: RwLock::new(HashMap::new()),
@@ -164,6 +251,8 @@ impl WawonaCore {
             keyboard_repeat_rate: repeat_rate,
             keyboard_repeat_delay: repeat_delay,
             advertise_fullscreen_shell: *self.advertise_fullscreen_shell.read().unwrap(),
+            clipboard_persistence: *self.clipboard_persistence.read().unwrap(),
+            core_window_grabs: *self.core_window_grabs.read().unwrap(),
         };
         
         // Create and start the compositor
@@ -174,6 +263,8 @@ impl WawonaCore {
         let mut state = self.state.write().unwrap();
         state.update_primary_output(width, height, scale);
         state.advertise_fullscreen_shell = config.advertise_fullscreen_shell;
+        state.data.clipboard_cache.enabled = config.clipboard_persistence;
+        state.core_window_grabs = config.core_window_grabs;
         state.decoration_policy = if config.force_ssd {
             crate::core::state::DecorationPolicy::ForceServer
         } else {
@@ -280,6 +371,44 @@ impl WawonaCore {
         }
     }
 
+    /// Draw server-side decorations in the core instead of leaving them to
+    /// the platform. Decorated windows then carry title bar and border
+    /// nodes in the render scene, whose pixels come from
+    /// [`get_decoration_buffer`](Self::get_decoration_buffer).
+    pub fn set_core_decorations(&self, enabled: bool) {
+        crate::wlog!(crate::util::logging::FFI, "FFI: set_core_decorations({})", enabled);
+        let mut state = self.state.write().unwrap();
+        state.decorations.enabled = enabled;
+        if !enabled {
+            state.decorations.windows.clear();
+        }
+        let window_ids: Vec<u32> = state.windows.keys().copied().collect();
+        for window_id in window_ids {
+            state.pending_compositor_events.push(CompositorEvent::RedrawNeeded { window_id });
+        }
+    }
+
+    /// Pixels of a core-drawn decoration node, by its surface id. The
+    /// node's texture handle changes whenever these do.
+    pub fn get_decoration_buffer(&self, surface_id: u32) -> Option<WindowBuffer> {
+        let state = self.state.read().unwrap();
+        let (window_id, _) = DecorationPiece::from_surface_id(surface_id)?;
+        let generation = state.decorations.windows.get(&window_id)?.generation;
+        let pixmap = state.decoration_pixels(surface_id)?;
+        Some(WindowBuffer {
+            window_id: WindowId::new(window_id as u64),
+            surface_id: SurfaceId::new(surface_id),
+            buffer: Buffer::new_shm(
+                BufferId::new(decoration_buffer_id(surface_id, generation)),
+                pixmap.to_bytes(),
+                pixmap.width,
+                pixmap.height,
+                BufferFormat::Argb8888,
+                pixmap.stride(),
+            ),
+        })
+    }
+
     /// Set whether to advertise zwp_fullscreen_shell_v1
     pub fn set_advertise_fullscreen_shell(&self, enabled: bool) {
         crate::wlog!(crate::util::logging::FFI, "FFI: set_advertise_fullscreen_shell({})", enabled);
@@ -289,6 +418,42 @@ impl WawonaCore {
         state.advertise_fullscreen_shell = enabled;
     }
     
+    /// Enable or disable the compositor-side clipboard cache.
+    ///
+    /// When enabled, the preferred MIME types of each new selection are read
+    /// into memory and served by the compositor after the source client exits.
+    pub fn set_clipboard_persistence(&self, enabled: bool) {
+        crate::wlog!(crate::util::logging::FFI, "FFI: set_clipboard_persistence({})", enabled);
+        *self.clipboard_persistence.write().unwrap() = enabled;
+
+        let dh = self.compositor.lock().unwrap().as_ref().map(|c| c.display_handle());
+        let mut state = self.state.write().unwrap();
+        state.data.clipboard_cache.enabled = enabled;
+        if !enabled {
+            // Nobody is left to serve a cached selection once the cache is gone
+            if matches!(state.seat.current_selection, Some(crate::core::state::SelectionSource::Cached)) {
+                if let Some(dh) = dh {
+                    state.set_clipboard_source(&dh, None);
+                }
+            }
+            state.data.clipboard_cache.clear();
+        }
+    }
+    
+    /// Move and resize windows in the core after xdg_toplevel.move/resize
+    /// instead of reporting MoveRequested/ResizeRequested, for platforms
+    /// without a window manager of their own
+    pub fn set_core_window_grabs(&self, enabled: bool) {
+        crate::wlog!(crate::util::logging::FFI, "FFI: set_core_window_grabs({})", enabled);
+        *self.core_window_grabs.write().unwrap() = enabled;
+
+        let mut state = self.state.write().unwrap();
+        state.core_window_grabs = enabled;
+        if !enabled {
+            state.cancel_grab(crate::core::input::grab::GrabKind::Window);
+        }
+    }
+    
     /// Stop the compositor
     pub fn stop(&self) -> Result<()> {
         let mut compositor_guard = self.compositor.lock().unwrap();
@@ -416,7 +581,19 @@ impl WawonaCore {
                 crate::core::input::InputEvent::PointerButton { button, state: core_state, time_ms }
             }
             InputEvent::PointerAxis { horizontal, vertical, time_ms } => {
-                crate::core::input::InputEvent::PointerAxis { horizontal, vertical, time_ms }
+                crate::core::input::InputEvent::PointerAxis {
+                    frame: crate::core::input::AxisFrame::continuous(horizontal, vertical, time_ms),
+                }
+            }
+            InputEvent::PointerAxisFrame { event } => {
+                crate::core::input::InputEvent::PointerAxis { frame: axis_frame_from_event(&event) }
+            }
+            InputEvent::TabletTool { events, time_ms } => {
+                let events = events.iter().map(|e| tablet_tool_event_to_core(e, 0.0, 0.0)).collect();
+                crate::core::input::InputEvent::TabletTool { events, time_ms }
+            }
+            InputEvent::TabletPad { event, time_ms } => {
+                crate::core::input::InputEvent::TabletPad { event: tablet_pad_event_to_core(&event), time_ms }
             }
             InputEvent::KeyboardKey { keycode, state, time_ms } => {
                 let core_state = match state {
@@ -559,6 +736,113 @@ impl WawonaCore {
         }
     }
 
+    /// Report a presented frame with exact timing.
+    ///
+    /// `timestamp_ns` is CLOCK_MONOTONIC (on Apple platforms, mach
+    /// continuous time converted to nanoseconds); `refresh_mhz` of 0 uses the
+    /// output's mode. `flags` should only claim what the platform knows to be
+    /// true, e.g. `hw_clock` for display-link vblank timestamps.
+    pub fn frame_presented_at(&self, timestamp_ns: u64, refresh_mhz: u32, flags: PresentationFlags) {
+        use wayland_protocols::wp::presentation_time::server::wp_presentation_feedback::Kind;
+
+        let instant = crate::core::time::monotonic_ns_to_instant(timestamp_ns);
+        self.runtime.lock().unwrap().report_presentation(instant, refresh_mhz);
+
+        let mut kind = Kind::empty();
+        kind.set(Kind::Vsync, flags.vsync);
+        kind.set(Kind::HwClock, flags.hw_clock);
+        kind.set(Kind::HwCompletion, flags.hw_completion);
+        kind.set(Kind::ZeroCopy, flags.zero_copy);
+
+        let mut state = self.state.write().unwrap();
+        state.ext.presentation.platform_driven = true;
+        let refresh_ns = if refresh_mhz > 0 {
+            (1_000_000_000_000 / refresh_mhz as u64) as u32
+        } else {
+            state.output_refresh_ns()
+        };
+        state.present_feedback(None, timestamp_ns, refresh_ns, kind);
+        state.lift_fifo_barriers();
+    }
+
+    /// Report a vblank on one output.
+    ///
+    /// For platforms with a display link per screen: only this output's
+    /// frame clock advances, and only surfaces visible on it get
+    /// presentation feedback and frame callbacks. Arguments are as for
+    /// [`frame_presented_at`](Self::frame_presented_at).
+    pub fn output_frame_presented(
+        &self,
+        output_id: OutputId,
+        timestamp_ns: u64,
+        refresh_mhz: u32,
+        flags: PresentationFlags,
+    ) {
+        use wayland_protocols::wp::presentation_time::server::wp_presentation_feedback::Kind;
+
+        let instant = crate::core::time::monotonic_ns_to_instant(timestamp_ns);
+        self.runtime.lock().unwrap().report_output_presentation(output_id.id, instant, refresh_mhz);
+
+        let mut kind = Kind::empty();
+        kind.set(Kind::Vsync, flags.vsync);
+        kind.set(Kind::HwClock, flags.hw_clock);
+        kind.set(Kind::HwCompletion, flags.hw_completion);
+        kind.set(Kind::ZeroCopy, flags.zero_copy);
+
+        let mut state = self.state.write().unwrap();
+        state.ext.presentation.platform_driven = true;
+        let refresh_mhz = if refresh_mhz > 0 {
+            refresh_mhz
+        } else {
+            state.outputs.iter().find(|o| o.id == output_id.id).map_or(0, |o| o.refresh)
+        };
+        let refresh_ns = if refresh_mhz > 0 {
+            (1_000_000_000_000 / refresh_mhz as u64) as u32
+        } else {
+            0
+        };
+        state.present_output_feedback(output_id.id, timestamp_ns, refresh_ns, kind);
+        state.flush_output_frame_callbacks(output_id.id, None);
+        state.lift_output_fifo_barriers(output_id.id);
+    }
+
+    /// Set an output's refresh rate and adaptive-sync range (both in mHz).
+    ///
+    /// `min_refresh_mhz` of 0 marks a fixed-rate display; ProMotion iPads
+    /// report e.g. 120000 with a minimum of 10000.
+    pub fn set_output_refresh(&self, output_id: OutputId, refresh_mhz: u32, min_refresh_mhz: u32) -> Result<()> {
+        crate::wlog!(crate::util::logging::FFI, "Output {} refresh: {} mHz (min {} mHz)",
+            output_id.id, refresh_mhz, min_refresh_mhz);
+        let mut state = self.state.write().unwrap();
+        let refresh = if refresh_mhz > 0 { Some(refresh_mhz) } else { None };
+        if !state.update_output_configuration(output_id.id, None, None, refresh, None, None, None) {
+            return Err(CompositorError::invalid_output_id(output_id.id));
+        }
+        state.set_output_vrr_range(output_id.id, min_refresh_mhz);
+        Ok(())
+    }
+
+    /// Milliseconds until rendering for an output's next frame should
+    /// start, following its current (possibly variable) refresh interval
+    pub fn output_time_until_next_frame_ms(&self, output_id: OutputId) -> u32 {
+        let runtime = self.runtime.lock().unwrap();
+        runtime.output_clock(output_id.id).map_or_else(
+            || runtime.time_until_next_frame().as_millis() as u32,
+            |clock| clock
+                .plan_render(std::time::Duration::from_millis(4))
+                .saturating_duration_since(std::time::Instant::now())
+                .as_millis() as u32,
+        )
+    }
+
+    /// Whether content on an output asked for async (tearing) presentation,
+    /// so the platform may present it without waiting for vblank
+    pub fn output_allows_tearing(&self, output_id: OutputId) -> bool {
+        self.runtime.lock().unwrap()
+            .output_clock(output_id.id)
+            .map_or(false, |clock| clock.allows_tearing())
+    }
+
     /// Report that a frame was presented
     /// 
     /// This should be called by the platform when the frame is actually displayed.
@@ -1192,6 +1476,18 @@ impl WawonaCore {
                     WindowEvent::SystemBell { surface_id }
                 );
             }
+            CompositorEvent::KeyboardLayoutChanged { layout, success } => {
+                crate::wlog!(crate::util::logging::FFI, "KeyboardLayoutChanged layout={:?} success={}", layout, success);
+                self.pending_window_events.write().unwrap().push(
+                    WindowEvent::KeyboardLayoutChanged { layout, success }
+                );
+            }
+            CompositorEvent::IdleStateChanged { idle } => {
+                crate::wlog!(crate::util::logging::FFI, "IdleStateChanged idle={}", idle);
+                self.pending_window_events.write().unwrap().push(
+                    WindowEvent::IdleStateChanged { idle }
+                );
+            }
         }
     }
 }
@@ -1211,8 +1507,8 @@ fn ensure_pointer_focus(
         .map(|(sid, _)| *sid);
 
     let target_sid = match target_sid {
-        Some(sid) => sid,
-        None => return,
+        Some(sid) if state.accepts_input(sid) => sid,
+        _ => return,
     };
 
     if state.seat.pointer.focus == Some(target_sid) {
@@ -1340,11 +1636,17 @@ impl WawonaCore {
             
         state.flush_frame_callbacks(surface_id.id, Some(timestamp));
 
-        let timestamp_ns = (timestamp as u64) * 1_000_000;
-        let refresh_ns: u64 = 1_000_000_000 / 60;
-        let seq = state.ext.presentation.next_seq;
-        state.ext.presentation.next_seq += 1;
-        state.ext.presentation.send_presented_events(timestamp_ns, refresh_ns, seq);
+        // `timestamp` is the wall-clock millisecond value used for frame
+        // callbacks; presentation feedback is stamped in CLOCK_MONOTONIC.
+        state.ext.presentation.platform_driven = true;
+        let refresh_ns = state.output_refresh_ns();
+        state.present_feedback(
+            Some(surface_id.id),
+            crate::core::time::monotonic_ns(),
+            refresh_ns,
+            wayland_protocols::wp::presentation_time::server::wp_presentation_feedback::Kind::Vsync,
+        );
+        state.lift_fifo_barriers();
 
         // Flush queued buffer releases from handle_surface_commit. This is the
         // correct time: the frame has been rendered and the old buffer's texture
@@ -1596,26 +1898,44 @@ impl WawonaCore {
     }
     
     /// Inject pointer axis (scroll) event
+    ///
+    /// `discrete` is in whole wheel detents and is forwarded as value120.
     pub fn inject_pointer_axis(
         &self,
-        _window_id: WindowId,
+        window_id: WindowId,
         axis: PointerAxis,
         value: f64,
-        _discrete: i32,
-        _source: AxisSource,
+        discrete: i32,
+        source: AxisSource,
         timestamp_ms: u32,
     ) {
+        let mut event = AxisEvent {
+            source,
+            time_ms: timestamp_ms,
+            ..Default::default()
+        };
+        match axis {
+            PointerAxis::Vertical => {
+                event.vertical = value;
+                event.vertical_v120 = discrete.saturating_mul(120);
+            }
+            PointerAxis::Horizontal => {
+                event.horizontal = value;
+                event.horizontal_v120 = discrete.saturating_mul(120);
+            }
+        }
+        self.inject_pointer_axis_event(window_id, event);
+    }
+
+    /// Inject a complete scroll frame (source, value120, stop, direction)
+    pub fn inject_pointer_axis_event(&self, _window_id: WindowId, event: AxisEvent) {
         if !self.is_running() {
             return;
         }
         let mut state = self.state.write().unwrap();
+        state.ext.idle_notify.record_activity();
         let focused_client = state.focused_pointer_client();
-        let wl_axis = match axis {
-            PointerAxis::Vertical => wayland_server::protocol::wl_pointer::Axis::VerticalScroll,
-            PointerAxis::Horizontal => wayland_server::protocol::wl_pointer::Axis::HorizontalScroll,
-        };
-        state.seat.broadcast_pointer_axis(timestamp_ms, wl_axis, value, focused_client.as_ref());
-        state.seat.broadcast_pointer_frame(focused_client.as_ref());
+        state.seat.broadcast_pointer_axis_frame(&axis_frame_from_event(&event), focused_client.as_ref());
     }
     
     /// Inject pointer frame event
@@ -1648,7 +1968,7 @@ impl WawonaCore {
             .find(|(_, &wid)| wid as u64 == window_id.id)
             .map(|(sid, _)| *sid);
             
-        if let Some(sid) = surface_id {
+        if let Some(sid) = surface_id.filter(|&sid| state.accepts_input(sid)) {
             if state.seat.pointer.button_count > 0 {
                 return;
             }
@@ -1730,6 +2050,10 @@ impl WawonaCore {
         // other shifted symbols) would not be recognised.
         let mods_changed = state.seat.keyboard.process_key(keycode, pressed)
             .map_or(false, |r| r.modifiers_changed);
+        state.seat.keyboard.update_repeat(keycode, pressed, timestamp_ms);
+        if mods_changed {
+            state.refresh_keyboard_layout();
+        }
         
         let focused_client = state.focused_keyboard_client();
         state.seat.broadcast_key(key_serial, timestamp_ms, keycode, wl_state, focused_client.as_ref());
@@ -1776,6 +2100,7 @@ impl WawonaCore {
                 );
             }
         }
+        state.refresh_keyboard_layout();
         
         let focused_client = state.focused_keyboard_client();
         state.seat.broadcast_modifiers(serial, modifiers.mods_depressed, modifiers.mods_latched, modifiers.mods_locked, modifiers.group, focused_client.as_ref());
@@ -1795,6 +2120,11 @@ impl WawonaCore {
             .map(|(sid, _)| *sid);
             
         if let Some(sid) = surface_id {
+            // Keyboard focus stays on the lock screen while locked, and on
+            // an exclusive layer surface while one is mapped
+            if !state.accepts_keyboard_focus(sid) {
+                return;
+            }
             crate::wlog!(crate::util::logging::FFI, "Keyboard enter: window={}, surface={}", 
                 window_id.id, sid);
             
@@ -1814,9 +2144,10 @@ impl WawonaCore {
                      state.seat.keyboard.focus = Some(sid);
                      state.seat.broadcast_keyboard_enter(serial, res, &pressed_keys);
 
-                     // Also send text-input-v3 enter so IME / emoji
-                     // commits reach this surface's text-input instance.
-                     state.ext.text_input.enter(res);
+                     // Text-input focus follows keyboard focus so IME /
+                     // emoji commits reach this surface's text input.
+                     drop(surface);
+                     state.update_text_input_focus();
                  } else {
                  crate::wlog!(crate::util::logging::FFI, "WARNING: Surface {} has no resource for keyboard enter", 
                      sid);
@@ -1849,7 +2180,8 @@ impl WawonaCore {
                  let surface = surface.read().unwrap();
                  if let Some(res) = &surface.resource {
                      // Send text-input-v3 leave before keyboard leave
-                     state.ext.text_input.leave(res);
+                     state.ext.text_input.set_focus(None);
+                     state.sync_input_method();
                      state.seat.broadcast_keyboard_leave(serial, res);
                  }
             }
@@ -1876,9 +2208,12 @@ impl WawonaCore {
         if let Some(window) = state.get_window(window_id.id as u32) {
             let window = window.read().unwrap();
             let surface_id = window.surface_id;
+            if !state.accepts_input(surface_id) {
+                return Ok(());
+            }
 
             // Track the touch point
-            state.seat.touch.touch_down(touch_id, surface_id, x, y);
+            state.seat.touch.touch_down(touch_id, surface_id, x, y, serial);
 
             // Broadcast to client
             if let Some(surface) = state.get_surface(surface_id) {
@@ -1982,6 +2317,54 @@ impl WawonaCore {
         state.seat.touch.touch_cancel();
     }
     
+    // =========================================================================
+    // Tablet Input
+    // =========================================================================
+
+    /// Inject one frame of tablet tool events for a window.
+    /// Coordinates are view-local, like pointer injection.
+    pub fn inject_tablet_tool(
+        &self,
+        window_id: WindowId,
+        events: Vec<TabletToolEvent>,
+        timestamp_ms: u32,
+    ) -> Result<()> {
+        if !self.is_running() {
+            return Err(CompositorError::NotStarted);
+        }
+        let mut state = self.state.write().unwrap();
+        let surface_id = state
+            .get_window(window_id.id as u32)
+            .map(|w| w.read().unwrap().surface_id)
+            .ok_or_else(|| CompositorError::platform_error(format!("unknown window {}", window_id.id)))?;
+        let (dx, dy) = apply_geometry_offset(&state, window_id, 0.0, 0.0);
+        let events: Vec<_> = events.iter().map(|e| tablet_tool_event_to_core(e, dx, dy)).collect();
+        state.inject_tablet_tool_on_surface(surface_id, &events, timestamp_ms);
+        Ok(())
+    }
+
+    /// Inject a tablet pad event; delivered to the keyboard-focused surface.
+    pub fn inject_tablet_pad(&self, event: TabletPadEvent, timestamp_ms: u32) {
+        if !self.is_running() {
+            return;
+        }
+        let mut state = self.state.write().unwrap();
+        state.inject_tablet_pad(&tablet_pad_event_to_core(&event), timestamp_ms);
+    }
+
+    /// Describe the platform's tablet pad (all zero = no pad).
+    /// Clients are sent the new pad, replacing any previous one.
+    pub fn set_tablet_pad(&self, buttons: u32, rings: u32, strips: u32) {
+        use crate::core::wayland::ext::tablet::TabletPadLayout;
+        let layout = if buttons == 0 && rings == 0 && strips == 0 {
+            None
+        } else {
+            Some(TabletPadLayout { buttons, rings, strips })
+        };
+        crate::wlog!(crate::util::logging::FFI, "Tablet pad layout: {:?}", layout);
+        self.state.write().unwrap().ext.tablet.set_pad_layout(layout);
+    }
+
     // =========================================================================
     // Text Input (IME / Emoji)
     // =========================================================================
@@ -2049,10 +2432,10 @@ impl WawonaCore {
         state.build_scene();
         
         let flattened_scene = state.scene.flatten();
-        let global_damage = state.scene_damage.global_damage.clone();
         
-        // Clear global damage after it's been consumed for rendering
-        state.scene_damage.clear();
+        // Diff against the previous frame; consumes client damage
+        state.update_scene_damage(&flattened_scene);
+        let global_damage = state.scene_damage.global_damage.clone().into_rects();
         
         // 2. Map internal FlattenedSurface to FFI RenderNode
         let mut ffi_nodes = Vec::new();
@@ -2072,6 +2455,13 @@ impl WawonaCore {
             // Fallback to buffer_id from surface current state if not in textures cache
             let texture_handle = if let Some(handle) = ffi_textures.get(&(surface.surface_id as u64)) {
                 *handle
+            } else if let Some((wid, _)) = DecorationPiece::from_surface_id(surface.surface_id) {
+                // Core-drawn decoration; pixels come from get_decoration_buffer
+                let generation = state.decorations.windows.get(&wid).map_or(0, |d| d.generation);
+                TextureHandle::new(
+                    decoration_buffer_id(surface.surface_id, generation),
+                    types::ClientId { id: 0 }
+                )
             } else if let Some(surf_ref) = state.get_surface(surface.surface_id) {
                 let surf = surf_ref.read().unwrap();
                 // ClientId doesn't easily map to an integer anymore.
@@ -2104,6 +2494,23 @@ impl WawonaCore {
             node.anchor_output_x = current_anchor.1;
             node.anchor_output_y = current_anchor.2;
             node.content_rect = surface.content_rect;
+            node.opaque = surface.opaque;
+            node.visible_region = surface.visible_region.rects().iter()
+                .map(|r| Rect::new(r.x, r.y, r.width, r.height))
+                .collect();
+            node.buffer_transform = surface.buffer.transform.into();
+            let (buf_w, buf_h) = surface.buffer.buffer_size;
+            if buf_w > 0 && buf_h > 0 {
+                let src = surface.buffer_source();
+                node.buffer_rect = ContentRect {
+                    x: (src.x / buf_w as f64) as f32,
+                    y: (src.y / buf_h as f64) as f32,
+                    w: (src.width / buf_w as f64) as f32,
+                    h: (src.height / buf_h as f64) as f32,
+                };
+            } else {
+                node.buffer_rect = node.content_rect;
+            }
             
             ffi_nodes.push(node);
         }
@@ -2115,15 +2522,34 @@ impl WawonaCore {
             scale,
             needs_redraw: true,
             damage: global_damage.into_iter().map(|r| Rect::new(r.x, r.y, r.width, r.height)).collect(),
+            lock_color: state.lock_fill_color(),
         }
     }
     
+    /// Damage to repaint on an output since the last `get_render_scene`,
+    /// in output-local coordinates, for a buffer last drawn `buffer_age`
+    /// frames ago (EGL/Metal buffer age; 0 = unknown contents, repaint all).
+    pub fn get_output_damage(&self, output_id: OutputId, buffer_age: u32) -> Vec<Rect> {
+        let state = self.state.read().unwrap();
+        state.scene_damage.output_damage(output_id.id, buffer_age)
+            .rects()
+            .iter()
+            .map(|r| Rect::new(r.x, r.y, r.width, r.height))
+            .collect()
+    }
+
+    /// Forget damage history so the next frame repaints everything, e.g.
+    /// after the renderer recreated its swapchain
+    pub fn reset_damage(&self) {
+        self.state.write().unwrap().scene_damage.reset();
+    }
+
     /// Notify the compositor that a frame has been presented to the user.
     /// 
     /// # Arguments
-    /// * `timestamp_ns` - The timestamp when the frame was actually displayed (nanoseconds)
-    /// * `seq` - The frame sequence number
-    pub fn commit_frame(&self, timestamp_ns: u64, seq: u64) {
+    /// * `timestamp_ns` - CLOCK_MONOTONIC time when the frame was actually displayed (nanoseconds)
+    /// * `_seq` - Unused; the MSC is derived from timestamps and the output refresh
+    pub fn commit_frame(&self, timestamp_ns: u64, _seq: u64) {
         if !self.is_running() {
             return;
         }
@@ -2134,8 +2560,15 @@ impl WawonaCore {
         state.flush_all_frame_callbacks();
         
         // 2. Send wp_presentation feedback events
-        let refresh_ns = 1_000_000_000 / 60; // TODO: Use actual refresh rate from output
-        state.ext.presentation.send_presented_events(timestamp_ns, refresh_ns, seq);
+        state.ext.presentation.platform_driven = true;
+        let refresh_ns = state.output_refresh_ns();
+        state.present_feedback(
+            None,
+            timestamp_ns,
+            refresh_ns,
+            wayland_protocols::wp::presentation_time::server::wp_presentation_feedback::Kind::Vsync,
+        );
+        state.lift_fifo_barriers();
         
         // 3. Flush buffer releases
         state.flush_buffer_releases();
@@ -2257,6 +2690,8 @@ impl WawonaCore {
             for tid in toplevel_ids {
                 state.send_toplevel_configure(tid.0.clone(), tid.1, width, height);
             }
+            #[cfg(feature = "desktop-protocols")]
+            state.session_lock_outputs_changed();
         }
     }
     
@@ -2285,8 +2720,127 @@ impl WawonaCore {
             let mut state = self.state.write().unwrap();
             state.keyboard_repeat_rate = rate;
             state.keyboard_repeat_delay = delay;
+            state.seat.keyboard.repeat_rate = rate;
+            state.seat.keyboard.repeat_delay = delay;
+            state.seat.keyboard.broadcast_repeat_info();
+        }
+    }
+
+    /// Generate key repeats in the compositor rather than in clients.
+    ///
+    /// Useful for frontends that deliver a single press per key (e.g. iOS
+    /// virtual keyboards). Clients are told the repeat rate is 0 while this
+    /// is enabled, and the compositor emits repeated wl_keyboard.key events
+    /// from `process_events`/`dispatch_events`.
+    pub fn set_compositor_key_repeat(&self, enabled: bool) {
+        crate::wlog!(crate::util::logging::FFI, "FFI: set_compositor_key_repeat({})", enabled);
+        let mut state = self.state.write().unwrap();
+        state.seat.keyboard.compositor_repeat = enabled;
+        if !enabled {
+            state.seat.keyboard.cancel_repeat();
         }
-        // TODO: Send wl_keyboard::repeat_info
+        state.seat.keyboard.broadcast_repeat_info();
+    }
+
+    /// Choose how frame callbacks of hidden surfaces are handled.
+    ///
+    /// By default occluded and off-screen surfaces get one callback per
+    /// second and minimized surfaces none at all, so hidden clients stop
+    /// rendering frames nobody sees.
+    pub fn set_frame_throttle(&self, config: FrameThrottleConfig) {
+        use crate::core::surface::frame_throttle::{self as throttle, HiddenFramePolicy as Policy};
+
+        crate::wlog!(crate::util::logging::FFI, "FFI: set_frame_throttle({:?})", config);
+        let policy = |p: HiddenFramePolicy| match p {
+            HiddenFramePolicy::FullRate => Policy::FullRate,
+            HiddenFramePolicy::Throttle { interval_ms } => Policy::Throttle {
+                interval: std::time::Duration::from_millis(interval_ms as u64),
+            },
+            HiddenFramePolicy::Hold => Policy::Hold,
+        };
+        self.state.write().unwrap().frame_throttle.config = throttle::FrameThrottleConfig {
+            occluded: policy(config.occluded),
+            minimized: policy(config.minimized),
+            inactive_workspace: policy(config.inactive_workspace),
+            off_screen: policy(config.off_screen),
+        };
+    }
+
+    /// Assign a window to a workspace (`None` = shown on all of them).
+    ///
+    /// Windows on inactive workspaces are treated as hidden for frame
+    /// callback throttling.
+    pub fn set_window_workspace(&self, window_id: WindowId, workspace: Option<u32>) -> Result<()> {
+        let state = self.state.read().unwrap();
+        let window = state.get_window(window_id.id as u32)
+            .ok_or_else(|| CompositorError::invalid_window_id(window_id.id))?;
+        window.write().unwrap().workspace = workspace;
+        Ok(())
+    }
+
+    /// Let pointer and touch input pass through a window to whatever is
+    /// below it (e.g. overlays and HUDs).
+    pub fn set_window_input_passthrough(&self, window_id: WindowId, passthrough: bool) -> Result<()> {
+        let state = self.state.read().unwrap();
+        let window = state.get_window(window_id.id as u32)
+            .ok_or_else(|| CompositorError::invalid_window_id(window_id.id))?;
+        window.write().unwrap().input_passthrough = passthrough;
+        Ok(())
+    }
+
+    /// Report the session as idle after `timeout_ms` without input, unless a
+    /// visible surface inhibits idleness. `IdleStateChanged` window events
+    /// tell the host when to dim or sleep and when to wake. 0 disables.
+    pub fn set_idle_timeout(&self, timeout_ms: u32) {
+        crate::wlog!(crate::util::logging::FFI, "FFI: set_idle_timeout({})", timeout_ms);
+        let timeout = (timeout_ms > 0).then(|| std::time::Duration::from_millis(timeout_ms as u64));
+        self.state.write().unwrap().ext.idle_notify.set_host_timeout(timeout);
+    }
+
+    /// Whether the host idle timeout has elapsed
+    pub fn is_idle(&self) -> bool {
+        self.state.read().unwrap().ext.idle_notify.host_idle()
+    }
+
+    /// Milliseconds until the compositor next needs `process_events` to run
+    /// (next frame, pending key repeat or idle timeout). Platforms without a
+    /// display-link driven loop can use this to schedule a timer.
+    pub fn time_until_next_wakeup_ms(&self) -> u32 {
+        let runtime = self.runtime.lock().unwrap();
+        let state = self.state.read().unwrap();
+        runtime.time_until_next_wakeup(&state).as_millis() as u32
+    }
+
+    /// Switch the keyboard layout at runtime from XKB rules/model/layout/variant/options.
+    ///
+    /// Every bound wl_keyboard receives the new keymap. On failure the
+    /// previous layout stays active and an error is returned.
+    pub fn set_keyboard_layout(
+        &self,
+        rules: String,
+        model: String,
+        layout: String,
+        variant: String,
+        options: Option<String>,
+    ) -> Result<()> {
+        crate::wlog!(crate::util::logging::FFI, "Keyboard layout: rules={:?} model={:?} layout={:?} variant={:?} options={:?}",
+            rules, model, layout, variant, options);
+        let mut state = self.state.write().unwrap();
+        state.set_keyboard_layout(&rules, &model, &layout, &variant, options)
+            .map_err(|e| CompositorError::platform_error(e.to_string()))
+    }
+
+    /// Switch to a keymap given in XKB text format (xkb_keymap { ... }).
+    pub fn set_keymap_string(&self, keymap: String) -> Result<()> {
+        crate::wlog!(crate::util::logging::FFI, "Keyboard keymap from string ({} bytes)", keymap.len());
+        let mut state = self.state.write().unwrap();
+        state.set_keymap_string(&keymap)
+            .map_err(|e| CompositorError::platform_error(e.to_string()))
+    }
+
+    /// Name of the active keyboard layout (e.g. "English (US)")
+    pub fn get_keyboard_layout(&self) -> String {
+        self.state.read().unwrap().seat.keyboard.layout_name.clone()
     }
     
     // =========================================================================
@@ -2366,7 +2920,7 @@ impl WawonaCore {
             return;
         }
         crate::wlog!(crate::util::logging::FFI, "Request window close: {}", window_id.id);
-        // TODO: Send xdg_toplevel::close
+        self.state.write().unwrap().close_window(window_id.id as u32);
     }
     
     /// Start interactive move
@@ -2610,16 +3164,13 @@ impl WawonaCore {
             return (String::new(), 0, 0);
         }
         let state = self.state.read().unwrap();
-        for (_id, instance) in &state.ext.text_input.instances {
-            if instance.enabled {
-                return (
-                    instance.surrounding_text.clone(),
-                    instance.surrounding_cursor,
-                    instance.surrounding_anchor,
-                );
-            }
-        }
-        (String::new(), 0, 0)
+        state.ext.text_input.active()
+            .map(|instance| (
+                instance.surrounding_text.clone(),
+                instance.surrounding_cursor,
+                instance.surrounding_anchor,
+            ))
+            .unwrap_or((String::new(), 0, 0))
     }
 
     /// Read the cursor rectangle reported by the focused Wayland client
@@ -2631,12 +3182,7 @@ impl WawonaCore {
             return (0, 0, 0, 0);
         }
         let state = self.state.read().unwrap();
-        for (_id, instance) in &state.ext.text_input.instances {
-            if instance.enabled {
-                return instance.cursor_rect;
-            }
-        }
-        (0, 0, 0, 0)
+        state.ext.text_input.active().map_or((0, 0, 0, 0), |instance| instance.cursor_rect)
     }
 
     /// Read the content type hint reported by the focused Wayland client
@@ -2647,15 +3193,8 @@ impl WawonaCore {
             return (0, 0);
         }
         let state = self.state.read().unwrap();
-        for (_id, instance) in &state.ext.text_input.instances {
-            if instance.enabled {
-                return (
-                    instance.content_type.hint,
-                    instance.content_type.purpose,
-                );
-            }
-        }
-        (0, 0)
+        state.ext.text_input.active()
+            .map_or((0, 0), |instance| (instance.content_type.hint, instance.content_type.purpose))
     }
 
     /// Get cursor rendering information for the C API.
@@ -2739,6 +3278,20 @@ impl WawonaCore {
         
         // We need to look up the buffer in state
         let state = self.state.read().unwrap();
+
+        if is_decoration_buffer(buffer_id) {
+            let surface_id = decoration_buffer_surface(buffer_id);
+            return match state.decoration_pixels(surface_id) {
+                Some(pixmap) => BufferRenderInfo {
+                    stride: pixmap.stride(),
+                    format: 0,
+                    iosurface_id: 0,
+                    width: pixmap.width,
+                    height: pixmap.height,
+                },
+                None => BufferRenderInfo { stride: 0, format: 0, iosurface_id: 0, width: 0, height: 0 },
+            };
+        }
         
         // Cast u64 to u32 for lookup (core uses u32 for buffer IDs)
         // Convert internal client ID back to backend ClientId
@@ -4,7 +4,7 @@ use std::os::raw::c_char;
 use std::ffi::{CStr, CString};
 use std::sync::Arc;
 use super::api::WawonaCore as WWNCore;
-use super::types::{WindowId, PointerButton, PointerAxis, AxisSource, ButtonState, KeyState, KeyboardModifiers};
+use super::types::{WindowId, PointerButton, PointerAxis, AxisSource, AxisEvent, TabletTool, TabletToolType, TabletToolEvent, TabletPadEvent, ButtonState, KeyState, KeyboardModifiers};
 
 
 /// Create a new WWNCore instance
@@ -165,6 +165,48 @@ pub extern "C" fn WWNCoreSetForceSSD(
     core.set_force_ssd(enabled);
 }
 
+/// Draw server-side decorations in the core
+#[no_mangle]
+pub extern "C" fn WWNCoreSetCoreDecorations(
+    core: *mut WWNCore,
+    enabled: bool
+) {
+    if core.is_null() {
+        return;
+    }
+    
+    let core = unsafe { &*core };
+    core.set_core_decorations(enabled);
+}
+
+/// Run interactive window move/resize in the core
+#[no_mangle]
+pub extern "C" fn WWNCoreSetCoreWindowGrabs(
+    core: *mut WWNCore,
+    enabled: bool
+) {
+    if core.is_null() {
+        return;
+    }
+    
+    let core = unsafe { &*core };
+    core.set_core_window_grabs(enabled);
+}
+
+/// Enable or disable clipboard persistence after the source client exits
+#[no_mangle]
+pub extern "C" fn WWNCoreSetClipboardPersistence(
+    core: *mut WWNCore,
+    enabled: bool
+) {
+    if core.is_null() {
+        return;
+    }
+    
+    let core = unsafe { &*core };
+    core.set_clipboard_persistence(enabled);
+}
+
 /// Inject window resize
 #[no_mangle]
 pub extern "C" fn WWNCoreInjectWindowResize(
@@ -203,6 +245,18 @@ pub extern "C" fn WWNCoreSetWindowActivatedSilent(
     core.set_window_activated(WindowId { id: window_id }, active, false);
 }
 
+/// Let pointer and touch input pass through a window to what is below it.
+#[no_mangle]
+pub extern "C" fn WWNCoreSetWindowInputPassthrough(
+    core: *mut WWNCore,
+    window_id: u64,
+    passthrough: bool
+) {
+    if core.is_null() { return; }
+    let core = unsafe { &*core };
+    let _ = core.set_window_input_passthrough(WindowId { id: window_id }, passthrough);
+}
+
 /// Flush all pending Wayland events to connected clients immediately.
 /// Call after generating events outside of the normal compositor tick
 /// to avoid them sitting in the buffer until the next tick fires.
@@ -558,6 +612,44 @@ pub extern "C" fn WWNCorePopPendingBuffer(core: *mut WWNCore) -> *mut CBufferDat
     std::ptr::null_mut()
 }
 
+/// Get the pixels of a core-drawn decoration node, by its surface id
+/// Returns NULL if the surface is not a decoration
+/// Caller must free with WWNBufferDataFree
+#[no_mangle]
+pub extern "C" fn WWNCoreGetDecorationBuffer(core: *mut WWNCore, surface_id: u32) -> *mut CBufferData {
+    if core.is_null() {
+        return std::ptr::null_mut();
+    }
+    
+    let core = unsafe { &*core };
+    
+    let Some(event) = core.get_decoration_buffer(surface_id) else {
+        return std::ptr::null_mut();
+    };
+    let super::types::BufferData::Shm { pixels, width, height, stride, format: _ } = event.buffer.data else {
+        return std::ptr::null_mut();
+    };
+    let mut pixels = pixels;
+    let size = pixels.len();
+    let capacity = pixels.capacity();
+    let ptr = pixels.as_mut_ptr();
+    std::mem::forget(pixels);
+    
+    Box::into_raw(Box::new(CBufferData {
+        window_id: event.window_id.id,
+        surface_id: event.surface_id.id,
+        buffer_id: event.buffer.id.id,
+        width,
+        height,
+        stride,
+        format: 0, // ARGB8888
+        pixels: ptr,
+        size,
+        capacity,
+        iosurface_id: 0,
+    }))
+}
+
 /// Free a CBufferData structure and its pixel data
 #[no_mangle]
 pub extern "C" fn WWNBufferDataFree(data: *mut CBufferData) {
@@ -593,6 +685,115 @@ pub extern "C" fn WWNCoreNotifyFramePresented(
     core.notify_frame_presented(sid, bid, timestamp);
 }
 
+/// Report a presented frame with exact timing
+/// timestamp_ns: CLOCK_MONOTONIC nanoseconds when the frame hit the screen
+/// refresh_mhz: output refresh in mHz (0 = use the output mode)
+/// flags: bit 0 = vsync, 1 = hw clock, 2 = hw completion, 3 = zero copy
+#[no_mangle]
+pub extern "C" fn WWNCoreFramePresentedAt(
+    core: *mut WWNCore,
+    timestamp_ns: u64,
+    refresh_mhz: u32,
+    flags: u32
+) {
+    if core.is_null() { return; }
+    let core = unsafe { &*core };
+    core.frame_presented_at(
+        timestamp_ns,
+        refresh_mhz,
+        super::types::PresentationFlags {
+            vsync: flags & 1 != 0,
+            hw_clock: flags & 2 != 0,
+            hw_completion: flags & 4 != 0,
+            zero_copy: flags & 8 != 0,
+        },
+    );
+}
+
+/// Configure frame callback throttling for hidden surfaces
+/// Each argument is an interval in ms for occluded, minimized, inactive
+/// workspace and off-screen surfaces: 0 = full rate, UINT32_MAX = hold
+#[no_mangle]
+pub extern "C" fn WWNCoreSetFrameThrottle(
+    core: *mut WWNCore,
+    occluded_ms: u32,
+    minimized_ms: u32,
+    inactive_workspace_ms: u32,
+    off_screen_ms: u32
+) {
+    use super::types::HiddenFramePolicy;
+
+    if core.is_null() { return; }
+    let core = unsafe { &*core };
+    let policy = |ms: u32| match ms {
+        0 => HiddenFramePolicy::FullRate,
+        u32::MAX => HiddenFramePolicy::Hold,
+        interval_ms => HiddenFramePolicy::Throttle { interval_ms },
+    };
+    core.set_frame_throttle(super::types::FrameThrottleConfig {
+        occluded: policy(occluded_ms),
+        minimized: policy(minimized_ms),
+        inactive_workspace: policy(inactive_workspace_ms),
+        off_screen: policy(off_screen_ms),
+    });
+}
+
+/// Report a vblank on one output (per-display display links)
+/// Same arguments as WWNCoreFramePresentedAt; only surfaces visible on the
+/// output receive presentation feedback and frame callbacks.
+#[no_mangle]
+pub extern "C" fn WWNCoreOutputFramePresented(
+    core: *mut WWNCore,
+    output_id: u32,
+    timestamp_ns: u64,
+    refresh_mhz: u32,
+    flags: u32
+) {
+    if core.is_null() { return; }
+    let core = unsafe { &*core };
+    core.output_frame_presented(
+        super::types::OutputId { id: output_id },
+        timestamp_ns,
+        refresh_mhz,
+        super::types::PresentationFlags {
+            vsync: flags & 1 != 0,
+            hw_clock: flags & 2 != 0,
+            hw_completion: flags & 4 != 0,
+            zero_copy: flags & 8 != 0,
+        },
+    );
+}
+
+/// Set an output's refresh rate and adaptive-sync minimum (both mHz, 0 = fixed rate)
+/// Returns false if the output is unknown
+#[no_mangle]
+pub extern "C" fn WWNCoreSetOutputRefresh(
+    core: *mut WWNCore,
+    output_id: u32,
+    refresh_mhz: u32,
+    min_refresh_mhz: u32
+) -> bool {
+    if core.is_null() { return false; }
+    let core = unsafe { &*core };
+    core.set_output_refresh(super::types::OutputId { id: output_id }, refresh_mhz, min_refresh_mhz).is_ok()
+}
+
+/// Milliseconds until rendering for an output's next frame should start
+#[no_mangle]
+pub extern "C" fn WWNCoreOutputTimeUntilNextFrame(core: *mut WWNCore, output_id: u32) -> u32 {
+    if core.is_null() { return 0; }
+    let core = unsafe { &*core };
+    core.output_time_until_next_frame_ms(super::types::OutputId { id: output_id })
+}
+
+/// Whether the output may present without waiting for vblank (tearing hint)
+#[no_mangle]
+pub extern "C" fn WWNCoreOutputAllowsTearing(core: *mut WWNCore, output_id: u32) -> bool {
+    if core.is_null() { return false; }
+    let core = unsafe { &*core };
+    core.output_allows_tearing(super::types::OutputId { id: output_id })
+}
+
 // ----------------------------------------------------------------------------
 // Input Injection API
 // ----------------------------------------------------------------------------
@@ -680,6 +881,46 @@ pub extern "C" fn WWNCoreInjectPointerAxis(
     );
 }
 
+/// Inject a full scroll frame
+/// source: 0 = wheel, 1 = finger, 2 = continuous, 3 = wheel tilt
+/// h_v120/v_v120: high-resolution wheel steps (120 = one detent)
+/// flags: bit 0 = horizontal stop, bit 1 = vertical stop, bit 2 = inverted (natural scrolling)
+#[no_mangle]
+pub extern "C" fn WWNCoreInjectPointerAxisEx(
+    core: *mut WWNCore,
+    window_id: u64,
+    source: u32,
+    horizontal: f64,
+    vertical: f64,
+    h_v120: i32,
+    v_v120: i32,
+    flags: u32,
+    timestamp_ms: u32
+) {
+    if core.is_null() { return; }
+    let core = unsafe { &*core };
+    let source = match source {
+        0 => AxisSource::Wheel,
+        2 => AxisSource::Continuous,
+        3 => AxisSource::WheelTilt,
+        _ => AxisSource::Finger,
+    };
+    core.inject_pointer_axis_event(
+        WindowId { id: window_id },
+        AxisEvent {
+            source,
+            horizontal,
+            vertical,
+            horizontal_v120: h_v120,
+            vertical_v120: v_v120,
+            horizontal_stop: flags & 1 != 0,
+            vertical_stop: flags & 2 != 0,
+            inverted: flags & 4 != 0,
+            time_ms: timestamp_ms,
+        },
+    );
+}
+
 /// Inject keyboard key event
 /// keycode: Linux key code
 /// state: 0 = Released, 1 = Pressed
@@ -754,6 +995,94 @@ pub extern "C" fn WWNCoreInjectKeyboardLeave(
     core.inject_keyboard_leave(WindowId { id: window_id });
 }
 
+/// Enable or disable compositor-generated key repeat
+#[no_mangle]
+pub extern "C" fn WWNCoreSetCompositorKeyRepeat(
+    core: *mut WWNCore,
+    enabled: bool
+) {
+    if core.is_null() { return; }
+    let core = unsafe { &*core };
+    core.set_compositor_key_repeat(enabled);
+}
+
+/// Report the session as idle after `timeout_ms` without input (0 disables)
+#[no_mangle]
+pub extern "C" fn WWNCoreSetIdleTimeout(
+    core: *mut WWNCore,
+    timeout_ms: u32
+) {
+    if core.is_null() { return; }
+    let core = unsafe { &*core };
+    core.set_idle_timeout(timeout_ms);
+}
+
+/// Whether the idle timeout set with WWNCoreSetIdleTimeout has elapsed
+#[no_mangle]
+pub extern "C" fn WWNCoreIsIdle(core: *const WWNCore) -> bool {
+    if core.is_null() { return false; }
+    let core = unsafe { &*core };
+    core.is_idle()
+}
+
+/// Read an optional C string; NULL and invalid UTF-8 map to None.
+fn opt_c_str(s: *const c_char) -> Option<String> {
+    if s.is_null() {
+        return None;
+    }
+    unsafe { CStr::from_ptr(s) }.to_str().ok().map(|s| s.to_string())
+}
+
+/// Switch the keyboard layout from XKB RMLVO names.
+///
+/// Any string may be NULL; empty names use the xkbcommon defaults.
+/// Returns false (keeping the previous layout) if the keymap fails to compile.
+#[no_mangle]
+pub extern "C" fn WWNCoreSetKeyboardLayout(
+    core: *mut WWNCore,
+    rules: *const c_char,
+    model: *const c_char,
+    layout: *const c_char,
+    variant: *const c_char,
+    options: *const c_char,
+) -> bool {
+    if core.is_null() { return false; }
+    let core = unsafe { &*core };
+    core.set_keyboard_layout(
+        opt_c_str(rules).unwrap_or_default(),
+        opt_c_str(model).unwrap_or_default(),
+        opt_c_str(layout).unwrap_or_default(),
+        opt_c_str(variant).unwrap_or_default(),
+        opt_c_str(options),
+    ).is_ok()
+}
+
+/// Switch to a keymap in XKB text format. Returns false on failure.
+#[no_mangle]
+pub extern "C" fn WWNCoreSetKeymapString(
+    core: *mut WWNCore,
+    keymap: *const c_char
+) -> bool {
+    if core.is_null() { return false; }
+    let core = unsafe { &*core };
+    match opt_c_str(keymap) {
+        Some(keymap) => core.set_keymap_string(keymap).is_ok(),
+        None => false,
+    }
+}
+
+/// Get the active keyboard layout name. Free with WWNStringFree.
+#[no_mangle]
+pub extern "C" fn WWNCoreGetKeyboardLayout(core: *const WWNCore) -> *mut c_char {
+    if core.is_null() {
+        return std::ptr::null_mut();
+    }
+    let core = unsafe { &*core };
+    CString::new(core.get_keyboard_layout()).ok()
+        .map(|s| s.into_raw())
+        .unwrap_or(std::ptr::null_mut())
+}
+
 // ============================================================================
 // Text Input (IME / Emoji)
 // ============================================================================
@@ -832,6 +1161,33 @@ pub extern "C" fn WWNCoreTextInputGetSurrounding(
     copy_len as u32
 }
 
+/// Get the damage to repaint on an output for a buffer of the given age
+/// (0 = unknown contents). Writes up to `max_rects` rects as x, y, width,
+/// height quadruples into `out_rects` (output-local coordinates).
+/// Returns the number of rects written.
+#[no_mangle]
+pub extern "C" fn WWNCoreGetOutputDamage(
+    core: *mut WWNCore,
+    output_id: u32,
+    buffer_age: u32,
+    out_rects: *mut i32,
+    max_rects: u32,
+) -> u32 {
+    if core.is_null() || out_rects.is_null() { return 0; }
+    let core = unsafe { &*core };
+    let rects = core.get_output_damage(super::types::OutputId { id: output_id }, buffer_age);
+    let count = std::cmp::min(rects.len(), max_rects as usize);
+    for (i, r) in rects.iter().take(count).enumerate() {
+        unsafe {
+            *out_rects.add(i * 4) = r.x;
+            *out_rects.add(i * 4 + 1) = r.y;
+            *out_rects.add(i * 4 + 2) = r.width as i32;
+            *out_rects.add(i * 4 + 3) = r.height as i32;
+        }
+    }
+    count as u32
+}
+
 /// Get content type (hint, purpose) reported by the Wayland client.
 #[no_mangle]
 pub extern "C" fn WWNCoreTextInputGetContentType(
@@ -965,6 +1321,161 @@ pub extern "C" fn WWNCoreInject_touch_frame(
     core.inject_input_event(event);
 }
 
+// ----------------------------------------------------------------------------
+// Tablet API
+// ----------------------------------------------------------------------------
+
+/// Bring a tablet tool into (or out of) proximity of a window
+/// tool_type: 0 = pen, 1 = eraser, 2 = brush, 3 = pencil, 4 = airbrush,
+///            5 = finger, 6 = mouse, 7 = lens
+/// capabilities: bit 0 = pressure, 1 = tilt, 2 = distance, 3 = rotation,
+///               4 = slider, 5 = wheel
+#[no_mangle]
+pub extern "C" fn WWNCoreInjectTabletProximity(
+    core: *mut WWNCore,
+    window_id: u64,
+    in_proximity: bool,
+    tool_type: u32,
+    serial: u64,
+    capabilities: u32,
+    x: f64,
+    y: f64,
+    timestamp_ms: u32
+) {
+    if core.is_null() { return; }
+    let core = unsafe { &*core };
+    let event = if in_proximity {
+        let tool_type = match tool_type {
+            1 => TabletToolType::Eraser,
+            2 => TabletToolType::Brush,
+            3 => TabletToolType::Pencil,
+            4 => TabletToolType::Airbrush,
+            5 => TabletToolType::Finger,
+            6 => TabletToolType::Mouse,
+            7 => TabletToolType::Lens,
+            _ => TabletToolType::Pen,
+        };
+        TabletToolEvent::ProximityIn {
+            tool: TabletTool {
+                tool_type,
+                serial,
+                has_pressure: capabilities & 1 != 0,
+                has_tilt: capabilities & 2 != 0,
+                has_distance: capabilities & 4 != 0,
+                has_rotation: capabilities & 8 != 0,
+                has_slider: capabilities & 16 != 0,
+                has_wheel: capabilities & 32 != 0,
+            },
+            x,
+            y,
+        }
+    } else {
+        TabletToolEvent::ProximityOut
+    };
+    let _ = core.inject_tablet_tool(WindowId { id: window_id }, vec![event], timestamp_ms);
+}
+
+/// Inject one stylus sample (position, tip state and axes) as a single frame
+/// pressure/distance: 0.0..1.0, tilt: degrees, rotation: degrees clockwise
+/// Axes the tool did not declare at proximity-in are not sent to clients
+#[no_mangle]
+pub extern "C" fn WWNCoreInjectTabletToolSample(
+    core: *mut WWNCore,
+    window_id: u64,
+    x: f64,
+    y: f64,
+    tip_down: bool,
+    pressure: f64,
+    distance: f64,
+    tilt_x: f64,
+    tilt_y: f64,
+    rotation: f64,
+    timestamp_ms: u32
+) {
+    if core.is_null() { return; }
+    let core = unsafe { &*core };
+    let events = vec![
+        TabletToolEvent::Motion { x, y },
+        TabletToolEvent::Pressure { pressure },
+        TabletToolEvent::Distance { distance },
+        TabletToolEvent::Tilt { x: tilt_x, y: tilt_y },
+        TabletToolEvent::Rotation { degrees: rotation },
+        if tip_down { TabletToolEvent::Down } else { TabletToolEvent::Up },
+    ];
+    let _ = core.inject_tablet_tool(WindowId { id: window_id }, events, timestamp_ms);
+}
+
+/// Inject a stylus barrel button (Linux BTN_STYLUS etc.)
+#[no_mangle]
+pub extern "C" fn WWNCoreInjectTabletToolButton(
+    core: *mut WWNCore,
+    window_id: u64,
+    button: u32,
+    pressed: bool,
+    timestamp_ms: u32
+) {
+    if core.is_null() { return; }
+    let core = unsafe { &*core };
+    let state = if pressed { ButtonState::Pressed } else { ButtonState::Released };
+    let event = TabletToolEvent::Button { button, state };
+    let _ = core.inject_tablet_tool(WindowId { id: window_id }, vec![event], timestamp_ms);
+}
+
+/// Describe the tablet pad (all zero removes it)
+#[no_mangle]
+pub extern "C" fn WWNCoreSetTabletPad(
+    core: *mut WWNCore,
+    buttons: u32,
+    rings: u32,
+    strips: u32
+) {
+    if core.is_null() { return; }
+    let core = unsafe { &*core };
+    core.set_tablet_pad(buttons, rings, strips);
+}
+
+/// Inject a tablet pad button
+#[no_mangle]
+pub extern "C" fn WWNCoreInjectTabletPadButton(
+    core: *mut WWNCore,
+    button: u32,
+    pressed: bool,
+    timestamp_ms: u32
+) {
+    if core.is_null() { return; }
+    let core = unsafe { &*core };
+    let state = if pressed { ButtonState::Pressed } else { ButtonState::Released };
+    core.inject_tablet_pad(TabletPadEvent::Button { button, state }, timestamp_ms);
+}
+
+/// Inject a tablet pad ring angle in degrees (negative = finger lifted)
+#[no_mangle]
+pub extern "C" fn WWNCoreInjectTabletPadRing(
+    core: *mut WWNCore,
+    ring: u32,
+    angle: f64,
+    timestamp_ms: u32
+) {
+    if core.is_null() { return; }
+    let core = unsafe { &*core };
+    let angle = if angle < 0.0 { None } else { Some(angle) };
+    core.inject_tablet_pad(TabletPadEvent::Ring { ring, angle }, timestamp_ms);
+}
+
+/// Inject a tablet pad strip position 0.0..1.0 (negative = finger lifted)
+#[no_mangle]
+pub extern "C" fn WWNCoreInjectTabletPadStrip(
+    core: *mut WWNCore,
+    strip: u32,
+    position: f64,
+    timestamp_ms: u32
+) {
+    if core.is_null() { return; }
+    let core = unsafe { &*core };
+    let position = if position < 0.0 { None } else { Some(position) };
+    core.inject_tablet_pad(TabletPadEvent::Strip { strip, position }, timestamp_ms);
+}
+
 // ----------------------------------------------------------------------------
 // Scene Graph API
 // ----------------------------------------------------------------------------
@@ -997,6 +1508,13 @@ pub struct CRenderNode {
     pub content_rect_y: f32,
     pub content_rect_w: f32,
     pub content_rect_h: f32,
+    /// wl_output.transform value of the buffer (0 = normal)
+    pub buffer_transform: u32,
+    /// Normalized area of the untransformed buffer to sample. Default: (0,0,1,1).
+    pub buffer_rect_x: f32,
+    pub buffer_rect_y: f32,
+    pub buffer_rect_w: f32,
+    pub buffer_rect_h: f32,
 }
 
 /// C-compatible RenderScene structure
@@ -1017,6 +1535,10 @@ pub struct CRenderScene {
     pub cursor_stride: u32,
     pub cursor_format: u32,
     pub cursor_iosurface_id: u32,
+    /// Session locked: fill the outputs with `lock_color` (ARGB8888)
+    /// before drawing the nodes
+    pub has_lock_color: bool,
+    pub lock_color: u32,
 }
 
 /// Get the current render scene
@@ -1029,6 +1551,8 @@ pub extern "C" fn WWNCoreGetRenderScene(core: *mut WWNCore) -> *mut CRenderScene
     // 1. Get the abstract scene (ffi::types::RenderScene)
     let scene = core.get_render_scene();
     
+    let lock_color = scene.lock_color;
+
     // Convert Vec<RenderNode> to Vec<CRenderNode>
     let mut c_nodes = Vec::with_capacity(scene.nodes.len());
     
@@ -1051,7 +1575,7 @@ pub extern "C" fn WWNCoreGetRenderScene(core: *mut WWNCore) -> *mut CRenderScene
             scale: node.scale,
             opacity: node.opacity,
             corner_radius: 0.0,
-            is_opaque: false, 
+            is_opaque: node.opaque,
             buffer_width: width,
             buffer_height: height,
             buffer_stride: stride,
@@ -1063,6 +1587,11 @@ pub extern "C" fn WWNCoreGetRenderScene(core: *mut WWNCore) -> *mut CRenderScene
             content_rect_y: node.content_rect.y,
             content_rect_w: node.content_rect.w,
             content_rect_h: node.content_rect.h,
+            buffer_transform: node.buffer_transform as u32,
+            buffer_rect_x: node.buffer_rect.x,
+            buffer_rect_y: node.buffer_rect.y,
+            buffer_rect_w: node.buffer_rect.w,
+            buffer_rect_h: node.buffer_rect.h,
         });
     }
     
@@ -1084,6 +1613,8 @@ pub extern "C" fn WWNCoreGetRenderScene(core: *mut WWNCore) -> *mut CRenderScene
         cursor_stride: cursor_info.stride,
         cursor_format: cursor_info.format,
         cursor_iosurface_id: cursor_info.iosurface_id,
+        has_lock_color: lock_color.is_some(),
+        lock_color: lock_color.unwrap_or(0),
     });
     std::mem::forget(c_nodes);
     
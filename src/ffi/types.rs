@@ -335,6 +335,22 @@ impl Default for OutputTransform {
     }
 }
 
+impl From<wayland_server::protocol::wl_output::Transform> for OutputTransform {
+    fn from(transform: wayland_server::protocol::wl_output::Transform) -> Self {
+        use wayland_server::protocol::wl_output::Transform;
+        match transform {
+            Transform::_90 => Self::Rotate90,
+            Transform::_180 => Self::Rotate180,
+            Transform::_270 => Self::Rotate270,
+            Transform::Flipped => Self::Flipped,
+            Transform::Flipped90 => Self::FlippedRotate90,
+            Transform::Flipped180 => Self::FlippedRotate180,
+            Transform::Flipped270 => Self::FlippedRotate270,
+            _ => Self::Normal,
+        }
+    }
+}
+
 /// Output subpixel layout
 #[derive(Debug, Clone, Copy, PartialEq, Eq, uniffi::Enum)]
 pub enum OutputSubpixel {
@@ -618,6 +634,12 @@ pub enum WindowEvent {
 
     // System bell / notification
     SystemBell { surface_id: u32 },
+
+    // Keyboard layout switch result (layout is the one now active)
+    KeyboardLayoutChanged { layout: String, success: bool },
+
+    // Host idle timeout elapsed (idle) or input resumed
+    IdleStateChanged { idle: bool },
 }
 
 // ============================================================================
@@ -823,6 +845,43 @@ pub struct CursorRenderInfo {
     pub iosurface_id: u32,
 }
 
+/// What the platform knows about how a frame reached the screen
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, uniffi::Record)]
+pub struct PresentationFlags {
+    /// Presentation was synchronized to the vertical retrace
+    pub vsync: bool,
+    /// Timestamp comes from the display hardware, not a software read
+    pub hw_clock: bool,
+    /// The display signalled completion of the update
+    pub hw_completion: bool,
+    /// The client buffer was scanned out directly, without compositing
+    pub zero_copy: bool,
+}
+
+/// What to do with frame callbacks of a surface nobody can see
+#[derive(Debug, Clone, Copy, PartialEq, Eq, uniffi::Enum)]
+pub enum HiddenFramePolicy {
+    /// Fire them as if the surface were visible
+    FullRate,
+    /// Fire them at most once every `interval_ms`
+    Throttle { interval_ms: u32 },
+    /// Hold them until the surface is visible again
+    Hold,
+}
+
+/// Frame callback policy for each way a surface can be hidden
+#[derive(Debug, Clone, Copy, PartialEq, Eq, uniffi::Record)]
+pub struct FrameThrottleConfig {
+    /// Covered by opaque surfaces above it
+    pub occluded: HiddenFramePolicy,
+    /// Part of a minimized window
+    pub minimized: HiddenFramePolicy,
+    /// Part of a window on a workspace that is not shown
+    pub inactive_workspace: HiddenFramePolicy,
+    /// Outside every output
+    pub off_screen: HiddenFramePolicy,
+}
+
 /// Pending screencopy — platform writes ARGB8888 pixels to ptr, then calls screencopy_done
 #[derive(Debug, Clone, uniffi::Record)]
 pub struct ScreencopyRequest {
@@ -860,6 +919,20 @@ pub enum InputEvent {
         vertical: f64,
         time_ms: u32
     },
+    /// Pointer axis (scroll) with source, discrete steps and stop information
+    PointerAxisFrame {
+        event: AxisEvent,
+    },
+    /// One frame of tablet tool changes (global coordinates)
+    TabletTool {
+        events: Vec<TabletToolEvent>,
+        time_ms: u32,
+    },
+    /// Tablet pad button, ring or strip
+    TabletPad {
+        event: TabletPadEvent,
+        time_ms: u32,
+    },
     /// Keyboard key press/release
     KeyboardKey {
         keycode: u32,
@@ -956,6 +1029,84 @@ impl Default for AxisSource {
     }
 }
 
+/// A full scroll event for both axes, delivered as one wl_pointer frame
+#[derive(Debug, Clone, Copy, PartialEq, Default, uniffi::Record)]
+pub struct AxisEvent {
+    pub source: AxisSource,
+    /// Continuous scroll distance in surface-local units
+    pub horizontal: f64,
+    pub vertical: f64,
+    /// High-resolution wheel steps (120 = one detent); 0 for non-wheel sources
+    pub horizontal_v120: i32,
+    pub vertical_v120: i32,
+    /// Scrolling on this axis has stopped (e.g. fingers lifted)
+    pub horizontal_stop: bool,
+    pub vertical_stop: bool,
+    /// Natural scrolling: content moves opposite to the physical motion
+    pub inverted: bool,
+    pub time_ms: u32,
+}
+
+/// Physical kind of a tablet tool
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, uniffi::Enum)]
+pub enum TabletToolType {
+    #[default]
+    Pen,
+    Eraser,
+    Brush,
+    Pencil,
+    Airbrush,
+    Finger,
+    Mouse,
+    Lens,
+}
+
+/// Tablet tool description, sent with proximity-in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, uniffi::Record)]
+pub struct TabletTool {
+    pub tool_type: TabletToolType,
+    /// Hardware serial (0 if unknown)
+    pub serial: u64,
+    pub has_pressure: bool,
+    pub has_tilt: bool,
+    pub has_distance: bool,
+    pub has_rotation: bool,
+    pub has_slider: bool,
+    pub has_wheel: bool,
+}
+
+/// A single tablet tool change; a batch of these is delivered as one frame
+#[derive(Debug, Clone, PartialEq, uniffi::Enum)]
+pub enum TabletToolEvent {
+    ProximityIn { tool: TabletTool, x: f64, y: f64 },
+    ProximityOut,
+    Motion { x: f64, y: f64 },
+    Down,
+    Up,
+    /// Normalized 0.0..=1.0
+    Pressure { pressure: f64 },
+    /// Normalized 0.0..=1.0
+    Distance { distance: f64 },
+    /// Degrees from the surface normal
+    Tilt { x: f64, y: f64 },
+    /// Degrees clockwise
+    Rotation { degrees: f64 },
+    /// -1.0..=1.0
+    Slider { position: f64 },
+    Wheel { degrees: f64, clicks: i32 },
+    Button { button: u32, state: ButtonState },
+}
+
+/// Tablet pad input
+#[derive(Debug, Clone, Copy, PartialEq, uniffi::Enum)]
+pub enum TabletPadEvent {
+    Button { button: u32, state: ButtonState },
+    /// Ring angle in degrees; None when the finger is lifted
+    Ring { ring: u32, angle: Option<f64> },
+    /// Strip position 0.0..=1.0; None when the finger is lifted
+    Strip { strip: u32, position: Option<f64> },
+}
+
 // Re-using KeyState for ButtonState logic to align with InputEvent
 pub type ButtonState = KeyState;
 
@@ -1170,6 +1321,17 @@ pub struct RenderNode {
     /// Normalized content rect within the buffer (0..1 range).
     /// Default [0,0,1,1] = full buffer. Non-default when CSD geometry crops content.
     pub content_rect: ContentRect,
+    /// The surface is fully opaque; it can be drawn without blending
+    pub opaque: bool,
+    /// Parts of the node not hidden by opaque nodes above it (output
+    /// coordinates); drawing can be clipped to these
+    pub visible_region: Vec<Rect>,
+    /// Transform the client applied to its buffer (wl_surface.set_buffer_transform);
+    /// the renderer applies the inverse when sampling
+    pub buffer_transform: OutputTransform,
+    /// Normalized area of the untransformed buffer drawn into the node,
+    /// combining the viewport source crop and the geometry crop
+    pub buffer_rect: ContentRect,
 }
 
 impl RenderNode {
@@ -1194,6 +1356,10 @@ impl RenderNode {
             anchor_output_x: 0,
             anchor_output_y: 0,
             content_rect: ContentRect::default(),
+            opaque: false,
+            visible_region: vec![],
+            buffer_transform: OutputTransform::Normal,
+            buffer_rect: ContentRect::default(),
         }
     }
 }
@@ -1207,6 +1373,10 @@ pub struct RenderScene {
     pub scale: f32,
     pub needs_redraw: bool,
     pub damage: Vec<Rect>,
+    /// ARGB8888 colour to fill the outputs with beneath the nodes; set
+    /// while the session is locked so nothing behind the lock screen shows,
+    /// even when the locker died
+    pub lock_color: Option<u32>,
 }
 
 impl RenderScene {
@@ -1218,6 +1388,7 @@ impl RenderScene {
             scale: 1.0,
             needs_redraw: false,
             damage: vec![],
+            lock_color: None,
         }
     }
     
@@ -1229,6 +1400,7 @@ impl RenderScene {
             scale,
             needs_redraw: false,
             damage: vec![],
+            lock_color: None,
         }
     }
 }
@@ -65,6 +65,7 @@ extern void WWNCoreSetOutputSize(void *core, uint32_t width, uint32_t height,
 extern void WWNCoreSetSafeAreaInsets(void *core, int32_t top, int32_t right,
                                      int32_t bottom, int32_t left);
 extern void WWNCoreSetForceSSD(void *core, int enabled);
+extern void WWNCoreSetCoreDecorations(void *core, int enabled);
 extern void WWNCoreFree(void *core);
 
 typedef struct {
@@ -77,6 +78,8 @@ typedef struct {
   uint64_t cursor_buffer_id;
   uint32_t cursor_width, cursor_height, cursor_stride, cursor_format;
   uint32_t cursor_iosurface_id;
+  uint8_t has_lock_color; /* Rust bool */
+  uint32_t lock_color;    /* ARGB8888 */
 } CRenderScene;
 
 extern CRenderScene *WWNCoreGetRenderScene(void *core);
@@ -94,6 +97,7 @@ typedef struct {
 } CBufferData;
 
 extern CBufferData *WWNCorePopPendingBuffer(void *core);
+extern CBufferData *WWNCoreGetDecorationBuffer(void *core, uint32_t surface_id);
 extern void WWNBufferDataFree(CBufferData *data);
 extern void WWNCoreNotifyFramePresented(void *core, uint32_t surface_id,
                                         uint64_t buffer_id, uint32_t timestamp);
@@ -993,6 +997,39 @@ typedef struct {
 
 /** Frame callback invoked at display vsync by AChoreographer (NDK API:
  * frameTimeNanos first, then data) */
+/* Core-drawn decorations: buffer ids carry bit 63, a generation in bits
+ * 32-62 and the decoration surface id in the low bits. Pixels are cached
+ * under the id without its generation so a repaint reuses the texture slot;
+ * the last uploaded id per surface tells when to fetch them again. */
+#define WWN_DECORATION_BUFFER_BIT (1ULL << 63)
+#define WWN_DECORATION_CACHE_SIZE 64
+static uint64_t g_decoration_uploaded[WWN_DECORATION_CACHE_SIZE];
+
+static void upload_decoration_buffers(VkCommandBuffer cmdBuf,
+                                      CRenderScene *scene) {
+  for (size_t i = 0; i < scene->count; i++) {
+    CRenderNode *node = &scene->nodes[i];
+    if (!(node->buffer_id & WWN_DECORATION_BUFFER_BIT))
+      continue;
+    uint64_t *uploaded =
+        &g_decoration_uploaded[node->surface_id % WWN_DECORATION_CACHE_SIZE];
+    if (*uploaded != node->buffer_id) {
+      CBufferData *buf = WWNCoreGetDecorationBuffer(g_core, node->surface_id);
+      if (buf) {
+        if (buf->pixels && buf->width > 0 && buf->height > 0) {
+          renderer_android_cache_buffer(
+              cmdBuf, WWN_DECORATION_BUFFER_BIT | node->surface_id,
+              buf->width, buf->height, buf->stride, buf->format, buf->pixels,
+              buf->size);
+          *uploaded = node->buffer_id;
+        }
+        WWNBufferDataFree(buf);
+      }
+    }
+    node->buffer_id = WWN_DECORATION_BUFFER_BIT | node->surface_id;
+  }
+}
+
 static void choreographer_frame_cb(int64_t frameTimeNanos, void *data) {
   (void)frameTimeNanos;
   RenderFrameCtx *ctx = (RenderFrameCtx *)data;
@@ -1051,6 +1088,16 @@ static void choreographer_frame_cb(int64_t frameTimeNanos, void *data) {
     }
   }
 
+  /* Fetch the scene before the render pass so decoration pixels can be
+   * uploaded with the client buffers; keep it alive for post-present
+   * notifications so buffer releases happen after the frame is submitted. */
+  CRenderScene *scene = NULL;
+  if (g_core) {
+    scene = WWNCoreGetRenderScene(g_core);
+    if (scene)
+      upload_decoration_buffers(ctx->cmdBuf, scene);
+  }
+
   /* Match CompositorBackground (0x0F1018) to reduce flashing when presenting
    * empty frames or during waypipe client connect. */
   VkClearValue clearValue = {{{15.f / 255.f, 16.f / 255.f, 24.f / 255.f, 1.0f}}};
@@ -1074,12 +1121,22 @@ static void choreographer_frame_cb(int64_t frameTimeNanos, void *data) {
   VkRect2D scissor = {{0, 0}, ctx->extent};
   vkCmdSetScissor(ctx->cmdBuf, 0, 1, &scissor);
 
-  /* Draw scene nodes as textured quads; keep scene alive for post-present
-   * notifications so buffer releases happen after the frame is submitted. */
-  CRenderScene *scene = NULL;
+  /* Draw scene nodes as textured quads */
   if (g_core) {
-    scene = WWNCoreGetRenderScene(g_core);
     if (scene) {
+      if (scene->has_lock_color) {
+        /* Session locked: nothing behind the lock surfaces may show */
+        uint32_t c = scene->lock_color;
+        VkClearAttachment fill = {
+            .aspectMask = VK_IMAGE_ASPECT_COLOR_BIT,
+            .colorAttachment = 0,
+            .clearValue = {{{((c >> 16) & 0xff) / 255.f,
+                             ((c >> 8) & 0xff) / 255.f, (c & 0xff) / 255.f,
+                             ((c >> 24) & 0xff) / 255.f}}}};
+        VkClearRect rect = {
+            .rect = {{0, 0}, ctx->extent}, .baseArrayLayer = 0, .layerCount = 1};
+        vkCmdClearAttachments(ctx->cmdBuf, 1, &fill, 1, &rect);
+      }
       if (scene->count > 0) {
         uint64_t new_wid = scene->nodes[0].window_id;
         if (new_wid != g_pointer_window_id) {
@@ -1851,9 +1908,11 @@ Java_com_aspauldingcode_wawona_WawonaNative_nativeApplySettings(
   config.openglDriver[sizeof(config.openglDriver) - 1] = '\0';
   WWNSettings_UpdateConfig(&config);
 
-  /* Push to Rust backend */
+  /* Push to Rust backend. No window manager draws frames here, so
+   * server-side decorations come from the core. */
   if (g_core) {
     WWNCoreSetForceSSD(g_core, forceServerSideDecorations ? 1 : 0);
+    WWNCoreSetCoreDecorations(g_core, forceServerSideDecorations ? 1 : 0);
     WWNCoreSetSafeAreaInsets(g_core, g_safeAreaTop, g_safeAreaRight,
                              g_safeAreaBottom, g_safeAreaLeft);
   }
@@ -120,6 +120,11 @@ typedef struct CRenderNode {
   float content_rect_y;
   float content_rect_w;
   float content_rect_h;
+  uint32_t buffer_transform;
+  float buffer_rect_x;
+  float buffer_rect_y;
+  float buffer_rect_w;
+  float buffer_rect_h;
 } CRenderNode;
 
 typedef struct CRenderScene {
@@ -138,6 +143,8 @@ typedef struct CRenderScene {
   uint32_t cursor_stride;
   uint32_t cursor_format;
   uint32_t cursor_iosurface_id;
+  bool has_lock_color;
+  uint32_t lock_color;
 } CRenderScene;
 
 extern CRenderScene *WWNCoreGetRenderScene(void *core);
@@ -231,6 +238,35 @@ static void handle_cursor_shape_update(uint32_t shape) {
 }
 #endif
 
+/// Whether a wl_output.transform value swaps the buffer's width and height
+static BOOL WWNBufferTransformSwapsAxes(uint32_t transform) {
+  return (transform & 1) != 0;
+}
+
+/// The layer transform that shows a buffer drawn with `transform` upright:
+/// the inverse of the client's wl_surface.set_buffer_transform, in the
+/// flipped (y-down) geometry of the content layer.
+static CGAffineTransform WWNBufferTransformMatrix(uint32_t transform) {
+  switch (transform) {
+  case 1: /* 90 */
+    return CGAffineTransformMake(0, 1, -1, 0, 0, 0);
+  case 2: /* 180 */
+    return CGAffineTransformMake(-1, 0, 0, -1, 0, 0);
+  case 3: /* 270 */
+    return CGAffineTransformMake(0, -1, 1, 0, 0, 0);
+  case 4: /* flipped */
+    return CGAffineTransformMake(-1, 0, 0, 1, 0, 0);
+  case 5: /* flipped 90 */
+    return CGAffineTransformMake(0, 1, 1, 0, 0, 0);
+  case 6: /* flipped 180 */
+    return CGAffineTransformMake(1, 0, 0, -1, 0, 0);
+  case 7: /* flipped 270 */
+    return CGAffineTransformMake(0, -1, -1, 0, 0, 0);
+  default:
+    return CGAffineTransformIdentity;
+  }
+}
+
 @implementation WWNCompositorBridge {
   void *_rustCore;
   NSTimer *_eventTimer;
@@ -681,6 +717,7 @@ static void handle_cursor_shape_update(uint32_t shape) {
         for (size_t i = 0; i < scene->count; i++) {
           [self updateLayerForNode:&scene->nodes[i]];
         }
+        [self _applyLockColorFromScene:scene];
 
 #if TARGET_OS_IPHONE || TARGET_OS_SIMULATOR
         // Forward cursor rendering info to all iOS window views
@@ -821,10 +858,42 @@ static void handle_cursor_shape_update(uint32_t shape) {
       [self updateLayerForNode:&scene->nodes[i]];
     }
   }
+  [self _applyLockColorFromScene:scene];
 
   WWNRenderSceneFree(scene);
 }
 
+/// While the session is locked the scene holds only lock surfaces. Hide
+/// every other surface layer and fill the views with the lock colour, so
+/// nothing behind the lock shows where no lock surface covers an output.
+- (void)_applyLockColorFromScene:(CRenderScene *)scene {
+  CGColorRef fill = NULL;
+  NSMutableSet<NSNumber *> *inScene =
+      [NSMutableSet setWithCapacity:scene->count];
+  if (scene->has_lock_color) {
+    uint32_t c = scene->lock_color;
+    fill = CGColorCreateSRGB(((c >> 16) & 0xff) / 255.0,
+                             ((c >> 8) & 0xff) / 255.0, (c & 0xff) / 255.0,
+                             ((c >> 24) & 0xff) / 255.0);
+    for (size_t i = 0; i < scene->count; i++) {
+      [inScene addObject:@(scene->nodes[i].surface_id)];
+    }
+  }
+
+  [CATransaction begin];
+  [CATransaction setDisableActions:YES];
+  [_surfaceLayers enumerateKeysAndObjectsUsingBlock:^(
+                      NSNumber *surfId, CALayer *layer, BOOL *stop) {
+    layer.hidden = fill != NULL && ![inScene containsObject:surfId];
+    layer.superlayer.backgroundColor = fill;
+  }];
+  [CATransaction commit];
+
+  if (fill) {
+    CGColorRelease(fill);
+  }
+}
+
 - (void)updateLayerForNode:(CRenderNode *)node {
   NSNumber *winId = @(node->window_id);
   NSNumber *surfId = @(node->surface_id);
@@ -892,17 +961,24 @@ static void handle_cursor_shape_update(uint32_t shape) {
   float localY = node->y - node->anchor_output_y;
   layer.position =
       CGPointMake(localX + node->width / 2, localY + node->height / 2);
-  layer.bounds = CGRectMake(0, 0, node->width, node->height);
+  // A rotated buffer is laid out in its own orientation and turned into
+  // place by the layer transform, around the layer's centre.
+  if (WWNBufferTransformSwapsAxes(node->buffer_transform)) {
+    layer.bounds = CGRectMake(0, 0, node->height, node->width);
+  } else {
+    layer.bounds = CGRectMake(0, 0, node->width, node->height);
+  }
+  layer.affineTransform = WWNBufferTransformMatrix(node->buffer_transform);
   layer.opacity = node->opacity;
   layer.cornerRadius = node->corner_radius;
 
-  // 2b. Crop buffer to content area when CSD geometry is set.
-  // The client's buffer may include shadow/frame around the content;
-  // xdg_surface.set_window_geometry defines the content rect.
-  // content_rect is pre-normalized (0..1) on the Rust side.
-  if (node->content_rect_w > 0.0f && node->content_rect_h > 0.0f) {
-    layer.contentsRect = CGRectMake(node->content_rect_x, node->content_rect_y,
-                                    node->content_rect_w, node->content_rect_h);
+  // 2b. Sample only the part of the buffer shown: the viewport source
+  // crop and the CSD window geometry (the client's buffer may include
+  // shadow/frame around the content). buffer_rect is in the untransformed
+  // buffer, pre-normalized (0..1) on the Rust side.
+  if (node->buffer_rect_w > 0.0f && node->buffer_rect_h > 0.0f) {
+    layer.contentsRect = CGRectMake(node->buffer_rect_x, node->buffer_rect_y,
+                                    node->buffer_rect_w, node->buffer_rect_h);
   }
 
   // 3. Update Contents from Cache
@@ -792,11 +792,11 @@ void renderer_android_draw_quads(VkCommandBuffer cmd_buf,
                     ext_x,
                     ext_y,
                     n->opacity,
-                    0,
-                    n->content_rect_x,
-                    n->content_rect_y,
-                    n->content_rect_w,
-                    n->content_rect_h};
+                    (float)n->buffer_transform,
+                    n->buffer_rect_x,
+                    n->buffer_rect_y,
+                    n->buffer_rect_w,
+                    n->buffer_rect_h};
     vkCmdPushConstants(
         cmd_buf, s_renderer->pipeline_layout,
         VK_SHADER_STAGE_VERTEX_BIT | VK_SHADER_STAGE_FRAGMENT_BIT, 0, 48, pc);
@@ -36,6 +36,11 @@ typedef struct CRenderNode {
   float content_rect_y;
   float content_rect_w;
   float content_rect_h;
+  uint32_t buffer_transform;
+  float buffer_rect_x;
+  float buffer_rect_y;
+  float buffer_rect_w;
+  float buffer_rect_h;
 } CRenderNode;
 
 /* Vulkan resources managed by the JNI bridge */
@@ -14,11 +14,11 @@ layout(push_constant) uniform PushConstants {
     float extent_x;
     float extent_y;
     float opacity;
-    float _pad;
-    float content_rect_x;
-    float content_rect_y;
-    float content_rect_w;
-    float content_rect_h;
+    float buffer_transform;
+    float buffer_rect_x;
+    float buffer_rect_y;
+    float buffer_rect_w;
+    float buffer_rect_h;
 } pc;
 
 void main() {
@@ -13,22 +13,38 @@ layout(push_constant) uniform PushConstants {
     float extent_x;
     float extent_y;
     float opacity;
-    float _pad;
-    float content_rect_x;
-    float content_rect_y;
-    float content_rect_w;
-    float content_rect_h;
+    float buffer_transform;
+    float buffer_rect_x;
+    float buffer_rect_y;
+    float buffer_rect_w;
+    float buffer_rect_h;
 } pc;
 
+// Map a point of the surface (0..1) to the untransformed buffer (0..1)
+vec2 bufferCoord(int transform, vec2 st) {
+    switch (transform) {
+    case 1: return vec2(st.y, 1.0 - st.x);        // 90
+    case 2: return vec2(1.0 - st.x, 1.0 - st.y);  // 180
+    case 3: return vec2(1.0 - st.y, st.x);        // 270
+    case 4: return vec2(1.0 - st.x, st.y);        // flipped
+    case 5: return vec2(st.y, st.x);              // flipped 90
+    case 6: return vec2(st.x, 1.0 - st.y);        // flipped 180
+    case 7: return vec2(1.0 - st.y, 1.0 - st.x);  // flipped 270
+    default: return st;
+    }
+}
+
 void main() {
     float ndc_x = (pc.pos_x + inPosition.x * pc.size_x) / pc.extent_x * 2.0 - 1.0;
     float ndc_y = (pc.pos_y + inPosition.y * pc.size_y) / pc.extent_y * 2.0 - 1.0;
     gl_Position = vec4(ndc_x, ndc_y, 0.0, 1.0);
     
-    // Apply normalization/cropping to texture coordinates.
-    // content_rect is already normalized (0..1) on the Rust side.
+    // Undo the client's buffer transform (a wl_output.transform value),
+    // then crop to the sampled area. buffer_rect is already normalized
+    // (0..1) within the untransformed buffer on the Rust side.
+    vec2 uv = bufferCoord(int(pc.buffer_transform + 0.5), inTexCoord);
     fragTexCoord = vec2(
-        pc.content_rect_x + inTexCoord.x * pc.content_rect_w,
-        pc.content_rect_y + inTexCoord.y * pc.content_rect_h
+        pc.buffer_rect_x + uv.x * pc.buffer_rect_w,
+        pc.buffer_rect_y + uv.y * pc.buffer_rect_h
     );
 }
@@ -0,0 +1,168 @@
+use crate::tests::harness::TestEnv;
+use crate::core::state::SelectionSource;
+use std::io::{Read, Write};
+use std::os::fd::AsFd;
+use wayland_client::{
+    protocol::{wl_callback, wl_data_device, wl_data_device_manager, wl_data_offer, wl_data_source, wl_registry, wl_seat},
+    Connection, Dispatch, EventQueue, Proxy, QueueHandle,
+};
+
+#[derive(Default)]
+struct ClientState {
+    seat: Option<wl_seat::WlSeat>,
+    data_device_manager: Option<wl_data_device_manager::WlDataDeviceManager>,
+    /// MIME types announced for each offer, by offer
+    offers: Vec<(wl_data_offer::WlDataOffer, Vec<String>)>,
+    /// Offer of the current selection
+    selection: Option<wl_data_offer::WlDataOffer>,
+    /// MIME types the source was asked to send, in order
+    sent: Vec<String>,
+}
+
+impl ClientState {
+    fn selection_mime_types(&self) -> Vec<String> {
+        let selection = self.selection.as_ref().expect("no selection");
+        self.offers.iter()
+            .find(|(offer, _)| offer == selection)
+            .map(|(_, mimes)| mimes.clone())
+            .unwrap_or_default()
+    }
+}
+
+impl Dispatch<wl_registry::WlRegistry, ()> for ClientState {
+    fn event(
+        state: &mut Self,
+        proxy: &wl_registry::WlRegistry,
+        event: wl_registry::Event,
+        _data: &(),
+        _conn: &Connection,
+        qh: &QueueHandle<Self>,
+    ) {
+        if let wl_registry::Event::Global { name, interface, version } = event {
+            if interface == "wl_seat" {
+                state.seat = Some(proxy.bind(name, version, qh, ()));
+            } else if interface == "wl_data_device_manager" {
+                state.data_device_manager = Some(proxy.bind(name, version, qh, ()));
+            }
+        }
+    }
+}
+
+impl Dispatch<wl_seat::WlSeat, ()> for ClientState {
+    fn event(_: &mut Self, _: &wl_seat::WlSeat, _: wl_seat::Event, _: &(), _: &Connection, _: &QueueHandle<Self>) {}
+}
+
+impl Dispatch<wl_callback::WlCallback, ()> for ClientState {
+    fn event(_: &mut Self, _: &wl_callback::WlCallback, _: wl_callback::Event, _: &(), _: &Connection, _: &QueueHandle<Self>) {}
+}
+
+impl Dispatch<wl_data_device_manager::WlDataDeviceManager, ()> for ClientState {
+    fn event(_: &mut Self, _: &wl_data_device_manager::WlDataDeviceManager, _: wl_data_device_manager::Event, _: &(), _: &Connection, _: &QueueHandle<Self>) {}
+}
+
+impl Dispatch<wl_data_device::WlDataDevice, ()> for ClientState {
+    fn event(
+        state: &mut Self,
+        _proxy: &wl_data_device::WlDataDevice,
+        event: wl_data_device::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        match event {
+            wl_data_device::Event::DataOffer { id } => state.offers.push((id, Vec::new())),
+            wl_data_device::Event::Selection { id } => state.selection = id,
+            _ => {}
+        }
+    }
+
+    wayland_client::event_created_child!(ClientState, wl_data_device::WlDataDevice, [
+        wl_data_device::EVT_DATA_OFFER_OPCODE => (wl_data_offer::WlDataOffer, ()),
+    ]);
+}
+
+impl Dispatch<wl_data_offer::WlDataOffer, ()> for ClientState {
+    fn event(
+        state: &mut Self,
+        proxy: &wl_data_offer::WlDataOffer,
+        event: wl_data_offer::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        if let wl_data_offer::Event::Offer { mime_type } = event {
+            if let Some((_, mimes)) = state.offers.iter_mut().find(|(offer, _)| offer.id() == proxy.id()) {
+                mimes.push(mime_type);
+            }
+        }
+    }
+}
+
+impl Dispatch<wl_data_source::WlDataSource, ()> for ClientState {
+    fn event(
+        state: &mut Self,
+        _proxy: &wl_data_source::WlDataSource,
+        event: wl_data_source::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        if let wl_data_source::Event::Send { mime_type, fd } = event {
+            std::fs::File::from(fd).write_all(b"copied text").unwrap();
+            state.sent.push(mime_type);
+        }
+    }
+}
+
+fn setup() -> (TestEnv, EventQueue<ClientState>, ClientState) {
+    let mut env = TestEnv::new();
+    let display = env.client.display();
+    let mut event_queue = env.client.new_event_queue::<ClientState>();
+    let qh = event_queue.handle();
+    let _registry = display.get_registry(&qh, ());
+    let mut client_state = ClientState::default();
+    env.wait_roundtrip(&mut event_queue, &mut client_state);
+    (env, event_queue, client_state)
+}
+
+#[test]
+fn test_clipboard_outlives_its_source() {
+    let (mut env, mut event_queue, mut client_state) = setup();
+    let qh = event_queue.handle();
+    env.state.data.clipboard_cache.enabled = true;
+
+    let manager = client_state.data_device_manager.clone().unwrap();
+    let device = manager.get_data_device(client_state.seat.as_ref().unwrap(), &qh, ());
+    let source = manager.create_data_source(&qh, ());
+    source.offer("application/x-private".to_string());
+    source.offer("text/plain;charset=utf-8".to_string());
+    device.set_selection(Some(&source), 0);
+    env.wait_roundtrip(&mut event_queue, &mut client_state);
+
+    // Only the types worth caching are copied out of the source
+    assert_eq!(client_state.sent, vec!["text/plain;charset=utf-8"]);
+    assert_eq!(client_state.selection_mime_types(), vec!["application/x-private", "text/plain;charset=utf-8"]);
+
+    // The owner still holds the selection, so the cache stays out of the way
+    let dh = env.display.handle();
+    env.state.service_clipboard_cache(&dh);
+    assert!(matches!(env.state.seat.current_selection, Some(SelectionSource::Wayland(_))));
+
+    // Once the source is gone the compositor offers what it cached
+    source.destroy();
+    env.wait_roundtrip(&mut event_queue, &mut client_state);
+    env.state.service_clipboard_cache(&dh);
+    env.wait_roundtrip(&mut event_queue, &mut client_state);
+    assert!(matches!(env.state.seat.current_selection, Some(SelectionSource::Cached)));
+    assert_eq!(client_state.selection_mime_types(), vec!["text/plain;charset=utf-8"]);
+
+    // Pasting from that offer reads the cached bytes
+    let (mut reader, writer) = std::os::unix::net::UnixStream::pair().unwrap();
+    client_state.selection.as_ref().unwrap().receive("text/plain;charset=utf-8".to_string(), writer.as_fd());
+    env.wait_roundtrip(&mut event_queue, &mut client_state);
+    drop(writer);
+    let mut pasted = Vec::new();
+    reader.read_to_end(&mut pasted).unwrap();
+    assert_eq!(pasted, b"copied text");
+    assert!(env.client.protocol_error().is_none());
+}
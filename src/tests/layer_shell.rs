@@ -0,0 +1,285 @@
+use crate::tests::harness::TestEnv;
+use std::collections::HashMap;
+use std::os::fd::AsFd;
+use wayland_client::{
+    protocol::{wl_buffer, wl_callback, wl_compositor, wl_registry, wl_shm, wl_shm_pool, wl_surface},
+    Connection, Dispatch, EventQueue, QueueHandle,
+};
+use wayland_protocols::xdg::shell::client::{xdg_popup, xdg_positioner, xdg_surface, xdg_wm_base};
+use wayland_protocols_wlr::layer_shell::v1::client::{
+    zwlr_layer_shell_v1::{self, Layer},
+    zwlr_layer_surface_v1::{self, Anchor, KeyboardInteractivity},
+};
+
+#[derive(Default)]
+struct ClientState {
+    compositor: Option<wl_compositor::WlCompositor>,
+    shm: Option<wl_shm::WlShm>,
+    wm_base: Option<xdg_wm_base::XdgWmBase>,
+    layer_shell: Option<zwlr_layer_shell_v1::ZwlrLayerShellV1>,
+    /// Last configure serial per layer surface, by namespace
+    layer_configures: HashMap<&'static str, u32>,
+    popup_geometry: Option<(i32, i32, i32, i32)>,
+}
+
+impl Dispatch<wl_registry::WlRegistry, ()> for ClientState {
+    fn event(
+        state: &mut Self,
+        proxy: &wl_registry::WlRegistry,
+        event: wl_registry::Event,
+        _data: &(),
+        _conn: &Connection,
+        qh: &QueueHandle<Self>,
+    ) {
+        if let wl_registry::Event::Global { name, interface, version } = event {
+            if interface == "wl_compositor" {
+                state.compositor = Some(proxy.bind(name, version, qh, ()));
+            } else if interface == "wl_shm" {
+                state.shm = Some(proxy.bind(name, version, qh, ()));
+            } else if interface == "xdg_wm_base" {
+                state.wm_base = Some(proxy.bind(name, version, qh, ()));
+            } else if interface == "zwlr_layer_shell_v1" {
+                state.layer_shell = Some(proxy.bind(name, version.min(4), qh, ()));
+            }
+        }
+    }
+}
+
+impl Dispatch<wl_compositor::WlCompositor, ()> for ClientState {
+    fn event(_: &mut Self, _: &wl_compositor::WlCompositor, _: wl_compositor::Event, _: &(), _: &Connection, _: &QueueHandle<Self>) {}
+}
+
+impl Dispatch<wl_surface::WlSurface, ()> for ClientState {
+    fn event(_: &mut Self, _: &wl_surface::WlSurface, _: wl_surface::Event, _: &(), _: &Connection, _: &QueueHandle<Self>) {}
+}
+
+impl Dispatch<wl_shm::WlShm, ()> for ClientState {
+    fn event(_: &mut Self, _: &wl_shm::WlShm, _: wl_shm::Event, _: &(), _: &Connection, _: &QueueHandle<Self>) {}
+}
+
+impl Dispatch<wl_shm_pool::WlShmPool, ()> for ClientState {
+    fn event(_: &mut Self, _: &wl_shm_pool::WlShmPool, _: wl_shm_pool::Event, _: &(), _: &Connection, _: &QueueHandle<Self>) {}
+}
+
+impl Dispatch<wl_buffer::WlBuffer, ()> for ClientState {
+    fn event(_: &mut Self, _: &wl_buffer::WlBuffer, _: wl_buffer::Event, _: &(), _: &Connection, _: &QueueHandle<Self>) {}
+}
+
+impl Dispatch<wl_callback::WlCallback, ()> for ClientState {
+    fn event(_: &mut Self, _: &wl_callback::WlCallback, _: wl_callback::Event, _: &(), _: &Connection, _: &QueueHandle<Self>) {}
+}
+
+impl Dispatch<xdg_wm_base::XdgWmBase, ()> for ClientState {
+    fn event(_: &mut Self, proxy: &xdg_wm_base::XdgWmBase, event: xdg_wm_base::Event, _: &(), _: &Connection, _: &QueueHandle<Self>) {
+        if let xdg_wm_base::Event::Ping { serial } = event {
+            proxy.pong(serial);
+        }
+    }
+}
+
+impl Dispatch<xdg_positioner::XdgPositioner, ()> for ClientState {
+    fn event(_: &mut Self, _: &xdg_positioner::XdgPositioner, _: xdg_positioner::Event, _: &(), _: &Connection, _: &QueueHandle<Self>) {}
+}
+
+impl Dispatch<xdg_surface::XdgSurface, ()> for ClientState {
+    fn event(_: &mut Self, _: &xdg_surface::XdgSurface, _: xdg_surface::Event, _: &(), _: &Connection, _: &QueueHandle<Self>) {}
+}
+
+impl Dispatch<xdg_popup::XdgPopup, ()> for ClientState {
+    fn event(
+        state: &mut Self,
+        _proxy: &xdg_popup::XdgPopup,
+        event: xdg_popup::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        if let xdg_popup::Event::Configure { x, y, width, height } = event {
+            state.popup_geometry = Some((x, y, width, height));
+        }
+    }
+}
+
+impl Dispatch<zwlr_layer_shell_v1::ZwlrLayerShellV1, ()> for ClientState {
+    fn event(_: &mut Self, _: &zwlr_layer_shell_v1::ZwlrLayerShellV1, _: zwlr_layer_shell_v1::Event, _: &(), _: &Connection, _: &QueueHandle<Self>) {}
+}
+
+impl Dispatch<zwlr_layer_surface_v1::ZwlrLayerSurfaceV1, &'static str> for ClientState {
+    fn event(
+        state: &mut Self,
+        _proxy: &zwlr_layer_surface_v1::ZwlrLayerSurfaceV1,
+        event: zwlr_layer_surface_v1::Event,
+        namespace: &&'static str,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        if let zwlr_layer_surface_v1::Event::Configure { serial, .. } = event {
+            state.layer_configures.insert(namespace, serial);
+        }
+    }
+}
+
+fn setup() -> (TestEnv, EventQueue<ClientState>, ClientState) {
+    let mut env = TestEnv::new();
+    let display = env.client.display();
+    let mut event_queue = env.client.new_event_queue::<ClientState>();
+    let qh = event_queue.handle();
+    let _registry = display.get_registry(&qh, ());
+    let mut client_state = ClientState::default();
+    env.wait_roundtrip(&mut event_queue, &mut client_state);
+    (env, event_queue, client_state)
+}
+
+fn create_buffer(client_state: &ClientState, qh: &QueueHandle<ClientState>) -> wl_buffer::WlBuffer {
+    let temp = tempfile::tempfile().unwrap();
+    temp.set_len(4096).unwrap();
+    let pool = client_state.shm.as_ref().unwrap().create_pool(temp.as_fd(), 4096, qh, ());
+    pool.create_buffer(0, 32, 32, 128, wl_shm::Format::Argb8888, qh, ())
+}
+
+/// What a test layer surface asks for
+struct LayerSpec {
+    namespace: &'static str,
+    layer: Layer,
+    interactivity: KeyboardInteractivity,
+    anchor: Anchor,
+    size: (u32, u32),
+}
+
+/// Create a layer surface, answer its initial configure and map it.
+/// Returns its wl_surface, its layer surface and its internal surface id.
+fn map_layer_surface(
+    env: &mut TestEnv,
+    event_queue: &mut EventQueue<ClientState>,
+    client_state: &mut ClientState,
+    spec: LayerSpec,
+) -> (wl_surface::WlSurface, zwlr_layer_surface_v1::ZwlrLayerSurfaceV1, u32) {
+    let LayerSpec { namespace, layer, interactivity, anchor, size } = spec;
+    let qh = event_queue.handle();
+    let surface = client_state.compositor.as_ref().unwrap().create_surface(&qh, ());
+    let layer_surface = client_state.layer_shell.as_ref().unwrap()
+        .get_layer_surface(&surface, None, layer, namespace.to_string(), &qh, namespace);
+    layer_surface.set_anchor(anchor);
+    layer_surface.set_size(size.0, size.1);
+    layer_surface.set_keyboard_interactivity(interactivity);
+    surface.commit();
+    env.wait_roundtrip(event_queue, client_state);
+
+    layer_surface.ack_configure(client_state.layer_configures[namespace]);
+    surface.attach(Some(&create_buffer(client_state, &qh)), 0, 0);
+    surface.commit();
+    env.wait_roundtrip(event_queue, client_state);
+
+    let surface_id = env.state.wlr.layer_surfaces.values()
+        .map(|ls| ls.read().unwrap())
+        .find(|ls| ls.namespace == namespace)
+        .map(|ls| ls.surface_id)
+        .expect("layer surface not tracked");
+    (surface, layer_surface, surface_id)
+}
+
+#[test]
+fn test_layer_keyboard_focus() {
+    let (mut env, mut event_queue, mut client_state) = setup();
+    let qh = event_queue.handle();
+    let _other = client_state.compositor.as_ref().unwrap().create_surface(&qh, ());
+    env.wait_roundtrip(&mut event_queue, &mut client_state);
+
+    // An on-demand panel takes focus only when clicked
+    let (_panel, _panel_layer, panel_id) = map_layer_surface(&mut env, &mut event_queue, &mut client_state, LayerSpec {
+        namespace: "panel",
+        layer: Layer::Top,
+        interactivity: KeyboardInteractivity::OnDemand,
+        anchor: Anchor::Top | Anchor::Left | Anchor::Right,
+        size: (0, 30),
+    });
+    assert_eq!(env.state.seat.keyboard.focus, None);
+    env.state.focus_layer_on_click(panel_id);
+    assert_eq!(env.state.seat.keyboard.focus, Some(panel_id));
+
+    // A click on a surface that isn't a layer surface takes it back
+    let other_id = *env.state.surfaces.keys().find(|&&sid| {
+        env.state.wlr.layer_surfaces.values().all(|ls| ls.read().unwrap().surface_id != sid)
+    }).unwrap();
+    env.state.focus_layer_on_click(other_id);
+    assert_eq!(env.state.seat.keyboard.focus, None);
+
+    // An exclusive overlay grabs focus as it maps and keeps it against clicks
+    let (launcher, _launcher_layer, launcher_id) = map_layer_surface(&mut env, &mut event_queue, &mut client_state, LayerSpec {
+        namespace: "launcher",
+        layer: Layer::Overlay,
+        interactivity: KeyboardInteractivity::Exclusive,
+        anchor: Anchor::empty(),
+        size: (400, 300),
+    });
+    assert_eq!(env.state.exclusive_keyboard_layer(), Some(launcher_id));
+    assert_eq!(env.state.seat.keyboard.focus, Some(launcher_id));
+    env.state.focus_layer_on_click(panel_id);
+    assert_eq!(env.state.seat.keyboard.focus, Some(launcher_id));
+
+    // Unmapping releases it
+    launcher.attach(None, 0, 0);
+    launcher.commit();
+    env.wait_roundtrip(&mut event_queue, &mut client_state);
+    assert_eq!(env.state.exclusive_keyboard_layer(), None);
+    assert_ne!(env.state.seat.keyboard.focus, Some(launcher_id));
+
+    // And the panel can be clicked into focus again
+    env.state.focus_layer_on_click(panel_id);
+    assert_eq!(env.state.seat.keyboard.focus, Some(panel_id));
+}
+
+#[test]
+fn test_layer_popup_constrained_to_output() {
+    let (mut env, mut event_queue, mut client_state) = setup();
+    let qh = event_queue.handle();
+
+    // A 30px bar along the bottom of the 1920x1080 output
+    let (_bar, bar_layer, _bar_id) = map_layer_surface(&mut env, &mut event_queue, &mut client_state, LayerSpec {
+        namespace: "bar",
+        layer: Layer::Top,
+        interactivity: KeyboardInteractivity::None,
+        anchor: Anchor::Bottom | Anchor::Left | Anchor::Right,
+        size: (0, 30),
+    });
+
+    // A menu hanging below a button at the bar's right end would leave
+    // the output at the bottom and the right
+    let wm_base = client_state.wm_base.clone().unwrap();
+    let positioner = wm_base.create_positioner(&qh, ());
+    positioner.set_size(200, 100);
+    positioner.set_anchor_rect(1800, 0, 50, 30);
+    positioner.set_anchor(xdg_positioner::Anchor::Bottom);
+    positioner.set_gravity(xdg_positioner::Gravity::Bottom);
+    positioner.set_constraint_adjustment(
+        xdg_positioner::ConstraintAdjustment::FlipY | xdg_positioner::ConstraintAdjustment::SlideX,
+    );
+    let menu = client_state.compositor.as_ref().unwrap().create_surface(&qh, ());
+    let menu_xdg = wm_base.get_xdg_surface(&menu, &qh, ());
+    let popup = menu_xdg.get_popup(None, &positioner, &qh, ());
+    bar_layer.get_popup(&popup);
+    env.wait_roundtrip(&mut event_queue, &mut client_state);
+
+    // Flipped above the bar and slid left to stay on the output
+    assert_eq!(client_state.popup_geometry, Some((1720, -100, 200, 100)));
+}
+
+#[test]
+fn test_layer_zero_size_needs_both_anchors() {
+    let (mut env, mut event_queue, mut client_state) = setup();
+    let qh = event_queue.handle();
+
+    // Stretching across the width takes both the left and right anchor
+    let surface = client_state.compositor.as_ref().unwrap().create_surface(&qh, ());
+    let layer_surface = client_state.layer_shell.as_ref().unwrap()
+        .get_layer_surface(&surface, None, Layer::Top, "bar".to_string(), &qh, "bar");
+    layer_surface.set_anchor(Anchor::Top | Anchor::Left);
+    layer_surface.set_size(0, 30);
+    surface.commit();
+    env.wait_roundtrip(&mut event_queue, &mut client_state);
+
+    let error = env.client.protocol_error().expect("a zero width without both anchors must be a protocol error");
+    assert_eq!(error.object_interface, "zwlr_layer_surface_v1");
+    assert_eq!(error.code, zwlr_layer_surface_v1::Error::InvalidSize as u32);
+    assert!(!client_state.layer_configures.contains_key("bar"));
+}
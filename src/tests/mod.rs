@@ -4,3 +4,12 @@ pub mod window;
 pub mod window_management;
 pub mod harness;
 pub mod integration;
+pub mod presentation;
+pub mod tablet;
+#[cfg(feature = "desktop-protocols")]
+pub mod session_lock;
+pub mod layer_shell;
+pub mod xdg_popup;
+pub mod text_input;
+pub mod data_device;
+pub mod pointer;
@@ -0,0 +1,200 @@
+use std::collections::HashMap;
+use crate::tests::harness::TestEnv;
+use crate::core::input::AxisFrame;
+use wayland_client::{
+    protocol::{wl_callback, wl_pointer, wl_registry, wl_seat},
+    Connection, Dispatch, EventQueue, QueueHandle,
+};
+use wayland_server::Resource as _;
+use wl_pointer::{Axis, AxisRelativeDirection, AxisSource};
+
+#[derive(Debug, Clone, PartialEq)]
+enum AxisEvent {
+    Source(AxisSource),
+    Axis(Axis, f64),
+    Stop(Axis),
+    Discrete(Axis, i32),
+    Value120(Axis, i32),
+    RelativeDirection(Axis, AxisRelativeDirection),
+    Frame,
+}
+
+#[derive(Default)]
+struct ClientState {
+    registry: Option<wl_registry::WlRegistry>,
+    seat_name: Option<u32>,
+    /// Axis events per pointer, keyed by the version its seat was bound at
+    events: HashMap<u32, Vec<AxisEvent>>,
+}
+
+impl Dispatch<wl_registry::WlRegistry, ()> for ClientState {
+    fn event(
+        state: &mut Self,
+        proxy: &wl_registry::WlRegistry,
+        event: wl_registry::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        if let wl_registry::Event::Global { name, interface, .. } = event {
+            if interface == "wl_seat" {
+                state.registry = Some(proxy.clone());
+                state.seat_name = Some(name);
+            }
+        }
+    }
+}
+
+impl Dispatch<wl_seat::WlSeat, ()> for ClientState {
+    fn event(_: &mut Self, _: &wl_seat::WlSeat, _: wl_seat::Event, _: &(), _: &Connection, _: &QueueHandle<Self>) {}
+}
+
+impl Dispatch<wl_callback::WlCallback, ()> for ClientState {
+    fn event(_: &mut Self, _: &wl_callback::WlCallback, _: wl_callback::Event, _: &(), _: &Connection, _: &QueueHandle<Self>) {}
+}
+
+impl Dispatch<wl_pointer::WlPointer, u32> for ClientState {
+    fn event(
+        state: &mut Self,
+        _proxy: &wl_pointer::WlPointer,
+        event: wl_pointer::Event,
+        version: &u32,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        let recorded = match event {
+            wl_pointer::Event::AxisSource { axis_source } => AxisEvent::Source(axis_source.into_result().unwrap()),
+            wl_pointer::Event::Axis { axis, value, .. } => AxisEvent::Axis(axis.into_result().unwrap(), value),
+            wl_pointer::Event::AxisStop { axis, .. } => AxisEvent::Stop(axis.into_result().unwrap()),
+            wl_pointer::Event::AxisDiscrete { axis, discrete } => AxisEvent::Discrete(axis.into_result().unwrap(), discrete),
+            wl_pointer::Event::AxisValue120 { axis, value120 } => AxisEvent::Value120(axis.into_result().unwrap(), value120),
+            wl_pointer::Event::AxisRelativeDirection { axis, direction } => {
+                AxisEvent::RelativeDirection(axis.into_result().unwrap(), direction.into_result().unwrap())
+            }
+            wl_pointer::Event::Frame => AxisEvent::Frame,
+            _ => return,
+        };
+        state.events.entry(*version).or_default().push(recorded);
+    }
+}
+
+/// Bind the seat once per version and get a pointer from each
+fn setup(versions: &[u32]) -> (TestEnv, EventQueue<ClientState>, ClientState) {
+    let mut env = TestEnv::new();
+    let display = env.client.display();
+    let mut event_queue = env.client.new_event_queue::<ClientState>();
+    let qh = event_queue.handle();
+    let _registry = display.get_registry(&qh, ());
+    let mut client_state = ClientState::default();
+    env.wait_roundtrip(&mut event_queue, &mut client_state);
+
+    let registry = client_state.registry.clone().unwrap();
+    let seat_name = client_state.seat_name.unwrap();
+    for &version in versions {
+        let seat = registry.bind::<wl_seat::WlSeat, _, _>(seat_name, version, &qh, ());
+        seat.get_pointer(&qh, version);
+    }
+    env.wait_roundtrip(&mut event_queue, &mut client_state);
+    client_state.events.clear();
+    (env, event_queue, client_state)
+}
+
+fn broadcast(env: &mut TestEnv, frame: &AxisFrame) {
+    let client = env.state.seat.pointer.resources.first().and_then(|p| p.client());
+    env.state.seat.pointer.broadcast_axis_frame(frame, client.as_ref());
+}
+
+#[test]
+fn test_axis_frame_per_version() {
+    let (mut env, mut event_queue, mut client_state) = setup(&[4, 5, 8, 9]);
+
+    // One wheel detent
+    broadcast(&mut env, &AxisFrame {
+        source: Some(AxisSource::Wheel),
+        vertical_v120: 120,
+        ..AxisFrame::continuous(0.0, 15.0, 10)
+    });
+    env.wait_roundtrip(&mut event_queue, &mut client_state);
+
+    let v = Axis::VerticalScroll;
+    // Before version 5 there is no framing, only the value
+    assert_eq!(client_state.events[&4], vec![AxisEvent::Axis(v, 15.0)]);
+    assert_eq!(client_state.events[&5], vec![
+        AxisEvent::Source(AxisSource::Wheel),
+        AxisEvent::Discrete(v, 1),
+        AxisEvent::Axis(v, 15.0),
+        AxisEvent::Frame,
+    ]);
+    // axis_value120 replaces axis_discrete from version 8
+    assert_eq!(client_state.events[&8], vec![
+        AxisEvent::Source(AxisSource::Wheel),
+        AxisEvent::Value120(v, 120),
+        AxisEvent::Axis(v, 15.0),
+        AxisEvent::Frame,
+    ]);
+    assert_eq!(client_state.events[&9], vec![
+        AxisEvent::Source(AxisSource::Wheel),
+        AxisEvent::RelativeDirection(v, AxisRelativeDirection::Identical),
+        AxisEvent::Value120(v, 120),
+        AxisEvent::Axis(v, 15.0),
+        AxisEvent::Frame,
+    ]);
+}
+
+#[test]
+fn test_axis_stop_per_version() {
+    let (mut env, mut event_queue, mut client_state) = setup(&[4, 5, 9]);
+
+    // Fingers lifted off a touchpad
+    broadcast(&mut env, &AxisFrame {
+        source: Some(AxisSource::Finger),
+        vertical_stop: true,
+        ..AxisFrame::continuous(0.0, 0.0, 10)
+    });
+    env.wait_roundtrip(&mut event_queue, &mut client_state);
+
+    let v = Axis::VerticalScroll;
+    assert!(!client_state.events.contains_key(&4));
+    for version in [5, 9] {
+        assert_eq!(client_state.events[&version], vec![
+            AxisEvent::Source(AxisSource::Finger),
+            AxisEvent::Stop(v),
+            AxisEvent::Frame,
+        ]);
+    }
+}
+
+#[test]
+fn test_axis_tilt_per_version() {
+    let (mut env, mut event_queue, mut client_state) = setup(&[5, 8, 9]);
+
+    // A tilted wheel with natural scrolling
+    broadcast(&mut env, &AxisFrame {
+        source: Some(AxisSource::WheelTilt),
+        horizontal_v120: 120,
+        inverted: true,
+        ..AxisFrame::continuous(10.0, 0.0, 10)
+    });
+    env.wait_roundtrip(&mut event_queue, &mut client_state);
+
+    let h = Axis::HorizontalScroll;
+    // wheel_tilt only exists from version 6, so version 5 gets no source
+    assert_eq!(client_state.events[&5], vec![
+        AxisEvent::Discrete(h, 1),
+        AxisEvent::Axis(h, 10.0),
+        AxisEvent::Frame,
+    ]);
+    assert_eq!(client_state.events[&8], vec![
+        AxisEvent::Source(AxisSource::WheelTilt),
+        AxisEvent::Value120(h, 120),
+        AxisEvent::Axis(h, 10.0),
+        AxisEvent::Frame,
+    ]);
+    assert_eq!(client_state.events[&9], vec![
+        AxisEvent::Source(AxisSource::WheelTilt),
+        AxisEvent::RelativeDirection(h, AxisRelativeDirection::Inverted),
+        AxisEvent::Value120(h, 120),
+        AxisEvent::Axis(h, 10.0),
+        AxisEvent::Frame,
+    ]);
+}
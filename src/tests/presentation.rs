@@ -0,0 +1,188 @@
+use crate::tests::harness::TestEnv;
+use wayland_client::{
+    protocol::{wl_compositor, wl_registry, wl_callback, wl_surface},
+    Connection, Dispatch, QueueHandle,
+};
+use wayland_protocols::wp::fifo::v1::client::{wp_fifo_manager_v1, wp_fifo_v1};
+use wayland_protocols::wp::presentation_time::client::{wp_presentation, wp_presentation_feedback};
+use crate::core::wayland::protocol::server::wp::presentation_time::server::wp_presentation_feedback::Kind;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Outcome {
+    Presented { seq: u64, refresh: u32 },
+    Discarded,
+}
+
+#[derive(Default)]
+struct ClientState {
+    compositor: Option<wl_compositor::WlCompositor>,
+    presentation: Option<wp_presentation::WpPresentation>,
+    fifo_manager: Option<wp_fifo_manager_v1::WpFifoManagerV1>,
+    clock_id: Option<u32>,
+    /// Outcome per feedback, by the index passed as user data
+    outcomes: Vec<(usize, Outcome)>,
+}
+
+impl Dispatch<wl_registry::WlRegistry, ()> for ClientState {
+    fn event(
+        state: &mut Self,
+        proxy: &wl_registry::WlRegistry,
+        event: wl_registry::Event,
+        _data: &(),
+        _conn: &Connection,
+        qh: &QueueHandle<Self>,
+    ) {
+        if let wl_registry::Event::Global { name, interface, version } = event {
+            if interface == "wl_compositor" {
+                state.compositor = Some(proxy.bind(name, version, qh, ()));
+            } else if interface == "wp_presentation" {
+                state.presentation = Some(proxy.bind(name, version, qh, ()));
+            } else if interface == "wp_fifo_manager_v1" {
+                state.fifo_manager = Some(proxy.bind(name, version, qh, ()));
+            }
+        }
+    }
+}
+
+impl Dispatch<wl_compositor::WlCompositor, ()> for ClientState {
+    fn event(_: &mut Self, _: &wl_compositor::WlCompositor, _: wl_compositor::Event, _: &(), _: &Connection, _: &QueueHandle<Self>) {}
+}
+
+impl Dispatch<wl_surface::WlSurface, ()> for ClientState {
+    fn event(_: &mut Self, _: &wl_surface::WlSurface, _: wl_surface::Event, _: &(), _: &Connection, _: &QueueHandle<Self>) {}
+}
+
+impl Dispatch<wl_callback::WlCallback, ()> for ClientState {
+    fn event(_: &mut Self, _: &wl_callback::WlCallback, _: wl_callback::Event, _: &(), _: &Connection, _: &QueueHandle<Self>) {}
+}
+
+impl Dispatch<wp_fifo_manager_v1::WpFifoManagerV1, ()> for ClientState {
+    fn event(_: &mut Self, _: &wp_fifo_manager_v1::WpFifoManagerV1, _: wp_fifo_manager_v1::Event, _: &(), _: &Connection, _: &QueueHandle<Self>) {}
+}
+
+impl Dispatch<wp_fifo_v1::WpFifoV1, ()> for ClientState {
+    fn event(_: &mut Self, _: &wp_fifo_v1::WpFifoV1, _: wp_fifo_v1::Event, _: &(), _: &Connection, _: &QueueHandle<Self>) {}
+}
+
+impl Dispatch<wp_presentation::WpPresentation, ()> for ClientState {
+    fn event(
+        state: &mut Self,
+        _proxy: &wp_presentation::WpPresentation,
+        event: wp_presentation::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        if let wp_presentation::Event::ClockId { clk_id } = event {
+            state.clock_id = Some(clk_id);
+        }
+    }
+}
+
+impl Dispatch<wp_presentation_feedback::WpPresentationFeedback, usize> for ClientState {
+    fn event(
+        state: &mut Self,
+        _proxy: &wp_presentation_feedback::WpPresentationFeedback,
+        event: wp_presentation_feedback::Event,
+        index: &usize,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        match event {
+            wp_presentation_feedback::Event::Presented { refresh, seq_hi, seq_lo, .. } => {
+                let seq = (seq_hi as u64) << 32 | seq_lo as u64;
+                state.outcomes.push((*index, Outcome::Presented { seq, refresh }));
+            }
+            wp_presentation_feedback::Event::Discarded => {
+                state.outcomes.push((*index, Outcome::Discarded));
+            }
+            _ => {}
+        }
+    }
+}
+
+#[test]
+fn test_presentation_feedback() {
+    let mut env = TestEnv::new();
+    let display = env.client.display();
+    let mut event_queue = env.client.new_event_queue::<ClientState>();
+    let qh = event_queue.handle();
+
+    let _registry = display.get_registry(&qh, ());
+    let mut client_state = ClientState::default();
+    env.wait_roundtrip(&mut event_queue, &mut client_state);
+    env.wait_roundtrip(&mut event_queue, &mut client_state);
+
+    // Timestamps are CLOCK_MONOTONIC, whatever its id on this platform
+    assert_eq!(client_state.clock_id, Some(libc::CLOCK_MONOTONIC as u32));
+
+    let presentation = client_state.presentation.clone().unwrap();
+    let surface = client_state.compositor.as_ref().unwrap().create_surface(&qh, ());
+
+    // Content replaced by a later commit before it was shown is discarded
+    let _first = presentation.feedback(&surface, &qh, 0);
+    surface.commit();
+    let _second = presentation.feedback(&surface, &qh, 1);
+    surface.commit();
+    env.wait_roundtrip(&mut event_queue, &mut client_state);
+    assert_eq!(client_state.outcomes, vec![(0, Outcome::Discarded)]);
+
+    // Presented feedback carries the output's vblank counter
+    env.state.present_feedback(None, 1_000_000_000, 16_666_666, Kind::Vsync);
+    env.wait_roundtrip(&mut event_queue, &mut client_state);
+    assert_eq!(client_state.outcomes[1], (1, Outcome::Presented { seq: 1, refresh: 16_666_666 }));
+
+    // The next frame, three refresh cycles later
+    let _third = presentation.feedback(&surface, &qh, 2);
+    surface.commit();
+    env.wait_roundtrip(&mut event_queue, &mut client_state);
+    env.state.present_feedback(None, 1_050_000_000, 16_666_666, Kind::Vsync);
+    env.wait_roundtrip(&mut event_queue, &mut client_state);
+    assert_eq!(client_state.outcomes[2], (2, Outcome::Presented { seq: 4, refresh: 16_666_666 }));
+
+    assert!(env.state.ext.presentation.feedbacks.is_empty());
+}
+
+#[test]
+fn test_presentation_feedback_queued_commit() {
+    let mut env = TestEnv::new();
+    let display = env.client.display();
+    let mut event_queue = env.client.new_event_queue::<ClientState>();
+    let qh = event_queue.handle();
+    let _registry = display.get_registry(&qh, ());
+    let mut client_state = ClientState::default();
+    env.wait_roundtrip(&mut event_queue, &mut client_state);
+
+    let presentation = client_state.presentation.clone().unwrap();
+    let surface = client_state.compositor.as_ref().unwrap().create_surface(&qh, ());
+    let fifo = client_state.fifo_manager.as_ref().unwrap().get_fifo(&surface, &qh, ());
+
+    // The first commit raises the barrier, the next two wait behind it
+    let _first = presentation.feedback(&surface, &qh, 0);
+    fifo.set_barrier();
+    surface.commit();
+    let _second = presentation.feedback(&surface, &qh, 1);
+    fifo.wait_barrier();
+    fifo.set_barrier();
+    surface.commit();
+    let _third = presentation.feedback(&surface, &qh, 2);
+    fifo.wait_barrier();
+    surface.commit();
+    env.wait_roundtrip(&mut event_queue, &mut client_state);
+
+    // Only the applied commit's feedback is presented
+    env.state.present_feedback(None, 1_000_000_000, 16_666_666, Kind::Vsync);
+    env.wait_roundtrip(&mut event_queue, &mut client_state);
+    assert_eq!(client_state.outcomes, vec![(0, Outcome::Presented { seq: 1, refresh: 16_666_666 })]);
+
+    // Lifting the barrier applies the second commit, which raises it again:
+    // its feedback is presented, the third's keeps waiting with its commit
+    env.state.lift_fifo_barriers();
+    env.state.process_commit_queue(std::time::Instant::now());
+    env.state.present_feedback(None, 1_016_666_666, 16_666_666, Kind::Vsync);
+    env.wait_roundtrip(&mut event_queue, &mut client_state);
+    assert_eq!(client_state.outcomes[1..], [(1, Outcome::Presented { seq: 2, refresh: 16_666_666 })]);
+    assert!(env.state.ext.presentation.feedbacks.is_empty());
+    let surface_id = *env.state.surfaces.keys().next().unwrap();
+    assert!(env.state.commit_queue.has_queued(surface_id));
+}
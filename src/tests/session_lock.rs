@@ -0,0 +1,419 @@
+use crate::tests::harness::TestEnv;
+use crate::core::state::OutputState;
+use crate::core::wayland::ext::session_lock::LockStatus;
+use crate::core::wayland::wayland::output::OutputGlobal;
+use std::collections::HashMap;
+use std::os::fd::AsFd;
+use wayland_client::{
+    protocol::{wl_buffer, wl_callback, wl_compositor, wl_output, wl_registry, wl_shm, wl_shm_pool, wl_surface},
+    Connection, Dispatch, EventQueue, QueueHandle,
+};
+use wayland_protocols::ext::session_lock::v1::client::{
+    ext_session_lock_manager_v1, ext_session_lock_surface_v1, ext_session_lock_v1,
+};
+
+#[derive(Default)]
+struct ClientState {
+    compositor: Option<wl_compositor::WlCompositor>,
+    shm: Option<wl_shm::WlShm>,
+    /// Every output, in the order announced
+    outputs: Vec<wl_output::WlOutput>,
+    lock_manager: Option<ext_session_lock_manager_v1::ExtSessionLockManagerV1>,
+    /// Configures (serial, width, height) per lock surface, by the index
+    /// passed as user data
+    configures: HashMap<usize, Vec<(u32, u32, u32)>>,
+    locked: bool,
+    finished: bool,
+}
+
+impl ClientState {
+    fn last_configure(&self, index: usize) -> (u32, u32, u32) {
+        *self.configures.get(&index).and_then(|c| c.last()).expect("lock surface not configured")
+    }
+}
+
+impl Dispatch<wl_registry::WlRegistry, ()> for ClientState {
+    fn event(
+        state: &mut Self,
+        proxy: &wl_registry::WlRegistry,
+        event: wl_registry::Event,
+        _data: &(),
+        _conn: &Connection,
+        qh: &QueueHandle<Self>,
+    ) {
+        if let wl_registry::Event::Global { name, interface, version } = event {
+            if interface == "wl_compositor" {
+                state.compositor = Some(proxy.bind(name, version, qh, ()));
+            } else if interface == "wl_shm" {
+                state.shm = Some(proxy.bind(name, version, qh, ()));
+            } else if interface == "wl_output" {
+                state.outputs.push(proxy.bind(name, version, qh, ()));
+            } else if interface == "ext_session_lock_manager_v1" {
+                state.lock_manager = Some(proxy.bind(name, version, qh, ()));
+            }
+        }
+    }
+}
+
+impl Dispatch<wl_compositor::WlCompositor, ()> for ClientState {
+    fn event(_: &mut Self, _: &wl_compositor::WlCompositor, _: wl_compositor::Event, _: &(), _: &Connection, _: &QueueHandle<Self>) {}
+}
+
+impl Dispatch<wl_surface::WlSurface, ()> for ClientState {
+    fn event(_: &mut Self, _: &wl_surface::WlSurface, _: wl_surface::Event, _: &(), _: &Connection, _: &QueueHandle<Self>) {}
+}
+
+impl Dispatch<wl_shm::WlShm, ()> for ClientState {
+    fn event(_: &mut Self, _: &wl_shm::WlShm, _: wl_shm::Event, _: &(), _: &Connection, _: &QueueHandle<Self>) {}
+}
+
+impl Dispatch<wl_shm_pool::WlShmPool, ()> for ClientState {
+    fn event(_: &mut Self, _: &wl_shm_pool::WlShmPool, _: wl_shm_pool::Event, _: &(), _: &Connection, _: &QueueHandle<Self>) {}
+}
+
+impl Dispatch<wl_buffer::WlBuffer, ()> for ClientState {
+    fn event(_: &mut Self, _: &wl_buffer::WlBuffer, _: wl_buffer::Event, _: &(), _: &Connection, _: &QueueHandle<Self>) {}
+}
+
+impl Dispatch<wl_output::WlOutput, ()> for ClientState {
+    fn event(_: &mut Self, _: &wl_output::WlOutput, _: wl_output::Event, _: &(), _: &Connection, _: &QueueHandle<Self>) {}
+}
+
+impl Dispatch<wl_callback::WlCallback, ()> for ClientState {
+    fn event(_: &mut Self, _: &wl_callback::WlCallback, _: wl_callback::Event, _: &(), _: &Connection, _: &QueueHandle<Self>) {}
+}
+
+impl Dispatch<ext_session_lock_manager_v1::ExtSessionLockManagerV1, ()> for ClientState {
+    fn event(_: &mut Self, _: &ext_session_lock_manager_v1::ExtSessionLockManagerV1, _: ext_session_lock_manager_v1::Event, _: &(), _: &Connection, _: &QueueHandle<Self>) {}
+}
+
+impl Dispatch<ext_session_lock_v1::ExtSessionLockV1, ()> for ClientState {
+    fn event(
+        state: &mut Self,
+        _proxy: &ext_session_lock_v1::ExtSessionLockV1,
+        event: ext_session_lock_v1::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        match event {
+            ext_session_lock_v1::Event::Locked => state.locked = true,
+            ext_session_lock_v1::Event::Finished => state.finished = true,
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<ext_session_lock_surface_v1::ExtSessionLockSurfaceV1, usize> for ClientState {
+    fn event(
+        state: &mut Self,
+        _proxy: &ext_session_lock_surface_v1::ExtSessionLockSurfaceV1,
+        event: ext_session_lock_surface_v1::Event,
+        index: &usize,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        if let ext_session_lock_surface_v1::Event::Configure { serial, width, height } = event {
+            state.configures.entry(*index).or_default().push((serial, width, height));
+        }
+    }
+}
+
+fn setup() -> (TestEnv, EventQueue<ClientState>, ClientState) {
+    let mut env = TestEnv::new();
+    let display = env.client.display();
+    let mut event_queue = env.client.new_event_queue::<ClientState>();
+    let qh = event_queue.handle();
+    let _registry = display.get_registry(&qh, ());
+    let mut client_state = ClientState::default();
+    env.wait_roundtrip(&mut event_queue, &mut client_state);
+    (env, event_queue, client_state)
+}
+
+fn create_buffer(client_state: &ClientState, qh: &QueueHandle<ClientState>, width: u32, height: u32) -> wl_buffer::WlBuffer {
+    let size = (width * height * 4) as i32;
+    let temp = tempfile::tempfile().unwrap();
+    temp.set_len(size as u64).unwrap();
+    let pool = client_state.shm.as_ref().unwrap().create_pool(temp.as_fd(), size, qh, ());
+    pool.create_buffer(0, width as i32, height as i32, width as i32 * 4, wl_shm::Format::Argb8888, qh, ())
+}
+
+/// Add an output at `x` and announce its wl_output global
+fn add_output(env: &mut TestEnv, id: u32, x: i32, width: u32, height: u32) {
+    let mut output = OutputState::new(id, format!("output-{}", id), width, height);
+    output.x = x;
+    env.state.outputs.push(output);
+    env.display.handle().create_global::<crate::core::state::CompositorState, wl_output::WlOutput, OutputGlobal>(3, OutputGlobal::new(id));
+}
+
+/// Create a lock surface on output `index`, named by that index
+fn create_lock_surface(
+    client_state: &ClientState,
+    qh: &QueueHandle<ClientState>,
+    lock: &ext_session_lock_v1::ExtSessionLockV1,
+    index: usize,
+) -> (wl_surface::WlSurface, ext_session_lock_surface_v1::ExtSessionLockSurfaceV1) {
+    let surface = client_state.compositor.as_ref().unwrap().create_surface(qh, ());
+    let lock_surface = lock.get_lock_surface(&surface, &client_state.outputs[index], qh, index);
+    (surface, lock_surface)
+}
+
+/// Ack the latest configure of lock surface `index` and commit a buffer of
+/// the configured size
+fn map_lock_surface(
+    client_state: &ClientState,
+    qh: &QueueHandle<ClientState>,
+    (surface, lock_surface): &(wl_surface::WlSurface, ext_session_lock_surface_v1::ExtSessionLockSurfaceV1),
+    index: usize,
+) {
+    let (serial, width, height) = client_state.last_configure(index);
+    lock_surface.ack_configure(serial);
+    surface.attach(Some(&create_buffer(client_state, qh, width, height)), 0, 0);
+    surface.commit();
+}
+
+/// Lock with one surface on the only output, mapped after its first ack
+fn lock_session(
+    env: &mut TestEnv,
+    event_queue: &mut EventQueue<ClientState>,
+    client_state: &mut ClientState,
+) -> (ext_session_lock_v1::ExtSessionLockV1, (wl_surface::WlSurface, ext_session_lock_surface_v1::ExtSessionLockSurfaceV1)) {
+    let qh = event_queue.handle();
+    let lock = client_state.lock_manager.as_ref().unwrap().lock(&qh, ());
+    let lock_surface = create_lock_surface(client_state, &qh, &lock, 0);
+    env.wait_roundtrip(event_queue, client_state);
+    assert!(matches!(env.state.ext.session_lock.status, LockStatus::Pending(_)));
+    assert!(!client_state.locked);
+
+    map_lock_surface(client_state, &qh, &lock_surface, 0);
+    env.wait_roundtrip(event_queue, client_state);
+    (lock, lock_surface)
+}
+
+#[test]
+fn test_session_lock_and_unlock() {
+    let (mut env, mut event_queue, mut client_state) = setup();
+    let (lock, _lock_surface) = lock_session(&mut env, &mut event_queue, &mut client_state);
+
+    // Every output shows a mapped lock surface
+    assert!(client_state.locked);
+    assert!(matches!(env.state.ext.session_lock.status, LockStatus::Locked(_)));
+    assert!(env.state.seat.keyboard.focus.is_some_and(|sid| env.state.ext.session_lock.is_lock_surface(sid)));
+
+    // A second locker is turned away while the first holds the session
+    let qh = event_queue.handle();
+    let _second = client_state.lock_manager.as_ref().unwrap().lock(&qh, ());
+    env.wait_roundtrip(&mut event_queue, &mut client_state);
+    assert!(client_state.finished);
+    assert!(matches!(env.state.ext.session_lock.status, LockStatus::Locked(_)));
+
+    lock.unlock_and_destroy();
+    env.wait_roundtrip(&mut event_queue, &mut client_state);
+    assert!(!env.state.ext.session_lock.is_locked());
+    assert!(env.state.ext.session_lock.lock_surfaces.is_empty());
+    assert!(env.client.protocol_error().is_none());
+}
+
+#[test]
+fn test_session_lock_destroy_after_locked() {
+    let (mut env, mut event_queue, mut client_state) = setup();
+    let (lock, _lock_surface) = lock_session(&mut env, &mut event_queue, &mut client_state);
+    assert!(client_state.locked);
+
+    // Only unlock_and_destroy may end a confirmed lock; the locker is
+    // disconnected and the session stays locked behind the fallback colour
+    lock.destroy();
+    env.wait_roundtrip(&mut event_queue, &mut client_state);
+    let error = env.client.protocol_error().expect("destroy after locked must be a protocol error");
+    assert_eq!(error.code, ext_session_lock_v1::Error::InvalidDestroy as u32);
+    assert!(matches!(env.state.ext.session_lock.status, LockStatus::Abandoned));
+    assert!(env.state.ext.session_lock.lock_surfaces.is_empty());
+    assert!(env.state.seat.keyboard.focus.is_none());
+}
+
+#[test]
+fn test_session_lock_unlock_before_locked() {
+    let (mut env, mut event_queue, mut client_state) = setup();
+    let qh = event_queue.handle();
+    let lock = client_state.lock_manager.as_ref().unwrap().lock(&qh, ());
+    env.wait_roundtrip(&mut event_queue, &mut client_state);
+    assert!(matches!(env.state.ext.session_lock.status, LockStatus::Pending(_)));
+
+    lock.unlock_and_destroy();
+    env.wait_roundtrip(&mut event_queue, &mut client_state);
+    let error = env.client.protocol_error().expect("unlock before locked must be a protocol error");
+    assert_eq!(error.code, ext_session_lock_v1::Error::InvalidUnlock as u32);
+    assert!(env.state.ext.session_lock.is_locked());
+}
+
+#[test]
+fn test_lock_surface_commit_before_ack() {
+    let (mut env, mut event_queue, mut client_state) = setup();
+    let qh = event_queue.handle();
+    let lock = client_state.lock_manager.as_ref().unwrap().lock(&qh, ());
+    let (surface, _lock_surface) = create_lock_surface(&client_state, &qh, &lock, 0);
+    surface.commit();
+    env.wait_roundtrip(&mut event_queue, &mut client_state);
+
+    let error = env.client.protocol_error().expect("commit before ack must be a protocol error");
+    assert_eq!(error.object_interface, "ext_session_lock_surface_v1");
+    assert_eq!(error.code, ext_session_lock_surface_v1::Error::CommitBeforeFirstAck as u32);
+}
+
+#[test]
+fn test_lock_surface_with_buffer() {
+    let (mut env, mut event_queue, mut client_state) = setup();
+    let qh = event_queue.handle();
+    let surface = client_state.compositor.as_ref().unwrap().create_surface(&qh, ());
+    surface.attach(Some(&create_buffer(&client_state, &qh, 32, 32)), 0, 0);
+    let lock = client_state.lock_manager.as_ref().unwrap().lock(&qh, ());
+    let _lock_surface = lock.get_lock_surface(&surface, &client_state.outputs[0], &qh, 0);
+    env.wait_roundtrip(&mut event_queue, &mut client_state);
+
+    let error = env.client.protocol_error().expect("a surface with a buffer can't become a lock surface");
+    assert_eq!(error.code, ext_session_lock_v1::Error::AlreadyConstructed as u32);
+    assert!(env.state.ext.session_lock.lock_surfaces.is_empty());
+}
+
+#[test]
+fn test_session_lock_every_output() {
+    let (mut env, mut event_queue, mut client_state) = setup();
+    add_output(&mut env, 1, 1920, 1280, 720);
+    env.wait_roundtrip(&mut event_queue, &mut client_state);
+    assert_eq!(client_state.outputs.len(), 2);
+    let qh = event_queue.handle();
+
+    let lock = client_state.lock_manager.as_ref().unwrap().lock(&qh, ());
+    let first = create_lock_surface(&client_state, &qh, &lock, 0);
+    let second = create_lock_surface(&client_state, &qh, &lock, 1);
+    env.wait_roundtrip(&mut event_queue, &mut client_state);
+
+    // Each lock surface is configured to the size of its own output
+    let primary = env.state.primary_output();
+    let (_, width, height) = client_state.last_configure(0);
+    assert_eq!((width, height), (primary.width, primary.height));
+    let (_, width, height) = client_state.last_configure(1);
+    assert_eq!((width, height), (1280, 720));
+
+    // One covered output isn't enough to confirm the lock
+    map_lock_surface(&client_state, &qh, &first, 0);
+    env.wait_roundtrip(&mut event_queue, &mut client_state);
+    assert!(!client_state.locked);
+    assert!(matches!(env.state.ext.session_lock.status, LockStatus::Pending(_)));
+
+    map_lock_surface(&client_state, &qh, &second, 1);
+    env.wait_roundtrip(&mut event_queue, &mut client_state);
+    assert!(client_state.locked);
+    assert!(matches!(env.state.ext.session_lock.status, LockStatus::Locked(_)));
+    assert!(env.client.protocol_error().is_none());
+}
+
+#[test]
+fn test_session_lock_output_resized() {
+    let (mut env, mut event_queue, mut client_state) = setup();
+    let (_lock, lock_surface) = lock_session(&mut env, &mut event_queue, &mut client_state);
+    let qh = event_queue.handle();
+    let (old_serial, old_width, old_height) = client_state.last_configure(0);
+
+    // A resized output reconfigures its lock surface
+    let output_id = env.state.primary_output().id;
+    env.state.update_output_configuration(output_id, Some(1024), Some(768), None, None, None, None);
+    env.wait_roundtrip(&mut event_queue, &mut client_state);
+    let (serial, width, height) = client_state.last_configure(0);
+    assert_ne!(serial, old_serial);
+    assert_eq!((width, height), (1024, 768));
+
+    // Until the new size is acked, buffers of the old one are still right
+    let (surface, _) = &lock_surface;
+    surface.attach(Some(&create_buffer(&client_state, &qh, old_width, old_height)), 0, 0);
+    surface.commit();
+    env.wait_roundtrip(&mut event_queue, &mut client_state);
+    assert!(env.client.protocol_error().is_none());
+
+    map_lock_surface(&client_state, &qh, &lock_surface, 0);
+    env.wait_roundtrip(&mut event_queue, &mut client_state);
+    assert!(env.client.protocol_error().is_none());
+    assert!(matches!(env.state.ext.session_lock.status, LockStatus::Locked(_)));
+}
+
+#[test]
+fn test_session_lock_output_added() {
+    let (mut env, mut event_queue, mut client_state) = setup();
+    let (lock, _first) = lock_session(&mut env, &mut event_queue, &mut client_state);
+    assert!(client_state.locked);
+    let qh = event_queue.handle();
+
+    // A new output is covered by the fallback colour until the locker
+    // gives it a lock surface of its size
+    add_output(&mut env, 1, 1920, 1280, 720);
+    env.state.session_lock_outputs_changed();
+    env.wait_roundtrip(&mut event_queue, &mut client_state);
+    assert!(matches!(env.state.ext.session_lock.status, LockStatus::Locked(_)));
+
+    let second = create_lock_surface(&client_state, &qh, &lock, 1);
+    env.wait_roundtrip(&mut event_queue, &mut client_state);
+    let (_, width, height) = client_state.last_configure(1);
+    assert_eq!((width, height), (1280, 720));
+    map_lock_surface(&client_state, &qh, &second, 1);
+    env.wait_roundtrip(&mut event_queue, &mut client_state);
+    assert!(env.client.protocol_error().is_none());
+    assert_eq!(env.state.ext.session_lock.lock_surfaces.len(), 2);
+}
+
+#[test]
+fn test_lock_surface_ack_older_serial() {
+    let (mut env, mut event_queue, mut client_state) = setup();
+    let qh = event_queue.handle();
+    let lock = client_state.lock_manager.as_ref().unwrap().lock(&qh, ());
+    let (surface, lock_surface) = create_lock_surface(&client_state, &qh, &lock, 0);
+    env.wait_roundtrip(&mut event_queue, &mut client_state);
+    let (first_serial, width, height) = client_state.last_configure(0);
+
+    // Reconfigured before the first ack
+    let output_id = env.state.primary_output().id;
+    env.state.update_output_configuration(output_id, Some(1024), Some(768), None, None, None, None);
+    env.wait_roundtrip(&mut event_queue, &mut client_state);
+    assert_eq!(client_state.configures[&0].len(), 2);
+
+    // Acking the older configure is fine, and sizes buffers by it
+    lock_surface.ack_configure(first_serial);
+    surface.attach(Some(&create_buffer(&client_state, &qh, width, height)), 0, 0);
+    surface.commit();
+    env.wait_roundtrip(&mut event_queue, &mut client_state);
+    assert!(env.client.protocol_error().is_none());
+}
+
+#[test]
+fn test_lock_surface_invalid_serial() {
+    let (mut env, mut event_queue, mut client_state) = setup();
+    let qh = event_queue.handle();
+    let lock = client_state.lock_manager.as_ref().unwrap().lock(&qh, ());
+    let (_surface, lock_surface) = create_lock_surface(&client_state, &qh, &lock, 0);
+    env.wait_roundtrip(&mut event_queue, &mut client_state);
+
+    let (serial, _, _) = client_state.last_configure(0);
+    lock_surface.ack_configure(serial + 100);
+    env.wait_roundtrip(&mut event_queue, &mut client_state);
+    let error = env.client.protocol_error().expect("acking a serial never sent must be a protocol error");
+    assert_eq!(error.object_interface, "ext_session_lock_surface_v1");
+    assert_eq!(error.code, ext_session_lock_surface_v1::Error::InvalidSerial as u32);
+}
+
+#[test]
+fn test_lock_surface_dimensions_mismatch() {
+    let (mut env, mut event_queue, mut client_state) = setup();
+    let qh = event_queue.handle();
+    let lock = client_state.lock_manager.as_ref().unwrap().lock(&qh, ());
+    let (surface, lock_surface) = create_lock_surface(&client_state, &qh, &lock, 0);
+    env.wait_roundtrip(&mut event_queue, &mut client_state);
+
+    let (serial, _, _) = client_state.last_configure(0);
+    lock_surface.ack_configure(serial);
+    surface.attach(Some(&create_buffer(&client_state, &qh, 32, 32)), 0, 0);
+    surface.commit();
+    env.wait_roundtrip(&mut event_queue, &mut client_state);
+    let error = env.client.protocol_error().expect("a buffer of another size must be a protocol error");
+    assert_eq!(error.object_interface, "ext_session_lock_surface_v1");
+    assert_eq!(error.code, ext_session_lock_surface_v1::Error::DimensionsMismatch as u32);
+    assert!(!client_state.locked);
+}
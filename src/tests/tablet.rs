@@ -0,0 +1,193 @@
+use crate::tests::harness::TestEnv;
+use crate::core::wayland::ext::tablet::{TabletToolEvent, TabletToolInfo, TabletToolKind};
+use wayland_client::{
+    protocol::{wl_compositor, wl_registry, wl_callback, wl_seat, wl_surface},
+    Connection, Dispatch, Proxy, QueueHandle, WEnum,
+};
+use wayland_protocols::wp::tablet::zv2::client::{
+    zwp_tablet_manager_v2, zwp_tablet_seat_v2, zwp_tablet_tool_v2, zwp_tablet_v2,
+};
+use zwp_tablet_tool_v2::{Capability, ZwpTabletToolV2};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ToolEvent {
+    ProximityIn,
+    ProximityOut,
+    Motion,
+    Pressure,
+    Distance,
+    Tilt,
+    Rotation,
+    Frame,
+}
+
+struct Tool {
+    proxy: ZwpTabletToolV2,
+    kind: Option<zwp_tablet_tool_v2::Type>,
+    capabilities: Vec<Capability>,
+    events: Vec<ToolEvent>,
+}
+
+#[derive(Default)]
+struct ClientState {
+    compositor: Option<wl_compositor::WlCompositor>,
+    seat: Option<wl_seat::WlSeat>,
+    tablet_manager: Option<zwp_tablet_manager_v2::ZwpTabletManagerV2>,
+    tools: Vec<Tool>,
+}
+
+impl ClientState {
+    fn tool(&self, kind: zwp_tablet_tool_v2::Type) -> &Tool {
+        self.tools.iter().find(|t| t.kind == Some(kind)).expect("tool not announced")
+    }
+}
+
+impl Dispatch<wl_registry::WlRegistry, ()> for ClientState {
+    fn event(
+        state: &mut Self,
+        proxy: &wl_registry::WlRegistry,
+        event: wl_registry::Event,
+        _data: &(),
+        _conn: &Connection,
+        qh: &QueueHandle<Self>,
+    ) {
+        if let wl_registry::Event::Global { name, interface, version } = event {
+            if interface == "wl_compositor" {
+                state.compositor = Some(proxy.bind(name, version, qh, ()));
+            } else if interface == "wl_seat" {
+                state.seat = Some(proxy.bind(name, version, qh, ()));
+            } else if interface == "zwp_tablet_manager_v2" {
+                state.tablet_manager = Some(proxy.bind(name, version, qh, ()));
+            }
+        }
+    }
+}
+
+impl Dispatch<wl_compositor::WlCompositor, ()> for ClientState {
+    fn event(_: &mut Self, _: &wl_compositor::WlCompositor, _: wl_compositor::Event, _: &(), _: &Connection, _: &QueueHandle<Self>) {}
+}
+
+impl Dispatch<wl_surface::WlSurface, ()> for ClientState {
+    fn event(_: &mut Self, _: &wl_surface::WlSurface, _: wl_surface::Event, _: &(), _: &Connection, _: &QueueHandle<Self>) {}
+}
+
+impl Dispatch<wl_seat::WlSeat, ()> for ClientState {
+    fn event(_: &mut Self, _: &wl_seat::WlSeat, _: wl_seat::Event, _: &(), _: &Connection, _: &QueueHandle<Self>) {}
+}
+
+impl Dispatch<wl_callback::WlCallback, ()> for ClientState {
+    fn event(_: &mut Self, _: &wl_callback::WlCallback, _: wl_callback::Event, _: &(), _: &Connection, _: &QueueHandle<Self>) {}
+}
+
+impl Dispatch<zwp_tablet_manager_v2::ZwpTabletManagerV2, ()> for ClientState {
+    fn event(_: &mut Self, _: &zwp_tablet_manager_v2::ZwpTabletManagerV2, _: zwp_tablet_manager_v2::Event, _: &(), _: &Connection, _: &QueueHandle<Self>) {}
+}
+
+impl Dispatch<zwp_tablet_v2::ZwpTabletV2, ()> for ClientState {
+    fn event(_: &mut Self, _: &zwp_tablet_v2::ZwpTabletV2, _: zwp_tablet_v2::Event, _: &(), _: &Connection, _: &QueueHandle<Self>) {}
+}
+
+impl Dispatch<zwp_tablet_seat_v2::ZwpTabletSeatV2, ()> for ClientState {
+    fn event(
+        state: &mut Self,
+        _proxy: &zwp_tablet_seat_v2::ZwpTabletSeatV2,
+        event: zwp_tablet_seat_v2::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        if let zwp_tablet_seat_v2::Event::ToolAdded { id } = event {
+            state.tools.push(Tool { proxy: id, kind: None, capabilities: Vec::new(), events: Vec::new() });
+        }
+    }
+
+    wayland_client::event_created_child!(ClientState, zwp_tablet_seat_v2::ZwpTabletSeatV2, [
+        zwp_tablet_seat_v2::EVT_TABLET_ADDED_OPCODE => (zwp_tablet_v2::ZwpTabletV2, ()),
+        zwp_tablet_seat_v2::EVT_TOOL_ADDED_OPCODE => (ZwpTabletToolV2, ()),
+    ]);
+}
+
+impl Dispatch<ZwpTabletToolV2, ()> for ClientState {
+    fn event(
+        state: &mut Self,
+        proxy: &ZwpTabletToolV2,
+        event: zwp_tablet_tool_v2::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        let Some(tool) = state.tools.iter_mut().find(|t| t.proxy.id() == proxy.id()) else { return };
+        let recorded = match event {
+            zwp_tablet_tool_v2::Event::Type { tool_type: WEnum::Value(kind) } => {
+                tool.kind = Some(kind);
+                return;
+            }
+            zwp_tablet_tool_v2::Event::Capability { capability: WEnum::Value(cap) } => {
+                tool.capabilities.push(cap);
+                return;
+            }
+            zwp_tablet_tool_v2::Event::ProximityIn { .. } => ToolEvent::ProximityIn,
+            zwp_tablet_tool_v2::Event::ProximityOut => ToolEvent::ProximityOut,
+            zwp_tablet_tool_v2::Event::Motion { .. } => ToolEvent::Motion,
+            zwp_tablet_tool_v2::Event::Pressure { .. } => ToolEvent::Pressure,
+            zwp_tablet_tool_v2::Event::Distance { .. } => ToolEvent::Distance,
+            zwp_tablet_tool_v2::Event::Tilt { .. } => ToolEvent::Tilt,
+            zwp_tablet_tool_v2::Event::Rotation { .. } => ToolEvent::Rotation,
+            zwp_tablet_tool_v2::Event::Frame { .. } => ToolEvent::Frame,
+            _ => return,
+        };
+        tool.events.push(recorded);
+    }
+}
+
+#[test]
+fn test_tablet_tools() {
+    let mut env = TestEnv::new();
+    let display = env.client.display();
+    let mut event_queue = env.client.new_event_queue::<ClientState>();
+    let qh = event_queue.handle();
+
+    let _registry = display.get_registry(&qh, ());
+    let mut client_state = ClientState::default();
+    env.wait_roundtrip(&mut event_queue, &mut client_state);
+
+    let seat = client_state.seat.clone().unwrap();
+    let _tablet_seat = client_state.tablet_manager.as_ref().unwrap().get_tablet_seat(&seat, &qh, ());
+    let _surface = client_state.compositor.as_ref().unwrap().create_surface(&qh, ());
+    env.wait_roundtrip(&mut event_queue, &mut client_state);
+    let surface_id = *env.state.surfaces.keys().next().unwrap();
+
+    // A pen without a hardware serial that only reports pressure: the
+    // other axes of the sample are dropped
+    let pen = TabletToolInfo { kind: TabletToolKind::Pen, has_pressure: true, ..Default::default() };
+    env.state.inject_tablet_tool_on_surface(surface_id, &[
+        TabletToolEvent::ProximityIn { tool: pen, x: 10.0, y: 10.0 },
+        TabletToolEvent::Pressure(0.5),
+        TabletToolEvent::Tilt { x: 5.0, y: -5.0 },
+        TabletToolEvent::Distance(0.2),
+        TabletToolEvent::Rotation(90.0),
+    ], 100);
+    env.wait_roundtrip(&mut event_queue, &mut client_state);
+
+    let pen = client_state.tool(zwp_tablet_tool_v2::Type::Pen);
+    assert_eq!(pen.capabilities, vec![Capability::Pressure]);
+    assert_eq!(pen.events, vec![ToolEvent::ProximityIn, ToolEvent::Motion, ToolEvent::Pressure, ToolEvent::Frame]);
+
+    // The eraser end of the same stylus shares its (zero) serial but is a
+    // tool of its own, with its own object
+    let eraser = TabletToolInfo { kind: TabletToolKind::Eraser, has_tilt: true, ..Default::default() };
+    env.state.inject_tablet_tool_on_surface(surface_id, &[
+        TabletToolEvent::ProximityIn { tool: eraser, x: 12.0, y: 12.0 },
+        TabletToolEvent::Pressure(0.5),
+        TabletToolEvent::Tilt { x: 5.0, y: -5.0 },
+    ], 200);
+    env.wait_roundtrip(&mut event_queue, &mut client_state);
+
+    assert_eq!(client_state.tools.len(), 2);
+    let pen = client_state.tool(zwp_tablet_tool_v2::Type::Pen);
+    // The pen's proximity_out closes with a frame of its own
+    assert_eq!(pen.events[4..], [ToolEvent::ProximityOut, ToolEvent::Frame]);
+    let eraser = client_state.tool(zwp_tablet_tool_v2::Type::Eraser);
+    assert_eq!(eraser.capabilities, vec![Capability::Tilt]);
+    assert_eq!(eraser.events, vec![ToolEvent::ProximityIn, ToolEvent::Motion, ToolEvent::Tilt, ToolEvent::Frame]);
+}
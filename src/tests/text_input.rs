@@ -0,0 +1,406 @@
+use crate::tests::harness::TestEnv;
+use wayland_client::{
+    backend::ObjectId,
+    protocol::{wl_callback, wl_compositor, wl_registry, wl_seat, wl_surface},
+    Connection, Dispatch, EventQueue, Proxy, QueueHandle,
+};
+use wayland_server::Resource as _;
+use wayland_protocols::wp::text_input::zv1::client::{zwp_text_input_manager_v1, zwp_text_input_v1};
+use wayland_protocols::wp::text_input::zv3::client::{zwp_text_input_manager_v3, zwp_text_input_v3};
+use wayland_protocols_misc::zwp_input_method_v2::client::{zwp_input_method_manager_v2, zwp_input_method_v2};
+use wayland_protocols_plasma::text_input::v2::client::{zwp_text_input_manager_v2, zwp_text_input_v2};
+
+#[derive(Debug, Clone, PartialEq)]
+enum TextInputEvent {
+    Enter(ObjectId),
+    Leave(ObjectId),
+    CommitString(Option<String>),
+    Done(u32),
+    // zwp_text_input_v1 and zwp_text_input_v2 carry serials
+    LeaveV1,
+    CommitStringV1(u32, String),
+    EnterV2(u32, ObjectId),
+    LeaveV2(u32, ObjectId),
+}
+
+#[derive(Default)]
+struct ClientState {
+    compositor: Option<wl_compositor::WlCompositor>,
+    seat: Option<wl_seat::WlSeat>,
+    text_input_manager: Option<zwp_text_input_manager_v3::ZwpTextInputManagerV3>,
+    text_input_manager_v1: Option<zwp_text_input_manager_v1::ZwpTextInputManagerV1>,
+    text_input_manager_v2: Option<zwp_text_input_manager_v2::ZwpTextInputManagerV2>,
+    input_method_manager: Option<zwp_input_method_manager_v2::ZwpInputMethodManagerV2>,
+    events: Vec<TextInputEvent>,
+    /// Surrounding text the input method was sent
+    input_method_surrounding: Vec<String>,
+}
+
+impl Dispatch<wl_registry::WlRegistry, ()> for ClientState {
+    fn event(
+        state: &mut Self,
+        proxy: &wl_registry::WlRegistry,
+        event: wl_registry::Event,
+        _data: &(),
+        _conn: &Connection,
+        qh: &QueueHandle<Self>,
+    ) {
+        if let wl_registry::Event::Global { name, interface, version } = event {
+            if interface == "wl_compositor" {
+                state.compositor = Some(proxy.bind(name, version, qh, ()));
+            } else if interface == "wl_seat" {
+                state.seat = Some(proxy.bind(name, version, qh, ()));
+            } else if interface == "zwp_text_input_manager_v3" {
+                state.text_input_manager = Some(proxy.bind(name, version, qh, ()));
+            } else if interface == "zwp_text_input_manager_v1" {
+                state.text_input_manager_v1 = Some(proxy.bind(name, version, qh, ()));
+            } else if interface == "zwp_text_input_manager_v2" {
+                state.text_input_manager_v2 = Some(proxy.bind(name, version, qh, ()));
+            } else if interface == "zwp_input_method_manager_v2" {
+                state.input_method_manager = Some(proxy.bind(name, version, qh, ()));
+            }
+        }
+    }
+}
+
+impl Dispatch<wl_compositor::WlCompositor, ()> for ClientState {
+    fn event(_: &mut Self, _: &wl_compositor::WlCompositor, _: wl_compositor::Event, _: &(), _: &Connection, _: &QueueHandle<Self>) {}
+}
+
+impl Dispatch<wl_surface::WlSurface, ()> for ClientState {
+    fn event(_: &mut Self, _: &wl_surface::WlSurface, _: wl_surface::Event, _: &(), _: &Connection, _: &QueueHandle<Self>) {}
+}
+
+impl Dispatch<wl_seat::WlSeat, ()> for ClientState {
+    fn event(_: &mut Self, _: &wl_seat::WlSeat, _: wl_seat::Event, _: &(), _: &Connection, _: &QueueHandle<Self>) {}
+}
+
+impl Dispatch<wl_callback::WlCallback, ()> for ClientState {
+    fn event(_: &mut Self, _: &wl_callback::WlCallback, _: wl_callback::Event, _: &(), _: &Connection, _: &QueueHandle<Self>) {}
+}
+
+impl Dispatch<zwp_text_input_manager_v3::ZwpTextInputManagerV3, ()> for ClientState {
+    fn event(_: &mut Self, _: &zwp_text_input_manager_v3::ZwpTextInputManagerV3, _: zwp_text_input_manager_v3::Event, _: &(), _: &Connection, _: &QueueHandle<Self>) {}
+}
+
+impl Dispatch<zwp_text_input_v3::ZwpTextInputV3, ()> for ClientState {
+    fn event(
+        state: &mut Self,
+        _proxy: &zwp_text_input_v3::ZwpTextInputV3,
+        event: zwp_text_input_v3::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        let recorded = match event {
+            zwp_text_input_v3::Event::Enter { surface } => TextInputEvent::Enter(surface.id()),
+            zwp_text_input_v3::Event::Leave { surface } => TextInputEvent::Leave(surface.id()),
+            zwp_text_input_v3::Event::CommitString { text } => TextInputEvent::CommitString(text),
+            zwp_text_input_v3::Event::Done { serial } => TextInputEvent::Done(serial),
+            _ => return,
+        };
+        state.events.push(recorded);
+    }
+}
+
+impl Dispatch<zwp_text_input_manager_v1::ZwpTextInputManagerV1, ()> for ClientState {
+    fn event(_: &mut Self, _: &zwp_text_input_manager_v1::ZwpTextInputManagerV1, _: zwp_text_input_manager_v1::Event, _: &(), _: &Connection, _: &QueueHandle<Self>) {}
+}
+
+impl Dispatch<zwp_text_input_v1::ZwpTextInputV1, ()> for ClientState {
+    fn event(
+        state: &mut Self,
+        _proxy: &zwp_text_input_v1::ZwpTextInputV1,
+        event: zwp_text_input_v1::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        let recorded = match event {
+            zwp_text_input_v1::Event::Enter { surface } => TextInputEvent::Enter(surface.id()),
+            zwp_text_input_v1::Event::Leave => TextInputEvent::LeaveV1,
+            zwp_text_input_v1::Event::CommitString { serial, text } => TextInputEvent::CommitStringV1(serial, text),
+            _ => return,
+        };
+        state.events.push(recorded);
+    }
+}
+
+impl Dispatch<zwp_text_input_manager_v2::ZwpTextInputManagerV2, ()> for ClientState {
+    fn event(_: &mut Self, _: &zwp_text_input_manager_v2::ZwpTextInputManagerV2, _: zwp_text_input_manager_v2::Event, _: &(), _: &Connection, _: &QueueHandle<Self>) {}
+}
+
+impl Dispatch<zwp_text_input_v2::ZwpTextInputV2, ()> for ClientState {
+    fn event(
+        state: &mut Self,
+        _proxy: &zwp_text_input_v2::ZwpTextInputV2,
+        event: zwp_text_input_v2::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        let recorded = match event {
+            zwp_text_input_v2::Event::Enter { serial, surface } => TextInputEvent::EnterV2(serial, surface.id()),
+            zwp_text_input_v2::Event::Leave { serial, surface } => TextInputEvent::LeaveV2(serial, surface.id()),
+            zwp_text_input_v2::Event::CommitString { text } => TextInputEvent::CommitString(Some(text)),
+            _ => return,
+        };
+        state.events.push(recorded);
+    }
+}
+
+impl Dispatch<zwp_input_method_manager_v2::ZwpInputMethodManagerV2, ()> for ClientState {
+    fn event(_: &mut Self, _: &zwp_input_method_manager_v2::ZwpInputMethodManagerV2, _: zwp_input_method_manager_v2::Event, _: &(), _: &Connection, _: &QueueHandle<Self>) {}
+}
+
+impl Dispatch<zwp_input_method_v2::ZwpInputMethodV2, ()> for ClientState {
+    fn event(
+        state: &mut Self,
+        _proxy: &zwp_input_method_v2::ZwpInputMethodV2,
+        event: zwp_input_method_v2::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        if let zwp_input_method_v2::Event::SurroundingText { text, .. } = event {
+            state.input_method_surrounding.push(text);
+        }
+    }
+}
+
+fn setup() -> (TestEnv, EventQueue<ClientState>, ClientState) {
+    let mut env = TestEnv::new();
+    let display = env.client.display();
+    let mut event_queue = env.client.new_event_queue::<ClientState>();
+    let qh = event_queue.handle();
+    let _registry = display.get_registry(&qh, ());
+    let mut client_state = ClientState::default();
+    env.wait_roundtrip(&mut event_queue, &mut client_state);
+    (env, event_queue, client_state)
+}
+
+/// Create a surface and return it with its internal surface id
+fn create_surface(
+    env: &mut TestEnv,
+    event_queue: &mut EventQueue<ClientState>,
+    client_state: &mut ClientState,
+) -> (wl_surface::WlSurface, u32) {
+    let qh = event_queue.handle();
+    let surface = client_state.compositor.as_ref().unwrap().create_surface(&qh, ());
+    env.wait_roundtrip(event_queue, client_state);
+    let surface_id = env.state.surfaces.values()
+        .map(|s| s.read().unwrap())
+        .find(|s| s.resource.as_ref().map(|r| r.id().protocol_id()) == Some(surface.id().protocol_id()))
+        .map(|s| s.id)
+        .expect("surface not tracked");
+    (surface, surface_id)
+}
+
+#[test]
+fn test_text_input_follows_keyboard_focus() {
+    let (mut env, mut event_queue, mut client_state) = setup();
+    let qh = event_queue.handle();
+    let (first, first_id) = create_surface(&mut env, &mut event_queue, &mut client_state);
+    let (second, second_id) = create_surface(&mut env, &mut event_queue, &mut client_state);
+
+    // A text input created while its client has focus enters right away
+    env.state.set_keyboard_focus_surface(Some(first_id));
+    let seat = client_state.seat.clone().unwrap();
+    let _text_input = client_state.text_input_manager.as_ref().unwrap().get_text_input(&seat, &qh, ());
+    env.wait_roundtrip(&mut event_queue, &mut client_state);
+    assert_eq!(client_state.events, vec![TextInputEvent::Enter(first.id())]);
+
+    // Moving focus leaves the old surface before entering the new one
+    client_state.events.clear();
+    env.state.set_keyboard_focus_surface(Some(second_id));
+    env.wait_roundtrip(&mut event_queue, &mut client_state);
+    assert_eq!(client_state.events, vec![TextInputEvent::Leave(first.id()), TextInputEvent::Enter(second.id())]);
+
+    // Losing focus leaves without entering anything
+    client_state.events.clear();
+    env.state.set_keyboard_focus_surface(None);
+    env.wait_roundtrip(&mut event_queue, &mut client_state);
+    assert_eq!(client_state.events, vec![TextInputEvent::Leave(second.id())]);
+    assert_eq!(env.state.ext.text_input.focused, None);
+}
+
+#[test]
+fn test_text_input_done_serial() {
+    let (mut env, mut event_queue, mut client_state) = setup();
+    let qh = event_queue.handle();
+    let (_surface, surface_id) = create_surface(&mut env, &mut event_queue, &mut client_state);
+    env.state.set_keyboard_focus_surface(Some(surface_id));
+    let seat = client_state.seat.clone().unwrap();
+    let text_input = client_state.text_input_manager.as_ref().unwrap().get_text_input(&seat, &qh, ());
+    env.wait_roundtrip(&mut event_queue, &mut client_state);
+
+    // Nothing reaches a text input that isn't enabled
+    client_state.events.clear();
+    env.state.ext.text_input.commit_string("lost");
+    env.wait_roundtrip(&mut event_queue, &mut client_state);
+    assert!(client_state.events.is_empty());
+
+    // Enabling takes effect on commit; done carries the number of commits
+    text_input.enable();
+    text_input.commit();
+    text_input.set_cursor_rectangle(0, 0, 1, 20);
+    text_input.commit();
+    env.wait_roundtrip(&mut event_queue, &mut client_state);
+    assert!(env.state.ext.text_input.active().is_some());
+    env.state.ext.text_input.commit_string("a");
+    env.state.ext.text_input.commit_string("b");
+    env.wait_roundtrip(&mut event_queue, &mut client_state);
+    assert_eq!(client_state.events, vec![
+        TextInputEvent::CommitString(Some("a".to_string())),
+        TextInputEvent::Done(2),
+        TextInputEvent::CommitString(Some("b".to_string())),
+        TextInputEvent::Done(2),
+    ]);
+
+    // A disable only applies once committed
+    client_state.events.clear();
+    text_input.disable();
+    env.wait_roundtrip(&mut event_queue, &mut client_state);
+    env.state.ext.text_input.commit_string("c");
+    text_input.commit();
+    env.wait_roundtrip(&mut event_queue, &mut client_state);
+    env.state.ext.text_input.commit_string("d");
+    env.wait_roundtrip(&mut event_queue, &mut client_state);
+    assert_eq!(client_state.events, vec![
+        TextInputEvent::CommitString(Some("c".to_string())),
+        TextInputEvent::Done(2),
+    ]);
+    assert!(env.state.ext.text_input.active().is_none());
+}
+
+#[test]
+fn test_text_input_v1_activation() {
+    let (mut env, mut event_queue, mut client_state) = setup();
+    let qh = event_queue.handle();
+    let (surface, surface_id) = create_surface(&mut env, &mut event_queue, &mut client_state);
+    let (_other, other_id) = create_surface(&mut env, &mut event_queue, &mut client_state);
+    let seat = client_state.seat.clone().unwrap();
+    let text_input = client_state.text_input_manager_v1.as_ref().unwrap().create_text_input(&qh, ());
+
+    // Activation without focus waits for the surface to take it
+    text_input.activate(&seat, &surface);
+    env.wait_roundtrip(&mut event_queue, &mut client_state);
+    assert!(client_state.events.is_empty());
+    env.state.set_keyboard_focus_surface(Some(surface_id));
+    env.wait_roundtrip(&mut event_queue, &mut client_state);
+    assert_eq!(client_state.events, vec![TextInputEvent::Enter(surface.id())]);
+
+    // Events carry the serial of the latest committed state
+    client_state.events.clear();
+    text_input.commit_state(5);
+    env.wait_roundtrip(&mut event_queue, &mut client_state);
+    env.state.ext.text_input.commit_string("a");
+    text_input.commit_state(6);
+    env.wait_roundtrip(&mut event_queue, &mut client_state);
+    env.state.ext.text_input.commit_string("b");
+    env.wait_roundtrip(&mut event_queue, &mut client_state);
+    assert_eq!(client_state.events, vec![
+        TextInputEvent::CommitStringV1(5, "a".to_string()),
+        TextInputEvent::CommitStringV1(6, "b".to_string()),
+    ]);
+
+    // Activation outlives focus moving away and back
+    client_state.events.clear();
+    env.state.set_keyboard_focus_surface(Some(other_id));
+    env.wait_roundtrip(&mut event_queue, &mut client_state);
+    env.state.set_keyboard_focus_surface(Some(surface_id));
+    env.wait_roundtrip(&mut event_queue, &mut client_state);
+    assert_eq!(client_state.events, vec![TextInputEvent::LeaveV1, TextInputEvent::Enter(surface.id())]);
+    assert!(env.state.ext.text_input.active().is_some());
+
+    // Deactivating leaves, and nothing reaches the text input afterwards
+    client_state.events.clear();
+    text_input.deactivate(&seat);
+    env.wait_roundtrip(&mut event_queue, &mut client_state);
+    env.state.ext.text_input.commit_string("c");
+    env.wait_roundtrip(&mut event_queue, &mut client_state);
+    assert_eq!(client_state.events, vec![TextInputEvent::LeaveV1]);
+    assert!(env.state.ext.text_input.active().is_none());
+}
+
+#[test]
+fn test_text_input_v2_enter_serial() {
+    let (mut env, mut event_queue, mut client_state) = setup();
+    let qh = event_queue.handle();
+    let (first, first_id) = create_surface(&mut env, &mut event_queue, &mut client_state);
+    let (second, second_id) = create_surface(&mut env, &mut event_queue, &mut client_state);
+    env.state.set_keyboard_focus_surface(Some(first_id));
+    let seat = client_state.seat.clone().unwrap();
+    let text_input = client_state.text_input_manager_v2.as_ref().unwrap().get_text_input(&seat, &qh, ());
+    env.wait_roundtrip(&mut event_queue, &mut client_state);
+    assert_eq!(client_state.events, vec![TextInputEvent::EnterV2(1, first.id())]);
+
+    // Each enter takes a new serial; leave repeats the serial of its enter
+    client_state.events.clear();
+    env.state.set_keyboard_focus_surface(Some(second_id));
+    env.wait_roundtrip(&mut event_queue, &mut client_state);
+    assert_eq!(client_state.events, vec![
+        TextInputEvent::LeaveV2(1, first.id()),
+        TextInputEvent::EnterV2(2, second.id()),
+    ]);
+
+    // Only the entered surface can be enabled, and enabling is immediate
+    client_state.events.clear();
+    text_input.enable(&first);
+    env.wait_roundtrip(&mut event_queue, &mut client_state);
+    assert!(env.state.ext.text_input.active().is_none());
+    text_input.enable(&second);
+    env.wait_roundtrip(&mut event_queue, &mut client_state);
+    env.state.ext.text_input.commit_string("a");
+    env.wait_roundtrip(&mut event_queue, &mut client_state);
+    assert_eq!(client_state.events, vec![TextInputEvent::CommitString(Some("a".to_string()))]);
+
+    // Leaving disables the text input until it is enabled again
+    client_state.events.clear();
+    env.state.set_keyboard_focus_surface(Some(first_id));
+    env.wait_roundtrip(&mut event_queue, &mut client_state);
+    env.state.ext.text_input.commit_string("b");
+    env.wait_roundtrip(&mut event_queue, &mut client_state);
+    assert_eq!(client_state.events, vec![
+        TextInputEvent::LeaveV2(2, second.id()),
+        TextInputEvent::EnterV2(3, first.id()),
+    ]);
+    assert!(env.state.ext.text_input.active().is_none());
+}
+
+#[cfg(feature = "desktop-protocols")]
+#[test]
+fn test_text_input_v2_update_state_serial() {
+    let (mut env, mut event_queue, mut client_state) = setup();
+    let qh = event_queue.handle();
+    let (surface, surface_id) = create_surface(&mut env, &mut event_queue, &mut client_state);
+    let seat = client_state.seat.clone().unwrap();
+    let _input_method = client_state.input_method_manager.as_ref().unwrap().get_input_method(&seat, &qh, ());
+    env.state.set_keyboard_focus_surface(Some(surface_id));
+    let text_input = client_state.text_input_manager_v2.as_ref().unwrap().get_text_input(&seat, &qh, ());
+    text_input.enable(&surface);
+    env.wait_roundtrip(&mut event_queue, &mut client_state);
+
+    // State sent with the serial of the current enter reaches the input method
+    client_state.input_method_surrounding.clear();
+    text_input.set_surrounding_text("hello".to_string(), 5, 5);
+    text_input.update_state(1, zwp_text_input_v2::UpdateReason::Change);
+    env.wait_roundtrip(&mut event_queue, &mut client_state);
+    assert_eq!(client_state.input_method_surrounding, vec!["hello".to_string()]);
+
+    // After focus leaves and comes back, state for the old enter is stale
+    env.state.set_keyboard_focus_surface(None);
+    env.state.set_keyboard_focus_surface(Some(surface_id));
+    text_input.enable(&surface);
+    env.wait_roundtrip(&mut event_queue, &mut client_state);
+    client_state.input_method_surrounding.clear();
+    text_input.set_surrounding_text("stale".to_string(), 0, 0);
+    text_input.update_state(1, zwp_text_input_v2::UpdateReason::Change);
+    env.wait_roundtrip(&mut event_queue, &mut client_state);
+    assert!(client_state.input_method_surrounding.is_empty());
+
+    text_input.set_surrounding_text("fresh".to_string(), 0, 0);
+    text_input.update_state(2, zwp_text_input_v2::UpdateReason::Change);
+    env.wait_roundtrip(&mut event_queue, &mut client_state);
+    assert_eq!(client_state.input_method_surrounding, vec!["fresh".to_string()]);
+}
@@ -0,0 +1,266 @@
+use crate::tests::harness::TestEnv;
+use wayland_client::{
+    protocol::{wl_callback, wl_compositor, wl_keyboard, wl_pointer, wl_registry, wl_seat, wl_surface},
+    Connection, Dispatch, EventQueue, Proxy, QueueHandle,
+};
+use wayland_protocols::xdg::shell::client::{xdg_popup, xdg_positioner, xdg_surface, xdg_toplevel, xdg_wm_base};
+
+const BTN_LEFT: u32 = 0x110;
+const KEY_MENU: u32 = 139;
+
+#[derive(Default)]
+struct ClientState {
+    compositor: Option<wl_compositor::WlCompositor>,
+    seat: Option<wl_seat::WlSeat>,
+    wm_base: Option<xdg_wm_base::XdgWmBase>,
+    /// Popups sent popup_done, by name, in the order they were dismissed
+    dismissed: Vec<&'static str>,
+}
+
+impl Dispatch<wl_registry::WlRegistry, ()> for ClientState {
+    fn event(
+        state: &mut Self,
+        proxy: &wl_registry::WlRegistry,
+        event: wl_registry::Event,
+        _data: &(),
+        _conn: &Connection,
+        qh: &QueueHandle<Self>,
+    ) {
+        if let wl_registry::Event::Global { name, interface, version } = event {
+            if interface == "wl_compositor" {
+                state.compositor = Some(proxy.bind(name, version, qh, ()));
+            } else if interface == "wl_seat" {
+                state.seat = Some(proxy.bind(name, version, qh, ()));
+            } else if interface == "xdg_wm_base" {
+                state.wm_base = Some(proxy.bind(name, version, qh, ()));
+            }
+        }
+    }
+}
+
+impl Dispatch<wl_compositor::WlCompositor, ()> for ClientState {
+    fn event(_: &mut Self, _: &wl_compositor::WlCompositor, _: wl_compositor::Event, _: &(), _: &Connection, _: &QueueHandle<Self>) {}
+}
+
+impl Dispatch<wl_surface::WlSurface, ()> for ClientState {
+    fn event(_: &mut Self, _: &wl_surface::WlSurface, _: wl_surface::Event, _: &(), _: &Connection, _: &QueueHandle<Self>) {}
+}
+
+impl Dispatch<wl_seat::WlSeat, ()> for ClientState {
+    fn event(_: &mut Self, _: &wl_seat::WlSeat, _: wl_seat::Event, _: &(), _: &Connection, _: &QueueHandle<Self>) {}
+}
+
+impl Dispatch<wl_callback::WlCallback, ()> for ClientState {
+    fn event(_: &mut Self, _: &wl_callback::WlCallback, _: wl_callback::Event, _: &(), _: &Connection, _: &QueueHandle<Self>) {}
+}
+
+impl Dispatch<xdg_wm_base::XdgWmBase, ()> for ClientState {
+    fn event(_: &mut Self, proxy: &xdg_wm_base::XdgWmBase, event: xdg_wm_base::Event, _: &(), _: &Connection, _: &QueueHandle<Self>) {
+        if let xdg_wm_base::Event::Ping { serial } = event {
+            proxy.pong(serial);
+        }
+    }
+}
+
+impl Dispatch<xdg_positioner::XdgPositioner, ()> for ClientState {
+    fn event(_: &mut Self, _: &xdg_positioner::XdgPositioner, _: xdg_positioner::Event, _: &(), _: &Connection, _: &QueueHandle<Self>) {}
+}
+
+impl Dispatch<xdg_surface::XdgSurface, ()> for ClientState {
+    fn event(_: &mut Self, _: &xdg_surface::XdgSurface, _: xdg_surface::Event, _: &(), _: &Connection, _: &QueueHandle<Self>) {}
+}
+
+impl Dispatch<xdg_toplevel::XdgToplevel, ()> for ClientState {
+    fn event(_: &mut Self, _: &xdg_toplevel::XdgToplevel, _: xdg_toplevel::Event, _: &(), _: &Connection, _: &QueueHandle<Self>) {}
+}
+
+impl Dispatch<xdg_popup::XdgPopup, &'static str> for ClientState {
+    fn event(
+        state: &mut Self,
+        _proxy: &xdg_popup::XdgPopup,
+        event: xdg_popup::Event,
+        name: &&'static str,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        if let xdg_popup::Event::PopupDone = event {
+            state.dismissed.push(name);
+        }
+    }
+}
+
+/// A client with a toplevel to hang popups off
+fn setup() -> (TestEnv, EventQueue<ClientState>, ClientState, xdg_surface::XdgSurface) {
+    let mut env = TestEnv::new();
+    let display = env.client.display();
+    let mut event_queue = env.client.new_event_queue::<ClientState>();
+    let qh = event_queue.handle();
+    let _registry = display.get_registry(&qh, ());
+    let mut client_state = ClientState::default();
+    env.wait_roundtrip(&mut event_queue, &mut client_state);
+
+    let surface = client_state.compositor.as_ref().unwrap().create_surface(&qh, ());
+    let xdg = client_state.wm_base.as_ref().unwrap().get_xdg_surface(&surface, &qh, ());
+    let _toplevel = xdg.get_toplevel(&qh, ());
+    surface.commit();
+    env.wait_roundtrip(&mut event_queue, &mut client_state);
+    (env, event_queue, client_state, xdg)
+}
+
+/// Create an unmapped popup of `parent`, named for popup_done bookkeeping
+fn create_popup(
+    client_state: &ClientState,
+    qh: &QueueHandle<ClientState>,
+    parent: &xdg_surface::XdgSurface,
+    name: &'static str,
+) -> (xdg_surface::XdgSurface, xdg_popup::XdgPopup) {
+    let wm_base = client_state.wm_base.as_ref().unwrap();
+    let positioner = wm_base.create_positioner(qh, ());
+    positioner.set_size(50, 50);
+    positioner.set_anchor_rect(0, 0, 10, 10);
+    let surface = client_state.compositor.as_ref().unwrap().create_surface(qh, ());
+    let xdg = wm_base.get_xdg_surface(&surface, qh, ());
+    let popup = xdg.get_popup(Some(parent), &positioner, qh, name);
+    (xdg, popup)
+}
+
+/// Press the left button and return the serial of the press
+fn press(env: &mut TestEnv, time: u32) -> u32 {
+    env.state.inject_pointer_button(BTN_LEFT, wl_pointer::ButtonState::Pressed, time);
+    env.state.seat.pointer.grab_serial.expect("a held press has a serial")
+}
+
+/// Internal surface id of a popup
+fn popup_surface(env: &TestEnv, popup: &xdg_popup::XdgPopup) -> u32 {
+    let pid = popup.id().protocol_id();
+    env.state.xdg.popups.iter()
+        .find(|((_, id), _)| *id == pid)
+        .map(|(_, p)| p.surface_id)
+        .expect("popup not tracked")
+}
+
+#[test]
+fn test_popup_grab_serial() {
+    let (mut env, mut event_queue, mut client_state, toplevel) = setup();
+    let qh = event_queue.handle();
+    let seat = client_state.seat.clone().unwrap();
+
+    // A serial no input was ever given is turned down
+    let serial = press(&mut env, 100);
+    let (_bogus_xdg, bogus) = create_popup(&client_state, &qh, &toplevel, "bogus");
+    bogus.grab(&seat, serial + 1000);
+    env.wait_roundtrip(&mut event_queue, &mut client_state);
+    assert_eq!(client_state.dismissed, vec!["bogus"]);
+
+    // So is the serial of a press already released
+    env.state.inject_pointer_button(BTN_LEFT, wl_pointer::ButtonState::Released, 110);
+    let (_released_xdg, released) = create_popup(&client_state, &qh, &toplevel, "released");
+    released.grab(&seat, serial);
+    env.wait_roundtrip(&mut event_queue, &mut client_state);
+    assert_eq!(client_state.dismissed, vec!["bogus", "released"]);
+    assert!(env.state.seat.popup_grab_stack.is_empty());
+
+    // A key still held opens a menu
+    env.state.inject_key(KEY_MENU, wl_keyboard::KeyState::Pressed, 120);
+    let (key, serial) = env.state.seat.keyboard.grab_serial.expect("a held key has a serial");
+    assert_eq!(key, KEY_MENU);
+    let (_menu_xdg, menu) = create_popup(&client_state, &qh, &toplevel, "menu");
+    menu.grab(&seat, serial);
+    env.wait_roundtrip(&mut event_queue, &mut client_state);
+    assert_eq!(client_state.dismissed, vec!["bogus", "released"]);
+    assert_eq!(env.state.seat.popup_grab_stack.len(), 1);
+    assert_eq!(env.state.seat.keyboard.focus, Some(popup_surface(&env, &menu)));
+    assert!(env.client.protocol_error().is_none());
+}
+
+#[test]
+fn test_nested_popup_grab() {
+    let (mut env, mut event_queue, mut client_state, toplevel) = setup();
+    let qh = event_queue.handle();
+    let seat = client_state.seat.clone().unwrap();
+
+    let serial = press(&mut env, 100);
+    let (menu_xdg, menu) = create_popup(&client_state, &qh, &toplevel, "menu");
+    menu.grab(&seat, serial);
+    let (submenu_xdg, submenu) = create_popup(&client_state, &qh, &menu_xdg, "submenu");
+    submenu.grab(&seat, serial);
+    let (_leaf_xdg, leaf) = create_popup(&client_state, &qh, &submenu_xdg, "leaf");
+    leaf.grab(&seat, serial);
+    env.wait_roundtrip(&mut event_queue, &mut client_state);
+    assert!(client_state.dismissed.is_empty());
+    assert_eq!(env.state.seat.popup_grab_stack.len(), 3);
+    assert_eq!(env.state.seat.keyboard.focus, Some(popup_surface(&env, &leaf)));
+
+    // Closing the topmost popup hands focus back to its parent
+    let submenu_id = popup_surface(&env, &submenu);
+    leaf.destroy();
+    env.wait_roundtrip(&mut event_queue, &mut client_state);
+    assert_eq!(env.state.seat.popup_grab_stack.len(), 2);
+    assert_eq!(env.state.seat.keyboard.focus, Some(submenu_id));
+
+    // A press outside the chain dismisses it, topmost first
+    env.state.inject_pointer_button(BTN_LEFT, wl_pointer::ButtonState::Released, 110);
+    press(&mut env, 120);
+    env.wait_roundtrip(&mut event_queue, &mut client_state);
+    assert_eq!(client_state.dismissed, vec!["submenu", "menu"]);
+    assert!(env.state.seat.popup_grab_stack.is_empty());
+    assert_ne!(env.state.seat.keyboard.focus, Some(submenu_id));
+    assert!(env.client.protocol_error().is_none());
+}
+
+#[test]
+fn test_popup_grab_parent_without_grab() {
+    let (mut env, mut event_queue, mut client_state, toplevel) = setup();
+    let qh = event_queue.handle();
+    let seat = client_state.seat.clone().unwrap();
+
+    // A grabbing popup may only nest in a popup that grabbed too
+    let serial = press(&mut env, 100);
+    let (tooltip_xdg, _tooltip) = create_popup(&client_state, &qh, &toplevel, "tooltip");
+    let (_menu_xdg, menu) = create_popup(&client_state, &qh, &tooltip_xdg, "menu");
+    menu.grab(&seat, serial);
+    env.wait_roundtrip(&mut event_queue, &mut client_state);
+
+    let error = env.client.protocol_error().expect("grab under a non-grabbing popup must be a protocol error");
+    assert_eq!(error.object_interface, "xdg_wm_base");
+    assert_eq!(error.code, xdg_wm_base::Error::InvalidPopupParent as u32);
+}
+
+#[test]
+fn test_popup_destroy_not_topmost() {
+    let (mut env, mut event_queue, mut client_state, toplevel) = setup();
+    let qh = event_queue.handle();
+    let seat = client_state.seat.clone().unwrap();
+
+    let serial = press(&mut env, 100);
+    let (menu_xdg, menu) = create_popup(&client_state, &qh, &toplevel, "menu");
+    menu.grab(&seat, serial);
+    let (_submenu_xdg, submenu) = create_popup(&client_state, &qh, &menu_xdg, "submenu");
+    submenu.grab(&seat, serial);
+    env.wait_roundtrip(&mut event_queue, &mut client_state);
+    assert!(env.client.protocol_error().is_none());
+
+    menu.destroy();
+    env.wait_roundtrip(&mut event_queue, &mut client_state);
+    let error = env.client.protocol_error().expect("destroying a popup under the topmost must be a protocol error");
+    assert_eq!(error.object_interface, "xdg_wm_base");
+    assert_eq!(error.code, xdg_wm_base::Error::NotTheTopmostPopup as u32);
+}
+
+#[test]
+fn test_popup_destroy_with_children_without_grab() {
+    let (mut env, mut event_queue, mut client_state, toplevel) = setup();
+    let qh = event_queue.handle();
+
+    // Tooltips nest like menus but never grab
+    let (tooltip_xdg, tooltip) = create_popup(&client_state, &qh, &toplevel, "tooltip");
+    let (_nested_xdg, _nested) = create_popup(&client_state, &qh, &tooltip_xdg, "nested");
+    env.wait_roundtrip(&mut event_queue, &mut client_state);
+    assert!(env.state.seat.popup_grab_stack.is_empty());
+
+    tooltip.destroy();
+    env.wait_roundtrip(&mut event_queue, &mut client_state);
+    let error = env.client.protocol_error().expect("destroying a popup with a live child must be a protocol error");
+    assert_eq!(error.object_interface, "xdg_wm_base");
+    assert_eq!(error.code, xdg_wm_base::Error::NotTheTopmostPopup as u32);
+}
@@ -1,3 +1,7 @@
+// 8x8 bitmap font for ASCII.
+//
+// One glyph per code point below 128, eight rows of eight pixels each;
+// the most significant bit of a row is its leftmost pixel.
 
 pub const FONT: [[u8; 8]; 128] = [
     [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // 0   
@@ -48,4 +48,53 @@ impl Rect {
             && self.y < other.y + other.height as i32
             && self.y + self.height as i32 > other.y
     }
+    
+    /// X coordinate just past the right edge
+    pub fn right(&self) -> i32 {
+        self.x + self.width as i32
+    }
+    
+    /// Y coordinate just past the bottom edge
+    pub fn bottom(&self) -> i32 {
+        self.y + self.height as i32
+    }
+    
+    pub fn is_empty(&self) -> bool {
+        self.width == 0 || self.height == 0
+    }
+    
+    /// Overlap of two rectangles, if any
+    pub fn intersection(&self, other: &Rect) -> Option<Rect> {
+        let x1 = self.x.max(other.x);
+        let y1 = self.y.max(other.y);
+        let x2 = self.right().min(other.right());
+        let y2 = self.bottom().min(other.bottom());
+        if x2 > x1 && y2 > y1 {
+            Some(Rect::new(x1, y1, (x2 - x1) as u32, (y2 - y1) as u32))
+        } else {
+            None
+        }
+    }
+    
+    /// The parts of this rectangle outside `other` (at most four)
+    pub fn subtract(&self, other: &Rect) -> Vec<Rect> {
+        let inter = match self.intersection(other) {
+            Some(inter) => inter,
+            None => return vec![*self],
+        };
+        let mut out = Vec::with_capacity(4);
+        if inter.y > self.y {
+            out.push(Rect::new(self.x, self.y, self.width, (inter.y - self.y) as u32));
+        }
+        if inter.bottom() < self.bottom() {
+            out.push(Rect::new(self.x, inter.bottom(), self.width, (self.bottom() - inter.bottom()) as u32));
+        }
+        if inter.x > self.x {
+            out.push(Rect::new(self.x, inter.y, (inter.x - self.x) as u32, inter.height));
+        }
+        if inter.right() < self.right() {
+            out.push(Rect::new(inter.right(), inter.y, (self.right() - inter.right()) as u32, inter.height));
+        }
+        out
+    }
 }
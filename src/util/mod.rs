@@ -1,6 +1,8 @@
 pub mod geometry;
+pub mod region;
 pub mod arena;
 pub mod id;
 pub mod tracing;
 pub mod logging;
 pub mod ssh;
+pub mod font8x8;
@@ -0,0 +1,250 @@
+//! Pixel regions as sets of non-overlapping rectangles
+
+use crate::util::geometry::Rect;
+
+/// An arbitrary area made of non-overlapping rectangles.
+///
+/// Used for damage tracking and occlusion: unions never double-count
+/// overlapping pixels, and subtraction is exact.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Region {
+    rects: Vec<Rect>,
+}
+
+impl Region {
+    pub fn new() -> Self {
+        Self { rects: Vec::new() }
+    }
+
+    pub fn from_rect(rect: Rect) -> Self {
+        let mut region = Self::new();
+        region.add_rect(rect);
+        region
+    }
+
+    pub fn from_rects<I: IntoIterator<Item = Rect>>(rects: I) -> Self {
+        let mut region = Self::new();
+        region.add_rects(rects);
+        region
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rects.is_empty()
+    }
+
+    /// The rectangles making up the region; they never overlap
+    pub fn rects(&self) -> &[Rect] {
+        &self.rects
+    }
+
+    pub fn into_rects(self) -> Vec<Rect> {
+        self.rects
+    }
+
+    pub fn clear(&mut self) {
+        self.rects.clear();
+    }
+
+    /// Number of covered pixels
+    pub fn area(&self) -> u64 {
+        self.rects.iter().map(|r| r.width as u64 * r.height as u64).sum()
+    }
+
+    pub fn bounding_box(&self) -> Option<Rect> {
+        let first = self.rects.first()?;
+        let (mut x1, mut y1) = (first.x, first.y);
+        let (mut x2, mut y2) = (first.right(), first.bottom());
+        for r in &self.rects[1..] {
+            x1 = x1.min(r.x);
+            y1 = y1.min(r.y);
+            x2 = x2.max(r.right());
+            y2 = y2.max(r.bottom());
+        }
+        Some(Rect::new(x1, y1, (x2 - x1) as u32, (y2 - y1) as u32))
+    }
+
+    pub fn contains_point(&self, x: i32, y: i32) -> bool {
+        self.rects.iter().any(|r| r.contains_point(x, y))
+    }
+
+    /// Add a rectangle to the region
+    pub fn add_rect(&mut self, rect: Rect) {
+        self.add_rects([rect]);
+    }
+
+    /// Add several rectangles, merging the result once at the end
+    pub fn add_rects<I: IntoIterator<Item = Rect>>(&mut self, rects: I) {
+        let mut added = false;
+        for rect in rects {
+            added |= self.insert(rect);
+        }
+        if added {
+            self.simplify();
+        }
+    }
+
+    /// Add another region to this one
+    pub fn union(&mut self, other: &Region) {
+        self.add_rects(other.rects.iter().copied());
+    }
+
+    /// Add the parts of `rect` not covered yet, without merging. Returns
+    /// whether anything was added.
+    fn insert(&mut self, rect: Rect) -> bool {
+        if rect.is_empty() {
+            return false;
+        }
+        let mut pieces = vec![rect];
+        for existing in &self.rects {
+            pieces = pieces.iter().flat_map(|p| p.subtract(existing)).collect();
+            if pieces.is_empty() {
+                return false;
+            }
+        }
+        self.rects.extend(pieces);
+        true
+    }
+
+    /// Remove a rectangle from the region
+    pub fn subtract_rect(&mut self, hole: &Rect) {
+        if !self.rects.iter().any(|r| r.intersects(hole)) {
+            return;
+        }
+        self.rects = self.rects.iter().flat_map(|r| r.subtract(hole)).collect();
+        self.simplify();
+    }
+
+    /// Remove another region from this one
+    pub fn subtract(&mut self, other: &Region) {
+        for hole in &other.rects {
+            if self.rects.is_empty() {
+                break;
+            }
+            self.subtract_rect(hole);
+        }
+    }
+
+    /// The part of the region inside `rect`
+    pub fn intersect_rect(&self, rect: &Rect) -> Region {
+        let mut result = Region {
+            rects: self.rects.iter().filter_map(|r| r.intersection(rect)).collect(),
+        };
+        result.simplify();
+        result
+    }
+
+    /// The part of the region also covered by `other`
+    pub fn intersect(&self, other: &Region) -> Region {
+        let mut result = Region {
+            // Pieces of two non-overlapping sets don't overlap either
+            rects: self.rects
+                .iter()
+                .flat_map(|a| other.rects.iter().filter_map(move |b| a.intersection(b)))
+                .collect(),
+        };
+        result.simplify();
+        result
+    }
+
+    /// Whether any pixel is covered by both regions
+    pub fn intersects(&self, other: &Region) -> bool {
+        self.rects.iter().any(|a| other.rects.iter().any(|b| a.intersects(b)))
+    }
+
+    pub fn translate(&mut self, dx: i32, dy: i32) {
+        for r in &mut self.rects {
+            r.x += dx;
+            r.y += dy;
+        }
+    }
+
+    /// Merge rectangles that share a full edge, keeping the region exact.
+    pub fn simplify(&mut self) {
+        let mut merged = true;
+        while merged {
+            merged = false;
+            'outer: for i in 0..self.rects.len() {
+                for j in (i + 1)..self.rects.len() {
+                    if let Some(joined) = join(&self.rects[i], &self.rects[j]) {
+                        self.rects[i] = joined;
+                        self.rects.swap_remove(j);
+                        merged = true;
+                        break 'outer;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Collapse the region to its bounding box when it has more than
+    /// `max_rects` rectangles. Over-covering is fine for damage and keeps
+    /// renderer scissoring cheap.
+    pub fn limit(&mut self, max_rects: usize) {
+        if self.rects.len() > max_rects {
+            if let Some(bbox) = self.bounding_box() {
+                self.rects = vec![bbox];
+            }
+        }
+    }
+}
+
+impl From<Rect> for Region {
+    fn from(rect: Rect) -> Self {
+        Region::from_rect(rect)
+    }
+}
+
+/// The rectangle covering exactly `a` and `b`, if they line up along a
+/// shared edge
+fn join(a: &Rect, b: &Rect) -> Option<Rect> {
+    if a.y == b.y && a.height == b.height && (a.right() == b.x || b.right() == a.x) {
+        Some(Rect::new(a.x.min(b.x), a.y, a.width + b.width, a.height))
+    } else if a.x == b.x && a.width == b.width && (a.bottom() == b.y || b.bottom() == a.y) {
+        Some(Rect::new(a.x, a.y.min(b.y), a.width, a.height + b.height))
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_union_does_not_double_count() {
+        let mut region = Region::from_rect(Rect::new(0, 0, 100, 100));
+        region.add_rect(Rect::new(50, 50, 100, 100));
+        assert_eq!(region.area(), 17_500);
+        assert_eq!(region.bounding_box(), Some(Rect::new(0, 0, 150, 150)));
+        assert!(!region.contains_point(120, 10));
+
+        // Adjacent halves simplify back into one rectangle
+        let region = Region::from_rects([Rect::new(0, 0, 100, 50), Rect::new(0, 50, 100, 50)]);
+        assert_eq!(region.rects(), &[Rect::new(0, 0, 100, 100)]);
+    }
+
+    #[test]
+    fn test_subtract_and_intersect() {
+        let mut region = Region::from_rect(Rect::new(0, 0, 100, 100));
+        region.subtract_rect(&Rect::new(25, 25, 50, 50));
+        assert_eq!(region.area(), 7_500);
+        assert!(!region.contains_point(50, 50));
+
+        let hole = Region::from_rect(Rect::new(25, 25, 50, 50));
+        assert!(region.intersect(&hole).is_empty());
+        assert!(!region.intersects(&hole));
+
+        let clipped = region.intersect_rect(&Rect::new(0, 0, 100, 10));
+        assert_eq!(clipped.rects(), &[Rect::new(0, 0, 100, 10)]);
+
+        region.union(&hole);
+        assert_eq!(region.rects(), &[Rect::new(0, 0, 100, 100)]);
+    }
+
+    #[test]
+    fn test_limit() {
+        let mut region = Region::from_rects([Rect::new(0, 0, 10, 10), Rect::new(50, 50, 10, 10)]);
+        region.limit(1);
+        assert_eq!(region.rects(), &[Rect::new(0, 0, 60, 60)]);
+    }
+}